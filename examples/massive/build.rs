@@ -1,11 +1,28 @@
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     time::UNIX_EPOCH,
 };
 
-use ugm::{parser::ParseOptions, speedy::Writable, texture::TextureCompression, Model};
+use meshopt::{SimplifyOptions, VertexDataAdapter};
+use ugm::{
+    mesh::{Mesh, PackedVertex},
+    parser::ParseOptions,
+    speedy::Writable,
+    texture::TextureCompression,
+    Model,
+};
 use xshell::Shell;
 
+/// Matches `terrarium::gpu_resources::LOD_EXTENSION`.
+const LOD_EXTENSION: &str = "lods";
+/// Maximum number of levels of detail generated for every mesh, excluding the original.
+const MAX_LODS: usize = 3;
+/// Fraction of triangles every level of detail keeps from the previous one.
+const LOD_TRIANGLE_RATIO: f32 = 0.5;
+/// Maximum simplification error relative to the size of the mesh.
+const LOD_TARGET_ERROR: f32 = 0.05;
+
 fn file_modified_time_in_seconds(path: &PathBuf) -> u64 {
     std::fs::metadata(path)
         .unwrap()
@@ -16,8 +33,97 @@ fn file_modified_time_in_seconds(path: &PathBuf) -> u64 {
         .as_secs()
 }
 
+/// Simplify `mesh` down to roughly `LOD_TRIANGLE_RATIO` of its triangles, `None` when it can no longer be simplified.
+fn simplify_mesh(mesh: &Mesh) -> Option<Mesh> {
+    let vertices = VertexDataAdapter::new(
+        bytemuck::cast_slice(&mesh.packed_vertices),
+        size_of::<PackedVertex>(),
+        0,
+    )
+    .unwrap();
+
+    let mut material_indices = mesh.triangle_material_indices.clone();
+    material_indices.sort_unstable();
+    material_indices.dedup();
+
+    // Every material is simplified separately, collapsed triangles would otherwise lose their material
+    let mut indices = Vec::new();
+    let mut triangle_material_indices = Vec::new();
+    for material_idx in material_indices {
+        let material_indices: Vec<u32> = mesh
+            .indices
+            .chunks_exact(3)
+            .zip(&mesh.triangle_material_indices)
+            .filter(|(_, triangle_material_idx)| **triangle_material_idx == material_idx)
+            .flat_map(|(triangle, _)| triangle.iter().copied())
+            .collect();
+
+        let target_count = ((material_indices.len() / 3) as f32 * LOD_TRIANGLE_RATIO) as usize * 3;
+        let simplified_indices = meshopt::simplify(
+            &material_indices,
+            &vertices,
+            target_count,
+            LOD_TARGET_ERROR,
+            SimplifyOptions::LockBorder,
+            None,
+        );
+
+        triangle_material_indices.extend(std::iter::repeat_n(
+            material_idx,
+            simplified_indices.len() / 3,
+        ));
+        indices.extend(simplified_indices);
+    }
+
+    // Stop once the error bound prevents any meaningful reduction
+    if indices.is_empty() || indices.len() as f32 > mesh.indices.len() as f32 * 0.9 {
+        return None;
+    }
+
+    // Only keep the vertices that are still referenced
+    let mut vertex_remap = HashMap::new();
+    let mut packed_vertices = Vec::new();
+    for index in &mut indices {
+        let vertex_idx = *index as usize;
+        *index = *vertex_remap.entry(vertex_idx).or_insert_with(|| {
+            packed_vertices.push(mesh.packed_vertices[vertex_idx]);
+            packed_vertices.len() as u32 - 1
+        });
+    }
+
+    let mut lod_mesh = mesh.clone();
+    lod_mesh.packed_vertices = packed_vertices;
+    lod_mesh.indices = indices;
+    lod_mesh.triangle_material_indices = triangle_material_indices;
+    Some(lod_mesh)
+}
+
+/// Successively simplified levels of detail of every mesh in `model`, skinned and morphed meshes are left out.
+fn generate_lods(model: &Model) -> Vec<Vec<Mesh>> {
+    model
+        .meshes
+        .iter()
+        .map(|mesh| {
+            let mut lods: Vec<Mesh> = Vec::new();
+            if mesh.is_empty() || !mesh.joint_indices.is_empty() || !mesh.morph_targets.is_empty() {
+                return lods;
+            }
+
+            while lods.len() < MAX_LODS {
+                let Some(lod_mesh) = simplify_mesh(lods.last().unwrap_or(mesh)) else {
+                    break;
+                };
+                lods.push(lod_mesh);
+            }
+            lods
+        })
+        .collect()
+}
+
 fn parse_model(model_path: PathBuf) {
-    if std::fs::exists(model_path.with_extension("ugm")).unwrap() {
+    if std::fs::exists(model_path.with_extension("ugm")).unwrap()
+        && std::fs::exists(model_path.with_extension(LOD_EXTENSION)).unwrap()
+    {
         let gltf_modified = file_modified_time_in_seconds(&model_path);
         let ugm_modified = file_modified_time_in_seconds(&model_path.with_extension("ugm"));
 
@@ -39,6 +145,9 @@ fn parse_model(model_path: PathBuf) {
 
     let ugm_bytes: Vec<u8> = model.write_to_vec().unwrap();
     std::fs::write(model_path.with_extension("ugm"), ugm_bytes).unwrap();
+
+    let lod_bytes: Vec<u8> = generate_lods(&model).write_to_vec().unwrap();
+    std::fs::write(model_path.with_extension(LOD_EXTENSION), lod_bytes).unwrap();
 }
 
 fn parse_assets(shell: &Shell, dir: &PathBuf) {
@@ -7,7 +7,7 @@ use glam::{Quat, UVec2, Vec3};
 use terrarium::{
     app_loop::{AppLoop, AppLoopHandler, AppLoopHandlerCreateDesc},
     egui,
-    gpu_resources::GpuResources,
+    gpu_resources::{environment_map::EnvironmentMap, GpuResources},
     helpers::{
         input_handler::InputHandler,
         timer::{FpsCounter, Timer},
@@ -89,7 +89,7 @@ impl AppLoop for ExampleApp {
         xr_camera_state: &mut XrCameraState,
         command_encoder: &mut wgpu::CommandEncoder,
         ctx: &wgpu_util::Context,
-        _pipeline_database: &mut wgpu_util::PipelineDatabase,
+        pipeline_database: &mut wgpu_util::PipelineDatabase,
     ) {
         let delta_time = self.frame_timer.elapsed();
         self.frame_timer.reset();
@@ -161,6 +161,21 @@ impl AppLoop for ExampleApp {
             }
 
             self.gpu_resources.mark_statics_dirty();
+
+            // The environment map is optional, the atmosphere lights the scene without one
+            let environment_map_path = "examples/massive/assets/environment.hdr";
+            if std::path::Path::new(environment_map_path).exists() {
+                let environment_map = EnvironmentMap::from_file(
+                    environment_map_path,
+                    command_encoder,
+                    ctx,
+                    pipeline_database,
+                )
+                .unwrap();
+                self.gpu_resources
+                    .sky_mut()
+                    .set_environment_map(Some(environment_map));
+            }
         }
 
         {
@@ -172,11 +187,6 @@ impl AppLoop for ExampleApp {
                     RIGHT,
                     (delta_time * 10.0).to_radians(),
                 ));
-            self.world
-                .entities::<TransformComponent>()
-                .get(self.emitter_entity.unwrap())
-                .unwrap()
-                .mark_dirty(&self.world.entities::<TransformComponent>());
         }
 
         self.world.update();
@@ -245,6 +255,10 @@ impl AppLoop for ExampleApp {
         self.input_handler.handle_xr_input(xr_frame_state, xr);
     }
 
+    fn optional_features() -> wgpu::Features {
+        Renderer::optional_features()
+    }
+
     fn required_features() -> wgpu::Features {
         Renderer::required_features()
     }
@@ -262,6 +276,7 @@ impl AppLoop for ExampleApp {
             max_texture_dimension_2d: 4096,
             max_binding_array_elements_per_shader_stage: 1024,
             max_storage_textures_per_shader_stage: 8,
+            max_color_attachment_bytes_per_sample: 64,
             ..wgpu::Limits::default()
         }
     }
@@ -5,10 +5,13 @@ use std::sync::Arc;
 use glam::{Mat4, Vec3};
 use rand::Rng;
 use specs::{Builder, WorldExt};
+use terrarium::gpu_resources::material_pool::AlphaMode;
 use terrarium::gpu_resources::{GpuMaterial, GpuMesh, GpuResources};
 use terrarium::wgpu_util;
 use terrarium::world::components::{
-    AreaLightComponent, DynamicComponent, MeshComponent, TransformComponent,
+    AnimationPlayerComponent, AreaLightComponent, DirectionalLightComponent, DynamicComponent,
+    LodMeshComponent, MeshComponent, MorphWeightsComponent, PointLightComponent,
+    SkinnedMeshComponent, SpotLightComponent, TransformComponent, VisibilityRangeComponent,
 };
 use terrarium::world::transform::Transform;
 use ugm::Model;
@@ -68,8 +71,16 @@ impl World {
         ecs.register::<EntityInfoComponent>();
         ecs.register::<MeshComponent>();
         ecs.register::<AreaLightComponent>();
+        ecs.register::<PointLightComponent>();
+        ecs.register::<SpotLightComponent>();
+        ecs.register::<DirectionalLightComponent>();
         ecs.register::<TransformComponent>();
         ecs.register::<DynamicComponent>();
+        ecs.register::<SkinnedMeshComponent>();
+        ecs.register::<AnimationPlayerComponent>();
+        ecs.register::<MorphWeightsComponent>();
+        ecs.register::<VisibilityRangeComponent>();
+        ecs.register::<LodMeshComponent>();
 
         Self {
             ecs,
@@ -190,13 +201,25 @@ impl World {
         let gpu_meshes: Vec<Arc<GpuMesh>> = model
             .meshes
             .iter()
-            .map(|mesh| gpu_resources.create_gpu_mesh(mesh, command_encoder, ctx))
+            .map(|mesh| {
+                let opaque = mesh.material_indices.iter().all(|material_idx| {
+                    AlphaMode::from(model.materials[*material_idx as usize].alpha_mode)
+                        == AlphaMode::Opaque
+                });
+                gpu_resources
+                    .create_gpu_mesh(mesh, opaque, command_encoder, ctx)
+                    .expect("Failed to upload mesh.")
+            })
             .collect();
 
         let gpu_materials: Vec<Arc<GpuMaterial>> = model
             .materials
             .iter()
-            .map(|material| gpu_resources.create_gpu_material(model, material, ctx))
+            .map(|material| {
+                gpu_resources
+                    .create_gpu_material(model, material, command_encoder, ctx)
+                    .expect("Failed to upload material.")
+            })
             .collect();
 
         let root = self.create_entity(root_transform, is_static, parent, |builder| builder);
@@ -222,22 +245,6 @@ pub trait EntityExt {
 impl EntityExt for specs::Entity {
     fn set_parent(&self, parent: Option<specs::Entity>, world: &mut World) {
         let mut transforms = world.entities_mut::<TransformComponent>();
-
-        if let Some(parent) = transforms.get(*self).unwrap().parent {
-            let parent_transform = transforms.get_mut(parent).unwrap();
-            parent_transform.children.remove(
-                parent_transform
-                    .children
-                    .iter()
-                    .position(|x| *x == *self)
-                    .unwrap(),
-            );
-        }
-
-        transforms.get_mut(*self).unwrap().parent = parent;
-        if let Some(parent) = transforms.get(*self).unwrap().parent {
-            let parent_transform = transforms.get_mut(parent).unwrap();
-            parent_transform.children.push(*self);
-        }
+        TransformComponent::reparent(&mut transforms, *self, parent, false).unwrap();
     }
 }
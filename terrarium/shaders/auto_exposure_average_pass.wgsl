@@ -0,0 +1,60 @@
+const NUM_BINS: u32 = 256;
+
+struct Constants {
+    resolution: vec2<u32>,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    delta_time: f32,
+    adaptation_speed: f32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+struct Exposure {
+    average_luminance: f32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, NUM_BINS>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> exposure: Exposure;
+
+var<workgroup> weighted_bins: array<f32, NUM_BINS>;
+
+@compute
+@workgroup_size(256)
+fn main(@builtin(local_invocation_index) local_index: u32) {
+    let count: u32 = atomicLoad(&histogram[local_index]);
+    weighted_bins[local_index] = f32(count) * f32(local_index);
+    // Clear for the next frame
+    atomicStore(&histogram[local_index], 0u);
+    workgroupBarrier();
+
+    for (var cutoff: u32 = NUM_BINS / 2; cutoff > 0; cutoff >>= 1u) {
+        if (local_index < cutoff) {
+            weighted_bins[local_index] += weighted_bins[local_index + cutoff];
+        }
+        workgroupBarrier();
+    }
+
+    if (local_index == 0) {
+        // Only thread 0 holds the black pixel count, which are excluded from the average
+        let num_pixels: f32 = f32(constants.resolution.x * constants.resolution.y * 2);
+        let num_lit_pixels: f32 = max(num_pixels - f32(count), 1.0);
+        let average_bin: f32 = max(weighted_bins[0] / num_lit_pixels - 1.0, 0.0);
+        let average_log_luminance: f32 = average_bin / f32(NUM_BINS - 2) * constants.log_luminance_range + constants.min_log_luminance;
+        let target_luminance: f32 = exp2(average_log_luminance);
+
+        let previous_luminance: f32 = exposure.average_luminance;
+        let adaptation: f32 = 1.0 - exp(-constants.delta_time * constants.adaptation_speed);
+        let adapted_luminance: f32 = previous_luminance + (target_luminance - previous_luminance) * adaptation;
+        exposure.average_luminance = select(adapted_luminance, target_luminance, previous_luminance <= 0.0);
+    }
+}
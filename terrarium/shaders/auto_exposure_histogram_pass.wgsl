@@ -0,0 +1,55 @@
+@include shared/color.wgsl
+
+const NUM_BINS: u32 = 256;
+
+struct Constants {
+    resolution: vec2<u32>,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    delta_time: f32,
+    adaptation_speed: f32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var color: texture_2d_array<f32>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, NUM_BINS>;
+
+var<workgroup> local_histogram: array<atomic<u32>, NUM_BINS>;
+
+// Bin 0 is reserved for pixels that are too dark to contribute to the average
+fn luminance_to_bin(luminance: f32) -> u32 {
+    if (luminance < 0.0001) { return 0u; }
+
+    let log_luminance: f32 = saturate((log2(luminance) - constants.min_log_luminance) / constants.log_luminance_range);
+    return u32(log_luminance * f32(NUM_BINS - 2) + 1.0);
+}
+
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32) {
+    atomicStore(&local_histogram[local_index], 0u);
+    workgroupBarrier();
+
+    let id: vec2<u32> = global_id.xy;
+    if (all(id < constants.resolution)) {
+        for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
+            let hdr: vec3<f32> = textureLoad(color, id, view_index, 0).rgb;
+            let bin: u32 = luminance_to_bin(linear_to_luma(hdr));
+            atomicAdd(&local_histogram[bin], 1u);
+        }
+    }
+
+    workgroupBarrier();
+    atomicAdd(&histogram[local_index], atomicLoad(&local_histogram[local_index]));
+}
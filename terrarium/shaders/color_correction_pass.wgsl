@@ -1,9 +1,29 @@
 @include shared/color.wgsl
 
+const TONE_MAPPER_REINHARD: u32 = 0;
+const TONE_MAPPER_ACES_FITTED: u32 = 1;
+const TONE_MAPPER_AGX: u32 = 2;
+const TONE_MAPPER_PBR_NEUTRAL: u32 = 3;
+
+// Exposure that maps the average scene luminance to middle grey
+const KEY_VALUE: f32 = 0.18;
+
 struct Constants {
     resolution: vec2<u32>,
+    tone_mapper: u32,
+    auto_exposure: u32,
+    exposure: f32,
+    color_grading_intensity: f32,
+    lut_size: u32,
     _padding0: u32,
+    lut_domain_min: vec3<f32>,
     _padding1: u32,
+    lut_domain_max: vec3<f32>,
+    _padding2: u32,
+}
+
+struct Exposure {
+    average_luminance: f32,
 }
 
 @group(0)
@@ -14,18 +34,122 @@ var<uniform> constants: Constants;
 @binding(1)
 var color: texture_storage_2d_array<rgba16float, read_write>;
 
-fn hdr_to_sdr(hdr: vec3<f32>) -> vec3<f32> {
-    // let a: f32 = 2.51;
-    // let b: f32 = 0.03;
-    // let c: f32 = 2.43;
-    // let d: f32 = 0.59;
-    // let e: f32 = 0.14;
-    
-    // let sdr: vec3<f32> = (hdr * (a * hdr + b)) / (hdr * (c * hdr + d) + e);
-    // return clamp(sdr, vec3<f32>(0.0), vec3<f32>(1.0));
+@group(0)
+@binding(2)
+var<storage, read> exposure: Exposure;
+
+@group(0)
+@binding(3)
+var lut: texture_3d<f32>;
+
+@group(0)
+@binding(4)
+var lut_sampler: sampler;
+
+fn tonemap_reinhard(hdr: vec3<f32>) -> vec3<f32> {
     return hdr / (hdr + 1.0);
 }
 
+// Stephen Hill's fit of the ACES RRT + ODT, including the sRGB <-> ACEScg conversions
+const ACES_INPUT: mat3x3<f32> = mat3x3<f32>(
+    vec3<f32>(0.59719, 0.07600, 0.02840),
+    vec3<f32>(0.35458, 0.90834, 0.13383),
+    vec3<f32>(0.04823, 0.01566, 0.83777)
+);
+
+const ACES_OUTPUT: mat3x3<f32> = mat3x3<f32>(
+    vec3<f32>(1.60475, -0.10208, -0.00327),
+    vec3<f32>(-0.53108, 1.10813, -0.07276),
+    vec3<f32>(-0.07367, -0.00605, 1.07602)
+);
+
+fn rrt_and_odt_fit(v: vec3<f32>) -> vec3<f32> {
+    let a: vec3<f32> = v * (v + 0.0245786) - 0.000090537;
+    let b: vec3<f32> = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return a / b;
+}
+
+fn tonemap_aces_fitted(hdr: vec3<f32>) -> vec3<f32> {
+    var sdr: vec3<f32> = ACES_INPUT * hdr;
+    sdr = rrt_and_odt_fit(sdr);
+    sdr = ACES_OUTPUT * sdr;
+    return saturate(sdr);
+}
+
+// Minimal AgX with the default look, using a polynomial fit of the contrast curve
+const AGX_INSET: mat3x3<f32> = mat3x3<f32>(
+    vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+    vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+    vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104)
+);
+
+const AGX_OUTSET: mat3x3<f32> = mat3x3<f32>(
+    vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+    vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+    vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116)
+);
+
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+fn agx_contrast_approx(x: vec3<f32>) -> vec3<f32> {
+    let x2: vec3<f32> = x * x;
+    let x4: vec3<f32> = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn tonemap_agx(hdr: vec3<f32>) -> vec3<f32> {
+    var sdr: vec3<f32> = AGX_INSET * hdr;
+    sdr = clamp(log2(max(sdr, vec3<f32>(1e-10))), vec3<f32>(AGX_MIN_EV), vec3<f32>(AGX_MAX_EV));
+    sdr = (sdr - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+    sdr = agx_contrast_approx(sdr);
+    sdr = AGX_OUTSET * sdr;
+    return pow(max(sdr, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+// https://github.com/KhronosGroup/ToneMapping/tree/main/PBR_Neutral
+fn tonemap_pbr_neutral(hdr: vec3<f32>) -> vec3<f32> {
+    let start_compression: f32 = 0.8 - 0.04;
+    let desaturation: f32 = 0.15;
+
+    let x: f32 = min(hdr.r, min(hdr.g, hdr.b));
+    let offset: f32 = select(0.04, x - 6.25 * x * x, x < 0.08);
+    var sdr: vec3<f32> = hdr - offset;
+
+    let peak: f32 = max(sdr.r, max(sdr.g, sdr.b));
+    if (peak < start_compression) { return sdr; }
+
+    let d: f32 = 1.0 - start_compression;
+    let new_peak: f32 = 1.0 - d * d / (peak + d - start_compression);
+    sdr *= new_peak / peak;
+
+    let g: f32 = 1.0 - 1.0 / (desaturation * (peak - new_peak) + 1.0);
+    return mix(sdr, vec3<f32>(new_peak), g);
+}
+
+fn hdr_to_sdr(hdr: vec3<f32>) -> vec3<f32> {
+    switch (constants.tone_mapper) {
+        case TONE_MAPPER_ACES_FITTED: { return tonemap_aces_fitted(hdr); }
+        case TONE_MAPPER_AGX: { return tonemap_agx(hdr); }
+        case TONE_MAPPER_PBR_NEUTRAL: { return tonemap_pbr_neutral(hdr); }
+        default: { return tonemap_reinhard(hdr); }
+    }
+}
+
+// Cube luts are authored against display encoded colors, so grading happens in srgb space
+fn apply_color_grading(sdr: vec3<f32>) -> vec3<f32> {
+    let srgb: vec3<f32> = linear_to_srgb(vec4<f32>(saturate(sdr), 1.0)).rgb;
+
+    let lut_size: f32 = f32(constants.lut_size);
+    let domain: vec3<f32> = saturate((srgb - constants.lut_domain_min) / (constants.lut_domain_max - constants.lut_domain_min));
+    // Remap to texel centers so the first and last entries are hit exactly
+    let uvw: vec3<f32> = domain * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+    let graded: vec3<f32> = textureSampleLevel(lut, lut_sampler, uvw, 0.0).rgb;
+
+    let result: vec3<f32> = mix(srgb, graded, constants.color_grading_intensity);
+    return srgb_to_linear(vec4<f32>(result, 1.0)).rgb;
+}
+
 @compute
 @workgroup_size(16, 16)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
@@ -33,9 +157,17 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
     let id: vec2<u32> = global_id.xy;
     if (any(id >= constants.resolution)) { return; }
 
+    var exposure_scale: f32 = constants.exposure;
+    if (constants.auto_exposure != 0) {
+        exposure_scale *= KEY_VALUE / max(exposure.average_luminance, 0.0001);
+    }
+
     for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
-        var hdr: vec3<f32> = textureLoad(color, id, view_index).rgb;
+        var hdr: vec3<f32> = textureLoad(color, id, view_index).rgb * exposure_scale;
         var sdr: vec3<f32> = hdr_to_sdr(hdr);
+        if (constants.color_grading_intensity > 0.0) {
+            sdr = apply_color_grading(sdr);
+        }
         textureStore(color, id, view_index, vec4<f32>(sdr, 1.0));
     }
-}
\ No newline at end of file
+}
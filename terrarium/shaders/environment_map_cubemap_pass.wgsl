@@ -0,0 +1,63 @@
+@include shared/environment_map.wgsl
+
+struct Constants {
+    source_is_cubemap: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var source: texture_2d_array<f32>;
+
+@group(0)
+@binding(2)
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+// The source is stored at full precision, which is not guaranteed to be filterable
+fn load_bilinear(uv: vec2<f32>, layer: u32, wrap_x: bool) -> vec3<f32> {
+    let resolution = vec2<i32>(textureDimensions(source));
+    let position: vec2<f32> = uv * vec2<f32>(resolution) - 0.5;
+    let base = vec2<i32>(floor(position));
+    let t: vec2<f32> = position - vec2<f32>(base);
+
+    var texels: array<vec3<f32>, 4>;
+    for (var i: u32 = 0; i < 4; i += 1) {
+        var texel: vec2<i32> = base + vec2<i32>(i32(i % 2), i32(i / 2));
+        if (wrap_x) {
+            texel.x = (texel.x % resolution.x + resolution.x) % resolution.x;
+        }
+        texel = clamp(texel, vec2<i32>(0), resolution - 1);
+        texels[i] = textureLoad(source, texel, layer, 0).rgb;
+    }
+
+    return mix(mix(texels[0], texels[1], t.x), mix(texels[2], texels[3], t.x), t.y);
+}
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let resolution: vec2<u32> = textureDimensions(dst);
+    let id: vec2<u32> = global_id.xy;
+    let face: u32 = global_id.z;
+    if (any(id >= resolution)) { return; }
+
+    let uv: vec2<f32> = (vec2<f32>(id) + vec2<f32>(0.5)) / vec2<f32>(resolution);
+    let direction: vec3<f32> = normalize(EnvironmentMap::cube_face_direction(face, uv));
+
+    var radiance: vec3<f32>;
+    if (constants.source_is_cubemap != 0) {
+        let face_uv: vec3<f32> = EnvironmentMap::cube_face_uv(direction);
+        radiance = load_bilinear(face_uv.xy, u32(face_uv.z), false);
+    } else {
+        radiance = load_bilinear(EnvironmentMap::equirectangular_uv(direction), 0, true);
+    }
+
+    // Keep bright spots like the sun within half float range
+    textureStore(dst, id, face, vec4<f32>(clamp(radiance, vec3<f32>(0.0), vec3<f32>(65000.0)), 1.0));
+}
@@ -0,0 +1,24 @@
+@group(0)
+@binding(0)
+var src: texture_2d_array<f32>;
+
+@group(0)
+@binding(1)
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let resolution: vec2<u32> = textureDimensions(dst);
+    let id: vec2<u32> = global_id.xy;
+    let face: u32 = global_id.z;
+    if (any(id >= resolution)) { return; }
+
+    let src_id: vec2<u32> = id * 2;
+    let radiance: vec3<f32> = (textureLoad(src, src_id, face, 0).rgb
+        + textureLoad(src, src_id + vec2<u32>(1, 0), face, 0).rgb
+        + textureLoad(src, src_id + vec2<u32>(0, 1), face, 0).rgb
+        + textureLoad(src, src_id + vec2<u32>(1, 1), face, 0).rgb) * 0.25;
+
+    textureStore(dst, id, face, vec4<f32>(radiance, 1.0));
+}
@@ -0,0 +1,68 @@
+@include shared/sampling.wgsl
+@include shared/environment_map.wgsl
+
+const THREAD_COUNT: u32 = 64;
+const SAMPLE_COUNT_SQRT: u32 = 64;
+
+struct Constants {
+    lod: f32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var radiance: texture_cube<f32>;
+
+@group(0)
+@binding(2)
+var radiance_sampler: sampler;
+
+@group(0)
+@binding(3)
+var<storage, read_write> irradiance_sh: array<vec4<f32>, IRRADIANCE_SH_COEFFICIENT_COUNT>;
+
+var<workgroup> shared_coefficients: array<array<vec3<f32>, IRRADIANCE_SH_COEFFICIENT_COUNT>, THREAD_COUNT>;
+
+// Project the environment onto spherical harmonics and convolve it with a clamped cosine,
+// from "An Efficient Representation for Irradiance Environment Maps" by Ramamoorthi and Hanrahan
+@compute
+@workgroup_size(THREAD_COUNT)
+fn main(@builtin(local_invocation_index) thread_idx: u32) {
+    var coefficients: array<vec3<f32>, IRRADIANCE_SH_COEFFICIENT_COUNT>;
+    for (var i: u32 = thread_idx; i < SAMPLE_COUNT_SQRT * SAMPLE_COUNT_SQRT; i += THREAD_COUNT) {
+        let uv: vec2<f32> = (vec2<f32>(f32(i % SAMPLE_COUNT_SQRT), f32(i / SAMPLE_COUNT_SQRT)) + vec2<f32>(0.5)) / f32(SAMPLE_COUNT_SQRT);
+        let direction: vec3<f32> = get_uniform_sphere_sample(uv);
+        let sample_radiance: vec3<f32> = textureSampleLevel(radiance, radiance_sampler, direction, constants.lod).rgb;
+
+        var basis: array<f32, IRRADIANCE_SH_COEFFICIENT_COUNT> = EnvironmentMap::sh_basis(direction);
+        for (var j: u32 = 0; j < IRRADIANCE_SH_COEFFICIENT_COUNT; j += 1) {
+            coefficients[j] += sample_radiance * basis[j];
+        }
+    }
+    shared_coefficients[thread_idx] = coefficients;
+    workgroupBarrier();
+
+    for (var stride: u32 = THREAD_COUNT / 2; stride > 0; stride /= 2) {
+        if (thread_idx < stride) {
+            for (var j: u32 = 0; j < IRRADIANCE_SH_COEFFICIENT_COUNT; j += 1) {
+                shared_coefficients[thread_idx][j] += shared_coefficients[thread_idx + stride][j];
+            }
+        }
+        workgroupBarrier();
+    }
+
+    if (thread_idx == 0) {
+        let sample_weight: f32 = 4.0 * PI / f32(SAMPLE_COUNT_SQRT * SAMPLE_COUNT_SQRT);
+        for (var j: u32 = 0; j < IRRADIANCE_SH_COEFFICIENT_COUNT; j += 1) {
+            // Clamped cosine convolution factors of each band
+            let band_factor: f32 = select(select(PI * 0.25, 2.0 * PI / 3.0, j < 4), PI, j == 0);
+            irradiance_sh[j] = vec4<f32>(shared_coefficients[0][j] * sample_weight * band_factor, 0.0);
+        }
+    }
+}
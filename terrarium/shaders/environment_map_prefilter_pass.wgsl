@@ -0,0 +1,83 @@
+@include shared/environment_map.wgsl
+
+struct Constants {
+    roughness: f32,
+    sample_count: u32,
+    radiance_resolution: u32,
+    _padding0: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var radiance: texture_cube<f32>;
+
+@group(0)
+@binding(2)
+var radiance_sampler: sampler;
+
+@group(0)
+@binding(3)
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+fn hammersley(i: u32, sample_count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(sample_count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, alpha: f32) -> vec3<f32> {
+    let phi: f32 = TWO_PI * xi.x;
+    let cos_theta: f32 = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+    let sin_theta: f32 = safe_sqrt(1.0 - cos_theta * cos_theta);
+
+    let h_tangent = vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+    return normalize(build_orthonormal_basis(n) * h_tangent);
+}
+
+// Prefiltered importance sampling from "GPU-Based Importance Sampling" by Colbert and Krivanek,
+// samples with a low pdf read from a blurrier mip to avoid fireflies with a limited sample count
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let resolution: vec2<u32> = textureDimensions(dst);
+    let id: vec2<u32> = global_id.xy;
+    let face: u32 = global_id.z;
+    if (any(id >= resolution)) { return; }
+
+    let uv: vec2<f32> = (vec2<f32>(id) + vec2<f32>(0.5)) / vec2<f32>(resolution);
+    // Assume the view direction equals the normal, as is common for split sum prefiltering
+    let n: vec3<f32> = normalize(EnvironmentMap::cube_face_direction(face, uv));
+
+    if (constants.roughness == 0.0) {
+        textureStore(dst, id, face, vec4<f32>(textureSampleLevel(radiance, radiance_sampler, n, 0.0).rgb, 1.0));
+        return;
+    }
+
+    let alpha: f32 = constants.roughness * constants.roughness;
+    let texel_solid_angle: f32 = 4.0 * PI / (6.0 * f32(constants.radiance_resolution * constants.radiance_resolution));
+
+    var prefiltered = vec3<f32>(0.0);
+    var total_weight: f32 = 0.0;
+    for (var i: u32 = 0; i < constants.sample_count; i += 1) {
+        let h: vec3<f32> = importance_sample_ggx(hammersley(i, constants.sample_count), n, alpha);
+        let l: vec3<f32> = 2.0 * dot(n, h) * h - n;
+        let n_dot_l: f32 = dot(n, l);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+
+        let n_dot_h: f32 = max(dot(n, h), 0.0);
+        let d_denominator: f32 = n_dot_h * n_dot_h * (alpha * alpha - 1.0) + 1.0;
+        let d: f32 = alpha * alpha / (PI * d_denominator * d_denominator);
+        let pdf: f32 = d * 0.25 + 0.0001;
+        let sample_solid_angle: f32 = 1.0 / (f32(constants.sample_count) * pdf);
+        let lod: f32 = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+
+        prefiltered += textureSampleLevel(radiance, radiance_sampler, l, lod).rgb * n_dot_l;
+        total_weight += n_dot_l;
+    }
+
+    textureStore(dst, id, face, vec4<f32>(prefiltered / max(total_weight, 0.0001), 1.0));
+}
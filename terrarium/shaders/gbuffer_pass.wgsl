@@ -2,63 +2,194 @@
 
 @include shared/vertex_pool_bindings.wgsl
 @include shared/material_pool_bindings.wgsl
+@include shared/sampling.wgsl
 
-struct VertexOutput {
-    @location(0) normal_ws: vec3<f32>,
-    @location(1) tex_coord: vec2<f32>,
-    @builtin(position) position_cs: vec4<f32>,
-};
+struct Constants {
+    resolution: vec2<u32>,
+    mipmapping: u32,
+    normal_mapping: u32,
+}
 
 struct PushConstant {
     local_to_world_space: mat4x4<f32>,
-    inv_trans_local_to_world_space: mat4x4<f32>,
+    inv_trans_local_to_world_space: mat3x3<f32>,
+    lod_fade: f32,
 }
 
 var<push_constant> pc : PushConstant;
 
 @group(0)
 @binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
 var<uniform> xr_camera: XrCamera;
 
+struct VertexOutput {
+    @builtin(position) position_cs: vec4<f32>,
+    @location(0) position_ws: vec3<f32>,
+    @location(1) prev_position_ws: vec3<f32>,
+    @location(2) normal_ws: vec3<f32>,
+    @location(3) tangent_ws: vec3<f32>,
+    @location(4) bitangent_ws: vec3<f32>,
+    @location(5) tex_coord: vec2<f32>,
+    @location(6) @interpolate(flat) material_descriptor_idx: u32,
+    @location(7) @interpolate(flat) lod_fade: f32,
+};
+
+// Matches the layout of the gbuffer textures, see gbuffer_bindings.wgsl
+struct FragmentOutput {
+    @location(0) position_and_depth: vec4<f32>,
+    @location(1) shading_and_geometric_normal: vec4<u32>,
+    @location(2) tex_coord_and_derivatives: vec4<f32>,
+    @location(3) velocity: vec4<f32>,
+    @location(4) material_descriptor_idx_and_normal_roughness: vec4<f32>,
+};
+
 @vertex
 fn vs_main(
-    @location(0) position: vec3<f32>,
-    @location(1) _packed_normal: u32,
-    @location(2) tex_coord: vec2<f32>,
-    @location(3) _packed_tangent: u32,
-    @location(4) tangent_handiness: f32,
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_idx: u32,
     @builtin(view_index) view_index: i32
 ) -> VertexOutput {
-    let packed_normal = PackedNormalizedXyz10(_packed_normal);
-    let packed_tangent = PackedNormalizedXyz10(_packed_tangent);
+    // Vertices are pulled from the vertex pool manually, this way every triangle knows its own material
+    let vertex_slice_index: u32 = vertex_pool_vertex_slice_indices[instance_idx];
+    let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[vertex_slice_index];
 
-    let normal: vec3<f32> = PackedNormalizedXyz10::unpack(packed_normal, 0);
-    let tangent: vec3<f32> = PackedNormalizedXyz10::unpack(packed_tangent, 0);
-    let bitangent: vec3<f32> = VertexPool::calculate_bitangent(normal, tangent, tangent_handiness);
+    let index: u32 = vertex_indices[vertex_pool_slice.first_index + vertex_index];
+    let vertex: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + index]);
 
-    let normal_ws: vec3<f32> = (pc.inv_trans_local_to_world_space * vec4<f32>(normal, 0.0)).xyz;
+    let triangle_idx: u32 = vertex_pool_slice.first_index / 3 + vertex_index / 3;
+    let material_descriptor_idx: u32 = VertexPoolBindings::material_idx(instance_idx, triangle_idx);
 
-    let position_world_space: vec4<f32> = pc.local_to_world_space * vec4<f32>(position, 1.0);
-    let position_clip_space: vec4<f32> = xr_camera.view_to_clip_space[view_index] * xr_camera.world_to_view_space[view_index] * position_world_space;
+    let bitangent: vec3<f32> = _calculate_bitangent(vertex.normal, vertex.tangent);
+
+    let position_ws: vec3<f32> = (pc.local_to_world_space * vec4<f32>(vertex.position, 1.0)).xyz;
+    var position_cs: vec4<f32> = xr_camera.view_to_clip_space[view_index] * xr_camera.world_to_view_space[view_index] * vec4<f32>(position_ws, 1.0);
+
+    // Apply the same sub-pixel jitter the ray traced gbuffer applies to its primary rays
+    let jitter_ndc: vec2<f32> = vec2<f32>(-xr_camera.jitter.x, xr_camera.jitter.y) * 2.0 / vec2<f32>(constants.resolution);
+    position_cs = vec4<f32>(position_cs.xy + jitter_ndc * position_cs.w, position_cs.zw);
 
     var result: VertexOutput;
-    result.normal_ws = normal_ws;
-    result.tex_coord = tex_coord;
-    result.position_cs = position_clip_space;
+    result.position_cs = position_cs;
+    result.position_ws = position_ws;
+    // Deformed meshes keep last frame's vertices around, static meshes point prev_first_vertex at their own vertices
+    let prev_position: vec3<f32> = vertices[vertex_pool_slice.prev_first_vertex + index].position;
+    let prev_position_ws: vec3<f32> = (pc.local_to_world_space * vec4<f32>(prev_position, 1.0)).xyz;
+    result.prev_position_ws = VertexPoolBindings::reproject_point(instance_idx, prev_position_ws);
+    result.normal_ws = pc.inv_trans_local_to_world_space * vertex.normal;
+    result.tangent_ws = pc.inv_trans_local_to_world_space * vertex.tangent.xyz;
+    result.bitangent_ws = pc.inv_trans_local_to_world_space * bitangent;
+    result.tex_coord = vertex.tex_coord;
+    result.material_descriptor_idx = material_descriptor_idx;
+    result.lod_fade = pc.lod_fade;
     return result;
 }
 
 @fragment
-fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    // let tex_coord: vec2<f32> = MaterialPoolBindings::transform_uv(0, vertex.tex_coord);
-    // let color: vec4<f32> = srgb_to_linear(_texture(0, tex_coord));
+fn fs_main(input: VertexOutput, @builtin(view_index) _view_index: i32) -> FragmentOutput {
+    let view_index = u32(_view_index);
+
+    // Derivatives have to be taken in uniform control flow
+    let position_ddx: vec3<f32> = dpdx(input.position_ws);
+    let position_ddy: vec3<f32> = dpdy(input.position_ws);
+    var ddx: vec2<f32> = dpdx(input.tex_coord);
+    var ddy: vec2<f32> = dpdy(input.tex_coord);
+    if (constants.mipmapping == 0) {
+        ddx = vec2<f32>(0.0);
+        ddy = vec2<f32>(0.0);
+    }
+
+    // Levels of detail in transition dither out with a positive fade and in with a negative one, covering complementary pixels
+    if (input.lod_fade != 0.0) {
+        let noise: f32 = interleaved_gradient_noise(input.position_cs.xy);
+        if ((input.lod_fade > 0.0 && noise < input.lod_fade) || (input.lod_fade < 0.0 && noise >= -input.lod_fade)) {
+            discard;
+        }
+    }
+
+    let material_descriptor: MaterialDescriptor = material_descriptors[input.material_descriptor_idx];
+
+    // Blended surfaces are composited by the transparent pass, masked surfaces are cut out below their cutoff
+    if (material_descriptor.alpha_mode == ALPHA_MODE_BLEND) {
+        discard;
+    }
+    if (material_descriptor.alpha_mode == ALPHA_MODE_MASK
+        && MaterialDescriptor::color(material_descriptor, input.tex_coord, ddx, ddy).a < material_descriptor.alpha_cutoff) {
+        discard;
+    }
 
-    let l: vec3<f32> = -normalize(vec3<f32>(0.1, -1.0, 0.2));
-    let color: vec3<f32> = vec3<f32>(1.0, 0.8, 0.7) * max(dot(vertex.normal_ws, l), 0.2);
+    let hit_normal_ws: vec3<f32> = normalize(input.normal_ws);
+    let hit_tangent_to_world = mat3x3<f32>(
+        normalize(input.tangent_ws),
+        normalize(input.bitangent_ws),
+        hit_normal_ws
+    );
+    var geometric_normal_ws: vec3<f32> = normalize(cross(position_ddy, position_ddx));
 
-    // if (color.a < 0.5) {
-    //     discard;
-    // }
+    // Apply normal mapping when available, unlike the name suggest, not front facing yet
+    var mapped_normal_and_roughness: vec4<f32>;
+    // Without a normal map of its own the clearcoat layer follows the interpolated normal
+    var front_facing_clearcoat_normal_ws: vec3<f32> = hit_normal_ws;
+    if (constants.normal_mapping > 0) {
+        mapped_normal_and_roughness = MaterialDescriptor::apply_normal_mapping(material_descriptor, input.tex_coord, ddx, ddy, hit_normal_ws, hit_tangent_to_world);
+        front_facing_clearcoat_normal_ws = MaterialDescriptor::apply_clearcoat_normal_mapping(material_descriptor, input.tex_coord, ddx, ddy, hit_normal_ws, hit_tangent_to_world).xyz;
+    } else {
+        mapped_normal_and_roughness = vec4<f32>(hit_normal_ws, 1.0);
+    }
+    var front_facing_shading_normal_ws: vec3<f32> = mapped_normal_and_roughness.xyz;
+    let normal_roughness: f32 = mapped_normal_and_roughness.w;
+    var front_facing_interpolated_normal_ws: vec3<f32> = hit_normal_ws;
+    // Isotropic materials store the mesh tangent, the packing has no representation for zero
+    var anisotropy_tangent_ws: vec3<f32> = hit_tangent_to_world[0];
+    if (material_descriptor.anisotropic > 0.0) {
+        anisotropy_tangent_ws = MaterialDescriptor::anisotropy_tangent(material_descriptor, input.tex_coord, ddx, ddy, hit_tangent_to_world);
+    }
 
-    return vec4<f32>(color, 1.0);
-}
\ No newline at end of file
+    let origin: vec3<f32> = XrCamera::origin(xr_camera, view_index);
+    let w_out_worldspace: vec3<f32> = normalize(origin - input.position_ws);
+
+    // Make sure the hit normal and normal mapped normal are front facing
+    if (dot(w_out_worldspace, geometric_normal_ws) < 0.0) {
+        geometric_normal_ws *= -1.0;
+    }
+    let back_face: bool = dot(w_out_worldspace, hit_normal_ws) < 0.0;
+    if (back_face) {
+        front_facing_shading_normal_ws *= -1.0;
+        front_facing_interpolated_normal_ws *= -1.0;
+        front_facing_clearcoat_normal_ws *= -1.0;
+    }
+
+    let current_position_cs: vec4<f32> = xr_camera.view_to_clip_space[view_index] * xr_camera.world_to_view_space[view_index] * vec4<f32>(input.position_ws, 1.0);
+    let prev_position_cs: vec4<f32> = xr_camera.prev_view_to_clip_space[view_index] * xr_camera.prev_world_to_view_space[view_index] * vec4<f32>(input.prev_position_ws, 1.0);
+
+    var position_ss: vec4<f32> = (current_position_cs / current_position_cs.w + 1.0) / 2.0;
+    position_ss = vec4<f32>(position_ss.x, 1.0 - position_ss.y, position_ss.zw);
+    var prev_position_ss: vec4<f32> = (prev_position_cs / prev_position_cs.w + 1.0) / 2.0;
+    prev_position_ss = vec4<f32>(prev_position_ss.x, 1.0 - prev_position_ss.y, prev_position_ss.zw);
+    let velocity: vec2<f32> = (position_ss - prev_position_ss).xy;
+
+    var result: FragmentOutput;
+    result.position_and_depth = vec4<f32>(input.position_ws, distance(origin, input.position_ws));
+    result.shading_and_geometric_normal = vec4<u32>(
+        PackedNormalizedXyz10::new(front_facing_shading_normal_ws, 0).data,
+        PackedNormalizedXyz10::new(geometric_normal_ws, 0).data,
+        PackedNormalizedXyz10::new(front_facing_interpolated_normal_ws, 0).data,
+        PackedNormalizedXyz10::new(front_facing_clearcoat_normal_ws, 0).data
+    );
+    result.tex_coord_and_derivatives = vec4<f32>(
+        input.tex_coord,
+        bitcast<f32>(pack2x16float(ddx)),
+        bitcast<f32>(pack2x16float(ddy))
+    );
+    result.velocity = vec4<f32>(velocity, 0.0, 0.0);
+    result.material_descriptor_idx_and_normal_roughness = vec4<f32>(
+        bitcast<f32>(input.material_descriptor_idx),
+        normal_roughness,
+        bitcast<f32>(PackedNormalizedXyz10::new(anisotropy_tangent_ws, 0).data),
+        0.0
+    );
+    return result;
+}
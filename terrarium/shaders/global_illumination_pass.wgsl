@@ -0,0 +1,243 @@
+@include shared/brdf.wgsl
+@include shared/xr.wgsl
+@include shared/trace.wgsl
+@include shared/sampling.wgsl
+@include shared/random.wgsl
+@include shared/scaled_resolution.wgsl
+
+@include shared/vertex_pool_bindings.wgsl
+@include shared/material_pool_bindings.wgsl
+@include shared/alpha_test_bindings.wgsl
+@include shared/sky_bindings.wgsl
+@include shared/gbuffer_bindings.wgsl
+@include shared/linear_transformed_cosines_bindings.wgsl
+@include shared/punctual_light_bindings.wgsl
+@include shared/emissive_light_bindings.wgsl
+
+struct Constants {
+    resolution: vec2<u32>,
+    global_illumination_resolution: vec2<u32>,
+    ambient_factor: f32,
+    view_index: u32,
+    render_distance: f32,
+    frame_idx: u32,
+    emissive_lights: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var<uniform> xr_camera: XrCamera;
+
+@group(0)
+@binding(2)
+var static_scene: acceleration_structure;
+
+@group(0)
+@binding(3)
+var dynamic_scene: acceleration_structure;
+
+@group(0)
+@binding(4)
+var global_illumination_out: texture_storage_2d_array<rgba16float, write>;
+
+fn trace_ray(origin: vec3<f32>, direction: vec3<f32>) -> RayIntersection {
+    // Blended surfaces are composited by the transparent pass instead
+    let static_intersection: RayIntersection = AlphaTest::trace_ray(static_scene, RayDesc(0u, 0xFFu, 0.0, constants.render_distance, origin, direction), true);
+    var static_t: f32 = 10000.0;
+    if (static_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        static_t = static_intersection.t;
+    }
+
+    let dynamic_intersection: RayIntersection = AlphaTest::trace_ray(dynamic_scene, RayDesc(0u, 0xFFu, 0.0, constants.render_distance, origin, direction), true);
+    var dynamic_t: f32 = 10000.0;
+    if (dynamic_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        dynamic_t = dynamic_intersection.t;
+    }
+
+    if (static_t < dynamic_t) {
+        return static_intersection;
+    } else {
+        return dynamic_intersection;
+    }
+}
+
+fn trace_visibility(hit_point: vec3<f32>, geometric_normal: vec3<f32>, direction: vec3<f32>, distance: f32) -> f32 {
+    let shadow_origin: vec3<f32> = hit_point + geometric_normal * 0.01;
+    let shadow_distance: f32 = distance - 0.01;
+
+    const TERMINATE_ON_FIRST_HIT: u32 = 0x4;
+
+    let static_intersection: RayIntersection = AlphaTest::trace_ray(static_scene, RayDesc(TERMINATE_ON_FIRST_HIT, 0xFFu, 0.0, shadow_distance, shadow_origin, direction), false);
+    if (static_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        return 0.0;
+    }
+
+    let dynamic_intersection: RayIntersection = AlphaTest::trace_ray(dynamic_scene, RayDesc(TERMINATE_ON_FIRST_HIT, 0xFFu, 0.0, shadow_distance, shadow_origin, direction), false);
+    if (dynamic_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        return 0.0;
+    }
+
+    return 1.0;
+}
+
+// Light arriving at a secondary hit, local lights are stochastically picked as the tile light lists only cover the screen
+fn shade_hit_lighting(material: Material, hit_point: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, rng: ptr<function, u32>) -> vec3<f32> {
+    var lighting = vec3<f32>(0.0);
+
+    for (var i: u32 = 0; i < punctual_light_constants.directional_light_count; i += 1) {
+        let light: vec3<f32> = PunctualLightBindings::shade_directional(material, i, normal, view_dir);
+        if (any(light > vec3<f32>(0.0))) {
+            lighting += light * trace_visibility(hit_point, normal, -directional_lights[i].direction, 10000.0);
+        }
+    }
+
+    let point_light_count: u32 = punctual_light_constants.point_light_count;
+    let spot_light_count: u32 = punctual_light_constants.spot_light_count;
+    let local_light_count: u32 = point_light_count + spot_light_count + ltc_constants.instance_count;
+    if (local_light_count > 0) {
+        let local_light_index: u32 = min(u32(random_uniform_float(rng) * f32(local_light_count)), local_light_count - 1);
+
+        var light: vec3<f32>;
+        var target_point: vec3<f32>;
+        if (local_light_index < point_light_count) {
+            light = PunctualLightBindings::shade_point(material, local_light_index, normal, view_dir, hit_point);
+            target_point = PunctualLightBindings::position(LIGHT_TYPE_POINT, local_light_index);
+        } else if (local_light_index < point_light_count + spot_light_count) {
+            let spot_light_index: u32 = local_light_index - point_light_count;
+            light = PunctualLightBindings::shade_spot(material, spot_light_index, normal, view_dir, hit_point);
+            target_point = PunctualLightBindings::position(LIGHT_TYPE_SPOT, spot_light_index);
+        } else {
+            let area_light_index: u32 = local_light_index - point_light_count - spot_light_count;
+            light = LtcBindings::shade(material, area_light_index, normal, view_dir, hit_point);
+            target_point = LtcBindings::closest_point(area_light_index, hit_point);
+        }
+
+        if (any(light > vec3<f32>(0.0))) {
+            let visibility: f32 = trace_visibility(hit_point, normal, normalize(target_point - hit_point), distance(target_point, hit_point));
+            lighting += light * visibility * f32(local_light_count);
+        }
+    }
+
+    // A single emissive triangle picked in proportion to its power, like the local lights only one shadow ray is spent on it
+    if (EmissiveLightBindings::has_lights()) {
+        let light_sample: EmissiveLightSample = EmissiveLightBindings::sample(random_uniform_float3(rng));
+        let light: vec3<f32> = EmissiveLightBindings::shade(material, light_sample, normal, view_dir, hit_point);
+        if (any(light > vec3<f32>(0.0))) {
+            let to_light: vec3<f32> = light_sample.position - hit_point;
+            lighting += light / light_sample.pdf * trace_visibility(hit_point, normal, normalize(to_light), length(to_light));
+        }
+    }
+
+    // The sun is part of the environment map when there is one, and is found by the rays missing the scene instead
+    if (!Sky::has_environment_map()) {
+        let to_sun: vec3<f32> = Sky::direction_to_sun(random_uniform_float2(rng));
+        let n_dot_l: f32 = dot(normal, to_sun);
+        if (n_dot_l > 0.0) {
+            // Matches the radiance and size of the sun disk drawn by `Sky::inscattering`
+            let sun_solid_angle: f32 = TWO_PI * (1.0 - cos(max(sky_constants.sun.size, 0.05) * 0.1));
+            let sun_irradiance: vec3<f32> = Sky::sun_intensity(to_sun) * 100.0 * sky_constants.sun.color * sun_solid_angle;
+
+            lighting += Material::eval_brdf(material, to_sun, view_dir, normal) * n_dot_l * sun_irradiance
+                * trace_visibility(hit_point, normal, to_sun, 10000.0);
+        }
+    }
+
+    return lighting;
+}
+
+fn shade_hit(intersection: RayIntersection, origin: vec3<f32>, direction: vec3<f32>, ddx: vec2<f32>, ddy: vec2<f32>, rng: ptr<function, u32>) -> vec3<f32> {
+    if (intersection.kind != RAY_QUERY_INTERSECTION_TRIANGLE) {
+        // Rays are cosine distributed, the sun is sampled explicitly at hits instead of relying on rays to find its disk
+        return Sky::inscattering(direction, true);
+    }
+
+    let vertex_slice_index: u32 = vertex_pool_vertex_slice_indices[intersection.instance_custom_data];
+    let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[vertex_slice_index];
+
+    let barycentrics = vec3<f32>(1.0 - intersection.barycentrics.x - intersection.barycentrics.y, intersection.barycentrics);
+
+    let i0: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 0];
+    let i1: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 1];
+    let i2: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 2];
+
+    let v0: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i0]);
+    let v1: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i1]);
+    let v2: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i2]);
+
+    let tex_coord: vec2<f32> = v0.tex_coord * barycentrics.x + v1.tex_coord * barycentrics.y + v2.tex_coord * barycentrics.z;
+
+    let material_descriptor_idx: u32 = VertexPoolBindings::material_idx(intersection.instance_custom_data, vertex_pool_slice.first_index / 3 + intersection.primitive_index);
+    let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx];
+    let material: Material = Material::from_material_descriptor(material_descriptor, tex_coord, ddx, ddy);
+
+    // Diffuse bounces don't need shading normals, the flat triangle normal facing the ray is used instead
+    let local_to_world_inv_trans: mat4x4<f32> = transpose(mat4x4<f32>(
+        vec4<f32>(intersection.world_to_object[0], 0.0),
+        vec4<f32>(intersection.world_to_object[1], 0.0),
+        vec4<f32>(intersection.world_to_object[2], 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0)
+    ));
+    let geometric_normal: vec3<f32> = normalize(cross(v1.position - v0.position, v2.position - v0.position));
+    var normal: vec3<f32> = normalize((local_to_world_inv_trans * vec4<f32>(geometric_normal, 1.0)).xyz);
+    if (dot(normal, direction) > 0.0) {
+        normal = -normal;
+    }
+
+    let hit_point: vec3<f32> = origin + direction * intersection.t;
+    let lighting: vec3<f32> = shade_hit_lighting(material, hit_point, normal, -direction, rng);
+
+    // Emission reaching the surface directly is already sampled by the lighting pass
+    var emission: vec3<f32> = material.emission;
+    if (constants.emissive_lights > 0) {
+        emission = vec3<f32>(0.0);
+    }
+
+    // The ambient term stands in for all further bounces
+    return emission + lighting + material.color * constants.ambient_factor;
+}
+
+// Traces a single cosine distributed ray per texel, storing the incoming radiance without the albedo of the surface it leaves from,
+// or a negative alpha for sky texels
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let global_illumination_id: vec2<u32> = global_id.xy;
+    if (any(global_illumination_id >= constants.global_illumination_resolution)) { return; }
+
+    let view_index: u32 = constants.view_index;
+    let id: vec2<u32> = ScaledResolution::render_id(global_illumination_id, constants.resolution, constants.global_illumination_resolution);
+
+    let position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(id, view_index);
+    if (GbufferPositionAndDepth::is_sky(position_and_depth)) {
+        textureStore(global_illumination_out, global_illumination_id, view_index, vec4<f32>(0.0, 0.0, 0.0, -1.0));
+        return;
+    }
+
+    let shading_and_geometric_normal: GbufferShadingAndGeometricNormal = Gbuffer::load_shading_and_geometric_normal(id, view_index);
+    let tex_coord_and_derivatives: GbufferTexCoordAndDerivatives = Gbuffer::load_tex_coord_and_derivatives(id, view_index);
+
+    var rng: u32 = pcg_hash((global_illumination_id.y * constants.global_illumination_resolution.x + global_illumination_id.x) ^ pcg_hash(constants.frame_idx * 2 + view_index));
+
+    let uv = vec2<f32>(
+        interleaved_gradient_noise_animated(global_illumination_id, constants.frame_idx),
+        interleaved_gradient_noise_animated(global_illumination_id + vec2<u32>(113, 127), constants.frame_idx)
+    );
+    let tangent_to_world: mat3x3<f32> = build_orthonormal_basis(shading_and_geometric_normal.interpolated_normal);
+    var direction: vec3<f32> = normalize(tangent_to_world * get_cosine_hemisphere_sample(uv));
+    // Samples below the surface fall back to the normal instead of self intersecting
+    if (dot(direction, shading_and_geometric_normal.geometric_normal) <= 0.0) {
+        direction = shading_and_geometric_normal.interpolated_normal;
+    }
+
+    let origin: vec3<f32> = position_and_depth.position + shading_and_geometric_normal.geometric_normal * 0.001;
+    let radiance: vec3<f32> = shade_hit(trace_ray(origin, direction), origin, direction, tex_coord_and_derivatives.ddx, tex_coord_and_derivatives.ddy, &rng);
+
+    textureStore(global_illumination_out, global_illumination_id, view_index, vec4<f32>(radiance, 1.0));
+}
@@ -3,6 +3,7 @@
 
 @include shared/linear_transformed_cosines_bindings.wgsl
 @include shared/debug_line_bindings.wgsl
+@include shared/punctual_light_bindings.wgsl
 
 struct Constants {
     resolution: vec2<u32>,
@@ -70,7 +71,21 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invo
         // }
 
         if (!culled) {
-            append_light(i);
+            append_light(encode_light_index(LIGHT_TYPE_AREA, i));
+        }
+    }
+
+    // Point and spot lights share the tile light list with area lights, directional lights are never culled
+    for (var i: u32 = local_index; i < punctual_light_constants.point_light_count; i += FRUSTUM_TILE_SIZE * FRUSTUM_TILE_SIZE) {
+        let aabb: Aabb = PointLightInstance::illuminated_aabb(point_lights[i]);
+        if (Frustum::intersect_aabb(gs_frustum, aabb)) {
+            append_light(encode_light_index(LIGHT_TYPE_POINT, i));
+        }
+    }
+    for (var i: u32 = local_index; i < punctual_light_constants.spot_light_count; i += FRUSTUM_TILE_SIZE * FRUSTUM_TILE_SIZE) {
+        let aabb: Aabb = SpotLightInstance::illuminated_aabb(spot_lights[i]);
+        if (Frustum::intersect_aabb(gs_frustum, aabb)) {
+            append_light(encode_light_index(LIGHT_TYPE_SPOT, i));
         }
     }
     workgroupBarrier();
@@ -1,29 +1,15 @@
-@include shared/brdf.wgsl
-@include shared/frustum.wgsl
-@include shared/xr.wgsl
+@include shared/random.wgsl
+@include shared/color.wgsl
 
 @include shared/vertex_pool_bindings.wgsl
 @include shared/material_pool_bindings.wgsl
+@include shared/alpha_test_bindings.wgsl
 @include shared/sky_bindings.wgsl
 @include shared/gbuffer_bindings.wgsl
 @include shared/linear_transformed_cosines_bindings.wgsl
-
-struct Constants {
-    resolution: vec2<u32>,
-    lighting_resolution: vec2<u32>,
-    shadows: u32,
-    shadow_bias: f32,
-    _padding0: u32,
-    _padding1: u32,
-}
-
-@group(0)
-@binding(0)
-var<uniform> constants: Constants;
-
-@group(0)
-@binding(1)
-var<uniform> xr_camera: XrCamera;
+@include shared/punctual_light_bindings.wgsl
+@include shared/emissive_light_bindings.wgsl
+@include shared/ltc_lighting.wgsl
 
 @group(0)
 @binding(2)
@@ -33,59 +19,89 @@ var static_scene: acceleration_structure;
 @binding(3)
 var dynamic_scene: acceleration_structure;
 
-@group(0)
-@binding(4)
-var lighting_out: texture_storage_2d_array<rgba16float, read_write>;
+fn trace_visibility(hit_point: vec3<f32>, geometric_normal: vec3<f32>, direction: vec3<f32>, distance: f32) -> f32 {
+    let shadow_origin: vec3<f32> = hit_point + geometric_normal * 0.01;
+    let shadow_distance: f32 = distance - 0.01 - constants.shadow_bias;
 
-@group(0)
-@binding(5)
-var<storage, read> light_index_list: array<u32>;
+    const TERMINATE_ON_FIRST_HIT: u32 = 0x4;
 
-@group(0)
-@binding(6)
-var light_grid: texture_storage_2d<rg32uint, read>;
+    // Blended surfaces cast shadows wherever they pass the alpha cutoff
+    let static_intersection: RayIntersection = AlphaTest::trace_ray(static_scene, RayDesc(TERMINATE_ON_FIRST_HIT, 0xFFu, 0.0, shadow_distance, shadow_origin, direction), false);
+    if (static_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        return 0.0;
+    }
 
-@compute
-@workgroup_size(FRUSTUM_TILE_SIZE, FRUSTUM_TILE_SIZE)
-fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
-    @builtin(workgroup_id) group_id: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>) {
-    var id: vec2<u32> = global_id.xy;
-    if (any(id >= constants.lighting_resolution)) { return; }
+    let dynamic_intersection: RayIntersection = AlphaTest::trace_ray(dynamic_scene, RayDesc(TERMINATE_ON_FIRST_HIT, 0xFFu, 0.0, shadow_distance, shadow_origin, direction), false);
+    if (dynamic_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        return 0.0;
+    }
 
-    let full_res_id: vec2<u32> = vec2<u32>(vec2<f32>(id) * (vec2<f32>(constants.resolution) / vec2<f32>(constants.lighting_resolution)));
+    return 1.0;
+}
 
-    // TODO: move to groupshared?
-    let light_offset_and_count: vec2<u32> = textureLoad(light_grid, group_id.xy).rg;
-    let light_index_start_offset: u32 = light_offset_and_count.x;
-    let light_count: u32 = light_offset_and_count.y;
+fn light_visibility(encoded_light_index: u32, hit_point: vec3<f32>, geometric_normal: vec3<f32>) -> f32 {
+    let light_type: u32 = decode_light_type(encoded_light_index);
+    let light_index: u32 = decode_light_index(encoded_light_index);
 
-    for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
-        let ray: XrCameraRay = XrCamera::raygen(xr_camera, full_res_id, constants.resolution, view_index);
+    var target_point: vec3<f32>;
+    if (light_type == LIGHT_TYPE_AREA) {
+        target_point = LtcBindings::closest_point(light_index, hit_point);
+    } else {
+        target_point = PunctualLightBindings::position(light_type, light_index);
+    }
 
-        let position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(full_res_id, view_index);
-        var emission: f32 = 0.0;
+    return trace_visibility(hit_point, geometric_normal, normalize(target_point - hit_point), distance(target_point, hit_point));
+}
 
-        var lighting = vec3<f32>(0.0);
-        if (!GbufferPositionAndDepth::is_sky(position_and_depth)) {
-            let material_descriptor_idx_and_normal_roughness: GbufferMaterialDescriptorIdxAndNormalRoughness
-                = Gbuffer::load_material_descriptor_idx_and_normal_roughness(full_res_id, view_index);
-            let tex_coord_and_derivatives: GbufferTexCoordAndDerivatives = Gbuffer::load_tex_coord_and_derivatives(full_res_id, view_index);
-            let shading_and_geometric_normal: GbufferShadingAndGeometricNormal = Gbuffer::load_shading_and_geometric_normal(full_res_id, view_index);
+fn directional_light_visibility(light_index: u32, hit_point: vec3<f32>, geometric_normal: vec3<f32>) -> f32 {
+    return trace_visibility(hit_point, geometric_normal, -directional_lights[light_index].direction, 10000.0);
+}
 
-            let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx_and_normal_roughness.material_descriptor_idx];
-            var material: Material = Material::from_material_descriptor(material_descriptor, tex_coord_and_derivatives.tex_coord, tex_coord_and_derivatives.ddx, tex_coord_and_derivatives.ddy);
+// Resampled importance sampling of the emissive triangles, the candidate selection of ReSTIR DI without its reuse:
+// candidates are drawn from the power based alias table and one of them is kept in proportion to its unshadowed contribution,
+// so that only a single shadow ray is traced per pixel
+fn shade_emissive_lights(material: Material, normal: vec3<f32>, geometric_normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>, rng: ptr<function, u32>) -> vec3<f32> {
+    var selected_sample: EmissiveLightSample;
+    var selected_light = vec3<f32>(0.0);
+    var selected_target_pdf: f32 = 0.0;
+    var weight_sum: f32 = 0.0;
+
+    for (var i: u32 = 0; i < constants.emissive_light_candidates; i += 1) {
+        let light_sample: EmissiveLightSample = EmissiveLightBindings::sample(random_uniform_float3(rng));
+        let light: vec3<f32> = EmissiveLightBindings::shade(material, light_sample, normal, view_dir, hit_point);
+
+        let target_pdf: f32 = linear_to_luma(light);
+        if (target_pdf <= 0.0) {
+            continue;
+        }
 
-            let geometric_roughness: f32 = safe_sqrt(1.0 - material_descriptor_idx_and_normal_roughness.normal_roughness);
-            material.roughness = safe_sqrt(sqr(material.roughness) + sqr(geometric_roughness));
+        let weight: f32 = target_pdf / light_sample.pdf;
+        weight_sum += weight;
+        if (random_uniform_float(rng) * weight_sum < weight) {
+            selected_sample = light_sample;
+            selected_light = light;
+            selected_target_pdf = target_pdf;
+        }
+    }
 
-            for (var local_light_index: u32 = 0; local_light_index < light_count; local_light_index += 1) {
-                let light_index: u32 = light_index_list[light_index_start_offset + local_light_index];
+    if (selected_target_pdf <= 0.0) {
+        return vec3<f32>(0.0);
+    }
 
-                lighting += LtcBindings::shade(material, light_index, shading_and_geometric_normal.shading_normal, shading_and_geometric_normal.geometric_normal, -ray.direction, position_and_depth.position,
-                    static_scene, dynamic_scene, constants.shadows > 0, constants.shadow_bias);
-            }
-        }
+    var lighting: vec3<f32> = selected_light * (weight_sum / (f32(constants.emissive_light_candidates) * selected_target_pdf));
+    if (constants.shadows > 0) {
+        let to_light: vec3<f32> = selected_sample.position - hit_point;
+        lighting *= trace_visibility(hit_point, geometric_normal, normalize(to_light), length(to_light));
+    }
+    return lighting;
+}
 
-        textureStore(lighting_out, id, view_index, vec4<f32>(lighting, 1.0));
+fn shade_additional_lights(material: Material, normal: vec3<f32>, geometric_normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>,
+    id: vec2<u32>, view_index: u32) -> vec3<f32> {
+    if (constants.emissive_light_candidates == 0 || !EmissiveLightBindings::has_lights()) {
+        return vec3<f32>(0.0);
     }
-}
\ No newline at end of file
+
+    var rng: u32 = pcg_hash((id.y * constants.lighting_resolution.x + id.x) ^ pcg_hash(constants.frame_idx * 2 + view_index));
+    return shade_emissive_lights(material, normal, geometric_normal, view_dir, hit_point, &rng);
+}
@@ -0,0 +1,89 @@
+@include shared/vertex_pool_bindings.wgsl
+@include shared/material_pool_bindings.wgsl
+@include shared/sky_bindings.wgsl
+@include shared/gbuffer_bindings.wgsl
+@include shared/linear_transformed_cosines_bindings.wgsl
+@include shared/punctual_light_bindings.wgsl
+@include shared/ltc_lighting.wgsl
+
+const MAX_SHADOW_MAPS: u32 = 16;
+const MAX_SHADOW_CASCADES: u32 = 4;
+
+struct ShadowMap {
+    world_to_clip_space: mat4x4<f32>,
+    encoded_light_index: u32,
+    normal_offset: f32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+@group(0)
+@binding(2)
+var shadow_map_texture: texture_depth_2d_array;
+
+@group(0)
+@binding(3)
+var<storage, read> shadow_maps: array<ShadowMap>;
+
+@group(0)
+@binding(7)
+var shadow_sampler: sampler_comparison;
+
+// Returns a negative value when the hit point lies outside of the shadow map frustum
+fn sample_shadow_map(shadow_map_idx: u32, hit_point: vec3<f32>, geometric_normal: vec3<f32>) -> f32 {
+    let shadow_map: ShadowMap = shadow_maps[shadow_map_idx];
+
+    let position_cs: vec4<f32> = shadow_map.world_to_clip_space * vec4<f32>(hit_point + geometric_normal * shadow_map.normal_offset, 1.0);
+    if (position_cs.w <= 0.0) {
+        return -1.0;
+    }
+
+    let position_ndc: vec3<f32> = position_cs.xyz / position_cs.w;
+    if (any(abs(position_ndc.xy) > vec2<f32>(1.0)) || position_ndc.z < 0.0 || position_ndc.z > 1.0) {
+        return -1.0;
+    }
+
+    let uv = vec2<f32>(position_ndc.x * 0.5 + 0.5, 0.5 - position_ndc.y * 0.5);
+    return textureSampleCompareLevel(shadow_map_texture, shadow_sampler, uv, shadow_map_idx, position_ndc.z);
+}
+
+fn light_visibility(encoded_light_index: u32, hit_point: vec3<f32>, geometric_normal: vec3<f32>) -> f32 {
+    // Point lights own one shadow map per cube face, the face containing the hit point is the one to sample
+    for (var i: u32 = 0; i < MAX_SHADOW_MAPS; i += 1) {
+        if (shadow_maps[i].encoded_light_index != encoded_light_index) {
+            continue;
+        }
+
+        let visibility: f32 = sample_shadow_map(i, hit_point, geometric_normal);
+        if (visibility >= 0.0) {
+            return visibility;
+        }
+    }
+
+    // Anything outside of the shadow map frustums is considered lit, this includes the back side of double sided lights.
+    // Only the lights closest to the camera get shadow maps, the rest remains unshadowed
+    return 1.0;
+}
+
+fn directional_light_visibility(light_index: u32, hit_point: vec3<f32>, geometric_normal: vec3<f32>) -> f32 {
+    // Cascades are ordered from the nearest to the farthest, the first one containing the hit point has the highest resolution
+    for (var i: u32 = MAX_SHADOW_MAPS; i < MAX_SHADOW_MAPS + MAX_SHADOW_CASCADES; i += 1) {
+        if (shadow_maps[i].encoded_light_index != light_index) {
+            continue;
+        }
+
+        let visibility: f32 = sample_shadow_map(i, hit_point, geometric_normal);
+        if (visibility >= 0.0) {
+            return visibility;
+        }
+    }
+
+    // Only the first directional light gets cascades, and only up to the render distance
+    return 1.0;
+}
+
+// Emissive triangles require ray traced visibility and are not sampled on this path
+fn shade_additional_lights(material: Material, normal: vec3<f32>, geometric_normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>,
+    id: vec2<u32>, view_index: u32) -> vec3<f32> {
+    return vec3<f32>(0.0);
+}
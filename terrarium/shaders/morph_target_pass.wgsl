@@ -0,0 +1,69 @@
+@include shared/vertex_pool.wgsl
+
+struct MorphTargetDelta {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    tangent: vec3<f32>,
+}
+
+struct MorphTargetInstance {
+    src_first_vertex: u32,
+    dst_first_vertex: u32,
+    prev_first_vertex: u32,
+    num_vertices: u32,
+    first_morph_target_delta: u32,
+    num_morph_targets: u32,
+    first_weight: u32,
+    _padding0: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read_write> vertices: array<PackedVertex>;
+
+@group(0)
+@binding(1)
+var<storage, read> morph_target_deltas: array<MorphTargetDelta>;
+
+@group(0)
+@binding(2)
+var<storage, read> weights: array<f32>;
+
+@group(0)
+@binding(3)
+var<storage, read> instances: array<MorphTargetInstance>;
+
+// One row of workgroups per instance, deltas of a single target are stored contiguously for all vertices
+@compute
+@workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let instance: MorphTargetInstance = instances[global_id.y];
+    let vertex_idx: u32 = global_id.x;
+    if (vertex_idx >= instance.num_vertices) { return; }
+
+    let vertex: Vertex = PackedVertex::unpack(vertices[instance.src_first_vertex + vertex_idx]);
+
+    // Keep last frame's result around for motion vectors
+    vertices[instance.prev_first_vertex + vertex_idx] = vertices[instance.dst_first_vertex + vertex_idx];
+
+    var position: vec3<f32> = vertex.position;
+    var normal: vec3<f32> = vertex.normal;
+    var tangent: vec3<f32> = vertex.tangent.xyz;
+    for (var i: u32 = 0; i < instance.num_morph_targets; i += 1) {
+        let weight: f32 = weights[instance.first_weight + i];
+        if (weight == 0.0) { continue; }
+
+        let delta: MorphTargetDelta = morph_target_deltas[instance.first_morph_target_delta + i * instance.num_vertices + vertex_idx];
+        position += delta.position * weight;
+        normal += delta.normal * weight;
+        tangent += delta.tangent * weight;
+    }
+
+    vertices[instance.dst_first_vertex + vertex_idx] = PackedVertex(
+        position,
+        PackedNormalizedXyz10::new(normalize(normal), 0),
+        vertex.tex_coord,
+        PackedNormalizedXyz10::new(normalize(tangent), 0),
+        vertex.tangent.w
+    );
+}
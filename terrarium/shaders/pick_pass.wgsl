@@ -0,0 +1,80 @@
+@include shared/vertex_pool_bindings.wgsl
+@include shared/material_pool_bindings.wgsl
+@include shared/alpha_test_bindings.wgsl
+
+struct Constants {
+    origin: vec3<f32>,
+    max_distance: f32,
+    direction: vec3<f32>,
+    _padding0: u32,
+}
+
+struct PickResult {
+    position: vec3<f32>,
+    distance: f32,
+    normal: vec3<f32>,
+    instance_idx: u32,
+    material_idx: u32,
+    hit: u32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var static_scene: acceleration_structure;
+
+@group(0)
+@binding(2)
+var dynamic_scene: acceleration_structure;
+
+@group(0)
+@binding(3)
+var<storage, read_write> result: PickResult;
+
+@compute
+@workgroup_size(1)
+fn main() {
+    // Blended surfaces are pickable as well, as long as they pass the alpha cutoff
+    let ray_desc = RayDesc(0u, 0xFFu, 0.0, constants.max_distance, constants.origin, constants.direction);
+    let static_intersection: RayIntersection = AlphaTest::trace_ray(static_scene, ray_desc, false);
+    let dynamic_intersection: RayIntersection = AlphaTest::trace_ray(dynamic_scene, ray_desc, false);
+
+    var intersection: RayIntersection = static_intersection;
+    if (dynamic_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE
+        && (static_intersection.kind != RAY_QUERY_INTERSECTION_TRIANGLE || dynamic_intersection.t < static_intersection.t)) {
+        intersection = dynamic_intersection;
+    }
+
+    var pick_result: PickResult;
+    if (intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        let vertex_slice_index: u32 = vertex_pool_vertex_slice_indices[intersection.instance_custom_data];
+        let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[vertex_slice_index];
+
+        let i0: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 0];
+        let i1: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 1];
+        let i2: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 2];
+        let p0_ws: vec3<f32> = (intersection.object_to_world * vec4<f32>(vertices[vertex_pool_slice.first_vertex + i0].position, 1.0)).xyz;
+        let p1_ws: vec3<f32> = (intersection.object_to_world * vec4<f32>(vertices[vertex_pool_slice.first_vertex + i1].position, 1.0)).xyz;
+        let p2_ws: vec3<f32> = (intersection.object_to_world * vec4<f32>(vertices[vertex_pool_slice.first_vertex + i2].position, 1.0)).xyz;
+
+        // Geometric normal facing the ray
+        var normal_ws: vec3<f32> = normalize(cross(p1_ws - p0_ws, p2_ws - p0_ws));
+        if (dot(normal_ws, constants.direction) > 0.0) {
+            normal_ws *= -1.0;
+        }
+
+        pick_result.position = constants.origin + constants.direction * intersection.t;
+        pick_result.distance = intersection.t;
+        pick_result.normal = normal_ws;
+        pick_result.instance_idx = intersection.instance_custom_data;
+        pick_result.material_idx = VertexPoolBindings::material_idx(intersection.instance_custom_data, vertex_pool_slice.first_index / 3 + intersection.primitive_index);
+        pick_result.hit = 1u;
+    }
+
+    result = pick_result;
+}
@@ -0,0 +1,162 @@
+@include shared/brdf.wgsl
+@include shared/xr.wgsl
+@include shared/trace.wgsl
+@include shared/sampling.wgsl
+@include shared/scaled_resolution.wgsl
+
+@include shared/vertex_pool_bindings.wgsl
+@include shared/material_pool_bindings.wgsl
+@include shared/alpha_test_bindings.wgsl
+@include shared/sky_bindings.wgsl
+@include shared/gbuffer_bindings.wgsl
+
+struct Constants {
+    resolution: vec2<u32>,
+    reflection_resolution: vec2<u32>,
+    ambient_factor: f32,
+    view_index: u32,
+    render_distance: f32,
+    reflection_max_roughness: f32,
+    frame_idx: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var<uniform> xr_camera: XrCamera;
+
+@group(0)
+@binding(2)
+var static_scene: acceleration_structure;
+
+@group(0)
+@binding(3)
+var dynamic_scene: acceleration_structure;
+
+@group(0)
+@binding(4)
+var reflection_out: texture_storage_2d_array<rgba16float, write>;
+
+fn trace_ray(origin: vec3<f32>, direction: vec3<f32>) -> RayIntersection {
+    // Blended surfaces are composited by the transparent pass instead
+    let static_intersection: RayIntersection = AlphaTest::trace_ray(static_scene, RayDesc(0u, 0xFFu, 0.0, constants.render_distance, origin, direction), true);
+    var static_t: f32 = 10000.0;
+    if (static_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        static_t = static_intersection.t;
+    }
+
+    let dynamic_intersection: RayIntersection = AlphaTest::trace_ray(dynamic_scene, RayDesc(0u, 0xFFu, 0.0, constants.render_distance, origin, direction), true);
+    var dynamic_t: f32 = 10000.0;
+    if (dynamic_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
+        dynamic_t = dynamic_intersection.t;
+    }
+    
+    if (static_t < dynamic_t) {
+        return static_intersection;
+    } else {
+        return dynamic_intersection;
+    }
+}
+
+fn shade_hit(intersection: RayIntersection, direction: vec3<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec3<f32> {
+    if (intersection.kind != RAY_QUERY_INTERSECTION_TRIANGLE) {
+        // Rays are already distributed over the specular lobe, so misses look up the unfiltered sky
+        return Sky::inscattering(direction, false);
+    }
+
+    let vertex_slice_index: u32 = vertex_pool_vertex_slice_indices[intersection.instance_custom_data];
+    let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[vertex_slice_index];
+
+    let barycentrics = vec3<f32>(1.0 - intersection.barycentrics.x - intersection.barycentrics.y, intersection.barycentrics);
+
+    let i0: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 0];
+    let i1: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 1];
+    let i2: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 2];
+
+    let v0: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i0]);
+    let v1: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i1]);
+    let v2: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i2]);
+
+    let tex_coord: vec2<f32> = v0.tex_coord * barycentrics.x + v1.tex_coord * barycentrics.y + v2.tex_coord * barycentrics.z;
+
+    let material_descriptor_idx: u32 = VertexPoolBindings::material_idx(intersection.instance_custom_data, vertex_pool_slice.first_index / 3 + intersection.primitive_index);
+    let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx];
+    let material: Material = Material::from_material_descriptor(material_descriptor, tex_coord, ddx, ddy);
+
+    return material.emission + material.color * constants.ambient_factor;
+}
+
+// Traces a single ggx importance sampled ray per texel, the weighted result is stored with the roughness it was traced at,
+// or a negative roughness for texels without traced specular
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let reflection_id: vec2<u32> = global_id.xy;
+    if (any(reflection_id >= constants.reflection_resolution)) { return; }
+
+    let view_index: u32 = constants.view_index;
+    let id: vec2<u32> = ScaledResolution::render_id(reflection_id, constants.resolution, constants.reflection_resolution);
+
+    let position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(id, view_index);
+
+    var reflection = vec3<f32>(0.0);
+    var roughness: f32 = -1.0;
+    if (!GbufferPositionAndDepth::is_sky(position_and_depth)) {
+        let shading_and_geometric_normal: GbufferShadingAndGeometricNormal = Gbuffer::load_shading_and_geometric_normal(id, view_index);
+        let material_descriptor_idx_and_normal_roughness: GbufferMaterialDescriptorIdxAndNormalRoughness
+            = Gbuffer::load_material_descriptor_idx_and_normal_roughness(id, view_index);
+        let tex_coord_and_derivatives: GbufferTexCoordAndDerivatives = Gbuffer::load_tex_coord_and_derivatives(id, view_index);
+        let ray: XrCameraRay = XrCamera::raygen(xr_camera, id, constants.resolution, view_index);
+
+        let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx_and_normal_roughness.material_descriptor_idx];
+        var material: Material = Material::from_material_descriptor(material_descriptor, tex_coord_and_derivatives.tex_coord, tex_coord_and_derivatives.ddx, tex_coord_and_derivatives.ddy);
+        material.clearcoat_normal = shading_and_geometric_normal.clearcoat_normal;
+        material.anisotropy_tangent = material_descriptor_idx_and_normal_roughness.anisotropy_tangent;
+
+        if (Material::has_traced_specular(material, constants.reflection_max_roughness)) {
+            let geometric_roughness: f32 = safe_sqrt(1.0 - material_descriptor_idx_and_normal_roughness.normal_roughness);
+            material.roughness = safe_sqrt(sqr(material.roughness) + sqr(geometric_roughness));
+            roughness = material.roughness;
+
+            let n: vec3<f32> = shading_and_geometric_normal.interpolated_normal;
+            let v: vec3<f32> = -ray.direction;
+            let n_dot_v: f32 = max(dot(v, n), 0.0);
+            let fresnel: vec3<f32> = fresnel_schlick(n_dot_v, Material::specular_f0(material));
+            let clearcoat_fresnel: f32 = Material::clearcoat_fresnel(material, max(dot(v, Material::clearcoat_normal(material, n)), 0.0));
+
+            let uv = vec2<f32>(
+                interleaved_gradient_noise_animated(reflection_id, constants.frame_idx),
+                interleaved_gradient_noise_animated(reflection_id + vec2<u32>(113, 127), constants.frame_idx)
+            );
+            var direction: vec3<f32> = reflect(ray.direction, sample_ggx_visible_normal(n, v, material.roughness, uv));
+            // Samples below the surface fall back to the mirror direction instead of self intersecting
+            if (dot(direction, shading_and_geometric_normal.geometric_normal) <= 0.0) {
+                direction = reflect(ray.direction, n);
+            }
+
+            // Base and clearcoat reflections share a single sampled direction
+            let origin: vec3<f32> = position_and_depth.position + shading_and_geometric_normal.geometric_normal * 0.001;
+            let reflection_weight: vec3<f32> = EnvironmentMap::specular_brdf(Material::specular_f0(material), material.roughness, n_dot_v) * (1.0 - clearcoat_fresnel)
+                + clearcoat_fresnel;
+
+            reflection = shade_hit(trace_ray(origin, direction), direction, tex_coord_and_derivatives.ddx, tex_coord_and_derivatives.ddy) * reflection_weight;
+
+            // Transmission is treated as thin walled, so rays continue straight through the surface
+            if (material.transmission > 0.0) {
+                let transmission_origin: vec3<f32> = position_and_depth.position - shading_and_geometric_normal.geometric_normal * 0.001;
+                let transmission_weight: vec3<f32> = material.color * material.transmission * (1.0 - material.metallic)
+                    * (vec3<f32>(1.0) - fresnel) * (1.0 - clearcoat_fresnel);
+
+                reflection += shade_hit(trace_ray(transmission_origin, ray.direction), ray.direction, tex_coord_and_derivatives.ddx, tex_coord_and_derivatives.ddy) * transmission_weight;
+            }
+        }
+    }
+
+    textureStore(reflection_out, reflection_id, view_index, vec4<f32>(reflection, roughness));
+}
@@ -0,0 +1,79 @@
+@include shared/math.wgsl
+@include shared/scaled_resolution.wgsl
+
+@include shared/gbuffer_bindings.wgsl
+
+const FILTER_RADIUS: i32 = 2;
+// Tap spacing in texels at full roughness, mirror reflections are left unfiltered
+const MAX_TAP_SPACING: f32 = 3.0;
+
+struct Constants {
+    resolution: vec2<u32>,
+    reflection_resolution: vec2<u32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var src: texture_2d_array<f32>;
+
+@group(0)
+@binding(2)
+var traced: texture_2d_array<f32>;
+
+@group(0)
+@binding(3)
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+// Edge aware blur with a footprint growing with roughness and shrinking as the temporal history converges
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let id: vec2<u32> = global_id.xy;
+    if (any(id >= constants.reflection_resolution)) { return; }
+
+    for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
+        let center: vec4<f32> = textureLoad(src, id, view_index, 0);
+        let roughness: f32 = textureLoad(traced, id, view_index, 0).a;
+        let tap_spacing: i32 = i32(round(roughness * MAX_TAP_SPACING / sqrt(max(center.a, 1.0))));
+        if (roughness < 0.0 || tap_spacing == 0) {
+            textureStore(dst, id, view_index, vec4<f32>(center.rgb, 1.0));
+            continue;
+        }
+
+        let render_id: vec2<u32> = ScaledResolution::render_id(id, constants.resolution, constants.reflection_resolution);
+        let depth: f32 = Gbuffer::load_position_and_depth(render_id, view_index).depth;
+        let normal: vec3<f32> = Gbuffer::load_shading_and_geometric_normal(render_id, view_index).interpolated_normal;
+
+        var sum = vec3<f32>(0.0);
+        var weight_sum: f32 = 0.0;
+        for (var y: i32 = -FILTER_RADIUS; y <= FILTER_RADIUS; y += 1) {
+            for (var x: i32 = -FILTER_RADIUS; x <= FILTER_RADIUS; x += 1) {
+                let sample_id: vec2<i32> = vec2<i32>(id) + vec2<i32>(x, y) * tap_spacing;
+                if (any(sample_id < vec2<i32>(0)) || any(sample_id >= vec2<i32>(constants.reflection_resolution))) { continue; }
+
+                let sample_roughness: f32 = textureLoad(traced, sample_id, view_index, 0).a;
+                if (sample_roughness < 0.0) { continue; }
+
+                let sample_render_id: vec2<u32> = ScaledResolution::render_id(vec2<u32>(sample_id), constants.resolution, constants.reflection_resolution);
+                let sample_depth: f32 = Gbuffer::load_position_and_depth(sample_render_id, view_index).depth;
+                let sample_normal: vec3<f32> = Gbuffer::load_shading_and_geometric_normal(sample_render_id, view_index).interpolated_normal;
+
+                let spatial_weight: f32 = exp(-f32(x * x + y * y) / 4.0);
+                let depth_weight: f32 = exp(-abs(sample_depth - depth) / max(depth * 0.05, 1e-4));
+                let normal_weight: f32 = pow(max(dot(sample_normal, normal), 0.0), 32.0);
+                let roughness_weight: f32 = exp(-abs(sample_roughness - roughness) * 10.0);
+                let weight: f32 = spatial_weight * depth_weight * normal_weight * roughness_weight;
+
+                sum += textureLoad(src, sample_id, view_index, 0).rgb * weight;
+                weight_sum += weight;
+            }
+        }
+
+        let reflection: vec3<f32> = select(center.rgb, sum / weight_sum, weight_sum > 0.0);
+        textureStore(dst, id, view_index, vec4<f32>(reflection, 1.0));
+    }
+}
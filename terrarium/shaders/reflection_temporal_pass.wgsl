@@ -0,0 +1,85 @@
+@include shared/math.wgsl
+@include shared/scaled_resolution.wgsl
+
+@include shared/gbuffer_bindings.wgsl
+
+const MAX_HISTORY_LENGTH: f32 = 32.0;
+// Width of the variance clipping box in standard deviations, wide enough to keep the single sample per pixel from flickering
+const CLIP_GAMMA: f32 = 1.5;
+
+struct Constants {
+    resolution: vec2<u32>,
+    reflection_resolution: vec2<u32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var src: texture_2d_array<f32>;
+
+@group(0)
+@binding(2)
+var history: texture_2d_array<f32>;
+
+@group(0)
+@binding(3)
+var history_sampler: sampler;
+
+@group(0)
+@binding(4)
+var dst: texture_storage_2d_array<rgba16float, write>;
+
+// Accumulates traced reflections over time, the history length is kept in alpha
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let id: vec2<u32> = global_id.xy;
+    if (any(id >= constants.reflection_resolution)) { return; }
+
+    for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
+        let current: vec4<f32> = textureLoad(src, id, view_index, 0);
+        if (current.a < 0.0) {
+            textureStore(dst, id, view_index, vec4<f32>(0.0));
+            continue;
+        }
+
+        // Neighborhood statistics of the current frame bound the reprojected history
+        var mean = vec3<f32>(0.0);
+        var second_moment = vec3<f32>(0.0);
+        var sample_count: f32 = 0.0;
+        for (var y: i32 = -1; y <= 1; y += 1) {
+            for (var x: i32 = -1; x <= 1; x += 1) {
+                let sample_id: vec2<i32> = vec2<i32>(id) + vec2<i32>(x, y);
+                if (any(sample_id < vec2<i32>(0)) || any(sample_id >= vec2<i32>(constants.reflection_resolution))) { continue; }
+
+                let neighbor: vec4<f32> = textureLoad(src, sample_id, view_index, 0);
+                if (neighbor.a < 0.0) { continue; }
+
+                mean += neighbor.rgb;
+                second_moment += neighbor.rgb * neighbor.rgb;
+                sample_count += 1.0;
+            }
+        }
+        mean /= sample_count;
+        let standard_deviation: vec3<f32> = sqrt(max(second_moment / sample_count - mean * mean, vec3<f32>(0.0)));
+
+        let render_id: vec2<u32> = ScaledResolution::render_id(id, constants.resolution, constants.reflection_resolution);
+        let uv: vec2<f32> = (vec2<f32>(id) + vec2<f32>(0.5)) / vec2<f32>(constants.reflection_resolution);
+        let history_uv: vec2<f32> = uv - Gbuffer::load_velocity(render_id, view_index);
+
+        var result: vec4<f32> = vec4<f32>(current.rgb, 1.0);
+        let valid_reprojection: bool = all(history_uv >= vec2<f32>(0.0)) && all(history_uv <= vec2<f32>(1.0));
+        if (valid_reprojection) {
+            let history_sample: vec4<f32> = textureSampleLevel(history, history_sampler, history_uv, view_index, 0.0);
+            let clamped_history: vec3<f32> = clamp(history_sample.rgb, mean - standard_deviation * CLIP_GAMMA, mean + standard_deviation * CLIP_GAMMA);
+
+            let history_length: f32 = min(history_sample.a, MAX_HISTORY_LENGTH - 1.0) + 1.0;
+            result = vec4<f32>(mix(clamped_history, current.rgb, 1.0 / history_length), history_length);
+        }
+
+        textureStore(dst, id, view_index, result);
+    }
+}
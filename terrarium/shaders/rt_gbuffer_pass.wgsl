@@ -1,18 +1,21 @@
+@include shared/brdf.wgsl
 @include shared/xr.wgsl
 @include shared/trace.wgsl
+@include shared/sampling.wgsl
 
 @include shared/vertex_pool_bindings.wgsl
 @include shared/material_pool_bindings.wgsl
+@include shared/alpha_test_bindings.wgsl
 @include shared/gbuffer_bindings.wgsl
 
 struct Constants {
     resolution: vec2<u32>,
     mipmapping: u32,
     normal_mapping: u32,
-    reflection_max_roughness: f32,
     view_index: u32,
     render_distance: f32,
     _padding0: u32,
+    _padding1: u32,
 }
 
 @group(0)
@@ -31,33 +34,24 @@ var static_scene: acceleration_structure;
 @binding(3)
 var dynamic_scene: acceleration_structure;
 
-@group(0)
-@binding(4)
-var<storage, read_write> reflection_counter: atomic<u32>;
-
-@group(0)
-@binding(5)
-var<storage, read_write> reflection_pid: array<u32>;
+// Must match `LOD_DITHER_LEVELS` in gpu_resources/mod.rs
+const LOD_DITHER_LEVELS: u32 = 7;
 
-fn push_reflection_pixel(id: vec2<u32>) {
-    let idx: u32 = atomicAdd(&reflection_counter, 1u);
-    reflection_pid[idx] = id.y * constants.resolution.x + id.x;
+// Levels of detail in transition are masked to the dither levels at which they are visible, covering complementary pixels
+fn lod_dither_mask(id: vec2<u32>) -> u32 {
+    let noise: f32 = interleaved_gradient_noise(vec2<f32>(id) + 0.5);
+    return 2u << min(u32(noise * f32(LOD_DITHER_LEVELS)), LOD_DITHER_LEVELS - 1);
 }
 
-fn trace_ray(origin: vec3<f32>, direction: vec3<f32>) -> RayIntersection {
-    var rq: ray_query;
-
-    rayQueryInitialize(&rq, static_scene, RayDesc(0u, 0xFFu, 0.0, constants.render_distance, origin, direction));
-    rayQueryProceed(&rq);
-    let static_intersection: RayIntersection = rayQueryGetCommittedIntersection(&rq);
+fn trace_ray(origin: vec3<f32>, direction: vec3<f32>, cull_mask: u32) -> RayIntersection {
+    // Blended surfaces are composited by the transparent pass instead
+    let static_intersection: RayIntersection = AlphaTest::trace_ray(static_scene, RayDesc(0u, cull_mask, 0.0, constants.render_distance, origin, direction), true);
     var static_t: f32 = 10000.0;
     if (static_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
         static_t = static_intersection.t;
     }
 
-    rayQueryInitialize(&rq, dynamic_scene, RayDesc(0u, 0xFFu, 0.0, constants.render_distance, origin, direction));
-    rayQueryProceed(&rq);
-    let dynamic_intersection: RayIntersection = rayQueryGetCommittedIntersection(&rq);
+    let dynamic_intersection: RayIntersection = AlphaTest::trace_ray(dynamic_scene, RayDesc(0u, cull_mask, 0.0, constants.render_distance, origin, direction), true);
     var dynamic_t: f32 = 10000.0;
     if (dynamic_intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
         dynamic_t = dynamic_intersection.t;
@@ -87,7 +81,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
     var position_ws = vec3<f32>(0.0);
     var depth_ws: f32 = 0.0;
 
-    let intersection: RayIntersection = trace_ray(origin, direction);
+    let intersection: RayIntersection = trace_ray(origin, direction, lod_dither_mask(id));
     if (intersection.kind == RAY_QUERY_INTERSECTION_TRIANGLE) {
         let vertex_slice_index: u32 = vertex_pool_vertex_slice_indices[intersection.instance_custom_data];
         let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[vertex_slice_index];
@@ -128,7 +122,13 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
         let geometric_normal: vec3<f32> = normalize(cross(v1.position - v0.position, v2.position - v0.position));
         var geometric_normal_ws: vec3<f32> = normalize((local_to_world_inv_trans * vec4<f32>(geometric_normal, 1.0)).xyz);
         let hit_point_ws: vec3<f32> = (intersection.object_to_world * vec4<f32>(hit_point, 1.0)).xyz;
-        let prev_hit_point_ws: vec3<f32> = VertexPoolBindings::reproject_point(intersection.instance_custom_data, hit_point_ws);
+
+        // Deformed meshes keep last frame's vertices around, static meshes point prev_first_vertex at their own vertices
+        let prev_p0: vec3<f32> = vertices[vertex_pool_slice.prev_first_vertex + i0].position;
+        let prev_p1: vec3<f32> = vertices[vertex_pool_slice.prev_first_vertex + i1].position;
+        let prev_p2: vec3<f32> = vertices[vertex_pool_slice.prev_first_vertex + i2].position;
+        let prev_hit_point: vec3<f32> = prev_p0 * barycentrics.x + prev_p1 * barycentrics.y + prev_p2 * barycentrics.z;
+        let prev_hit_point_ws: vec3<f32> = VertexPoolBindings::reproject_point(intersection.instance_custom_data, (intersection.object_to_world * vec4<f32>(prev_hit_point, 1.0)).xyz);
 
         let hit_tangent_to_world = mat3x3<f32>(
             hit_tangent_ws,
@@ -164,14 +164,22 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
 
         // Apply normal mapping when available, unlike the name suggest, not front facing yet
         var mapped_normal_and_roughness: vec4<f32>;
+        // Without a normal map of its own the clearcoat layer follows the interpolated normal
+        var front_facing_clearcoat_normal_ws: vec3<f32> = hit_normal_ws;
         if (constants.normal_mapping > 0) {
             mapped_normal_and_roughness = MaterialDescriptor::apply_normal_mapping(material_descriptor, tex_coord, ddx, ddy, hit_normal_ws, hit_tangent_to_world);
+            front_facing_clearcoat_normal_ws = MaterialDescriptor::apply_clearcoat_normal_mapping(material_descriptor, tex_coord, ddx, ddy, hit_normal_ws, hit_tangent_to_world).xyz;
         } else {
             mapped_normal_and_roughness = vec4<f32>(hit_normal_ws, 1.0);
         }
         var front_facing_shading_normal_ws: vec3<f32> = mapped_normal_and_roughness.xyz;
         let normal_roughness: f32 = mapped_normal_and_roughness.w;
         var front_facing_interpolated_normal_ws: vec3<f32> = hit_normal_ws;
+        // Isotropic materials store the mesh tangent, the packing has no representation for zero
+        var anisotropy_tangent_ws: vec3<f32> = hit_tangent_to_world[0];
+        if (material_descriptor.anisotropic > 0.0) {
+            anisotropy_tangent_ws = MaterialDescriptor::anisotropy_tangent(material_descriptor, tex_coord, ddx, ddy, hit_tangent_to_world);
+        }
 
         let w_out_worldspace: vec3<f32> = -direction;
 
@@ -181,6 +189,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
             geometric_normal_ws *= -1.0;
             front_facing_shading_normal_ws *= -1.0;
             front_facing_interpolated_normal_ws *= -1.0;
+            front_facing_clearcoat_normal_ws *= -1.0;
         }
 
         let current_position_cs: vec4<f32> = xr_camera.view_to_clip_space[view_index] * xr_camera.world_to_view_space[view_index] * vec4<f32>(hit_point_ws, 1.0);
@@ -194,15 +203,10 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
 
         position_ws = hit_point_ws;
         depth_ws = intersection.t;
-        Gbuffer::store_shading_and_geometric_normal(front_facing_shading_normal_ws, geometric_normal_ws, front_facing_interpolated_normal_ws, id, view_index);
+        Gbuffer::store_shading_and_geometric_normal(front_facing_shading_normal_ws, geometric_normal_ws, front_facing_interpolated_normal_ws, front_facing_clearcoat_normal_ws, id, view_index);
         Gbuffer::store_tex_coord_and_derivatives(tex_coord, ddx, ddy, id, view_index);
         Gbuffer::store_velocity(velocity, id, view_index);
-        Gbuffer::store_material_descriptor_idx_and_normal_roughness(material_descriptor_idx, normal_roughness, id, view_index);
-
-        let roughness: f32 = MaterialDescriptor::metallic_roughness(material_descriptor, tex_coord, ddx, ddy).y;
-        if (roughness < constants.reflection_max_roughness) {
-            push_reflection_pixel(id);
-        }
+        Gbuffer::store_material_descriptor_idx_and_normal_roughness(material_descriptor_idx, normal_roughness, anisotropy_tangent_ws, id, view_index);
     }
 
     Gbuffer::store_position_and_depth(position_ws, depth_ws, id, view_index);
@@ -18,15 +18,17 @@ const SHADING_MODE_VELOCITY: u32 = 6;
 const SHADING_MODE_FOG: u32 = 7;
 const SHADING_MODE_REFLECTION: u32 = 8;
 const SHADING_MODE_SIMPLE_LIGHTING: u32 = 9;
+const SHADING_MODE_AMBIENT_OCCLUSION: u32 = 10;
+const SHADING_MODE_INDIRECT: u32 = 11;
 
 struct Constants {
     resolution: vec2<u32>,
     shading_mode: u32,
     ambient_factor: f32,
     reflection_max_roughness: f32,
+    ambient_occlusion: u32,
+    global_illumination: u32,
     _padding0: u32,
-    _padding1: u32,
-    _padding2: u32,
 }
 
 @group(0)
@@ -47,17 +49,23 @@ var lighting: texture_2d_array<f32>;
 
 @group(0)
 @binding(6)
-var mirror_reflections: texture_2d_array<f32>;
+var reflections: texture_2d_array<f32>;
 
 @group(0)
 @binding(7)
 var linear_sampler: sampler;
 
-fn shade_fog(shade_color: vec3<f32>, position_and_depth: GbufferPositionAndDepth, view_origin: vec3<f32>, view_dir: vec3<f32>, l: vec3<f32>) -> vec3<f32> {
-    let density: f32 = sky_constants.atmosphere.density * Sky::atmosphere_density(view_origin, position_and_depth.position);
-    let fog_strength: f32 = 1.0 - exp(-position_and_depth.depth * density);
-    let inscattering: vec3<f32> = Sky::inscattering(view_dir, true);
-    return mix(shade_color, inscattering, fog_strength);
+@group(0)
+@binding(8)
+var ambient_occlusion: texture_2d_array<f32>;
+
+@group(0)
+@binding(9)
+var global_illumination: texture_2d_array<f32>;
+
+fn shade_fog(shade_color: vec3<f32>, position_and_depth: GbufferPositionAndDepth, view_origin: vec3<f32>) -> vec3<f32> {
+    let aerial_perspective: AtmosphereScattering = Sky::aerial_perspective(view_origin, position_and_depth.position);
+    return shade_color * aerial_perspective.transmittance + aerial_perspective.luminance;
 }
 
 @compute
@@ -83,6 +91,10 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
 
             let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx_and_normal_roughness.material_descriptor_idx];
             var material: Material = Material::from_material_descriptor(material_descriptor, tex_coord_and_derivatives.tex_coord, tex_coord_and_derivatives.ddx, tex_coord_and_derivatives.ddy);
+            material.clearcoat_normal = shading_and_geometric_normal.clearcoat_normal;
+            material.anisotropy_tangent = material_descriptor_idx_and_normal_roughness.anisotropy_tangent;
+            // Must match the pixels traced by the reflection pass, reflections of other pixels are zero
+            let has_traced_specular: bool = Material::has_traced_specular(material, constants.reflection_max_roughness);
 
             let geometric_roughness: f32 = safe_sqrt(1.0 - material_descriptor_idx_and_normal_roughness.normal_roughness);
             material.roughness = safe_sqrt(sqr(material.roughness) + sqr(geometric_roughness));
@@ -99,22 +111,52 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
 
                 color = reflectance * n_dot_l + ambient + material.emission;
             } else {
-                let l: vec3<f32> = sky_constants.world_up;
+                var occlusion_factor: f32 = 1.0;
+                if (constants.ambient_occlusion != 0) {
+                    occlusion_factor = textureSampleLevel(ambient_occlusion, linear_sampler, uv, view_index, 0.0).r;
+                }
 
-                let ambient: vec3<f32> = material.color * constants.ambient_factor * material.roughness;
+                let diffuse_albedo: vec3<f32> = material.color * (1.0 - material.metallic) * (1.0 - material.transmission);
 
-                let ltc_shading: vec3<f32> = textureSampleLevel(lighting, linear_sampler, uv, view_index, 0.0).rgb;
+                // Traced diffuse rays already account for occlusion, so ambient occlusion is left to the remaining ambient terms
+                var indirect_diffuse = vec3<f32>(0.0);
+                if (constants.global_illumination != 0) {
+                    indirect_diffuse = diffuse_albedo * textureSampleLevel(global_illumination, linear_sampler, uv, view_index, 0.0).rgb;
+                }
+
+                var ambient: vec3<f32>;
+                if (Sky::has_environment_map()) {
+                    let n: vec3<f32> = shading_and_geometric_normal.shading_normal;
+                    let n_dot_v: f32 = max(dot(-ray.direction, n), 0.0);
+                    var diffuse = vec3<f32>(0.0);
+                    if (constants.global_illumination == 0) {
+                        diffuse = diffuse_albedo * Sky::environment_irradiance(n) * INV_PI;
+                    }
+
+                    // Traced reflections fully replace the environment specular of the surfaces they cover
+                    let specular_weight: f32 = select(1.0, 0.0, has_traced_specular);
+                    let specular: vec3<f32> = Sky::environment_radiance(reflect(ray.direction, n), material.roughness)
+                        * EnvironmentMap::specular_brdf(Material::specular_f0(material), material.roughness, n_dot_v) * specular_weight;
+
+                    ambient = (diffuse + specular) * occlusion_factor;
+                } else if (constants.global_illumination == 0) {
+                    ambient = material.color * constants.ambient_factor * material.roughness * (1.0 - material.transmission) * occlusion_factor;
+                } else {
+                    ambient = vec3<f32>(0.0);
+                }
+                ambient += indirect_diffuse;
 
-                let f0: vec3<f32> = mix(vec3<f32>(0.04), material.color, material.metallic);
-                let fresnel: vec3<f32> = fresnel_schlick(dot(-ray.direction, shading_and_geometric_normal.interpolated_normal), f0);
-                let reflection_roughness_factor: f32 = sqr(1.0 - clamp(material.roughness / constants.reflection_max_roughness, 0.0, 1.0));
+                let ltc_shading: vec3<f32> = textureSampleLevel(lighting, linear_sampler, uv, view_index, 0.0).rgb;
 
-                var reflection: vec3<f32> = textureSampleLevel(mirror_reflections, linear_sampler, uv, view_index, 0.0).rgb;
-                reflection *= fresnel * reflection_roughness_factor;
+                // Reflections and transmission are already weighted by the reflection pass
+                var reflection = vec3<f32>(0.0);
+                if (has_traced_specular) {
+                    reflection = textureSampleLevel(reflections, linear_sampler, uv, view_index, 0.0).rgb;
+                }
 
                 if (constants.shading_mode == SHADING_MODE_FULL) {
                     color = ltc_shading + ambient + material.emission + reflection;
-                    color = shade_fog(color, position_and_depth, ray.origin, ray.direction, l);
+                    color = shade_fog(color, position_and_depth, ray.origin);
                 } else if (constants.shading_mode == SHADING_MODE_LIGHTING_ONLY) {
                     color = ltc_shading;
                 } else if (constants.shading_mode == SHADING_MODE_ALBEDO) {
@@ -129,9 +171,13 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
                     let velocity: vec2<f32> = Gbuffer::load_velocity(id, view_index);
                     color = vec3<f32>(abs(velocity) * 10.0, 0.0);
                 } else if (constants.shading_mode == SHADING_MODE_FOG) {
-                    color = shade_fog(vec3<f32>(1.0), position_and_depth, ray.origin, ray.direction, l);
+                    color = shade_fog(vec3<f32>(1.0), position_and_depth, ray.origin);
                 } else if (constants.shading_mode == SHADING_MODE_REFLECTION) {
                     color = reflection;
+                } else if (constants.shading_mode == SHADING_MODE_AMBIENT_OCCLUSION) {
+                    color = vec3<f32>(occlusion_factor);
+                } else if (constants.shading_mode == SHADING_MODE_INDIRECT) {
+                    color = ambient;
                 }
             }
         } else {
@@ -0,0 +1,41 @@
+// Requires vertex_pool_bindings.wgsl and material_pool_bindings.wgsl to be included as well
+
+// Whether a candidate intersection with non-opaque geometry occludes the ray
+// Blended surfaces are composited by the transparent pass, `skip_blended` leaves them out entirely rather than alpha testing them
+fn AlphaTest::is_opaque_hit(intersection: RayIntersection, skip_blended: bool) -> bool {
+    let vertex_slice_index: u32 = vertex_pool_vertex_slice_indices[intersection.instance_custom_data];
+    let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[vertex_slice_index];
+
+    let material_descriptor_idx: u32 = VertexPoolBindings::material_idx(intersection.instance_custom_data, vertex_pool_slice.first_index / 3 + intersection.primitive_index);
+    let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx];
+    if (material_descriptor.alpha_mode == ALPHA_MODE_OPAQUE) {
+        return true;
+    }
+    if (material_descriptor.alpha_mode == ALPHA_MODE_BLEND && skip_blended) {
+        return false;
+    }
+
+    let i0: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 0];
+    let i1: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 1];
+    let i2: u32 = vertex_indices[vertex_pool_slice.first_index + intersection.primitive_index * 3 + 2];
+    let barycentrics = vec3<f32>(1.0 - intersection.barycentrics.x - intersection.barycentrics.y, intersection.barycentrics);
+    let tex_coord: vec2<f32> = vertices[vertex_pool_slice.first_vertex + i0].tex_coord * barycentrics.x
+        + vertices[vertex_pool_slice.first_vertex + i1].tex_coord * barycentrics.y
+        + vertices[vertex_pool_slice.first_vertex + i2].tex_coord * barycentrics.z;
+
+    let alpha: f32 = MaterialDescriptor::color(material_descriptor, tex_coord, vec2<f32>(0.0), vec2<f32>(0.0)).a;
+    return alpha >= material_descriptor.alpha_cutoff;
+}
+
+// Closest intersection of `ray_desc`, non-opaque geometry is alpha tested on every candidate
+fn AlphaTest::trace_ray(scene: acceleration_structure, ray_desc: RayDesc, skip_blended: bool) -> RayIntersection {
+    var rq: ray_query;
+    rayQueryInitialize(&rq, scene, ray_desc);
+    while (rayQueryProceed(&rq)) {
+        let candidate: RayIntersection = rayQueryGetCandidateIntersection(&rq);
+        if (candidate.kind == RAY_QUERY_INTERSECTION_TRIANGLE && AlphaTest::is_opaque_hit(candidate, skip_blended)) {
+            rayQueryConfirmIntersection(&rq);
+        }
+    }
+    return rayQueryGetCommittedIntersection(&rq);
+}
@@ -33,21 +33,148 @@ fn geometry_smith(n: vec3<f32>, v: vec3<f32>, l: vec3<f32>, roughness: f32) -> f
     return ggx1 * ggx2;
 }
 
+// Anisotropic ggx distribution with separate roughness along the tangent and bitangent, "Physically-Based Shading at Disney" by Burley
+fn distribution_ggx_anisotropic(t_dot_h: f32, b_dot_h: f32, n_dot_h: f32, alpha_t: f32, alpha_b: f32) -> f32 {
+    let a2: f32 = alpha_t * alpha_b;
+    let f = vec3<f32>(alpha_b * t_dot_h, alpha_t * b_dot_h, a2 * n_dot_h);
+    let w2: f32 = a2 / dot(f, f);
+    return a2 * sqr(w2) / PI;
+}
+
+// Height correlated smith visibility of the anisotropic ggx distribution, includes the 1 / (4 * n_dot_l * n_dot_v) of the microfacet model
+fn visibility_smith_ggx_anisotropic(t_dot_v: f32, b_dot_v: f32, n_dot_v: f32, t_dot_l: f32, b_dot_l: f32, n_dot_l: f32, alpha_t: f32, alpha_b: f32) -> f32 {
+    let lambda_v: f32 = n_dot_l * length(vec3<f32>(alpha_t * t_dot_v, alpha_b * b_dot_v, n_dot_v));
+    let lambda_l: f32 = n_dot_v * length(vec3<f32>(alpha_t * t_dot_l, alpha_b * b_dot_l, n_dot_l));
+    return 0.5 / (lambda_v + lambda_l + 0.0001);
+}
+
+fn distribution_charlie(n_dot_h: f32, roughness: f32) -> f32 {
+    let inv_alpha: f32 = 1.0 / max(sqr(roughness), 0.0001);
+    let sin2_h: f32 = max(1.0 - sqr(n_dot_h), 0.0078125);
+    return (2.0 + inv_alpha) * pow(sin2_h, inv_alpha * 0.5) / (2.0 * PI);
+}
+
+fn visibility_neubelt(n_dot_l: f32, n_dot_v: f32) -> f32 {
+    return 1.0 / (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v) + 0.0001);
+}
+
+// Microfacet normal around `n` from the distribution of ggx normals visible from `v`, "Sampling the GGX Distribution of Visible Normals" by Heitz
+fn sample_ggx_visible_normal(n: vec3<f32>, v: vec3<f32>, roughness: f32, uv: vec2<f32>) -> vec3<f32> {
+    let alpha: f32 = max(sqr(roughness), 0.001);
+    let tangent_to_world: mat3x3<f32> = build_orthonormal_basis(n);
+    let v_tangent: vec3<f32> = v * tangent_to_world;
+
+    // Stretch the view direction to sample a hemisphere of the unit roughness configuration
+    let v_hemisphere: vec3<f32> = normalize(vec3<f32>(alpha * v_tangent.x, alpha * v_tangent.y, max(v_tangent.z, 0.0)));
+    let length_squared: f32 = sqr(v_hemisphere.x) + sqr(v_hemisphere.y);
+    var t1 = vec3<f32>(1.0, 0.0, 0.0);
+    if (length_squared > 0.0) {
+        t1 = vec3<f32>(-v_hemisphere.y, v_hemisphere.x, 0.0) * inverseSqrt(length_squared);
+    }
+    let t2: vec3<f32> = cross(v_hemisphere, t1);
+
+    let r: f32 = sqrt(uv.x);
+    let phi: f32 = TWO_PI * uv.y;
+    let p1: f32 = r * cos(phi);
+    let s: f32 = 0.5 * (1.0 + v_hemisphere.z);
+    let p2: f32 = (1.0 - s) * safe_sqrt(1.0 - sqr(p1)) + s * r * sin(phi);
+
+    let n_hemisphere: vec3<f32> = p1 * t1 + p2 * t2 + safe_sqrt(1.0 - sqr(p1) - sqr(p2)) * v_hemisphere;
+    let n_tangent: vec3<f32> = normalize(vec3<f32>(alpha * n_hemisphere.x, alpha * n_hemisphere.y, max(n_hemisphere.z, 0.0)));
+    return normalize(tangent_to_world * n_tangent);
+}
+
+// Specular reflectance at normal incidence, dielectric reflectance follows KHR_materials_specular and KHR_materials_ior
+fn Material::specular_f0(_self: Material) -> vec3<f32> {
+    let dielectric_f0: vec3<f32> = min(vec3<f32>(sqr((_self.eta - 1.0) / (_self.eta + 1.0))) * _self.specular_tint, vec3<f32>(1.0)) * _self.specular;
+    return mix(dielectric_f0, _self.color, _self.metallic);
+}
+
+// Normal of the clearcoat layer, falls back to the shading normal when the material has none of its own
+fn Material::clearcoat_normal(_self: Material, normal_ws: vec3<f32>) -> vec3<f32> {
+    return select(normal_ws, _self.clearcoat_normal, any(_self.clearcoat_normal != vec3<f32>(0.0)));
+}
+
+// Fresnel of the clearcoat layer, scaled by its strength
+fn Material::clearcoat_fresnel(_self: Material, cos_theta: f32) -> f32 {
+    return _self.clearcoat * fresnel_schlick(cos_theta, vec3<f32>(0.04)).x;
+}
+
+// Whether the specular lobe is stretched along `anisotropy_tangent`, requires the passes to fill it in from the gbuffer
+fn Material::is_anisotropic(_self: Material) -> bool {
+    return _self.anisotropy > 0.0 && any(_self.anisotropy_tangent != vec3<f32>(0.0));
+}
+
+// Specular lobe following KHR_materials_anisotropy, roughness along the tangent grows towards one with the anisotropy strength
+fn Material::eval_anisotropic_specular(_self: Material, w_in_ws: vec3<f32>, w_out_ws: vec3<f32>, normal_ws: vec3<f32>, h: vec3<f32>) -> f32 {
+    let alpha_b: f32 = max(sqr(_self.roughness), 0.001);
+    let alpha_t: f32 = mix(alpha_b, 1.0, sqr(_self.anisotropy));
+
+    // The tangent is stored before normal mapping, so it's orthogonalized against the shading normal here
+    let t: vec3<f32> = normalize(_self.anisotropy_tangent - normal_ws * dot(normal_ws, _self.anisotropy_tangent));
+    let b: vec3<f32> = cross(normal_ws, t);
+
+    let n_dot_l: f32 = max(dot(normal_ws, w_in_ws), 0.0);
+    let n_dot_v: f32 = max(dot(normal_ws, w_out_ws), 0.0);
+    let n_dot_h: f32 = max(dot(normal_ws, h), 0.0);
+
+    let D: f32 = distribution_ggx_anisotropic(dot(t, h), dot(b, h), n_dot_h, alpha_t, alpha_b);
+    let V: f32 = visibility_smith_ggx_anisotropic(dot(t, w_out_ws), dot(b, w_out_ws), n_dot_v, dot(t, w_in_ws), dot(b, w_in_ws), n_dot_l, alpha_t, alpha_b);
+    return D * V;
+}
+
 fn Material::eval_brdf(_self: Material, w_in_ws: vec3<f32>, w_out_ws: vec3<f32>, normal_ws: vec3<f32>) -> vec3<f32> {
     let h: vec3<f32> = normalize(w_in_ws + w_out_ws);
-    let f0: vec3<f32> = mix(vec3<f32>(0.04), _self.color, _self.metallic);
+    let f0: vec3<f32> = Material::specular_f0(_self);
+    let n_dot_l: f32 = max(dot(normal_ws, w_in_ws), 0.0);
+    let n_dot_v: f32 = max(dot(normal_ws, w_out_ws), 0.0);
+    let v_dot_h: f32 = max(dot(h, w_out_ws), 0.0);
 
     let NDF: f32 = distribution_ggx(normal_ws, h, _self.roughness);        
     let G: f32 = geometry_smith(normal_ws, w_out_ws, w_in_ws, _self.roughness);      
-    let F: vec3<f32> = fresnel_schlick(max(dot(h, w_out_ws), 0.0), f0);       
+    let F: vec3<f32> = fresnel_schlick(v_dot_h, f0);       
     
+    // Transmitted light leaves through the back side, so it no longer contributes to diffuse reflection
     let kS: vec3<f32> = F;
     var kD: vec3<f32> = vec3(1.0) - kS;
-    kD *= 1.0 - _self.metallic;
+    kD *= (1.0 - _self.metallic) * (1.0 - _self.transmission);
     
-    let numerator: vec3<f32> = NDF * G * F;
-    let denominator: f32 = 4.0 * max(dot(normal_ws, w_out_ws), 0.0) * max(dot(normal_ws, w_in_ws), 0.0) + 0.0001;
-    let specular: vec3<f32> = numerator / denominator;
+    var specular: vec3<f32>;
+    if (Material::is_anisotropic(_self)) {
+        specular = Material::eval_anisotropic_specular(_self, w_in_ws, w_out_ws, normal_ws, h) * F;
+    } else {
+        let numerator: vec3<f32> = NDF * G * F;
+        let denominator: f32 = 4.0 * n_dot_v * n_dot_l + 0.0001;
+        specular = numerator / denominator;
+    }
 
-    return kD * _self.color / PI + specular;
-}
\ No newline at end of file
+    var brdf: vec3<f32> = kD * _self.color / PI + specular;
+
+    if (_self.sheen > 0.0) {
+        let sheen_color: vec3<f32> = _self.sheen * _self.sheen_tint;
+        brdf += sheen_color * distribution_charlie(max(dot(normal_ws, h), 0.0), _self.roughness) * visibility_neubelt(n_dot_l, n_dot_v);
+    }
+
+    // Clearcoat is layered on top, light reaching the base is attenuated by its fresnel
+    if (_self.clearcoat > 0.0) {
+        let clearcoat_normal_ws: vec3<f32> = Material::clearcoat_normal(_self, normal_ws);
+        let clearcoat_n_dot_l: f32 = max(dot(clearcoat_normal_ws, w_in_ws), 0.0);
+        let clearcoat_n_dot_v: f32 = max(dot(clearcoat_normal_ws, w_out_ws), 0.0);
+
+        let clearcoat_fresnel: f32 = Material::clearcoat_fresnel(_self, v_dot_h);
+        let clearcoat_ndf: f32 = distribution_ggx(clearcoat_normal_ws, h, _self.clearcoat_roughness);
+        let clearcoat_g: f32 = geometry_smith(clearcoat_normal_ws, w_out_ws, w_in_ws, _self.clearcoat_roughness);
+        let clearcoat_specular: f32 = clearcoat_ndf * clearcoat_g * clearcoat_fresnel / (4.0 * clearcoat_n_dot_v * clearcoat_n_dot_l + 0.0001);
+
+        brdf = brdf * (1.0 - clearcoat_fresnel) + clearcoat_specular;
+    }
+
+    return brdf;
+}
+
+// Whether the reflection pass traces the specular and transmission lobes of this material
+fn Material::has_traced_specular(_self: Material, reflection_max_roughness: f32) -> bool {
+    return _self.roughness < reflection_max_roughness
+        || (_self.clearcoat > 0.0 && _self.clearcoat_roughness < reflection_max_roughness)
+        || _self.transmission > 0.0;
+}
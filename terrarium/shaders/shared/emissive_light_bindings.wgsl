@@ -0,0 +1,114 @@
+// Requires vertex_pool_bindings.wgsl and material_pool_bindings.wgsl to be included as well
+
+@include brdf.wgsl
+
+struct EmissiveLightConstants {
+    triangle_count: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+struct EmissiveInstance {
+    local_to_world: mat4x4<f32>,
+    instance_idx: u32,
+    vertex_slice_index: u32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+struct EmissiveTriangle {
+    instance: u32,
+    triangle_idx: u32,
+    pdf: f32,
+    alias_threshold: f32,
+    alias: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+struct EmissiveLightSample {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    emission: vec3<f32>,
+    // Probability density per unit of world space area
+    pdf: f32,
+}
+
+@group(6)
+@binding(0)
+var<uniform> emissive_light_constants: EmissiveLightConstants;
+
+@group(6)
+@binding(1)
+var<storage, read> emissive_instances: array<EmissiveInstance>;
+
+@group(6)
+@binding(2)
+var<storage, read> emissive_triangles: array<EmissiveTriangle>;
+
+fn EmissiveLightBindings::has_lights() -> bool {
+    return emissive_light_constants.triangle_count > 0;
+}
+
+// Triangles are picked in proportion to their power through the alias table, the point on it uniformly
+fn EmissiveLightBindings::sample(rng: vec3<f32>) -> EmissiveLightSample {
+    let scaled_rng: f32 = rng.x * f32(emissive_light_constants.triangle_count);
+    var triangle_index: u32 = min(u32(scaled_rng), emissive_light_constants.triangle_count - 1);
+    if (fract(scaled_rng) >= emissive_triangles[triangle_index].alias_threshold) {
+        triangle_index = emissive_triangles[triangle_index].alias;
+    }
+
+    let triangle: EmissiveTriangle = emissive_triangles[triangle_index];
+    let instance: EmissiveInstance = emissive_instances[triangle.instance];
+    let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[instance.vertex_slice_index];
+
+    let i0: u32 = vertex_indices[vertex_pool_slice.first_index + triangle.triangle_idx * 3 + 0];
+    let i1: u32 = vertex_indices[vertex_pool_slice.first_index + triangle.triangle_idx * 3 + 1];
+    let i2: u32 = vertex_indices[vertex_pool_slice.first_index + triangle.triangle_idx * 3 + 2];
+
+    let v0: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i0]);
+    let v1: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i1]);
+    let v2: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + i2]);
+
+    let p0: vec3<f32> = (instance.local_to_world * vec4<f32>(v0.position, 1.0)).xyz;
+    let p1: vec3<f32> = (instance.local_to_world * vec4<f32>(v1.position, 1.0)).xyz;
+    let p2: vec3<f32> = (instance.local_to_world * vec4<f32>(v2.position, 1.0)).xyz;
+
+    let r: f32 = sqrt(rng.y);
+    let barycentrics = vec3<f32>(1.0 - r, r * (1.0 - rng.z), r * rng.z);
+
+    let tex_coord: vec2<f32> = v0.tex_coord * barycentrics.x + v1.tex_coord * barycentrics.y + v2.tex_coord * barycentrics.z;
+    let material_descriptor_idx: u32 = VertexPoolBindings::material_idx(instance.instance_idx, vertex_pool_slice.first_index / 3 + triangle.triangle_idx);
+    let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx];
+
+    let area_normal: vec3<f32> = cross(p1 - p0, p2 - p0);
+    let area: f32 = max(length(area_normal) * 0.5, 1e-8);
+
+    var light_sample: EmissiveLightSample;
+    light_sample.position = p0 * barycentrics.x + p1 * barycentrics.y + p2 * barycentrics.z;
+    light_sample.normal = area_normal / (area * 2.0);
+    light_sample.emission = MaterialDescriptor::emission(material_descriptor, tex_coord, vec2<f32>(0.0), vec2<f32>(0.0));
+    light_sample.pdf = triangle.pdf / area;
+    return light_sample;
+}
+
+// Unshadowed contribution of a point on an emissive triangle per unit of its area, shadowing and dividing by the pdf is left up to the caller
+fn EmissiveLightBindings::shade(material: Material, light_sample: EmissiveLightSample, normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>) -> vec3<f32> {
+    let to_light: vec3<f32> = light_sample.position - hit_point;
+    let distance_squared: f32 = dot(to_light, to_light);
+    if (distance_squared < 1e-8) {
+        return vec3<f32>(0.0);
+    }
+    let light_dir: vec3<f32> = to_light * inverseSqrt(distance_squared);
+
+    let n_dot_l: f32 = dot(normal, light_dir);
+    if (n_dot_l <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    // Emissive surfaces glow on both sides, matching how they are shaded
+    let light_cos: f32 = abs(dot(light_sample.normal, light_dir));
+    return Material::eval_brdf(material, light_dir, view_dir, normal) * n_dot_l * light_sample.emission * light_cos / distance_squared;
+}
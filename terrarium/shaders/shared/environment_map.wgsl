@@ -0,0 +1,83 @@
+@include math.wgsl
+
+const IRRADIANCE_SH_COEFFICIENT_COUNT: u32 = 9;
+
+// Direction through a point on a cubemap face, faces are ordered +x, -x, +y, -y, +z, -z
+fn EnvironmentMap::cube_face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let a: f32 = uv.x * 2.0 - 1.0;
+    let b: f32 = uv.y * 2.0 - 1.0;
+
+    switch (face) {
+        case 0u: { return vec3<f32>(1.0, -b, -a); }
+        case 1u: { return vec3<f32>(-1.0, -b, a); }
+        case 2u: { return vec3<f32>(a, 1.0, b); }
+        case 3u: { return vec3<f32>(a, -1.0, -b); }
+        case 4u: { return vec3<f32>(a, -b, 1.0); }
+        default: { return vec3<f32>(-a, -b, -1.0); }
+    }
+}
+
+// Inverse of `EnvironmentMap::cube_face_direction`, returns the uv in xy and the face in z
+fn EnvironmentMap::cube_face_uv(direction: vec3<f32>) -> vec3<f32> {
+    let abs_direction: vec3<f32> = abs(direction);
+
+    var face: u32;
+    var sc: f32;
+    var tc: f32;
+    var ma: f32;
+    if (abs_direction.x >= abs_direction.y && abs_direction.x >= abs_direction.z) {
+        face = select(1u, 0u, direction.x > 0.0);
+        sc = select(direction.z, -direction.z, direction.x > 0.0);
+        tc = -direction.y;
+        ma = abs_direction.x;
+    } else if (abs_direction.y >= abs_direction.z) {
+        face = select(3u, 2u, direction.y > 0.0);
+        sc = direction.x;
+        tc = select(-direction.z, direction.z, direction.y > 0.0);
+        ma = abs_direction.y;
+    } else {
+        face = select(5u, 4u, direction.z > 0.0);
+        sc = select(-direction.x, direction.x, direction.z > 0.0);
+        tc = -direction.y;
+        ma = abs_direction.z;
+    }
+
+    return vec3<f32>((sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5, f32(face));
+}
+
+// Equirectangular images have their up axis along y and wrap around horizontally
+fn EnvironmentMap::equirectangular_uv(direction: vec3<f32>) -> vec2<f32> {
+    return vec2<f32>(
+        atan2(direction.z, direction.x) * INV_2_PI + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) * INV_PI
+    );
+}
+
+// Real spherical harmonics basis up to the second band
+fn EnvironmentMap::sh_basis(direction: vec3<f32>) -> array<f32, 9> {
+    let x: f32 = direction.x;
+    let y: f32 = direction.y;
+    let z: f32 = direction.z;
+
+    return array<f32, 9>(
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y)
+    );
+}
+
+// Analytic fit of the split sum specular brdf integral, from "Physically Based Shading on Mobile" by Karis
+fn EnvironmentMap::specular_brdf(f0: vec3<f32>, roughness: f32, n_dot_v: f32) -> vec3<f32> {
+    let c0 = vec4<f32>(-1.0, -0.0275, -0.572, 0.022);
+    let c1 = vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let r: vec4<f32> = roughness * c0 + c1;
+    let a004: f32 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let ab: vec2<f32> = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return f0 * ab.x + ab.y;
+}
@@ -11,7 +11,7 @@ var gbuffer_position_and_depth: texture_storage_2d_array<rgba32float, read_write
 // R: shading_normal (PackedNormalizedXyz10)
 // G: geometric_normal (PackedNormalizedXyz10)
 // B: interpolated_normal (PackedNormalizedXyz10)
-// A: empty
+// A: clearcoat_normal (PackedNormalizedXyz10)
 var gbuffer_shading_and_geometric_normal: texture_storage_2d_array<rgba32uint, read_write>;
 
 @group(4)
@@ -30,7 +30,8 @@ var gbuffer_velocity: texture_storage_2d_array<rg32float, read_write>;
 @binding(4)
 // R: material_descriptor_idx (u32)
 // G: normal_roughness (f32)
-var gbuffer_material_descriptor_idx_and_normal_roughness: texture_storage_2d_array<rg32float, read_write>;
+// B: anisotropy_tangent (PackedNormalizedXyz10)
+var gbuffer_material_descriptor_idx_and_normal_roughness: texture_storage_2d_array<rgba32float, read_write>;
 
 struct GbufferPositionAndDepth {
     position: vec3<f32>,
@@ -45,6 +46,7 @@ struct GbufferShadingAndGeometricNormal {
     shading_normal: vec3<f32>,
     geometric_normal: vec3<f32>,
     interpolated_normal: vec3<f32>,
+    clearcoat_normal: vec3<f32>,
 }
 
 struct GbufferTexCoordAndDerivatives {
@@ -56,6 +58,7 @@ struct GbufferTexCoordAndDerivatives {
 struct GbufferMaterialDescriptorIdxAndNormalRoughness {
     material_descriptor_idx: u32,
     normal_roughness: f32,
+    anisotropy_tangent: vec3<f32>,
 }
 
 fn Gbuffer::load_position_and_depth(id: vec2<u32>, view_index: u32) -> GbufferPositionAndDepth {
@@ -71,23 +74,25 @@ fn Gbuffer::store_position_and_depth(position: vec3<f32>, depth: f32, id: vec2<u
 }
 
 fn Gbuffer::load_shading_and_geometric_normal(id: vec2<u32>, view_index: u32) -> GbufferShadingAndGeometricNormal {
-    let data: vec3<u32> = textureLoad(gbuffer_shading_and_geometric_normal, id, view_index).rgb;
+    let data: vec4<u32> = textureLoad(gbuffer_shading_and_geometric_normal, id, view_index);
 
     return GbufferShadingAndGeometricNormal(
         PackedNormalizedXyz10::unpack(PackedNormalizedXyz10(data.x), 0),
         PackedNormalizedXyz10::unpack(PackedNormalizedXyz10(data.y), 0),
-        PackedNormalizedXyz10::unpack(PackedNormalizedXyz10(data.z), 0)
+        PackedNormalizedXyz10::unpack(PackedNormalizedXyz10(data.z), 0),
+        PackedNormalizedXyz10::unpack(PackedNormalizedXyz10(data.w), 0)
     );
 }
 
-fn Gbuffer::store_shading_and_geometric_normal(shading_normal: vec3<f32>, geometric_normal: vec3<f32>, interpolated_normal: vec3<f32>, id: vec2<u32>, view_index: u32) {
-    let data = vec3<u32>(
+fn Gbuffer::store_shading_and_geometric_normal(shading_normal: vec3<f32>, geometric_normal: vec3<f32>, interpolated_normal: vec3<f32>, clearcoat_normal: vec3<f32>, id: vec2<u32>, view_index: u32) {
+    let data = vec4<u32>(
         PackedNormalizedXyz10::new(shading_normal, 0).data,
         PackedNormalizedXyz10::new(geometric_normal, 0).data,
-        PackedNormalizedXyz10::new(interpolated_normal, 0).data
+        PackedNormalizedXyz10::new(interpolated_normal, 0).data,
+        PackedNormalizedXyz10::new(clearcoat_normal, 0).data
     );
 
-    textureStore(gbuffer_shading_and_geometric_normal, id, view_index, vec4<u32>(data, 0));
+    textureStore(gbuffer_shading_and_geometric_normal, id, view_index, data);
 }
 
 fn Gbuffer::load_tex_coord_and_derivatives(id: vec2<u32>, view_index: u32) -> GbufferTexCoordAndDerivatives {
@@ -119,19 +124,21 @@ fn Gbuffer::store_velocity(velocity: vec2<f32>, id: vec2<u32>, view_index: u32)
 }
 
 fn Gbuffer::load_material_descriptor_idx_and_normal_roughness(id: vec2<u32>, view_index: u32) -> GbufferMaterialDescriptorIdxAndNormalRoughness {
-    let data: vec2<f32> = textureLoad(gbuffer_material_descriptor_idx_and_normal_roughness, id, view_index).rg;
+    let data: vec3<f32> = textureLoad(gbuffer_material_descriptor_idx_and_normal_roughness, id, view_index).rgb;
 
     return GbufferMaterialDescriptorIdxAndNormalRoughness(
         bitcast<u32>(data.r),
-        data.g
+        data.g,
+        PackedNormalizedXyz10::unpack(PackedNormalizedXyz10(bitcast<u32>(data.b)), 0)
     );
 }
 
-fn Gbuffer::store_material_descriptor_idx_and_normal_roughness(material_descriptor_idx: u32, normal_roughness: f32, id: vec2<u32>, view_index: u32) {
-    let data = vec2<f32>(
+fn Gbuffer::store_material_descriptor_idx_and_normal_roughness(material_descriptor_idx: u32, normal_roughness: f32, anisotropy_tangent: vec3<f32>, id: vec2<u32>, view_index: u32) {
+    let data = vec3<f32>(
         bitcast<f32>(material_descriptor_idx),
-        normal_roughness
+        normal_roughness,
+        bitcast<f32>(PackedNormalizedXyz10::new(anisotropy_tangent, 0).data)
     );
 
-    textureStore(gbuffer_material_descriptor_idx_and_normal_roughness, id, view_index, vec4<f32>(data, 0.0, 0.0));
+    textureStore(gbuffer_material_descriptor_idx_and_normal_roughness, id, view_index, vec4<f32>(data, 0.0));
 }
\ No newline at end of file
@@ -0,0 +1,115 @@
+// Tiled lighting of the gbuffer shared by both render paths, which only differ in how shadows are resolved.
+// Requires material_pool_bindings.wgsl, gbuffer_bindings.wgsl, linear_transformed_cosines_bindings.wgsl and punctual_light_bindings.wgsl
+// to be included as well. The including pass has to define:
+//  - fn light_visibility(encoded_light_index: u32, hit_point: vec3<f32>, geometric_normal: vec3<f32>) -> f32
+//  - fn directional_light_visibility(light_index: u32, hit_point: vec3<f32>, geometric_normal: vec3<f32>) -> f32
+//  - fn shade_additional_lights(material: Material, normal: vec3<f32>, geometric_normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>,
+//        id: vec2<u32>, view_index: u32) -> vec3<f32>
+
+@include brdf.wgsl
+@include frustum.wgsl
+@include xr.wgsl
+
+struct Constants {
+    resolution: vec2<u32>,
+    lighting_resolution: vec2<u32>,
+    shadows: u32,
+    shadow_bias: f32,
+    frame_idx: u32,
+    emissive_light_candidates: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var<uniform> xr_camera: XrCamera;
+
+@group(0)
+@binding(4)
+var lighting_out: texture_storage_2d_array<rgba16float, read_write>;
+
+@group(0)
+@binding(5)
+var<storage, read> light_index_list: array<u32>;
+
+@group(0)
+@binding(6)
+var light_grid: texture_storage_2d<rg32uint, read>;
+
+fn shade_light(material: Material, encoded_light_index: u32, normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>) -> vec3<f32> {
+    let light_type: u32 = decode_light_type(encoded_light_index);
+    let light_index: u32 = decode_light_index(encoded_light_index);
+
+    if (light_type == LIGHT_TYPE_POINT) {
+        return PunctualLightBindings::shade_point(material, light_index, normal, view_dir, hit_point);
+    } else if (light_type == LIGHT_TYPE_SPOT) {
+        return PunctualLightBindings::shade_spot(material, light_index, normal, view_dir, hit_point);
+    }
+    return LtcBindings::shade(material, light_index, normal, view_dir, hit_point);
+}
+
+@compute
+@workgroup_size(FRUSTUM_TILE_SIZE, FRUSTUM_TILE_SIZE)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(workgroup_id) group_id: vec3<u32>, @builtin(num_workgroups) num_groups: vec3<u32>) {
+    var id: vec2<u32> = global_id.xy;
+    if (any(id >= constants.lighting_resolution)) { return; }
+
+    let full_res_id: vec2<u32> = vec2<u32>(vec2<f32>(id) * (vec2<f32>(constants.resolution) / vec2<f32>(constants.lighting_resolution)));
+
+    // TODO: move to groupshared?
+    let light_offset_and_count: vec2<u32> = textureLoad(light_grid, group_id.xy).rg;
+    let light_index_start_offset: u32 = light_offset_and_count.x;
+    let light_count: u32 = light_offset_and_count.y;
+
+    for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
+        let ray: XrCameraRay = XrCamera::raygen(xr_camera, full_res_id, constants.resolution, view_index);
+
+        let position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(full_res_id, view_index);
+
+        var lighting = vec3<f32>(0.0);
+        if (!GbufferPositionAndDepth::is_sky(position_and_depth)) {
+            let material_descriptor_idx_and_normal_roughness: GbufferMaterialDescriptorIdxAndNormalRoughness
+                = Gbuffer::load_material_descriptor_idx_and_normal_roughness(full_res_id, view_index);
+            let tex_coord_and_derivatives: GbufferTexCoordAndDerivatives = Gbuffer::load_tex_coord_and_derivatives(full_res_id, view_index);
+            let shading_and_geometric_normal: GbufferShadingAndGeometricNormal = Gbuffer::load_shading_and_geometric_normal(full_res_id, view_index);
+
+            let material_descriptor: MaterialDescriptor = material_descriptors[material_descriptor_idx_and_normal_roughness.material_descriptor_idx];
+            var material: Material = Material::from_material_descriptor(material_descriptor, tex_coord_and_derivatives.tex_coord, tex_coord_and_derivatives.ddx, tex_coord_and_derivatives.ddy);
+            material.clearcoat_normal = shading_and_geometric_normal.clearcoat_normal;
+            material.anisotropy_tangent = material_descriptor_idx_and_normal_roughness.anisotropy_tangent;
+
+            let geometric_roughness: f32 = safe_sqrt(1.0 - material_descriptor_idx_and_normal_roughness.normal_roughness);
+            material.roughness = safe_sqrt(sqr(material.roughness) + sqr(geometric_roughness));
+
+            for (var local_light_index: u32 = 0; local_light_index < light_count; local_light_index += 1) {
+                let light_index: u32 = light_index_list[light_index_start_offset + local_light_index];
+
+                var light: vec3<f32> = shade_light(material, light_index, shading_and_geometric_normal.shading_normal, -ray.direction, position_and_depth.position);
+                if (constants.shadows > 0 && any(light > vec3<f32>(0.0))) {
+                    light *= light_visibility(light_index, position_and_depth.position, shading_and_geometric_normal.geometric_normal);
+                }
+
+                lighting += light;
+            }
+
+            // Directional lights affect every tile, they are evaluated for each pixel instead of being culled
+            for (var i: u32 = 0; i < punctual_light_constants.directional_light_count; i += 1) {
+                var light: vec3<f32> = PunctualLightBindings::shade_directional(material, i, shading_and_geometric_normal.shading_normal, -ray.direction);
+                if (constants.shadows > 0 && any(light > vec3<f32>(0.0))) {
+                    light *= directional_light_visibility(i, position_and_depth.position, shading_and_geometric_normal.geometric_normal);
+                }
+
+                lighting += light;
+            }
+
+            lighting += shade_additional_lights(material, shading_and_geometric_normal.shading_normal, shading_and_geometric_normal.geometric_normal,
+                -ray.direction, position_and_depth.position, id, view_index);
+        }
+
+        textureStore(lighting_out, id, view_index, vec4<f32>(lighting, 1.0));
+    }
+}
\ No newline at end of file
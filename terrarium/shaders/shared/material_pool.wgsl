@@ -3,6 +3,10 @@
 const INVALID_TEXTURE: u32 = U32_MAX;
 const MAX_MATERIAL_POOL_TEXTURES: u32 = 1024u;
 
+const ALPHA_MODE_OPAQUE: u32 = 0;
+const ALPHA_MODE_MASK: u32 = 1;
+const ALPHA_MODE_BLEND: u32 = 2;
+
 struct TextureTransform {
     uv_offset: vec2<f32>,
     uv_scale: vec2<f32>,
@@ -37,9 +41,9 @@ struct MaterialDescriptor {
     alpha_cutoff: f32,
     sheen_tint_texture: u32,
     clearcoat_normal_texture: u32,
-    _padding0: u32,
-    _padding1: u32,
-    _padding2: u32,
+    alpha_mode: u32,
+    anisotropy_texture: u32,
+    anisotropy_rotation: f32,
 
     sheen_tint: vec3<f32>,
     transmission_texture: u32,
@@ -57,10 +61,12 @@ struct Material {
     absorption: vec3<f32>,
     specular: f32,
     specular_tint: vec3<f32>,
-    anisotropic: f32,
     sheen: f32,
     sheen_tint: vec3<f32>,
     clearcoat: f32,
     clearcoat_roughness: f32,
+    clearcoat_normal: vec3<f32>,
+    anisotropy: f32,
+    anisotropy_tangent: vec3<f32>,
     alpha_cutoff: f32,
 }
\ No newline at end of file
@@ -96,11 +96,34 @@ fn MaterialDescriptor::sheen_tint(_self: MaterialDescriptor, tex_coord: vec2<f32
     var sheen_tint: vec3<f32> = _self.sheen_tint;
     if (_self.sheen_tint_texture != INVALID_TEXTURE && dot(sheen_tint, sheen_tint) > 0.0) {
         let transformed_tex_coord: vec2<f32> = MaterialPoolBindings::transform_uv(_self.sheen_tint_texture, tex_coord);
-        sheen_tint *= srgb_to_linear(_texture(_self.sheen_tint_texture, transformed_tex_coord, ddx, ddy)).rgb;
+        sheen_tint *= _texture(_self.sheen_tint_texture, transformed_tex_coord, ddx, ddy).rgb;
     }
     return sheen_tint;
 }
 
+fn MaterialDescriptor::anisotropy(_self: MaterialDescriptor, tex_coord: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> f32 {
+    var anisotropy: f32 = _self.anisotropic;
+    if (_self.anisotropy_texture != INVALID_TEXTURE && anisotropy > 0.0) {
+        let transformed_tex_coord: vec2<f32> = MaterialPoolBindings::transform_uv(_self.anisotropy_texture, tex_coord);
+        anisotropy *= _texture(_self.anisotropy_texture, transformed_tex_coord, ddx, ddy).b;
+    }
+    return anisotropy;
+}
+
+// World space direction the highlights stretch along, the mesh tangent rotated by the anisotropy rotation and texture
+fn MaterialDescriptor::anisotropy_tangent(_self: MaterialDescriptor, tex_coord: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>, hit_tangent_to_world: mat3x3<f32>) -> vec3<f32> {
+    var direction_ts = vec2<f32>(1.0, 0.0);
+    if (_self.anisotropy_texture != INVALID_TEXTURE) {
+        let transformed_tex_coord: vec2<f32> = MaterialPoolBindings::transform_uv(_self.anisotropy_texture, tex_coord);
+        direction_ts = _texture(_self.anisotropy_texture, transformed_tex_coord, ddx, ddy).rg * 2.0 - 1.0;
+    }
+
+    let c: f32 = cos(_self.anisotropy_rotation);
+    let s: f32 = sin(_self.anisotropy_rotation);
+    direction_ts = vec2<f32>(c * direction_ts.x - s * direction_ts.y, s * direction_ts.x + c * direction_ts.y);
+    return normalize(hit_tangent_to_world * vec3<f32>(direction_ts, 0.0));
+}
+
 fn MaterialDescriptor::normal_ts(_self: MaterialDescriptor, tex_coord: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec3<f32> {
     if (_self.normal_texture == INVALID_TEXTURE) {
         return vec3<f32>(0.0);
@@ -162,11 +185,15 @@ fn Material::from_material_descriptor_with_color(material_descriptor: MaterialDe
     material.absorption = material_descriptor.absorption;
     material.specular = material_descriptor.specular;
     material.specular_tint = material_descriptor.specular_tint;
-    material.anisotropic = material_descriptor.anisotropic;
     material.sheen = MaterialDescriptor::sheen(material_descriptor, tex_coord, ddx, ddy);
     material.sheen_tint = MaterialDescriptor::sheen_tint(material_descriptor, tex_coord, ddx, ddy);
     material.clearcoat = MaterialDescriptor::clearcoat(material_descriptor, tex_coord, ddx, ddy);
     material.clearcoat_roughness = MaterialDescriptor::clearcoat_roughness(material_descriptor, tex_coord, ddx, ddy);
+    // Filled in from the gbuffer by the passes that have it, zero means the clearcoat follows the shading normal
+    material.clearcoat_normal = vec3<f32>(0.0);
+    material.anisotropy = MaterialDescriptor::anisotropy(material_descriptor, tex_coord, ddx, ddy);
+    // Filled in from the gbuffer by the passes that have it, zero evaluates the specular lobe isotropically
+    material.anisotropy_tangent = vec3<f32>(0.0);
     material.alpha_cutoff = material_descriptor.alpha_cutoff;
     return material;
 }
@@ -0,0 +1,93 @@
+@include punctual_lights.wgsl
+
+@include brdf.wgsl
+
+struct PunctualLightConstants {
+    point_light_count: u32,
+    spot_light_count: u32,
+    directional_light_count: u32,
+    _padding0: u32,
+}
+
+@group(7)
+@binding(0)
+var<uniform> punctual_light_constants: PunctualLightConstants;
+
+@group(7)
+@binding(1)
+var<storage, read> point_lights: array<PointLightInstance>;
+
+@group(7)
+@binding(2)
+var<storage, read> spot_lights: array<SpotLightInstance>;
+
+@group(7)
+@binding(3)
+var<storage, read> directional_lights: array<DirectionalLightInstance>;
+
+// Unshadowed contribution of a single point light, shadowing is left up to the caller
+fn PunctualLightBindings::shade_point(material: Material, instance_idx: u32, normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>) -> vec3<f32> {
+    let instance: PointLightInstance = point_lights[instance_idx];
+
+    let to_light: vec3<f32> = instance.position - hit_point;
+    let distance: f32 = length(to_light);
+    if (distance >= instance.range) {
+        return vec3<f32>(0.0);
+    }
+    let light_dir: vec3<f32> = to_light / distance;
+
+    let n_dot_l: f32 = dot(normal, light_dir);
+    if (n_dot_l <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let attenuation: f32 = punctual_light_attenuation(distance, instance.range);
+    return Material::eval_brdf(material, light_dir, view_dir, normal) * n_dot_l * attenuation * instance.color;
+}
+
+// Unshadowed contribution of a single spot light, shadowing is left up to the caller
+fn PunctualLightBindings::shade_spot(material: Material, instance_idx: u32, normal: vec3<f32>, view_dir: vec3<f32>, hit_point: vec3<f32>) -> vec3<f32> {
+    let instance: SpotLightInstance = spot_lights[instance_idx];
+
+    let to_light: vec3<f32> = instance.position - hit_point;
+    let distance: f32 = length(to_light);
+    if (distance >= instance.range) {
+        return vec3<f32>(0.0);
+    }
+    let light_dir: vec3<f32> = to_light / distance;
+
+    let n_dot_l: f32 = dot(normal, light_dir);
+    if (n_dot_l <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let cos_angle: f32 = dot(-light_dir, instance.direction);
+    let cone: f32 = sqr(saturate((cos_angle - instance.cos_outer_angle) / (instance.cos_inner_angle - instance.cos_outer_angle)));
+    if (cone <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let attenuation: f32 = punctual_light_attenuation(distance, instance.range) * cone;
+    return Material::eval_brdf(material, light_dir, view_dir, normal) * n_dot_l * attenuation * instance.color;
+}
+
+// Unshadowed contribution of a single directional light, shadowing is left up to the caller
+fn PunctualLightBindings::shade_directional(material: Material, instance_idx: u32, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    let instance: DirectionalLightInstance = directional_lights[instance_idx];
+
+    let light_dir: vec3<f32> = -instance.direction;
+    let n_dot_l: f32 = dot(normal, light_dir);
+    if (n_dot_l <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    return Material::eval_brdf(material, light_dir, view_dir, normal) * n_dot_l * instance.color;
+}
+
+// Position of a tile light, used as the target for shadow rays
+fn PunctualLightBindings::position(light_type: u32, instance_idx: u32) -> vec3<f32> {
+    if (light_type == LIGHT_TYPE_POINT) {
+        return point_lights[instance_idx].position;
+    }
+    return spot_lights[instance_idx].position;
+}
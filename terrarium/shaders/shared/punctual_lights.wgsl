@@ -0,0 +1,57 @@
+@include math.wgsl
+
+// Light types encoded in the top bits of light indices stored in a tile's light list
+const LIGHT_TYPE_AREA: u32 = 0;
+const LIGHT_TYPE_POINT: u32 = 1;
+const LIGHT_TYPE_SPOT: u32 = 2;
+const LIGHT_TYPE_SHIFT: u32 = 30;
+const LIGHT_INDEX_MASK: u32 = 0x3FFFFFFFu;
+
+fn encode_light_index(light_type: u32, light_index: u32) -> u32 {
+    return (light_type << LIGHT_TYPE_SHIFT) | (light_index & LIGHT_INDEX_MASK);
+}
+
+fn decode_light_type(encoded_light_index: u32) -> u32 {
+    return encoded_light_index >> LIGHT_TYPE_SHIFT;
+}
+
+fn decode_light_index(encoded_light_index: u32) -> u32 {
+    return encoded_light_index & LIGHT_INDEX_MASK;
+}
+
+struct PointLightInstance {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    _padding0: u32,
+}
+
+struct SpotLightInstance {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    cos_inner_angle: f32,
+    direction: vec3<f32>,
+    cos_outer_angle: f32,
+}
+
+struct DirectionalLightInstance {
+    direction: vec3<f32>,
+    _padding0: u32,
+    color: vec3<f32>,
+    _padding1: u32,
+}
+
+// Inverse square falloff, smoothly windowed to reach zero at `range`
+fn punctual_light_attenuation(distance: f32, range: f32) -> f32 {
+    let window: f32 = sqr(saturate(1.0 - sqr(sqr(distance / range))));
+    return window / (distance * distance + 1.0);
+}
+
+fn PointLightInstance::illuminated_aabb(_self: PointLightInstance) -> Aabb {
+    return Aabb::new(_self.position - vec3<f32>(_self.range), _self.position + vec3<f32>(_self.range));
+}
+
+fn SpotLightInstance::illuminated_aabb(_self: SpotLightInstance) -> Aabb {
+    return Aabb::new(_self.position - vec3<f32>(_self.range), _self.position + vec3<f32>(_self.range));
+}
@@ -0,0 +1,5 @@
+// Render resolution pixel at the center of a texel of a lower resolution target, such as reflections or global illumination
+fn ScaledResolution::render_id(scaled_id: vec2<u32>, resolution: vec2<u32>, scaled_resolution: vec2<u32>) -> vec2<u32> {
+    let position: vec2<f32> = (vec2<f32>(scaled_id) + vec2<f32>(0.5)) * vec2<f32>(resolution) / vec2<f32>(scaled_resolution);
+    return min(vec2<u32>(position), resolution - vec2<u32>(1));
+}
@@ -0,0 +1,246 @@
+@include math.wgsl
+
+// Atmosphere distances are in kilometers and its coefficients per kilometer, world space is in meters
+const METERS_TO_KILOMETERS: f32 = 0.001;
+
+struct SunInfo {
+    direction: vec3<f32>,
+    size: f32,
+    color: vec3<f32>,
+    intensity: f32,
+}
+
+struct AtmosphereInfo {
+    rayleigh_scattering: vec3<f32>,
+    rayleigh_scale_height: f32,
+    mie_scattering: vec3<f32>,
+    mie_scale_height: f32,
+    mie_absorption: vec3<f32>,
+    mie_anisotropy: f32,
+    ozone_absorption: vec3<f32>,
+    ozone_center_height: f32,
+    ground_albedo: vec3<f32>,
+    ozone_width: f32,
+    planet_radius: f32,
+    atmosphere_height: f32,
+    aerial_perspective_scale: f32,
+    _padding0: u32,
+}
+
+struct SkyConstants {
+    sun: SunInfo,
+    atmosphere: AtmosphereInfo,
+    world_up: vec3<f32>,
+    camera_altitude: f32,
+    environment_intensity: f32,
+    environment_specular_mip_count: u32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+struct AtmosphereMedium {
+    rayleigh_scattering: vec3<f32>,
+    mie_scattering: vec3<f32>,
+    extinction: vec3<f32>,
+}
+
+struct AtmosphereScattering {
+    luminance: vec3<f32>,
+    transmittance: vec3<f32>,
+}
+
+// Distance from the planet center of the camera, kept just inside the atmosphere
+fn SkyConstants::view_height(sky_constants: SkyConstants) -> f32 {
+    let atmosphere: AtmosphereInfo = sky_constants.atmosphere;
+    let altitude: f32 = clamp(sky_constants.camera_altitude * METERS_TO_KILOMETERS, 0.001, atmosphere.atmosphere_height - 0.01);
+    return atmosphere.planet_radius + altitude;
+}
+
+fn AtmosphereInfo::top_radius(atmosphere: AtmosphereInfo) -> f32 {
+    return atmosphere.planet_radius + atmosphere.atmosphere_height;
+}
+
+fn AtmosphereInfo::medium(atmosphere: AtmosphereInfo, altitude: f32) -> AtmosphereMedium {
+    let clamped_altitude: f32 = max(altitude, 0.0);
+    let rayleigh_density: f32 = exp(-clamped_altitude / atmosphere.rayleigh_scale_height);
+    let mie_density: f32 = exp(-clamped_altitude / atmosphere.mie_scale_height);
+    // Ozone is modeled as a tent shaped layer
+    let ozone_density: f32 = max(1.0 - abs(clamped_altitude - atmosphere.ozone_center_height) / (atmosphere.ozone_width * 0.5), 0.0);
+
+    var medium: AtmosphereMedium;
+    medium.rayleigh_scattering = atmosphere.rayleigh_scattering * rayleigh_density;
+    medium.mie_scattering = atmosphere.mie_scattering * mie_density;
+    medium.extinction = medium.rayleigh_scattering + medium.mie_scattering
+        + atmosphere.mie_absorption * mie_density + atmosphere.ozone_absorption * ozone_density;
+    return medium;
+}
+
+// Distance to the nearest intersection in front of the origin with a sphere around the planet center, negative on a miss
+fn AtmosphereInfo::ray_sphere_intersection(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> f32 {
+    let b: f32 = dot(origin, direction);
+    let c: f32 = dot(origin, origin) - radius * radius;
+    let discriminant: f32 = b * b - c;
+    if (discriminant < 0.0) {
+        return -1.0;
+    }
+
+    let sqrt_discriminant: f32 = sqrt(discriminant);
+    let t0: f32 = -b - sqrt_discriminant;
+    if (t0 >= 0.0) {
+        return t0;
+    }
+    return -b + sqrt_discriminant;
+}
+
+fn AtmosphereInfo::rayleigh_phase(cos_theta: f32) -> f32 {
+    return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+// Cornette-Shanks phase function
+fn AtmosphereInfo::mie_phase(g: f32, cos_theta: f32) -> f32 {
+    let g2: f32 = g * g;
+    let k: f32 = 3.0 / (8.0 * PI) * (1.0 - g2) / (2.0 + g2);
+    return k * (1.0 + cos_theta * cos_theta) / pow(max(1.0 + g2 - 2.0 * g * cos_theta, 1e-4), 1.5);
+}
+
+// Transmittance lut mapping from "Precomputed Atmospheric Scattering" by Bruneton and Neyret
+fn AtmosphereInfo::transmittance_lut_uv(atmosphere: AtmosphereInfo, view_height: f32, cos_zenith: f32) -> vec2<f32> {
+    let top_radius: f32 = AtmosphereInfo::top_radius(atmosphere);
+    let h: f32 = safe_sqrt(top_radius * top_radius - atmosphere.planet_radius * atmosphere.planet_radius);
+    let rho: f32 = safe_sqrt(view_height * view_height - atmosphere.planet_radius * atmosphere.planet_radius);
+
+    let discriminant: f32 = view_height * view_height * (cos_zenith * cos_zenith - 1.0) + top_radius * top_radius;
+    let d: f32 = max(-view_height * cos_zenith + safe_sqrt(discriminant), 0.0);
+    let d_min: f32 = top_radius - view_height;
+    let d_max: f32 = rho + h;
+
+    return vec2<f32>((d - d_min) / (d_max - d_min), rho / h);
+}
+
+// Inverse of `AtmosphereInfo::transmittance_lut_uv`, returns the view height and cosine of the zenith angle
+fn AtmosphereInfo::transmittance_lut_parameters(atmosphere: AtmosphereInfo, uv: vec2<f32>) -> vec2<f32> {
+    let top_radius: f32 = AtmosphereInfo::top_radius(atmosphere);
+    let h: f32 = safe_sqrt(top_radius * top_radius - atmosphere.planet_radius * atmosphere.planet_radius);
+    let rho: f32 = h * uv.y;
+    let view_height: f32 = sqrt(rho * rho + atmosphere.planet_radius * atmosphere.planet_radius);
+
+    let d_min: f32 = top_radius - view_height;
+    let d_max: f32 = rho + h;
+    let d: f32 = d_min + uv.x * (d_max - d_min);
+
+    var cos_zenith: f32 = 1.0;
+    if (d > 0.0) {
+        cos_zenith = (h * h - rho * rho - d * d) / (2.0 * view_height * d);
+    }
+    return vec2<f32>(view_height, clamp(cos_zenith, -1.0, 1.0));
+}
+
+// Sky view lut mapping from "A Scalable and Production Ready Sky and Atmosphere Rendering Technique" by Hillaire,
+// the horizontal axis covers the azimuth relative to the sun and the vertical axis concentrates texels around the horizon
+fn AtmosphereInfo::sky_view_lut_uv(atmosphere: AtmosphereInfo, view_height: f32, cos_view_zenith: f32, cos_light_view: f32) -> vec2<f32> {
+    let cos_beta: f32 = safe_sqrt(view_height * view_height - atmosphere.planet_radius * atmosphere.planet_radius) / view_height;
+    let beta: f32 = acos(cos_beta);
+    let zenith_horizon_angle: f32 = PI - beta;
+    let view_zenith_angle: f32 = acos(clamp(cos_view_zenith, -1.0, 1.0));
+
+    var v: f32;
+    if (view_zenith_angle < zenith_horizon_angle) {
+        v = (1.0 - safe_sqrt(1.0 - view_zenith_angle / zenith_horizon_angle)) * 0.5;
+    } else {
+        v = safe_sqrt((view_zenith_angle - zenith_horizon_angle) / beta) * 0.5 + 0.5;
+    }
+    let u: f32 = safe_sqrt(0.5 - 0.5 * cos_light_view);
+
+    return vec2<f32>(u, v);
+}
+
+// Inverse of `AtmosphereInfo::sky_view_lut_uv`, returns the cosines of the view zenith angle and the azimuth relative to the sun
+fn AtmosphereInfo::sky_view_lut_parameters(atmosphere: AtmosphereInfo, view_height: f32, uv: vec2<f32>) -> vec2<f32> {
+    let cos_beta: f32 = safe_sqrt(view_height * view_height - atmosphere.planet_radius * atmosphere.planet_radius) / view_height;
+    let beta: f32 = acos(cos_beta);
+    let zenith_horizon_angle: f32 = PI - beta;
+
+    var view_zenith_angle: f32;
+    if (uv.y < 0.5) {
+        let coord: f32 = 1.0 - 2.0 * uv.y;
+        view_zenith_angle = zenith_horizon_angle * (1.0 - coord * coord);
+    } else {
+        let coord: f32 = 2.0 * uv.y - 1.0;
+        view_zenith_angle = zenith_horizon_angle + beta * coord * coord;
+    }
+    let cos_light_view: f32 = 1.0 - 2.0 * uv.x * uv.x;
+
+    return vec2<f32>(cos(view_zenith_angle), cos_light_view);
+}
+
+fn AtmosphereInfo::sample_transmittance(atmosphere: AtmosphereInfo, transmittance_lut: texture_2d<f32>, lut_sampler: sampler,
+    view_height: f32, cos_zenith: f32) -> vec3<f32> {
+    let uv: vec2<f32> = AtmosphereInfo::transmittance_lut_uv(atmosphere, view_height, cos_zenith);
+    return textureSampleLevel(transmittance_lut, lut_sampler, uv, 0.0).rgb;
+}
+
+// Transmittance from a point relative to the planet center towards the sun, zero in the shadow of the planet
+fn AtmosphereInfo::sun_transmittance(atmosphere: AtmosphereInfo, transmittance_lut: texture_2d<f32>, lut_sampler: sampler,
+    position: vec3<f32>, to_sun: vec3<f32>) -> vec3<f32> {
+    if (AtmosphereInfo::ray_sphere_intersection(position, to_sun, atmosphere.planet_radius) >= 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let view_height: f32 = length(position);
+    let cos_zenith: f32 = dot(position / view_height, to_sun);
+    return AtmosphereInfo::sample_transmittance(atmosphere, transmittance_lut, lut_sampler, view_height, cos_zenith);
+}
+
+fn AtmosphereInfo::sample_multi_scattering(atmosphere: AtmosphereInfo, multi_scattering_lut: texture_2d<f32>, lut_sampler: sampler,
+    view_height: f32, cos_sun_zenith: f32) -> vec3<f32> {
+    let uv: vec2<f32> = vec2<f32>(
+        cos_sun_zenith * 0.5 + 0.5,
+        (view_height - atmosphere.planet_radius) / atmosphere.atmosphere_height
+    );
+    return textureSampleLevel(multi_scattering_lut, lut_sampler, clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)), 0.0).rgb;
+}
+
+// March a ray relative to the planet center until it leaves the atmosphere, hits the ground or reaches `max_distance`,
+// gathering single scattering and the multiple scattering approximation from a sun of unit illuminance
+fn AtmosphereInfo::integrate_scattering(atmosphere: AtmosphereInfo, transmittance_lut: texture_2d<f32>, multi_scattering_lut: texture_2d<f32>,
+    lut_sampler: sampler, origin: vec3<f32>, direction: vec3<f32>, to_sun: vec3<f32>, max_distance: f32, sample_count: u32) -> AtmosphereScattering {
+    var result: AtmosphereScattering;
+    result.luminance = vec3<f32>(0.0);
+    result.transmittance = vec3<f32>(1.0);
+
+    var ray_length: f32 = AtmosphereInfo::ray_sphere_intersection(origin, direction, AtmosphereInfo::top_radius(atmosphere));
+    if (ray_length < 0.0) {
+        return result;
+    }
+    let ground_distance: f32 = AtmosphereInfo::ray_sphere_intersection(origin, direction, atmosphere.planet_radius);
+    if (ground_distance >= 0.0) {
+        ray_length = min(ray_length, ground_distance);
+    }
+    ray_length = min(ray_length, max_distance);
+
+    let cos_theta: f32 = dot(direction, to_sun);
+    let rayleigh_phase: f32 = AtmosphereInfo::rayleigh_phase(cos_theta);
+    let mie_phase: f32 = AtmosphereInfo::mie_phase(atmosphere.mie_anisotropy, cos_theta);
+
+    let step_size: f32 = ray_length / f32(sample_count);
+    for (var i: u32 = 0; i < sample_count; i += 1) {
+        let position: vec3<f32> = origin + direction * (f32(i) + 0.5) * step_size;
+        let height: f32 = length(position);
+        let medium: AtmosphereMedium = AtmosphereInfo::medium(atmosphere, height - atmosphere.planet_radius);
+        let step_transmittance: vec3<f32> = exp(-medium.extinction * step_size);
+
+        let sun_transmittance: vec3<f32> = AtmosphereInfo::sun_transmittance(atmosphere, transmittance_lut, lut_sampler, position, to_sun);
+        let multi_scattering: vec3<f32> = AtmosphereInfo::sample_multi_scattering(atmosphere, multi_scattering_lut, lut_sampler,
+            height, dot(position / height, to_sun));
+
+        let in_scattering: vec3<f32> = (medium.rayleigh_scattering * rayleigh_phase + medium.mie_scattering * mie_phase) * sun_transmittance
+            + (medium.rayleigh_scattering + medium.mie_scattering) * multi_scattering;
+        // Integrate analytically over the step, which keeps long steps through dense media energy conserving
+        let step_scattering: vec3<f32> = (in_scattering - in_scattering * step_transmittance) / max(medium.extinction, vec3<f32>(1e-6));
+
+        result.luminance += result.transmittance * step_scattering;
+        result.transmittance *= step_transmittance;
+    }
+
+    return result;
+}
@@ -1,33 +1,44 @@
 @include sampling.wgsl
+@include sky.wgsl
+@include environment_map.wgsl
 
-const SUN_DISTANCE: f32 = 1e+6;
+const AERIAL_PERSPECTIVE_SAMPLE_COUNT: u32 = 8;
 
-struct SunInfo {
-    direction: vec3<f32>,
-    size: f32,
-    color: vec3<f32>,   
-    intensity: f32,
-}
+@group(3)
+@binding(0)
+var<uniform> sky_constants: SkyConstants;
 
-struct AtmosphereInfo {
-    inscattering_color: vec3<f32>,
-    density: f32,
-    density_noise_scale: f32,
-    density_noise_min: f32,
-    density_noise_max: f32,
-    _padding0: u32,
-}
+@group(3)
+@binding(1)
+var sky_transmittance_lut: texture_2d<f32>;
 
-struct SkyConstants {
-    sun: SunInfo,
-    atmosphere: AtmosphereInfo,
-    world_up: vec3<f32>,
-    _padding0: u32,
-}
+@group(3)
+@binding(2)
+var sky_multi_scattering_lut: texture_2d<f32>;
 
 @group(3)
-@binding(0)
-var<uniform> sky_constants: SkyConstants;
+@binding(3)
+var sky_view_lut: texture_2d<f32>;
+
+@group(3)
+@binding(4)
+var sky_lut_sampler: sampler;
+
+@group(3)
+@binding(5)
+var sky_environment_radiance: texture_cube<f32>;
+
+@group(3)
+@binding(6)
+var sky_environment_specular: texture_cube<f32>;
+
+@group(3)
+@binding(7)
+var<uniform> sky_environment_irradiance_sh: array<vec4<f32>, IRRADIANCE_SH_COEFFICIENT_COUNT>;
+
+@group(3)
+@binding(8)
+var sky_environment_sampler: sampler;
 
 fn Sky::sun_intensity(direction: vec3<f32>) -> f32 {
     return sky_constants.sun.intensity;
@@ -41,84 +52,87 @@ fn Sky::sun_solid_angle() -> f32 {
     return TWO_PI * (1.0 - cos(sky_constants.sun.size * 0.1));
 }
 
-fn Sky::inscattering(direction: vec3<f32>, skip_sun: bool) -> vec3<f32> {
-    const CUTOFF_ANGLE: f32 = PI / 1.95;
-    let zenith_angle_cos: f32 = dot(-sky_constants.sun.direction, sky_constants.world_up);
-    let intensity: f32 = max((clamp(zenith_angle_cos + 1.0, 0.0, 1.0) - 0.9) / 0.1, 0.0);
-    
-    let view_dir_dot_l: f32 = max(dot(direction, -sky_constants.sun.direction), 0.0);
-    var inscattering: vec3<f32> = mix(sky_constants.atmosphere.inscattering_color, vec3<f32>(1.0, 0.9, 0.7), pow(view_dir_dot_l, 8.0) * intensity);
-
-    if (!skip_sun) {
-        var intensity = Sky::sun_intensity(direction);
-
-        let l: vec3<f32> = -sky_constants.sun.direction;
-        let cos_theta: f32 = dot(direction, l);
-        let sun_angular_diameter_cos: f32 = cos(max(sky_constants.sun.size, 0.05) * 0.1);
-        let sundisk: f32 = select(0.0, 1.0, cos_theta > sun_angular_diameter_cos);
+// Cosine of the azimuth between a direction and the sun around the world up axis
+fn Sky::cos_light_view(direction: vec3<f32>, to_sun: vec3<f32>) -> f32 {
+    let up: vec3<f32> = sky_constants.world_up;
+    let horizontal_direction: vec3<f32> = direction - up * dot(direction, up);
+    let horizontal_to_sun: vec3<f32> = to_sun - up * dot(to_sun, up);
 
-       inscattering += intensity * 100.0 * sundisk * sky_constants.sun.color;
+    let length_product: f32 = length(horizontal_direction) * length(horizontal_to_sun);
+    if (length_product < 1e-6) {
+        return 1.0;
     }
-
-    return inscattering;
+    return clamp(dot(horizontal_direction, horizontal_to_sun) / length_product, -1.0, 1.0);
 }
 
-fn random3(c: vec3<f32>) -> vec3<f32> {
-    var j = 4096.0 * sin(dot(c, vec3<f32>(17.0, 59.4, 15.0)));
-    var r: vec3<f32>;
-    r.z = fract(512.0 * j);
-    j *= 0.125;
-    r.x = fract(512.0 * j);
-    j *= 0.125;
-    r.y = fract(512.0 * j);
-    return r - 0.5;
+fn Sky::has_environment_map() -> bool {
+    return sky_constants.environment_specular_mip_count > 0;
 }
 
-fn simplex3d(p: vec3<f32>) -> f32 {
-    const F3: f32 = 0.3333333;
-    const G3: f32 = 0.1666667;
+// Radiance of the sky as seen from the camera, the sun disk is colored by `SunInfo::color` as it already describes the sun light reaching the ground
+fn Sky::inscattering(direction: vec3<f32>, skip_sun: bool) -> vec3<f32> {
+    // The sun is part of the environment map, if it contains one at all
+    if (Sky::has_environment_map()) {
+        return textureSampleLevel(sky_environment_radiance, sky_environment_sampler, direction, 0.0).rgb * sky_constants.environment_intensity;
+    }
+
+    let atmosphere: AtmosphereInfo = sky_constants.atmosphere;
+    let view_height: f32 = SkyConstants::view_height(sky_constants);
+    let to_sun: vec3<f32> = -sky_constants.sun.direction;
+    let cos_view_zenith: f32 = dot(direction, sky_constants.world_up);
 
-    let s = floor(p + dot(p, vec3<f32>(F3)));
-    let x = p - s + dot(s, vec3<f32>(G3));
+    let uv: vec2<f32> = AtmosphereInfo::sky_view_lut_uv(atmosphere, view_height, cos_view_zenith, Sky::cos_light_view(direction, to_sun));
+    var inscattering: vec3<f32> = textureSampleLevel(sky_view_lut, sky_lut_sampler, uv, 0.0).rgb * sky_constants.sun.intensity;
 
-    let e = step(vec3<f32>(0.0), x - x.yzx);
-    let i1 = e * (1.0 - e.zxy);
-    let i2 = 1.0 - e.zxy * (1.0 - e);
+    if (!skip_sun) {
+        let origin = vec3<f32>(0.0, view_height, 0.0);
+        let local_direction = vec3<f32>(safe_sqrt(1.0 - cos_view_zenith * cos_view_zenith), cos_view_zenith, 0.0);
+        let occluded_by_planet: bool = AtmosphereInfo::ray_sphere_intersection(origin, local_direction, atmosphere.planet_radius) >= 0.0;
 
-    let x1 = x - i1 + vec3<f32>(G3);
-    let x2 = x - i2 + vec3<f32>(2.0 * G3);
-    let x3 = x - vec3<f32>(1.0) + vec3<f32>(3.0 * G3);
+        let cos_theta: f32 = dot(direction, to_sun);
+        let sun_angular_diameter_cos: f32 = cos(max(sky_constants.sun.size, 0.05) * 0.1);
+        let sundisk: f32 = select(0.0, 1.0, cos_theta > sun_angular_diameter_cos && !occluded_by_planet);
 
-    var w: vec4<f32>;
-    var d: vec4<f32>;
+        inscattering += Sky::sun_intensity(direction) * 100.0 * sundisk * sky_constants.sun.color;
+    }
 
-    w.x = dot(x, x);
-    w.y = dot(x1, x1);
-    w.z = dot(x2, x2);
-    w.w = dot(x3, x3);
+    return inscattering;
+}
 
-    w = max(vec4<f32>(0.6) - w, vec4<f32>(0.0));
+// Incoming radiance along a direction, prefiltered for a ggx lobe of the given roughness
+fn Sky::environment_radiance(direction: vec3<f32>, roughness: f32) -> vec3<f32> {
+    if (!Sky::has_environment_map()) {
+        return Sky::inscattering(direction, false);
+    }
 
-    d.x = dot(random3(s), x);
-    d.y = dot(random3(s + i1), x1);
-    d.z = dot(random3(s + i2), x2);
-    d.w = dot(random3(s + vec3<f32>(1.0)), x3);
+    let lod: f32 = clamp(roughness, 0.0, 1.0) * f32(sky_constants.environment_specular_mip_count - 1);
+    return textureSampleLevel(sky_environment_specular, sky_environment_sampler, direction, lod).rgb * sky_constants.environment_intensity;
+}
 
-    w = w * w;
-    w = w * w;
-    d = d * w;
+// Cosine weighted irradiance of the environment map around a normal, zero without an environment map
+fn Sky::environment_irradiance(normal: vec3<f32>) -> vec3<f32> {
+    var basis: array<f32, IRRADIANCE_SH_COEFFICIENT_COUNT> = EnvironmentMap::sh_basis(normal);
 
-    return dot(d, vec4<f32>(52.0));
+    var irradiance = vec3<f32>(0.0);
+    for (var i: u32 = 0; i < IRRADIANCE_SH_COEFFICIENT_COUNT; i += 1) {
+        irradiance += sky_environment_irradiance_sh[i].rgb * basis[i];
+    }
+    return max(irradiance, vec3<f32>(0.0)) * sky_constants.environment_intensity;
 }
 
-fn Sky::atmosphere_density(view_origin: vec3<f32>, hit_point_ws: vec3<f32>) -> f32 {
-    let noise_scale: f32 = sky_constants.atmosphere.density_noise_scale / 100.0;
+// Light scattered towards the camera and transmittance along the view ray up to a world space point
+fn Sky::aerial_perspective(view_origin: vec3<f32>, hit_point_ws: vec3<f32>) -> AtmosphereScattering {
+    let atmosphere: AtmosphereInfo = sky_constants.atmosphere;
+    let up: vec3<f32> = sky_constants.world_up;
 
-    let noise_ws: f32 = simplex3d(noise_scale * hit_point_ws) * 0.5 + 0.5;
-    let density_ws: f32 = mix(sky_constants.atmosphere.density_noise_min, sky_constants.atmosphere.density_noise_max, noise_ws);
+    let hit_distance: f32 = distance(view_origin, hit_point_ws) * METERS_TO_KILOMETERS * atmosphere.aerial_perspective_scale;
+    if (hit_distance <= 0.0) {
+        return AtmosphereScattering(vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+    let direction: vec3<f32> = normalize(hit_point_ws - view_origin);
 
-    let noise_vs: f32 = simplex3d(noise_scale * 0.3 * view_origin) * 0.5 + 0.5;
-    let density_vs: f32 = mix(0.5, 1.0, noise_vs);
-    
-    return density_ws * density_vs;
-}
\ No newline at end of file
+    var result: AtmosphereScattering = AtmosphereInfo::integrate_scattering(atmosphere, sky_transmittance_lut, sky_multi_scattering_lut, sky_lut_sampler,
+        up * SkyConstants::view_height(sky_constants), direction, -sky_constants.sun.direction, hit_distance, AERIAL_PERSPECTIVE_SAMPLE_COUNT);
+    result.luminance *= sky_constants.sun.intensity;
+    return result;
+}
@@ -5,6 +5,10 @@ struct VertexPoolSlice {
     num_vertices: u32,
     first_index: u32,
     num_indices: u32,
+    prev_first_vertex: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
 };
 
 struct Vertex {
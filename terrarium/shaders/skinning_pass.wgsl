@@ -0,0 +1,68 @@
+@include shared/vertex_pool.wgsl
+
+struct SkinVertex {
+    joint_indices: vec4<u32>,
+    joint_weights: vec4<f32>,
+}
+
+struct SkinningInstance {
+    src_first_vertex: u32,
+    dst_first_vertex: u32,
+    num_vertices: u32,
+    first_skin_vertex: u32,
+    first_joint_matrix: u32,
+    prev_first_vertex: u32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+@group(0)
+@binding(0)
+var<storage, read_write> vertices: array<PackedVertex>;
+
+@group(0)
+@binding(1)
+var<storage, read> skin_vertices: array<SkinVertex>;
+
+@group(0)
+@binding(2)
+var<storage, read> joint_matrices: array<mat4x4<f32>>;
+
+@group(0)
+@binding(3)
+var<storage, read> instances: array<SkinningInstance>;
+
+// One row of workgroups per instance, linear blend skinning from the bind pose allocation into the instance allocation
+@compute
+@workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let instance: SkinningInstance = instances[global_id.y];
+    let vertex_idx: u32 = global_id.x;
+    if (vertex_idx >= instance.num_vertices) { return; }
+
+    let vertex: Vertex = PackedVertex::unpack(vertices[instance.src_first_vertex + vertex_idx]);
+
+    // Keep last frame's result around for motion vectors, in place skinning follows the morph target pass which already did so
+    if (instance.src_first_vertex != instance.dst_first_vertex) {
+        vertices[instance.prev_first_vertex + vertex_idx] = vertices[instance.dst_first_vertex + vertex_idx];
+    }
+    let skin_vertex: SkinVertex = skin_vertices[instance.first_skin_vertex + vertex_idx];
+
+    var skin_matrix = mat4x4<f32>();
+    for (var i: u32 = 0; i < 4; i += 1) {
+        let joint_matrix: mat4x4<f32> = joint_matrices[instance.first_joint_matrix + skin_vertex.joint_indices[i]];
+        skin_matrix += joint_matrix * skin_vertex.joint_weights[i];
+    }
+
+    let position: vec3<f32> = (skin_matrix * vec4<f32>(vertex.position, 1.0)).xyz;
+    let normal: vec3<f32> = normalize((skin_matrix * vec4<f32>(vertex.normal, 0.0)).xyz);
+    let tangent: vec3<f32> = normalize((skin_matrix * vec4<f32>(vertex.tangent.xyz, 0.0)).xyz);
+
+    vertices[instance.dst_first_vertex + vertex_idx] = PackedVertex(
+        position,
+        PackedNormalizedXyz10::new(normal, 0),
+        vertex.tex_coord,
+        PackedNormalizedXyz10::new(tangent, 0),
+        vertex.tangent.w
+    );
+}
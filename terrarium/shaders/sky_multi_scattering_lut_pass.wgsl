@@ -0,0 +1,96 @@
+@include shared/sampling.wgsl
+@include shared/sky.wgsl
+
+// Directions are stratified over the sphere, each marched in a fixed number of steps
+const DIRECTION_SAMPLE_COUNT_SQRT: u32 = 8;
+const SAMPLE_COUNT: u32 = 20;
+
+@group(0)
+@binding(0)
+var<uniform> sky_constants: SkyConstants;
+
+@group(0)
+@binding(1)
+var transmittance_lut: texture_2d<f32>;
+
+@group(0)
+@binding(2)
+var lut_sampler: sampler;
+
+@group(0)
+@binding(3)
+var multi_scattering_lut: texture_storage_2d<rgba16float, write>;
+
+// Multiple scattering approximation from "A Scalable and Production Ready Sky and Atmosphere Rendering Technique" by Hillaire,
+// second order scattering towards a point is assumed to be isotropic and repeated as a geometric series of the fraction transferred each order
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let resolution: vec2<u32> = textureDimensions(multi_scattering_lut);
+    let id: vec2<u32> = global_id.xy;
+    if (any(id >= resolution)) { return; }
+
+    let uv: vec2<f32> = (vec2<f32>(id) + vec2<f32>(0.5)) / vec2<f32>(resolution);
+    let atmosphere: AtmosphereInfo = sky_constants.atmosphere;
+    let cos_sun_zenith: f32 = uv.x * 2.0 - 1.0;
+    let view_height: f32 = atmosphere.planet_radius + clamp(uv.y, 0.001, 0.999) * atmosphere.atmosphere_height;
+
+    let origin = vec3<f32>(0.0, view_height, 0.0);
+    let to_sun = vec3<f32>(safe_sqrt(1.0 - cos_sun_zenith * cos_sun_zenith), cos_sun_zenith, 0.0);
+    let top_radius: f32 = AtmosphereInfo::top_radius(atmosphere);
+
+    var second_order_luminance = vec3<f32>(0.0);
+    var transfer = vec3<f32>(0.0);
+    for (var y: u32 = 0; y < DIRECTION_SAMPLE_COUNT_SQRT; y += 1) {
+        for (var x: u32 = 0; x < DIRECTION_SAMPLE_COUNT_SQRT; x += 1) {
+            let direction_uv: vec2<f32> = (vec2<f32>(f32(x), f32(y)) + vec2<f32>(0.5)) / f32(DIRECTION_SAMPLE_COUNT_SQRT);
+            let direction: vec3<f32> = get_uniform_sphere_sample(direction_uv);
+
+            var ray_length: f32 = AtmosphereInfo::ray_sphere_intersection(origin, direction, top_radius);
+            let ground_distance: f32 = AtmosphereInfo::ray_sphere_intersection(origin, direction, atmosphere.planet_radius);
+            let hits_ground: bool = ground_distance >= 0.0;
+            if (hits_ground) {
+                ray_length = min(ray_length, ground_distance);
+            }
+
+            let step_size: f32 = ray_length / f32(SAMPLE_COUNT);
+            var luminance = vec3<f32>(0.0);
+            var transferred = vec3<f32>(0.0);
+            var transmittance = vec3<f32>(1.0);
+            for (var i: u32 = 0; i < SAMPLE_COUNT; i += 1) {
+                let position: vec3<f32> = origin + direction * (f32(i) + 0.5) * step_size;
+                let medium: AtmosphereMedium = AtmosphereInfo::medium(atmosphere, length(position) - atmosphere.planet_radius);
+                let step_transmittance: vec3<f32> = exp(-medium.extinction * step_size);
+                let scattering: vec3<f32> = medium.rayleigh_scattering + medium.mie_scattering;
+                let extinction: vec3<f32> = max(medium.extinction, vec3<f32>(1e-6));
+
+                let sun_transmittance: vec3<f32> = AtmosphereInfo::sun_transmittance(atmosphere, transmittance_lut, lut_sampler, position, to_sun);
+                let in_scattering: vec3<f32> = scattering * INV_4_PI * sun_transmittance;
+
+                luminance += transmittance * (in_scattering - in_scattering * step_transmittance) / extinction;
+                transferred += transmittance * (scattering - scattering * step_transmittance) / extinction;
+                transmittance *= step_transmittance;
+            }
+
+            // Sun light bouncing off the ground, assumed to be lambertian
+            if (hits_ground) {
+                let position: vec3<f32> = origin + direction * ray_length;
+                let normal: vec3<f32> = normalize(position);
+                let sun_transmittance: vec3<f32> = AtmosphereInfo::sample_transmittance(atmosphere, transmittance_lut, lut_sampler,
+                    length(position), dot(normal, to_sun));
+                luminance += transmittance * sun_transmittance * max(dot(normal, to_sun), 0.0) * atmosphere.ground_albedo * INV_PI;
+            }
+
+            second_order_luminance += luminance;
+            transfer += transferred;
+        }
+    }
+
+    // Integrating over the sphere with an isotropic phase function of 1 / 4 pi turns both sums into averages
+    let direction_sample_count: f32 = f32(DIRECTION_SAMPLE_COUNT_SQRT * DIRECTION_SAMPLE_COUNT_SQRT);
+    second_order_luminance /= direction_sample_count;
+    transfer /= direction_sample_count;
+
+    let multi_scattering: vec3<f32> = second_order_luminance / (vec3<f32>(1.0) - transfer);
+    textureStore(multi_scattering_lut, id, vec4<f32>(multi_scattering, 1.0));
+}
@@ -0,0 +1,39 @@
+@include shared/sky.wgsl
+
+const SAMPLE_COUNT: u32 = 40;
+
+@group(0)
+@binding(0)
+var<uniform> sky_constants: SkyConstants;
+
+@group(0)
+@binding(1)
+var transmittance_lut: texture_storage_2d<rgba16float, write>;
+
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let resolution: vec2<u32> = textureDimensions(transmittance_lut);
+    let id: vec2<u32> = global_id.xy;
+    if (any(id >= resolution)) { return; }
+
+    let uv: vec2<f32> = (vec2<f32>(id) + vec2<f32>(0.5)) / vec2<f32>(resolution);
+    let atmosphere: AtmosphereInfo = sky_constants.atmosphere;
+    let parameters: vec2<f32> = AtmosphereInfo::transmittance_lut_parameters(atmosphere, uv);
+    let view_height: f32 = parameters.x;
+    let cos_zenith: f32 = parameters.y;
+
+    let origin = vec3<f32>(0.0, view_height, 0.0);
+    let direction = vec3<f32>(safe_sqrt(1.0 - cos_zenith * cos_zenith), cos_zenith, 0.0);
+    let ray_length: f32 = max(AtmosphereInfo::ray_sphere_intersection(origin, direction, AtmosphereInfo::top_radius(atmosphere)), 0.0);
+
+    let step_size: f32 = ray_length / f32(SAMPLE_COUNT);
+    var optical_depth = vec3<f32>(0.0);
+    for (var i: u32 = 0; i < SAMPLE_COUNT; i += 1) {
+        let position: vec3<f32> = origin + direction * (f32(i) + 0.5) * step_size;
+        let medium: AtmosphereMedium = AtmosphereInfo::medium(atmosphere, length(position) - atmosphere.planet_radius);
+        optical_depth += medium.extinction * step_size;
+    }
+
+    textureStore(transmittance_lut, id, vec4<f32>(exp(-optical_depth), 1.0));
+}
@@ -0,0 +1,65 @@
+@include shared/sky.wgsl
+
+const SAMPLE_COUNT: u32 = 30;
+
+@group(0)
+@binding(0)
+var<uniform> sky_constants: SkyConstants;
+
+@group(0)
+@binding(1)
+var transmittance_lut: texture_2d<f32>;
+
+@group(0)
+@binding(2)
+var multi_scattering_lut: texture_2d<f32>;
+
+@group(0)
+@binding(3)
+var lut_sampler: sampler;
+
+@group(0)
+@binding(4)
+var sky_view_lut: texture_storage_2d<rgba16float, write>;
+
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let resolution: vec2<u32> = textureDimensions(sky_view_lut);
+    let id: vec2<u32> = global_id.xy;
+    if (any(id >= resolution)) { return; }
+
+    let uv: vec2<f32> = (vec2<f32>(id) + vec2<f32>(0.5)) / vec2<f32>(resolution);
+    let atmosphere: AtmosphereInfo = sky_constants.atmosphere;
+    let view_height: f32 = SkyConstants::view_height(sky_constants);
+    let parameters: vec2<f32> = AtmosphereInfo::sky_view_lut_parameters(atmosphere, view_height, uv);
+    let cos_view_zenith: f32 = parameters.x;
+    let cos_light_view: f32 = parameters.y;
+
+    // Local frame with the up axis along y and the sun within the xy plane
+    let cos_sun_zenith: f32 = dot(-sky_constants.sun.direction, sky_constants.world_up);
+    let to_sun = vec3<f32>(safe_sqrt(1.0 - cos_sun_zenith * cos_sun_zenith), cos_sun_zenith, 0.0);
+    let sin_view_zenith: f32 = safe_sqrt(1.0 - cos_view_zenith * cos_view_zenith);
+    let direction = vec3<f32>(
+        sin_view_zenith * cos_light_view,
+        cos_view_zenith,
+        sin_view_zenith * safe_sqrt(1.0 - cos_light_view * cos_light_view)
+    );
+    let origin = vec3<f32>(0.0, view_height, 0.0);
+
+    let scattering: AtmosphereScattering = AtmosphereInfo::integrate_scattering(atmosphere, transmittance_lut, multi_scattering_lut, lut_sampler,
+        origin, direction, to_sun, F32_MAX, SAMPLE_COUNT);
+    var luminance: vec3<f32> = scattering.luminance;
+
+    // Sun light bouncing off the ground, assumed to be lambertian
+    let ground_distance: f32 = AtmosphereInfo::ray_sphere_intersection(origin, direction, atmosphere.planet_radius);
+    if (ground_distance >= 0.0) {
+        let position: vec3<f32> = origin + direction * ground_distance;
+        let normal: vec3<f32> = normalize(position);
+        let sun_transmittance: vec3<f32> = AtmosphereInfo::sample_transmittance(atmosphere, transmittance_lut, lut_sampler,
+            length(position), dot(normal, to_sun));
+        luminance += scattering.transmittance * sun_transmittance * max(dot(normal, to_sun), 0.0) * atmosphere.ground_albedo * INV_PI;
+    }
+
+    textureStore(sky_view_lut, id, vec4<f32>(luminance, 1.0));
+}
@@ -0,0 +1,62 @@
+@include shared/math.wgsl
+
+@include shared/gbuffer_bindings.wgsl
+
+const BLUR_RADIUS: i32 = 4;
+const BLUR_SIGMA: f32 = 2.0;
+
+struct Constants {
+    resolution: vec2<u32>,
+    direction: vec2<i32>,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var src: texture_2d_array<f32>;
+
+@group(0)
+@binding(2)
+var dst: texture_storage_2d_array<r32float, write>;
+
+// Separable bilateral blur, taps are rejected across depth and normal discontinuities to keep contact shadows sharp
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let id: vec2<u32> = global_id.xy;
+    if (any(id >= constants.resolution)) { return; }
+
+    for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
+        let position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(id, view_index);
+        if (GbufferPositionAndDepth::is_sky(position_and_depth)) {
+            textureStore(dst, id, view_index, vec4<f32>(1.0));
+            continue;
+        }
+        let normal: vec3<f32> = Gbuffer::load_shading_and_geometric_normal(id, view_index).interpolated_normal;
+
+        var sum: f32 = 0.0;
+        var weight_sum: f32 = 0.0;
+        for (var i: i32 = -BLUR_RADIUS; i <= BLUR_RADIUS; i += 1) {
+            let sample_id: vec2<i32> = vec2<i32>(id) + constants.direction * i;
+            if (any(sample_id < vec2<i32>(0)) || any(sample_id >= vec2<i32>(constants.resolution))) { continue; }
+
+            let sample_position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(vec2<u32>(sample_id), view_index);
+            if (GbufferPositionAndDepth::is_sky(sample_position_and_depth)) { continue; }
+            let sample_normal: vec3<f32> = Gbuffer::load_shading_and_geometric_normal(vec2<u32>(sample_id), view_index).interpolated_normal;
+
+            let spatial_weight: f32 = exp(-f32(i * i) / (2.0 * sqr(BLUR_SIGMA)));
+            let depth_weight: f32 = exp(-abs(sample_position_and_depth.depth - position_and_depth.depth) / max(position_and_depth.depth * 0.05, 1e-4));
+            let normal_weight: f32 = pow(max(dot(sample_normal, normal), 0.0), 32.0);
+            let weight: f32 = spatial_weight * depth_weight * normal_weight;
+
+            sum += textureLoad(src, sample_id, view_index, 0).r * weight;
+            weight_sum += weight;
+        }
+
+        let occlusion_factor: f32 = select(textureLoad(src, id, view_index, 0).r, sum / weight_sum, weight_sum > 0.0);
+        textureStore(dst, id, view_index, vec4<f32>(occlusion_factor));
+    }
+}
@@ -1,109 +1,84 @@
-// @include shared/color.wgsl
-// @include shared/random.wgsl
-// @include shared/sampling.wgsl
-// @include shared/xr.wgsl
-
-// struct Constants {
-//     resolution: vec2<u32>,
-//     shadow_resolution: vec2<u32>,
-//     seed: u32,
-//     sample_count: u32,
-//     radius: f32,
-//     intensity: f32,
-//     bias: f32,
-//     _padding0: u32,
-//     _padding1: u32,
-//     _padding2: u32,
-// }
-
-// @group(0)
-// @binding(0)
-// var<uniform> constants: Constants;
-
-// @group(0)
-// @binding(1)
-// var<uniform> xr_camera: XrCamera;
-
-// @group(0)
-// @binding(2)
-// var<storage, read> gbuffer_left: array<PackedGBufferTexel>;
-// @group(0)
-// @binding(3)
-// var<storage, read> gbuffer_right: array<PackedGBufferTexel>;
-
-// @group(0)
-// @binding(4)
-// var shadow: texture_storage_2d_array<r16float, read_write>;
-
-// @compute
-// @workgroup_size(16, 16)
-// fn main(@builtin(global_invocation_id) global_id: vec3<u32>,
-//     @builtin(num_workgroups) dispatch_size: vec3<u32>) {
-//     // let shadow_id: vec2<u32> = global_id.xy;
-//     // if (any(shadow_id >= constants.shadow_resolution)) { return; }
-    
-//     // let id = vec2<u32>(
-//     //     u32(f32(constants.resolution.x) / f32(constants.shadow_resolution.x) * f32(shadow_id.x)),
-//     //     u32(f32(constants.resolution.y) / f32(constants.shadow_resolution.y) * f32(shadow_id.y))
-//     // );
-//     // let i: u32 = id.y * constants.resolution.x + id.x;
-
-//     // var rng: u32 = pcg_hash(i ^ xor_shift_u32(constants.seed));
-
-//     // for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
-//     //     var gbuffer_texel: GBufferTexel;
-//     //     if (view_index == 0) {
-//     //         gbuffer_texel = PackedGBufferTexel::unpack(gbuffer_left[i]);
-//     //     } else {
-//     //         gbuffer_texel = PackedGBufferTexel::unpack(gbuffer_right[i]);
-//     //     }
-
-//     //     if (!GBufferTexel::is_sky(gbuffer_texel)) {
-//     //         let center_point_ws: vec3<f32> = gbuffer_texel.position_ws;
-
-//     //         let tangent_to_world = mat3x3<f32>(
-//     //             gbuffer_texel.tangent_ws,
-//     //             -GBufferTexel::bitangent_ws(gbuffer_texel),
-//     //             gbuffer_texel.normal_ws
-//     //         );
-
-//     //         var occlusion: f32 = 0.0;
-
-//     //         for (var j: u32 = 0; j < constants.sample_count; j += 1) {
-//     //             var scale: f32 = f32(j) / f32(constants.sample_count);
-//     //             scale = mix(0.1, 1.0, sqr(scale));
-//     //             let sample_point_ts: vec3<f32> = get_uniform_hemisphere_sample(random_uniform_float2(&rng)) * scale * constants.radius;
-//     //             let sample_point_ws: vec3<f32> = center_point_ws + tangent_to_world * sample_point_ts;
-
-//     //             var sample_uv: vec4<f32> = xr_camera.view_to_clip_space[view_index] * xr_camera.world_to_view_space[view_index] * vec4<f32>(sample_point_ws, 1.0);
-//     //             sample_uv = (sample_uv / sample_uv.w) * 0.5 + 0.5;
-//     //             sample_uv.y = 1.0 - sample_uv.y;
-
-//     //             let sample_id: vec2<u32> = vec2<u32>(sample_uv.xy * vec2<f32>(constants.resolution));
-//     //             let sample_i = sample_id.y * constants.resolution.x + sample_id.x;
-
-//     //             var sample_gbuffer_texel: GBufferTexel;
-//     //             if (view_index == 0) {
-//     //                 sample_gbuffer_texel = PackedGBufferTexel::unpack(gbuffer_left[sample_i]);
-//     //             } else {
-//     //                 sample_gbuffer_texel = PackedGBufferTexel::unpack(gbuffer_right[sample_i]);
-//     //             }
-
-//     //             if (!GBufferTexel::is_sky(sample_gbuffer_texel)) {
-//     //                 let range_check: f32 = smoothstep(1.0, 0.0, abs(gbuffer_texel.depth_ws - sample_gbuffer_texel.depth_ws));
-//     //                 let check: f32 = saturate(dot(sample_point_ws - center_point_ws, gbuffer_texel.normal_ws) * 10.0);
-
-//     //                 if (gbuffer_texel.depth_ws >= sample_gbuffer_texel.depth_ws + constants.bias) {
-//     //                     occlusion += check * range_check;
-//     //                 }
-//     //             }
-//     //         }
-
-//     //         let occlusion_factor: f32 = mix(1.0, 1.0 - (occlusion / f32(constants.sample_count)), constants.intensity);
-
-//     //         var shadow_factor: f32 = textureLoad(shadow, shadow_id, view_index).r;
-//     //         shadow_factor = 1.0 - ((1.0 - shadow_factor) * occlusion_factor);
-//     //         textureStore(shadow, shadow_id, view_index, vec4<f32>(vec3<f32>(shadow_factor), 1.0));
-//     //     }
-//     // }
-// }
\ No newline at end of file
+@include shared/math.wgsl
+@include shared/sampling.wgsl
+@include shared/xr.wgsl
+
+@include shared/gbuffer_bindings.wgsl
+
+struct Constants {
+    resolution: vec2<u32>,
+    frame_idx: u32,
+    sample_count: u32,
+    radius: f32,
+    intensity: f32,
+    bias: f32,
+    _padding0: u32,
+}
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var<uniform> xr_camera: XrCamera;
+
+@group(0)
+@binding(2)
+var ambient_occlusion: texture_storage_2d_array<r32float, write>;
+
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let id: vec2<u32> = global_id.xy;
+    if (any(id >= constants.resolution)) { return; }
+
+    // Rotates the sample pattern per pixel and frame, the remaining noise is resolved by the blur and taa
+    let noise: f32 = interleaved_gradient_noise_animated(id, constants.frame_idx);
+
+    for (var view_index: u32 = 0; view_index < 2; view_index += 1) {
+        let position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(id, view_index);
+        if (GbufferPositionAndDepth::is_sky(position_and_depth)) {
+            textureStore(ambient_occlusion, id, view_index, vec4<f32>(1.0));
+            continue;
+        }
+
+        let normal: vec3<f32> = Gbuffer::load_shading_and_geometric_normal(id, view_index).interpolated_normal;
+        let tangent_to_world: mat3x3<f32> = build_orthonormal_basis(normal);
+
+        let view_origin: vec3<f32> = XrCamera::origin(xr_camera, view_index);
+        let world_to_clip_space: mat4x4<f32> = xr_camera.view_to_clip_space[view_index] * xr_camera.world_to_view_space[view_index];
+        let center_distance: f32 = distance(view_origin, position_and_depth.position);
+
+        var occlusion: f32 = 0.0;
+        for (var i: u32 = 0; i < constants.sample_count; i += 1) {
+            let t: f32 = (f32(i) + noise) / f32(constants.sample_count);
+            let xi = vec2<f32>(t, fract(f32(i) * GOLDEN_RATIO + noise));
+            // Distribute more samples close to the center
+            let scale: f32 = mix(0.1, 1.0, sqr(t));
+            let sample_point: vec3<f32> = position_and_depth.position + tangent_to_world * get_cosine_hemisphere_sample(xi) * scale * constants.radius;
+
+            let clip: vec4<f32> = world_to_clip_space * vec4<f32>(sample_point, 1.0);
+            if (clip.w <= 0.0) { continue; }
+            var uv: vec2<f32> = (clip.xy / clip.w) * 0.5 + 0.5;
+            uv.y = 1.0 - uv.y;
+            if (any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0))) { continue; }
+
+            let sample_id = vec2<u32>(uv * vec2<f32>(constants.resolution));
+            let sample_position_and_depth: GbufferPositionAndDepth = Gbuffer::load_position_and_depth(sample_id, view_index);
+            if (GbufferPositionAndDepth::is_sky(sample_position_and_depth)) { continue; }
+
+            let sample_distance: f32 = distance(view_origin, sample_point);
+            let surface_distance: f32 = distance(view_origin, sample_position_and_depth.position);
+            // Surfaces far in front of the sample belong to other objects and shouldn't darken this one
+            let range_check: f32 = smoothstep(0.0, 1.0, constants.radius / max(abs(center_distance - surface_distance), 1e-4));
+
+            if (surface_distance + constants.bias < sample_distance) {
+                occlusion += range_check;
+            }
+        }
+
+        let visibility: f32 = 1.0 - occlusion / f32(max(constants.sample_count, 1u));
+        let occlusion_factor: f32 = mix(1.0, visibility, constants.intensity);
+        textureStore(ambient_occlusion, id, view_index, vec4<f32>(occlusion_factor));
+    }
+}
@@ -0,0 +1,121 @@
+@include shared/xr.wgsl
+
+@include shared/vertex_pool_bindings.wgsl
+@include shared/material_pool_bindings.wgsl
+@include shared/sky_bindings.wgsl
+@include shared/punctual_light_bindings.wgsl
+
+struct Constants {
+    resolution: vec2<u32>,
+    ambient_factor: f32,
+    _padding0: u32,
+}
+
+struct PushConstant {
+    local_to_world_space: mat4x4<f32>,
+    inv_trans_local_to_world_space: mat4x4<f32>,
+}
+
+var<push_constant> pc : PushConstant;
+
+@group(0)
+@binding(0)
+var<uniform> constants: Constants;
+
+@group(0)
+@binding(1)
+var<uniform> xr_camera: XrCamera;
+
+@group(0)
+@binding(2)
+var gbuffer_position_and_depth: texture_2d_array<f32>;
+
+struct VertexOutput {
+    @builtin(position) position_cs: vec4<f32>,
+    @location(0) position_ws: vec3<f32>,
+    @location(1) normal_ws: vec3<f32>,
+    @location(2) tex_coord: vec2<f32>,
+    @location(3) @interpolate(flat) material_descriptor_idx: u32,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_idx: u32,
+    @builtin(view_index) view_index: i32
+) -> VertexOutput {
+    let vertex_slice_index: u32 = vertex_pool_vertex_slice_indices[instance_idx];
+    let vertex_pool_slice: VertexPoolSlice = vertex_pool_slices[vertex_slice_index];
+
+    let index: u32 = vertex_indices[vertex_pool_slice.first_index + vertex_index];
+    let vertex: Vertex = PackedVertex::unpack(vertices[vertex_pool_slice.first_vertex + index]);
+
+    let triangle_idx: u32 = vertex_pool_slice.first_index / 3 + vertex_index / 3;
+    let material_descriptor_idx: u32 = VertexPoolBindings::material_idx(instance_idx, triangle_idx);
+
+    let position_ws: vec3<f32> = (pc.local_to_world_space * vec4<f32>(vertex.position, 1.0)).xyz;
+    var position_cs: vec4<f32> = xr_camera.view_to_clip_space[view_index] * xr_camera.world_to_view_space[view_index] * vec4<f32>(position_ws, 1.0);
+
+    // Match the jitter of the gbuffer, otherwise the depth test against it flickers along edges
+    let jitter_ndc: vec2<f32> = vec2<f32>(-xr_camera.jitter.x, xr_camera.jitter.y) * 2.0 / vec2<f32>(constants.resolution);
+    position_cs = vec4<f32>(position_cs.xy + jitter_ndc * position_cs.w, position_cs.zw);
+
+    var result: VertexOutput;
+    result.position_cs = position_cs;
+    result.position_ws = position_ws;
+    result.normal_ws = (pc.inv_trans_local_to_world_space * vec4<f32>(vertex.normal, 0.0)).xyz;
+    result.tex_coord = vertex.tex_coord;
+    result.material_descriptor_idx = material_descriptor_idx;
+    return result;
+}
+
+fn shade_fog(shade_color: vec3<f32>, position_ws: vec3<f32>, view_origin: vec3<f32>) -> vec3<f32> {
+    let aerial_perspective: AtmosphereScattering = Sky::aerial_perspective(view_origin, position_ws);
+    return shade_color * aerial_perspective.transmittance + aerial_perspective.luminance;
+}
+
+@fragment
+fn fs_main(input: VertexOutput, @builtin(view_index) _view_index: i32) -> @location(0) vec4<f32> {
+    let view_index = u32(_view_index);
+
+    // Derivatives have to be taken in uniform control flow
+    let ddx: vec2<f32> = dpdx(input.tex_coord);
+    let ddy: vec2<f32> = dpdy(input.tex_coord);
+
+    let material_descriptor: MaterialDescriptor = material_descriptors[input.material_descriptor_idx];
+    // Opaque and masked triangles of the same mesh are already part of the gbuffer
+    if (material_descriptor.alpha_mode != ALPHA_MODE_BLEND) {
+        discard;
+    }
+
+    let origin: vec3<f32> = XrCamera::origin(xr_camera, view_index);
+    let depth: f32 = distance(origin, input.position_ws);
+
+    // There is no depth attachment, occlusion is resolved against the camera distance stored in the gbuffer
+    let gbuffer_depth: f32 = textureLoad(gbuffer_position_and_depth, vec2<u32>(input.position_cs.xy), view_index, 0).w;
+    if (gbuffer_depth > 0.0 && depth > gbuffer_depth) {
+        discard;
+    }
+
+    let material: Material = Material::from_material_descriptor(material_descriptor, input.tex_coord, ddx, ddy);
+    let view_dir: vec3<f32> = normalize(origin - input.position_ws);
+    var normal_ws: vec3<f32> = normalize(input.normal_ws);
+    if (dot(view_dir, normal_ws) < 0.0) {
+        normal_ws *= -1.0;
+    }
+
+    // Blended surfaces are lit by unshadowed punctual lights only, area lights and reflections are left out
+    var color: vec3<f32> = material.color * constants.ambient_factor * material.roughness * (1.0 - material.transmission) + material.emission;
+    for (var i: u32 = 0; i < punctual_light_constants.point_light_count; i += 1) {
+        color += PunctualLightBindings::shade_point(material, i, normal_ws, view_dir, input.position_ws);
+    }
+    for (var i: u32 = 0; i < punctual_light_constants.spot_light_count; i += 1) {
+        color += PunctualLightBindings::shade_spot(material, i, normal_ws, view_dir, input.position_ws);
+    }
+    for (var i: u32 = 0; i < punctual_light_constants.directional_light_count; i += 1) {
+        color += PunctualLightBindings::shade_directional(material, i, normal_ws, view_dir);
+    }
+    color = shade_fog(color, input.position_ws, origin);
+
+    return vec4<f32>(color, material.luminance);
+}
@@ -1,4 +1,5 @@
 use futures::executor::block_on;
+use glam::UVec2;
 use std::sync::Arc;
 use winit::{
     application::ApplicationHandler,
@@ -13,6 +14,7 @@ use crate::{
     render_passes::blit_pass::{self, BlitPassParameters},
     wgpu_util::{self},
     xr::{XrCameraData, XrCameraState},
+    Renderer,
 };
 
 pub trait AppLoop: 'static + Sized {
@@ -381,6 +383,7 @@ impl<R: AppLoop> State<R> {
         no_gpu_validation: bool,
     ) -> Self {
         let context = if let Ok(context) = wgpu_util::Context::init_with_xr(
+            R::optional_features(),
             R::required_features(),
             R::required_limits(),
             no_gpu_validation,
@@ -453,27 +456,9 @@ impl<R: AppLoop> State<R> {
         surface_config: &wgpu::SurfaceConfiguration,
         device: &wgpu::Device,
     ) -> wgpu::Texture {
-        let width = surface_config.width;
-        let height = surface_config.height;
-        let mip_level_count = (((width.max(height) as f32).log2()).floor() + 1.0) as u32;
-
-        device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("terrarium::render_target"),
-            size: wgpu::Extent3d {
-                width: surface_config.width,
-                height: surface_config.height,
-                depth_or_array_layers: 2,
-            },
-            mip_level_count,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::COPY_DST
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
-        })
+        Renderer::create_render_target(
+            UVec2::new(surface_config.width, surface_config.height),
+            device,
+        )
     }
 }
@@ -0,0 +1,260 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+/// Largest `LUT_3D_SIZE` allowed by the `.cube` specification.
+const MAX_CUBE_LUT_SIZE: u32 = 256;
+
+/// Contents of a `.cube` file, parsed without touching the gpu.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub size: u32,
+    pub domain_min: Vec3,
+    pub domain_max: Vec3,
+    /// Entries with red changing fastest.
+    pub data: Vec<[f32; 4]>,
+}
+
+impl CubeLut {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut size = None;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut data = Vec::new();
+
+        fn parse_vec3<'a>(values: impl Iterator<Item = &'a str>) -> Result<Vec3> {
+            let values = values
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<f32>, _>>()?;
+            if values.len() != 3 {
+                bail!("Expected 3 values, found {}.", values.len());
+            }
+            Ok(Vec3::from_slice(&values))
+        }
+
+        for (line_idx, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let keyword = tokens.next().unwrap();
+            let result = match keyword {
+                "LUT_1D_SIZE" => Err(anyhow::anyhow!("1D luts are not supported.")),
+                "LUT_3D_SIZE" => tokens
+                    .next()
+                    .context("Missing lut size.")
+                    .and_then(|value| Ok(value.parse::<u32>()?))
+                    .map(|value| size = Some(value)),
+                "DOMAIN_MIN" => parse_vec3(tokens).map(|value| domain_min = value),
+                "DOMAIN_MAX" => parse_vec3(tokens).map(|value| domain_max = value),
+                // Resolve's spelling of the domain, the same range on every axis
+                "LUT_3D_INPUT_RANGE" => tokens
+                    .map(|value| value.parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|values| match values[..] {
+                        [min, max] => {
+                            domain_min = Vec3::splat(min);
+                            domain_max = Vec3::splat(max);
+                            Ok(())
+                        }
+                        _ => bail!("Expected 2 values, found {}.", values.len()),
+                    }),
+                // Entries always start with a number, other keywords such as TITLE or
+                // LUT_1D_INPUT_RANGE don't affect a 3D lut
+                _ if keyword.parse::<f32>().is_err() => Ok(()),
+                _ => parse_vec3(line.split_whitespace())
+                    .map(|value| data.push([value.x, value.y, value.z, 1.0])),
+            };
+            result.with_context(|| format!("Invalid lut entry at line {}", line_idx + 1))?;
+        }
+
+        let Some(size) = size else {
+            bail!("Missing LUT_3D_SIZE.");
+        };
+        if !(2..=MAX_CUBE_LUT_SIZE).contains(&size) {
+            bail!(
+                "Lut size must be between 2 and {}, found {}.",
+                MAX_CUBE_LUT_SIZE,
+                size
+            );
+        }
+        let num_entries = size as usize * size as usize * size as usize;
+        if data.len() != num_entries {
+            bail!(
+                "Expected {} lut entries, found {}.",
+                num_entries,
+                data.len()
+            );
+        }
+        if domain_min.cmpge(domain_max).any() {
+            bail!("DOMAIN_MIN must be smaller than DOMAIN_MAX.");
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+}
+
+/// 3D lookup table applied after tonemapping, see `Renderer::set_color_grading_lut`.
+pub struct ColorGradingLut {
+    size: u32,
+    domain_min: Vec3,
+    domain_max: Vec3,
+    texture_view: wgpu::TextureView,
+}
+
+impl ColorGradingLut {
+    /// Lut that leaves colors untouched, trilinear filtering makes two entries per axis sufficient.
+    pub fn identity(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let mut data = Vec::new();
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    data.push([r as f32, g as f32, b as f32, 1.0]);
+                }
+            }
+        }
+
+        Self::new(2, Vec3::ZERO, Vec3::ONE, &data, device, queue)
+    }
+
+    /// Load an Adobe / Resolve `.cube` file containing a 3D lut.
+    pub fn from_cube_file<P: AsRef<Path>>(
+        path: P,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lut {}", path.display()))?;
+        Self::from_cube_str(&src, device, queue)
+            .with_context(|| format!("Failed to parse lut {}", path.display()))
+    }
+
+    pub fn from_cube_str(src: &str, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let lut = CubeLut::parse(src)?;
+        let max_size = device.limits().max_texture_dimension_3d;
+        if lut.size > max_size {
+            bail!(
+                "Lut size {} exceeds the maximum 3D texture size {} of the device.",
+                lut.size,
+                max_size
+            );
+        }
+        Ok(Self::new(
+            lut.size,
+            lut.domain_min,
+            lut.domain_max,
+            &lut.data,
+            device,
+            queue,
+        ))
+    }
+
+    fn new(
+        size: u32,
+        domain_min: Vec3,
+        domain_max: Vec3,
+        data: &[[f32; 4]],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        // Entries are stored with red changing fastest, matching the layout of a 3D texture
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("terrarium::color_grading_lut"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(data),
+        );
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D3),
+            ..Default::default()
+        });
+
+        Self {
+            size,
+            domain_min,
+            domain_max,
+            texture_view,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn domain_min(&self) -> Vec3 {
+        self.domain_min
+    }
+
+    pub fn domain_max(&self) -> Vec3 {
+        self.domain_max
+    }
+
+    pub fn texture_view(&self) -> &wgpu::TextureView {
+        &self.texture_view
+    }
+}
+
+#[test]
+fn parse_cube_lut() {
+    let src = "# Created by hand
+TITLE \"Invert\"
+LUT_3D_SIZE 2
+LUT_3D_INPUT_RANGE 0.0 2.0
+LUT_1D_INPUT_RANGE 0.0 1.0
+
+1.0 1.0 1.0
+0.0 1.0 1.0
+1.0 0.0 1.0
+0.0 0.0 1.0
+1.0 1.0 0.0
+0.0 1.0 0.0
+1.0 0.0 0.0
+0.0 0.0 0.0
+";
+
+    let lut = CubeLut::parse(src).unwrap();
+    assert_eq!(lut.size, 2);
+    assert_eq!(lut.domain_min, Vec3::ZERO);
+    assert_eq!(lut.domain_max, Vec3::splat(2.0));
+    assert_eq!(lut.data.len(), 8);
+    assert_eq!(lut.data[1], [0.0, 1.0, 1.0, 1.0]);
+}
+
+#[test]
+fn parse_invalid_cube_lut() {
+    assert!(CubeLut::parse("1.0 1.0 1.0").is_err());
+    assert!(CubeLut::parse("LUT_1D_SIZE 2").is_err());
+    assert!(CubeLut::parse("LUT_3D_SIZE 2\n0.0 0.0 0.0").is_err());
+    assert!(CubeLut::parse("LUT_3D_SIZE 2\n0.0 0.0").is_err());
+    assert!(CubeLut::parse("LUT_3D_SIZE 1\n0.0 0.0 0.0").is_err());
+    assert!(CubeLut::parse("LUT_3D_SIZE 4294967295\n0.0 0.0 0.0").is_err());
+    assert!(CubeLut::parse("LUT_3D_SIZE 2097152\n0.0 0.0 0.0").is_err());
+
+    let mut src = String::from("LUT_3D_SIZE 2\nDOMAIN_MIN 1 1 1\nDOMAIN_MAX 0 0 0\n");
+    src.push_str(&"0.0 0.0 0.0\n".repeat(8));
+    assert!(CubeLut::parse(&src).is_err());
+}
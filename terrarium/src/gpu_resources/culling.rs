@@ -0,0 +1,204 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::xr::XrCameraData;
+
+/// Culling applied to dynamic mesh instances and lights in `GpuResources::update`, static instances are never culled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CullingParameters {
+    /// Instances with bounds entirely beyond this distance from the camera are culled, point and spot lights once their range lies beyond it as well.
+    pub render_distance: f32,
+    /// Area lights beyond this distance from the camera are culled.
+    pub area_light_distance: f32,
+    /// Also cull instances outside the view frustum of both eyes.
+    /// These are left out of the tlas as well, meaning they no longer cast shadows or show up in reflections.
+    pub frustum_culling: bool,
+}
+
+/// Number of instances and lights submitted and culled during the last `GpuResources::update`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub submitted_instances: u32,
+    pub distance_culled_instances: u32,
+    /// Instances outside the range of their `VisibilityRangeComponent`.
+    pub range_culled_instances: u32,
+    pub frustum_culled_instances: u32,
+    /// Instances that passed culling but no longer fit within the dynamic instance limit,
+    /// plus static instances synchronized this update that no longer fit within the static instance limit.
+    pub overflowed_instances: u32,
+    /// Deformable instances that passed culling but no longer fit within the skinning or morph target limits.
+    pub deformation_overflowed_instances: u32,
+    /// Instances whose evicted mesh could not be rebuilt within the residency budget.
+    pub non_resident_instances: u32,
+    pub submitted_lights: u32,
+    pub culled_lights: u32,
+    /// Punctual lights that passed culling but no longer fit within the light limits.
+    pub overflowed_lights: u32,
+}
+
+impl CullingStats {
+    pub fn culled_instances(&self) -> u32 {
+        self.distance_culled_instances
+            + self.range_culled_instances
+            + self.frustum_culled_instances
+            + self.overflowed_instances
+            + self.deformation_overflowed_instances
+            + self.non_resident_instances
+    }
+}
+
+/// World space bounding sphere of mesh bounds transformed by `local_to_world`.
+pub fn bounding_sphere(local_to_world: Mat4, bounds_min: Vec3, bounds_max: Vec3) -> (Vec3, f32) {
+    let center = local_to_world.transform_point3((bounds_min + bounds_max) * 0.5);
+    let half_extent = (bounds_max - bounds_min) * 0.5;
+    (
+        center,
+        half_extent.length() * max_axis_scale(local_to_world),
+    )
+}
+
+/// World space bounding sphere of bind pose bounds after morphing and skinning.
+/// Morphing grows the bounds by `morph_padding` first, every skinning matrix then moves a copy of them.
+pub fn deformed_bounding_sphere(
+    local_to_world: Mat4,
+    bounds_min: Vec3,
+    bounds_max: Vec3,
+    morph_padding: f32,
+    joint_matrices: &[Mat4],
+) -> (Vec3, f32) {
+    let center = (bounds_min + bounds_max) * 0.5;
+    let mut radius = (bounds_max - bounds_min).length() * 0.5 + morph_padding;
+
+    // Skinned vertices are weighted averages of their joint transformed positions,
+    // so they remain within a sphere around the bind pose center enclosing all joint transformed bounds
+    if !joint_matrices.is_empty() {
+        radius = joint_matrices
+            .iter()
+            .map(|joint_matrix| {
+                joint_matrix.transform_point3(center).distance(center)
+                    + radius * max_axis_scale(*joint_matrix)
+            })
+            .fold(0.0, f32::max);
+    }
+
+    (
+        local_to_world.transform_point3(center),
+        radius * max_axis_scale(local_to_world),
+    )
+}
+
+// Scale of the largest axis keeps the sphere conservative under non-uniform scale
+fn max_axis_scale(matrix: Mat4) -> f32 {
+    matrix
+        .x_axis
+        .truncate()
+        .length()
+        .max(matrix.y_axis.truncate().length())
+        .max(matrix.z_axis.truncate().length())
+}
+
+/// Side planes and near plane of both eyes, the far plane is left to distance culling.
+pub(crate) struct Frustum {
+    planes: [[Vec4; 5]; 2],
+}
+
+impl Frustum {
+    pub fn new(xr_camera_data: &XrCameraData) -> Self {
+        let planes = std::array::from_fn(|i| {
+            let world_to_clip_space =
+                xr_camera_data.view_to_clip_space[i] * xr_camera_data.world_to_view_space[i];
+            let row = |j: usize| world_to_clip_space.row(j);
+
+            // Clip space depth ranges from zero to w
+            [
+                row(3) + row(0),
+                row(3) - row(0),
+                row(3) + row(1),
+                row(3) - row(1),
+                row(2),
+            ]
+            .map(|plane| plane / plane.truncate().length())
+        });
+
+        Self { planes }
+    }
+
+    /// Whether the sphere is at least partially inside the frustum of either eye.
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes.iter().any(|planes| {
+            planes
+                .iter()
+                .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+        })
+    }
+}
+
+#[test]
+fn bounding_sphere_of_scaled_bounds() {
+    let local_to_world = Mat4::from_scale_rotation_translation(
+        Vec3::new(1.0, 3.0, 1.0),
+        glam::Quat::IDENTITY,
+        Vec3::X,
+    );
+    let (center, radius) = bounding_sphere(local_to_world, Vec3::ZERO, Vec3::splat(2.0));
+    assert!(center.abs_diff_eq(Vec3::new(2.0, 3.0, 1.0), 1e-5));
+    assert!((radius - 3.0f32.sqrt() * 3.0).abs() < 1e-5);
+}
+
+#[test]
+fn deformed_bounding_sphere_encloses_joints() {
+    let (center, radius) =
+        deformed_bounding_sphere(Mat4::IDENTITY, -Vec3::ONE, Vec3::ONE, 0.5, &[]);
+    assert_eq!(center, Vec3::ZERO);
+    assert!((radius - (3.0f32.sqrt() + 0.5)).abs() < 1e-5);
+
+    let joint_matrices = [
+        Mat4::IDENTITY,
+        Mat4::from_translation(Vec3::new(0.0, 4.0, 0.0)),
+        Mat4::from_scale(Vec3::splat(2.0)),
+    ];
+    let (center, radius) =
+        deformed_bounding_sphere(Mat4::IDENTITY, -Vec3::ONE, Vec3::ONE, 0.0, &joint_matrices);
+    assert_eq!(center, Vec3::ZERO);
+    assert!((radius - (4.0 + 3.0f32.sqrt())).abs() < 1e-5);
+}
+
+#[cfg(test)]
+fn test_camera_data() -> XrCameraData {
+    let mut xr_camera_data = XrCameraData::default();
+    xr_camera_data.world_to_view_space = [Mat4::look_to_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y); 2];
+    xr_camera_data.view_to_clip_space =
+        [Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0); 2];
+    xr_camera_data
+}
+
+#[test]
+fn frustum_intersects_sphere() {
+    let frustum = Frustum::new(&test_camera_data());
+
+    assert!(frustum.intersects_sphere(Vec3::new(0.0, 0.0, -10.0), 1.0));
+    // Far away, but still within the side planes as the far plane is left to distance culling
+    assert!(frustum.intersects_sphere(Vec3::new(0.0, 0.0, -1e5), 1.0));
+    assert!(!frustum.intersects_sphere(Vec3::new(0.0, 0.0, 10.0), 1.0));
+    assert!(!frustum.intersects_sphere(Vec3::new(20.0, 0.0, -10.0), 1.0));
+    assert!(!frustum.intersects_sphere(Vec3::new(0.0, -20.0, -10.0), 1.0));
+
+    // Partially inside the right plane at a 45 degree field of view
+    assert!(frustum.intersects_sphere(Vec3::new(10.5, 0.0, -10.0), 1.0));
+    assert!(!frustum.intersects_sphere(Vec3::new(12.0, 0.0, -10.0), 1.0));
+}
+
+#[test]
+fn culled_instances_include_overflows() {
+    let culling_stats = CullingStats {
+        submitted_instances: 10,
+        distance_culled_instances: 1,
+        range_culled_instances: 2,
+        frustum_culled_instances: 3,
+        overflowed_instances: 4,
+        deformation_overflowed_instances: 5,
+        submitted_lights: 6,
+        culled_lights: 7,
+        overflowed_lights: 8,
+    };
+    assert_eq!(culling_stats.culled_instances(), 15);
+}
@@ -0,0 +1,407 @@
+use std::{ops::Range, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use ugm::mesh::Mesh;
+
+use super::GpuMesh;
+
+const MAX_EMISSIVE_INSTANCES: usize = 1024 * 4;
+const MAX_EMISSIVE_TRIANGLES: usize = 1024 * 256;
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct Constants {
+    triangle_count: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct EmissiveInstance {
+    local_to_world: Mat4,
+    /// Vertex pool instance index, used to look up the materials of its triangles.
+    instance_idx: u32,
+    vertex_slice_index: u32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+/// Single emissive triangle together with its entry of the alias table.
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct EmissiveTriangle {
+    /// Index into the emissive instances.
+    instance: u32,
+    /// Index of the triangle within its mesh.
+    triangle_idx: u32,
+    /// Probability of picking this triangle, proportional to its emitted power.
+    pdf: f32,
+    /// Probability of keeping this entry instead of switching to `alias`.
+    alias_threshold: f32,
+    alias: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+/// Instance as passed to `EmissiveLights::submit_instance`, compared against those of the previous frame to skip unchanged rebuilds.
+#[derive(Clone)]
+struct EmissiveSubmission {
+    local_to_world: Mat4,
+    instance_idx: u32,
+    vertex_slice_index: u32,
+    light_triangles: Arc<MeshLightTriangles>,
+    /// Emission of every material slot, as a range of `EmissiveLights::emissions`.
+    emissions: Range<usize>,
+}
+
+impl PartialEq for EmissiveSubmission {
+    fn eq(&self, other: &Self) -> bool {
+        self.local_to_world == other.local_to_world
+            && self.instance_idx == other.instance_idx
+            && self.vertex_slice_index == other.vertex_slice_index
+            && Arc::ptr_eq(&self.light_triangles, &other.light_triangles)
+            && self.emissions == other.emissions
+    }
+}
+
+/// Object space area of every triangle of a mesh grouped by material slot, collected once at upload
+/// so that instances with emissive materials can be turned into lights without their vertices.
+#[derive(Debug, Default)]
+pub struct MeshLightTriangles {
+    /// Triangle index and area of every non-degenerate triangle, indexed by material slot.
+    slots: Vec<Vec<(u32, f32)>>,
+}
+
+impl MeshLightTriangles {
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        let mut slots: Vec<Vec<(u32, f32)>> = Vec::new();
+        for (triangle_idx, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+            let [p0, p1, p2] = [triangle[0], triangle[1], triangle[2]]
+                .map(|i| Vec3::from(mesh.packed_vertices[i as usize].position));
+            let area = (p1 - p0).cross(p2 - p0).length() * 0.5;
+            if area <= 0.0 {
+                continue;
+            }
+
+            let slot = mesh
+                .triangle_material_indices
+                .get(triangle_idx)
+                .copied()
+                .unwrap_or(0) as usize;
+            if slots.len() <= slot {
+                slots.resize_with(slot + 1, Vec::new);
+            }
+            slots[slot].push((triangle_idx as u32, area));
+        }
+
+        Self { slots }
+    }
+}
+
+/// Gpu instances of all triangles with an emissive material, sampled in proportion to their power through an alias table.
+/// Only submitted for `RenderPath::RayTraced`, as sampling them requires ray traced visibility.
+/// Instances are submitted every frame, but the alias table is only rebuilt and uploaded when they differ from the previous frame.
+pub struct EmissiveLights {
+    constants_buffer: wgpu::Buffer,
+    instances_buffer: wgpu::Buffer,
+    triangles_buffer: wgpu::Buffer,
+    submissions: Vec<EmissiveSubmission>,
+    emissions: Vec<Vec3>,
+    prev_submissions: Vec<EmissiveSubmission>,
+    prev_emissions: Vec<Vec3>,
+    instances: Vec<EmissiveInstance>,
+    triangles: Vec<EmissiveTriangle>,
+    triangle_powers: Vec<f32>,
+
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl EmissiveLights {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let constants_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::emissive_lights constants"),
+            size: size_of::<Constants>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let instances_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::emissive_lights instances"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<EmissiveInstance>() * MAX_EMISSIVE_INSTANCES) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let triangles_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::emissive_lights triangles"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<EmissiveTriangle>() * MAX_EMISSIVE_TRIANGLES) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: constants_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instances_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: triangles_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            constants_buffer,
+            instances_buffer,
+            triangles_buffer,
+            submissions: Vec::new(),
+            emissions: Vec::new(),
+            prev_submissions: Vec::new(),
+            prev_emissions: Vec::new(),
+            instances: Vec::new(),
+            triangles: Vec::new(),
+            triangle_powers: Vec::new(),
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn write_instances(&mut self, queue: &wgpu::Queue) {
+        // The buffers still hold the alias table of the previous frame
+        if self.submissions == self.prev_submissions && self.emissions == self.prev_emissions {
+            return;
+        }
+
+        self.build_triangles();
+        if !build_alias_table(&mut self.triangles, &self.triangle_powers) {
+            self.triangles.clear();
+            self.instances.clear();
+        }
+
+        queue.write_buffer(
+            &self.constants_buffer,
+            0,
+            bytemuck::bytes_of(&Constants {
+                triangle_count: self.triangles.len() as u32,
+                _padding0: 0,
+                _padding1: 0,
+                _padding2: 0,
+            }),
+        );
+
+        queue.write_buffer(
+            &self.instances_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        queue.write_buffer(
+            &self.triangles_buffer,
+            0,
+            bytemuck::cast_slice(&self.triangles),
+        );
+    }
+
+    /// Submit a mesh instance using an emissive material for the current frame, `emissions` holds the emission of every material slot.
+    /// Instances beyond the capacity of the buffers are silently dropped.
+    pub fn submit_instance(
+        &mut self,
+        local_to_world: Mat4,
+        instance_idx: u32,
+        gpu_mesh: &GpuMesh,
+        emissions: impl Iterator<Item = Vec3>,
+    ) {
+        let first_emission = self.emissions.len();
+        self.emissions.extend(emissions);
+        self.submissions.push(EmissiveSubmission {
+            local_to_world,
+            instance_idx,
+            vertex_slice_index: gpu_mesh.vertex_pool_alloc().index,
+            light_triangles: gpu_mesh.light_triangles.clone(),
+            emissions: first_emission..self.emissions.len(),
+        });
+    }
+
+    /// Expand the submissions of this frame into their emissive triangles.
+    fn build_triangles(&mut self) {
+        self.instances.clear();
+        self.triangles.clear();
+        self.triangle_powers.clear();
+
+        for submission in &self.submissions {
+            if self.instances.len() >= MAX_EMISSIVE_INSTANCES {
+                break;
+            }
+
+            // Powers only steer the sampling, the exact world space area is computed on the gpu
+            let area_scale = submission
+                .local_to_world
+                .determinant()
+                .abs()
+                .powf(2.0 / 3.0);
+            let instance = self.instances.len() as u32;
+            let num_triangles = self.triangles.len();
+
+            for (slot, emission) in self.emissions[submission.emissions.clone()]
+                .iter()
+                .enumerate()
+            {
+                let luminance = emission.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+                if luminance <= 0.0 {
+                    continue;
+                }
+                let Some(triangles) = submission.light_triangles.slots.get(slot) else {
+                    continue;
+                };
+
+                for &(triangle_idx, area) in triangles {
+                    if self.triangles.len() >= MAX_EMISSIVE_TRIANGLES {
+                        break;
+                    }
+
+                    self.triangles.push(EmissiveTriangle {
+                        instance,
+                        triangle_idx,
+                        ..Zeroable::zeroed()
+                    });
+                    self.triangle_powers.push(luminance * area * area_scale);
+                }
+            }
+
+            if self.triangles.len() > num_triangles {
+                self.instances.push(EmissiveInstance {
+                    local_to_world: submission.local_to_world,
+                    instance_idx: submission.instance_idx,
+                    vertex_slice_index: submission.vertex_slice_index,
+                    _padding0: 0,
+                    _padding1: 0,
+                });
+            }
+        }
+    }
+
+    /// Keeps the submissions of this frame around to detect changes during the next `write_instances`.
+    pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.submissions, &mut self.prev_submissions);
+        std::mem::swap(&mut self.emissions, &mut self.prev_emissions);
+        self.submissions.clear();
+        self.emissions.clear();
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+// Vose's alias method, picking a triangle then takes a single uniform number and at most one indirection.
+// Returns false when the triangles emit no power at all.
+fn build_alias_table(triangles: &mut [EmissiveTriangle], triangle_powers: &[f32]) -> bool {
+    let total_power: f32 = triangle_powers.iter().sum();
+    if total_power <= 0.0 {
+        return false;
+    }
+
+    let num_triangles = triangles.len() as f32;
+    let mut scaled_powers: Vec<f32> = Vec::with_capacity(triangles.len());
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+    for (i, (triangle, power)) in triangles.iter_mut().zip(triangle_powers).enumerate() {
+        triangle.pdf = power / total_power;
+        triangle.alias_threshold = 1.0;
+        triangle.alias = i as u32;
+
+        let scaled_power = triangle.pdf * num_triangles;
+        scaled_powers.push(scaled_power);
+        if scaled_power < 1.0 {
+            small.push(i);
+        } else {
+            large.push(i);
+        }
+    }
+
+    while !small.is_empty() && !large.is_empty() {
+        let s = small.pop().unwrap();
+        let l = *large.last().unwrap();
+
+        triangles[s].alias_threshold = scaled_powers[s];
+        triangles[s].alias = l as u32;
+
+        scaled_powers[l] -= 1.0 - scaled_powers[s];
+        if scaled_powers[l] < 1.0 {
+            large.pop();
+            small.push(l);
+        }
+    }
+
+    true
+}
+
+#[test]
+fn alias_table_matches_pdf() {
+    let triangle_powers = [1.0, 2.0, 3.0, 4.0, 0.0, 10.0];
+    let mut triangles = vec![EmissiveTriangle::zeroed(); triangle_powers.len()];
+    assert!(build_alias_table(&mut triangles, &triangle_powers));
+
+    // Probability of ending up at every triangle when sampling the table uniformly
+    let mut probabilities = vec![0.0; triangles.len()];
+    for (i, triangle) in triangles.iter().enumerate() {
+        probabilities[i] += triangle.alias_threshold / triangles.len() as f32;
+        probabilities[triangle.alias as usize] +=
+            (1.0 - triangle.alias_threshold) / triangles.len() as f32;
+    }
+
+    let total_power: f32 = triangle_powers.iter().sum();
+    for ((triangle, power), probability) in triangles.iter().zip(triangle_powers).zip(probabilities)
+    {
+        assert!((triangle.pdf - power / total_power).abs() < 1e-6);
+        assert!((probability - triangle.pdf).abs() < 1e-5);
+    }
+
+    let mut triangles = vec![EmissiveTriangle::zeroed(); 2];
+    assert!(!build_alias_table(&mut triangles, &[0.0, 0.0]));
+}
@@ -0,0 +1,581 @@
+use std::{io::Cursor, path::Path};
+
+use anyhow::{bail, Context, Result};
+use ddsfile::{Caps2, D3DFormat, Dds, DxgiFormat, MiscFlag};
+use glam::Vec4;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    render_passes::environment_map_pass::{self, EnvironmentMapPassParameters},
+    wgpu_util::{self, PipelineDatabase},
+};
+
+const MIN_RADIANCE_RESOLUTION: u32 = 128;
+const MAX_RADIANCE_RESOLUTION: u32 = 512;
+const SPECULAR_RESOLUTION: u32 = 128;
+const SPECULAR_MIP_COUNT: u32 = 6;
+/// Second order spherical harmonics, stored as vec4s to match uniform buffer alignment.
+const IRRADIANCE_SH_COEFFICIENT_COUNT: u64 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvironmentProjection {
+    /// Latitude-longitude image with its up axis along +y.
+    Equirectangular,
+    /// Six square faces ordered +x, -x, +y, -y, +z, -z.
+    Cubemap,
+}
+
+/// High dynamic range environment decoded on the cpu, see `EnvironmentMap::new`.
+pub struct EnvironmentImage {
+    width: u32,
+    height: u32,
+    projection: EnvironmentProjection,
+    /// Linear radiance of every layer, one after another.
+    data: Vec<Vec4>,
+}
+
+impl EnvironmentImage {
+    /// Load a Radiance `.hdr` or a floating point `.dds` file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read environment map {}", path.display()))?;
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        let image = match extension.as_deref() {
+            Some("hdr") => Self::from_hdr(&bytes),
+            Some("dds") => Self::from_dds(&bytes),
+            _ => bail!("Unsupported environment map extension, expected .hdr or .dds."),
+        };
+        image.with_context(|| format!("Failed to parse environment map {}", path.display()))
+    }
+
+    /// Parse an equirectangular Radiance rgbe image, both flat and run length encoded scanlines are supported.
+    pub fn from_hdr(bytes: &[u8]) -> Result<Self> {
+        let mut cursor = 0;
+
+        if !read_hdr_line(bytes, &mut cursor)?.starts_with(b"#?") {
+            bail!("Missing Radiance header.");
+        }
+        loop {
+            let line = read_hdr_line(bytes, &mut cursor)?;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+                bail!(
+                    "Unsupported pixel format {}.",
+                    String::from_utf8_lossy(line)
+                );
+            }
+        }
+
+        let resolution = String::from_utf8_lossy(read_hdr_line(bytes, &mut cursor)?).into_owned();
+        let tokens = resolution.split_whitespace().collect::<Vec<_>>();
+        let (height, width) = match tokens.as_slice() {
+            ["-Y", height, "+X", width] => (height.parse::<u32>()?, width.parse::<u32>()?),
+            _ => bail!("Unsupported image orientation {}.", resolution),
+        };
+        check_size(width, height, EnvironmentProjection::Equirectangular)?;
+
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            read_hdr_scanline(bytes, &mut cursor, &mut scanline)
+                .with_context(|| format!("Invalid scanline {}", y))?;
+
+            data.extend(scanline.iter().map(|&[r, g, b, e]| {
+                if e == 0 {
+                    Vec4::new(0.0, 0.0, 0.0, 1.0)
+                } else {
+                    // Mantissas are stored as bytes, hence the additional 8 in the exponent bias
+                    let scale = (e as f32 - 136.0).exp2();
+                    Vec4::new(r as f32 * scale, g as f32 * scale, b as f32 * scale, 1.0)
+                }
+            }));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            projection: EnvironmentProjection::Equirectangular,
+            data,
+        })
+    }
+
+    /// Parse a 16 or 32 bit float rgba dds, either an equirectangular image or a cubemap.
+    pub fn from_dds(bytes: &[u8]) -> Result<Self> {
+        let dds = Dds::read(&mut Cursor::new(bytes))?;
+        let width = dds.get_width();
+        let height = dds.get_height();
+
+        let half_float = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+            (Some(DxgiFormat::R32G32B32A32_Float), _) | (_, Some(D3DFormat::A32B32G32R32F)) => {
+                false
+            }
+            (Some(DxgiFormat::R16G16B16A16_Float), _) | (_, Some(D3DFormat::A16B16G16R16F)) => true,
+            (dxgi_format, d3d_format) => bail!(
+                "Unsupported format {:?} {:?}, expected 16 or 32 bit float rgba.",
+                dxgi_format,
+                d3d_format
+            ),
+        };
+
+        let cubemap = dds.header.caps2.contains(Caps2::CUBEMAP)
+            || dds
+                .header10
+                .as_ref()
+                .is_some_and(|header10| header10.misc_flag.contains(MiscFlag::TEXTURECUBE));
+        let (projection, layer_count) = if cubemap {
+            (EnvironmentProjection::Cubemap, 6)
+        } else {
+            (EnvironmentProjection::Equirectangular, 1)
+        };
+        check_size(width, height, projection)?;
+
+        // Only the top mip is used, each layer is followed by its own mip chain
+        let bytes_per_texel = if half_float { 8 } else { 16 };
+        let texel_count = (width * height) as usize;
+        let layer_stride = dds.data.len() / layer_count;
+        if layer_stride < texel_count * bytes_per_texel {
+            bail!("Image data is truncated.");
+        }
+
+        let mut data = Vec::with_capacity(texel_count * layer_count);
+        for layer in 0..layer_count {
+            let texels = &dds.data[layer * layer_stride..][..texel_count * bytes_per_texel];
+            data.extend(texels.chunks_exact(bytes_per_texel).map(|texel| {
+                let channel = |i: usize| {
+                    if half_float {
+                        f16_to_f32(u16::from_le_bytes([texel[i * 2], texel[i * 2 + 1]]))
+                    } else {
+                        f32::from_le_bytes(texel[i * 4..i * 4 + 4].try_into().unwrap())
+                    }
+                };
+                // Negative and nan values would bleed into every prefiltered texel
+                Vec4::new(channel(0), channel(1), channel(2), 1.0).max(Vec4::ZERO)
+            }));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            projection,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn projection(&self) -> EnvironmentProjection {
+        self.projection
+    }
+}
+
+// Equirectangular images are projected onto faces a quarter of their width
+fn check_size(width: u32, height: u32, projection: EnvironmentProjection) -> Result<()> {
+    match projection {
+        EnvironmentProjection::Equirectangular if width < 4 || height < 2 => bail!(
+            "Equirectangular image of {}x{} is too small, expected at least 4x2.",
+            width,
+            height
+        ),
+        EnvironmentProjection::Cubemap if width == 0 || width != height => {
+            bail!("Cubemap faces must be square, found {}x{}.", width, height)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn read_hdr_line<'a>(bytes: &'a [u8], cursor: &mut usize) -> Result<&'a [u8]> {
+    let remaining = &bytes[*cursor..];
+    let end = remaining
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .context("Unexpected end of header.")?;
+    *cursor += end + 1;
+
+    let line = &remaining[..end];
+    Ok(line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+fn read_hdr_scanline(bytes: &[u8], cursor: &mut usize, scanline: &mut [[u8; 4]]) -> Result<()> {
+    let width = scanline.len();
+    let header = bytes
+        .get(*cursor..*cursor + 4)
+        .context("Unexpected end of image.")?;
+
+    // New style run length encoding stores each channel separately, flagged by a leading 2, 2
+    let run_length_encoded =
+        (8..32768).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+    if !run_length_encoded {
+        let texels = bytes
+            .get(*cursor..*cursor + width * 4)
+            .context("Unexpected end of image.")?;
+        for (texel, rgbe) in scanline.iter_mut().zip(texels.chunks_exact(4)) {
+            texel.copy_from_slice(rgbe);
+        }
+        *cursor += width * 4;
+        return Ok(());
+    }
+
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        bail!("Scanline width does not match the image width.");
+    }
+    *cursor += 4;
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(*cursor).context("Unexpected end of image.")? as usize;
+            *cursor += 1;
+
+            if count > 128 {
+                let count = count - 128;
+                let value = *bytes.get(*cursor).context("Unexpected end of image.")?;
+                *cursor += 1;
+                if x + count > width {
+                    bail!("Run exceeds the scanline.");
+                }
+                for texel in &mut scanline[x..x + count] {
+                    texel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    bail!("Invalid run length.");
+                }
+                let values = bytes
+                    .get(*cursor..*cursor + count)
+                    .context("Unexpected end of image.")?;
+                for (texel, value) in scanline[x..x + count].iter_mut().zip(values) {
+                    texel[channel] = *value;
+                }
+                *cursor += count;
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    let magnitude = match exponent {
+        0 => mantissa as f32 * (-24.0f32).exp2(),
+        31 if mantissa == 0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => f32::from_bits((exponent + 112) << 23 | mantissa << 13),
+    };
+    f32::from_bits(magnitude.to_bits() | sign)
+}
+
+/// Environment lighting prefiltered on the gpu, set through `Sky::set_environment_map`.
+/// Holds the radiance as a mipmapped cubemap, a specular cubemap with roughness increasing per mip
+/// and second order spherical harmonics of the diffuse irradiance.
+pub struct EnvironmentMap {
+    radiance_texture: wgpu::Texture,
+    specular_texture: wgpu::Texture,
+    irradiance_sh_buffer: wgpu::Buffer,
+}
+
+impl EnvironmentMap {
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+        pipeline_database: &mut PipelineDatabase,
+    ) -> Result<Self> {
+        let image = EnvironmentImage::from_file(path)?;
+        Self::new(&image, command_encoder, ctx, pipeline_database)
+    }
+
+    /// Upload `image` and encode its prefiltering into `command_encoder`.
+    pub fn new(
+        image: &EnvironmentImage,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+        pipeline_database: &mut PipelineDatabase,
+    ) -> Result<Self> {
+        let device = &ctx.device;
+
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        if image.width > max_dimension || image.height > max_dimension {
+            bail!(
+                "Environment map of {}x{} exceeds the maximum texture dimension of {}.",
+                image.width,
+                image.height,
+                max_dimension
+            );
+        }
+
+        let (source_layer_count, face_resolution) = match image.projection {
+            EnvironmentProjection::Equirectangular => (1, image.width / 4),
+            EnvironmentProjection::Cubemap => (6, image.width),
+        };
+
+        let source_texture = device.create_texture_with_data(
+            &ctx.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("terrarium::environment_map source"),
+                size: wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: source_layer_count,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            bytemuck::cast_slice(&image.data),
+        );
+
+        let radiance_resolution = face_resolution
+            .next_power_of_two()
+            .clamp(MIN_RADIANCE_RESOLUTION, MAX_RADIANCE_RESOLUTION);
+        let radiance_texture = create_cube_texture(
+            "terrarium::environment_map radiance",
+            radiance_resolution,
+            radiance_resolution.ilog2() + 1,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            device,
+        );
+        let specular_texture = create_cube_texture(
+            "terrarium::environment_map specular",
+            SPECULAR_RESOLUTION,
+            SPECULAR_MIP_COUNT,
+            wgpu::TextureUsages::STORAGE_BINDING,
+            device,
+        );
+        let irradiance_sh_buffer = create_irradiance_sh_buffer(wgpu::BufferUsages::STORAGE, device);
+
+        environment_map_pass::encode(
+            &EnvironmentMapPassParameters {
+                source_texture: &source_texture,
+                source_is_cubemap: image.projection == EnvironmentProjection::Cubemap,
+                radiance_texture: &radiance_texture,
+                specular_texture: &specular_texture,
+                irradiance_sh_buffer: &irradiance_sh_buffer,
+            },
+            device,
+            command_encoder,
+            pipeline_database,
+        );
+
+        Ok(Self {
+            radiance_texture,
+            specular_texture,
+            irradiance_sh_buffer,
+        })
+    }
+
+    /// Black placeholder bound while no environment map is set.
+    pub(crate) fn empty(device: &wgpu::Device) -> Self {
+        Self {
+            radiance_texture: create_cube_texture(
+                "terrarium::environment_map empty radiance",
+                1,
+                1,
+                wgpu::TextureUsages::empty(),
+                device,
+            ),
+            specular_texture: create_cube_texture(
+                "terrarium::environment_map empty specular",
+                1,
+                1,
+                wgpu::TextureUsages::empty(),
+                device,
+            ),
+            irradiance_sh_buffer: create_irradiance_sh_buffer(wgpu::BufferUsages::empty(), device),
+        }
+    }
+
+    pub fn radiance_texture(&self) -> &wgpu::Texture {
+        &self.radiance_texture
+    }
+
+    pub fn specular_texture(&self) -> &wgpu::Texture {
+        &self.specular_texture
+    }
+
+    pub fn irradiance_sh_buffer(&self) -> &wgpu::Buffer {
+        &self.irradiance_sh_buffer
+    }
+}
+
+fn create_cube_texture(
+    label: &str,
+    resolution: u32,
+    mip_level_count: u32,
+    usage: wgpu::TextureUsages,
+    device: &wgpu::Device,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | usage,
+        view_formats: &[],
+    })
+}
+
+fn create_irradiance_sh_buffer(usage: wgpu::BufferUsages, device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("terrarium::environment_map irradiance_sh"),
+        size: IRRADIANCE_SH_COEFFICIENT_COUNT * std::mem::size_of::<Vec4>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | usage,
+        mapped_at_creation: false,
+    })
+}
+
+#[cfg(test)]
+fn hdr_bytes(width: u32, height: u32, scanlines: &[u8]) -> Vec<u8> {
+    let mut bytes = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )
+    .into_bytes();
+    bytes.extend_from_slice(scanlines);
+    bytes
+}
+
+#[test]
+fn parse_hdr() {
+    // Flat scanline of 4 texels followed by a run length encoded one of 8
+    let mut scanlines = Vec::new();
+    for _ in 0..4 {
+        scanlines.extend_from_slice(&[128, 64, 0, 129]);
+    }
+    scanlines.extend_from_slice(&[2, 2, 0, 8]);
+    for value in [128, 64, 0, 129] {
+        scanlines.extend_from_slice(&[128 + 8, value]);
+    }
+    let image = EnvironmentImage::from_hdr(&hdr_bytes(4, 1, &scanlines[..16]));
+    assert!(image.is_err());
+
+    let image =
+        EnvironmentImage::from_hdr(&hdr_bytes(4, 2, &[&scanlines[..16]; 2].concat())).unwrap();
+    assert_eq!((image.width(), image.height()), (4, 2));
+    assert_eq!(image.projection(), EnvironmentProjection::Equirectangular);
+    assert_eq!(image.data[0], Vec4::new(1.0, 0.5, 0.0, 1.0));
+
+    let image =
+        EnvironmentImage::from_hdr(&hdr_bytes(8, 2, &[&scanlines[16..]; 2].concat())).unwrap();
+    assert_eq!(image.data.len(), 16);
+    assert!(image
+        .data
+        .iter()
+        .all(|texel| *texel == Vec4::new(1.0, 0.5, 0.0, 1.0)));
+}
+
+#[test]
+fn parse_invalid_hdr() {
+    assert!(EnvironmentImage::from_hdr(b"").is_err());
+    assert!(EnvironmentImage::from_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n").is_err());
+    assert!(EnvironmentImage::from_hdr(&hdr_bytes(0, 0, &[])).is_err());
+    assert!(EnvironmentImage::from_hdr(&hdr_bytes(4, 2, &[0; 20])).is_err());
+}
+
+#[cfg(test)]
+fn dds_bytes(
+    width: u32,
+    height: u32,
+    is_cubemap: bool,
+    format: DxgiFormat,
+    data: &[u8],
+) -> Vec<u8> {
+    let mut dds = Dds::new_dxgi(ddsfile::NewDxgiParams {
+        height,
+        width,
+        depth: None,
+        format,
+        mipmap_levels: None,
+        array_layers: None,
+        caps2: None,
+        is_cubemap,
+        resource_dimension: ddsfile::D3D10ResourceDimension::Texture2D,
+        alpha_mode: ddsfile::AlphaMode::Unknown,
+    })
+    .unwrap();
+    dds.data = data.to_vec();
+
+    let mut bytes = Vec::new();
+    dds.write(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn parse_dds() {
+    let texel = [2.0f32, -1.0, 0.5, 1.0];
+    let data: Vec<u8> = std::iter::repeat_n(texel, 6 * 4 * 4)
+        .flat_map(|texel| texel.map(f32::to_le_bytes))
+        .flatten()
+        .collect();
+
+    let image = EnvironmentImage::from_dds(&dds_bytes(
+        4,
+        4,
+        true,
+        DxgiFormat::R32G32B32A32_Float,
+        &data,
+    ))
+    .unwrap();
+    assert_eq!(image.projection(), EnvironmentProjection::Cubemap);
+    assert_eq!(image.data.len(), 6 * 4 * 4);
+    assert_eq!(image.data[0], Vec4::new(2.0, 0.0, 0.5, 1.0));
+
+    // 1.0, 0.5 and -2.0 as half floats
+    let half_data: Vec<u8> = std::iter::repeat_n([0x3c00u16, 0x3800, 0xc000, 0x3c00], 8 * 4)
+        .flat_map(|texel| texel.map(u16::to_le_bytes))
+        .flatten()
+        .collect();
+    let image = EnvironmentImage::from_dds(&dds_bytes(
+        8,
+        4,
+        false,
+        DxgiFormat::R16G16B16A16_Float,
+        &half_data,
+    ))
+    .unwrap();
+    assert_eq!(image.projection(), EnvironmentProjection::Equirectangular);
+    assert_eq!(image.data[0], Vec4::new(1.0, 0.5, 0.0, 1.0));
+}
+
+#[test]
+fn parse_invalid_dds() {
+    let format = DxgiFormat::R32G32B32A32_Float;
+    assert!(EnvironmentImage::from_dds(&dds_bytes(2, 1, false, format, &[0; 2 * 16])).is_err());
+    assert!(EnvironmentImage::from_dds(&dds_bytes(8, 4, false, format, &[0; 16])).is_err());
+    assert!(EnvironmentImage::from_dds(&dds_bytes(
+        4,
+        4,
+        false,
+        DxgiFormat::R8G8B8A8_UNorm,
+        &[0; 4 * 4 * 4]
+    ))
+    .is_err());
+}
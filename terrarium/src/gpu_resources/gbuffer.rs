@@ -1,6 +1,16 @@
 use glam::UVec2;
 
+/// Texture formats of all gbuffer textures, in binding order.
+pub const GBUFFER_FORMATS: [wgpu::TextureFormat; 5] = [
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::Rgba32Uint,
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::Rg32Float,
+    wgpu::TextureFormat::Rgba32Float,
+];
+
 pub struct Gbuffer {
+    texture_views: [wgpu::TextureView; 5],
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
 }
@@ -19,7 +29,9 @@ impl Gbuffer {
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
                 format,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
                 view_formats: &[],
             });
 
@@ -43,7 +55,7 @@ impl Gbuffer {
         let velocity_texture = create_texture("velocity", wgpu::TextureFormat::Rg32Float);
         let material_descriptor_idx_and_normal_roughness_texture = create_texture(
             "material_descriptor_idx_and_normal_roughness",
-            wgpu::TextureFormat::Rg32Float,
+            wgpu::TextureFormat::Rgba32Float,
         );
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -94,7 +106,7 @@ impl Gbuffer {
                     visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::StorageTexture {
                         access: wgpu::StorageTextureAccess::ReadWrite,
-                        format: wgpu::TextureFormat::Rg32Float,
+                        format: wgpu::TextureFormat::Rgba32Float,
                         view_dimension: wgpu::TextureViewDimension::D2Array,
                     },
                     count: None,
@@ -136,11 +148,37 @@ impl Gbuffer {
         });
 
         Self {
+            texture_views: [
+                position_and_depth_texture,
+                shading_and_geometric_normal_texture,
+                tex_coord_and_derivatives_texture,
+                velocity_texture,
+                material_descriptor_idx_and_normal_roughness_texture,
+            ],
             bind_group_layout,
             bind_group,
         }
     }
 
+    /// Color attachments for rasterizing directly into the gbuffer, in binding order. All textures are cleared to zero, marking untouched texels as sky.
+    pub fn color_attachments(&self) -> [Option<wgpu::RenderPassColorAttachment>; 5] {
+        std::array::from_fn(|i| {
+            Some(wgpu::RenderPassColorAttachment {
+                view: &self.texture_views[i],
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })
+        })
+    }
+
+    /// Sampleable view of the world space position and camera distance, zero distance marks the sky.
+    pub fn position_and_depth_view(&self) -> &wgpu::TextureView {
+        &self.texture_views[0]
+    }
+
     pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
         &self.bind_group_layout
     }
@@ -38,6 +38,14 @@ impl LinearBlockAllocator {
         }
     }
 
+    pub fn used_size(&self) -> u64 {
+        self.used_bytes
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.total_bytes
+    }
+
     pub fn allocate(&mut self, size: u64) -> Option<LinearBlockAllocation> {
         println!("USAGE: {} / {}", self.used_bytes, self.total_bytes);
 
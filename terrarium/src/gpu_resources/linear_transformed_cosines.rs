@@ -256,6 +256,34 @@ impl LinearTransformedCosines {
         assert!(self.instances.len() < MAX_INSTANCES);
     }
 
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Local to world space transform of an instance submitted this frame.
+    pub fn instance_transform(&self, instance_idx: usize) -> Mat4 {
+        let transform = &self.instances[instance_idx].transform;
+        Mat4::from_cols_array(&[
+            transform[0],
+            transform[1],
+            transform[2],
+            transform[3],
+            transform[4],
+            transform[5],
+            transform[6],
+            transform[7],
+            transform[8],
+            transform[9],
+            transform[10],
+            transform[11],
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        ])
+        .transpose()
+    }
+
     pub fn end_frame(&mut self) {
         self.instances.clear();
         self.instances_inv_transform.clear();
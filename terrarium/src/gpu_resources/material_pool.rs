@@ -1,5 +1,6 @@
-use std::{collections::HashMap, num::NonZeroU32};
+use std::{collections::HashMap, num::NonZeroU32, ops::Range};
 
+use anyhow::{bail, Result};
 use bytemuck::{Pod, Zeroable};
 use glam::{Vec2, Vec3};
 use ugm::{material::Material, texture::Texture, Model};
@@ -7,8 +8,12 @@ use uuid::Uuid;
 
 use crate::wgpu_util::empty_texture_view;
 
+use super::residency::{mip_chain_bytes, MAX_TEXTURE_UPLOADS_PER_FRAME};
+
 pub const MAX_MATERIAL_POOL_MATERIALS: usize = 1024 * 8;
 pub const MAX_MATERIAL_POOL_TEXTURES: usize = 1024;
+/// Textures are never streamed out below this resolution.
+const MIN_STREAMED_TEXTURE_SIZE: u32 = 64;
 
 #[derive(Pod, Clone, Copy, Zeroable)]
 #[repr(C)]
@@ -35,6 +40,7 @@ pub struct MaterialDescriptor {
     pub absorption: Vec3,
     pub specular: f32,
     pub specular_tint: Vec3,
+    /// Stretches specular highlights along the anisotropy direction, following KHR_materials_anisotropy.
     pub anisotropic: f32,
     pub sheen: f32,
     sheen_texture: u32,
@@ -45,22 +51,310 @@ pub struct MaterialDescriptor {
     pub alpha_cutoff: f32,
     sheen_tint_texture: u32,
     clearcoat_normal_texture: u32,
-    _padding0: u32,
-    _padding1: u32,
-    _padding2: u32,
+    alpha_mode: u32,
+    anisotropy_texture: u32,
+    /// Rotation of the anisotropy direction from the mesh tangent in radians, counter clockwise.
+    pub anisotropy_rotation: f32,
     pub sheen_tint: Vec3,
     transmission_texture: u32,
 }
 
+impl Default for MaterialDescriptor {
+    fn default() -> Self {
+        Self {
+            color: Vec3::ONE,
+            color_texture: u32::MAX,
+            metallic: 0.0,
+            roughness: 0.5,
+            metallic_roughness_texture: u32::MAX,
+            normal_scale: 1.0,
+            emission: Vec3::ZERO,
+            normal_texture: u32::MAX,
+            emission_texture: u32::MAX,
+            transmission: 0.0,
+            eta: 1.5,
+            subsurface: 0.0,
+            absorption: Vec3::ZERO,
+            specular: 1.0,
+            specular_tint: Vec3::ONE,
+            anisotropic: 0.0,
+            sheen: 0.0,
+            sheen_texture: u32::MAX,
+            clearcoat: 0.0,
+            clearcoat_texture: u32::MAX,
+            clearcoat_roughness: 0.03,
+            clearcoat_roughness_texture: u32::MAX,
+            alpha_cutoff: 0.5,
+            sheen_tint_texture: u32::MAX,
+            clearcoat_normal_texture: u32::MAX,
+            alpha_mode: AlphaMode::Opaque as u32,
+            anisotropy_texture: u32::MAX,
+            anisotropy_rotation: 0.0,
+            sheen_tint: Vec3::ONE,
+            transmission_texture: u32::MAX,
+        }
+    }
+}
+
+impl MaterialDescriptor {
+    /// Index of the pool texture bound to `slot`, `None` if the slot is empty.
+    pub fn texture(&self, slot: MaterialTextureSlot) -> Option<u32> {
+        let texture_idx = match slot {
+            MaterialTextureSlot::Color => self.color_texture,
+            MaterialTextureSlot::MetallicRoughness => self.metallic_roughness_texture,
+            MaterialTextureSlot::Normal => self.normal_texture,
+            MaterialTextureSlot::Emission => self.emission_texture,
+            MaterialTextureSlot::Transmission => self.transmission_texture,
+            MaterialTextureSlot::Sheen => self.sheen_texture,
+            MaterialTextureSlot::SheenTint => self.sheen_tint_texture,
+            MaterialTextureSlot::Clearcoat => self.clearcoat_texture,
+            MaterialTextureSlot::ClearcoatRoughness => self.clearcoat_roughness_texture,
+            MaterialTextureSlot::ClearcoatNormal => self.clearcoat_normal_texture,
+            MaterialTextureSlot::Anisotropy => self.anisotropy_texture,
+        };
+
+        (texture_idx != u32::MAX).then_some(texture_idx)
+    }
+
+    pub fn alpha_mode(&self) -> AlphaMode {
+        match self.alpha_mode {
+            1 => AlphaMode::Mask,
+            2 => AlphaMode::Blend,
+            _ => AlphaMode::Opaque,
+        }
+    }
+
+    /// Note that the blas of meshes created as opaque skip alpha testing regardless of their materials.
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) {
+        self.alpha_mode = alpha_mode as u32;
+    }
+
+    fn texture_mut(&mut self, slot: MaterialTextureSlot) -> &mut u32 {
+        match slot {
+            MaterialTextureSlot::Color => &mut self.color_texture,
+            MaterialTextureSlot::MetallicRoughness => &mut self.metallic_roughness_texture,
+            MaterialTextureSlot::Normal => &mut self.normal_texture,
+            MaterialTextureSlot::Emission => &mut self.emission_texture,
+            MaterialTextureSlot::Transmission => &mut self.transmission_texture,
+            MaterialTextureSlot::Sheen => &mut self.sheen_texture,
+            MaterialTextureSlot::SheenTint => &mut self.sheen_tint_texture,
+            MaterialTextureSlot::Clearcoat => &mut self.clearcoat_texture,
+            MaterialTextureSlot::ClearcoatRoughness => &mut self.clearcoat_roughness_texture,
+            MaterialTextureSlot::ClearcoatNormal => &mut self.clearcoat_normal_texture,
+            MaterialTextureSlot::Anisotropy => &mut self.anisotropy_texture,
+        }
+    }
+
+    fn texture_indices(&self) -> impl Iterator<Item = u32> + '_ {
+        MaterialTextureSlot::ALL
+            .iter()
+            .filter_map(|slot| self.texture(*slot))
+    }
+}
+
+/// How the alpha channel of a material's color is interpreted.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    /// Surfaces with an alpha below `MaterialDescriptor::alpha_cutoff` are discarded.
+    Mask,
+    /// Left out of the gbuffer and composited on top of the shaded image by the transparent pass.
+    Blend,
+}
+
+impl From<ugm::material::AlphaMode> for AlphaMode {
+    fn from(alpha_mode: ugm::material::AlphaMode) -> Self {
+        match alpha_mode {
+            ugm::material::AlphaMode::Opaque => Self::Opaque,
+            ugm::material::AlphaMode::Mask => Self::Mask,
+            ugm::material::AlphaMode::Blend => Self::Blend,
+        }
+    }
+}
+
+/// Texture slots of a `MaterialDescriptor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaterialTextureSlot {
+    Color,
+    MetallicRoughness,
+    Normal,
+    Emission,
+    Transmission,
+    Sheen,
+    SheenTint,
+    Clearcoat,
+    ClearcoatRoughness,
+    ClearcoatNormal,
+    /// Direction in tangent space in red and green, strength in blue.
+    Anisotropy,
+}
+
+impl MaterialTextureSlot {
+    pub const ALL: [Self; 11] = [
+        Self::Color,
+        Self::MetallicRoughness,
+        Self::Normal,
+        Self::Emission,
+        Self::Transmission,
+        Self::Sheen,
+        Self::SheenTint,
+        Self::Clearcoat,
+        Self::ClearcoatRoughness,
+        Self::ClearcoatNormal,
+        Self::Anisotropy,
+    ];
+
+    /// Color, emission and sheen tint textures are sampled as srgb, all other slots contain linear data.
+    pub fn is_srgb(&self) -> bool {
+        matches!(self, Self::Color | Self::Emission | Self::SheenTint)
+    }
+}
+
+/// Material created from code rather than loaded from a model, see `MaterialPool::build_material`.
+#[derive(Clone, Default)]
+pub struct MaterialBuilder {
+    material_descriptor: MaterialDescriptor,
+    textures: Vec<(MaterialTextureSlot, Texture)>,
+}
+
+impl MaterialBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_color(mut self, color: Vec3) -> Self {
+        self.material_descriptor.color = color;
+        self
+    }
+
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.material_descriptor.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.material_descriptor.roughness = roughness;
+        self
+    }
+
+    pub fn with_normal_scale(mut self, normal_scale: f32) -> Self {
+        self.material_descriptor.normal_scale = normal_scale;
+        self
+    }
+
+    pub fn with_emission(mut self, emission: Vec3) -> Self {
+        self.material_descriptor.emission = emission;
+        self
+    }
+
+    pub fn with_transmission(mut self, transmission: f32, eta: f32) -> Self {
+        self.material_descriptor.transmission = transmission;
+        self.material_descriptor.eta = eta;
+        self
+    }
+
+    pub fn with_subsurface(mut self, subsurface: f32, absorption: Vec3) -> Self {
+        self.material_descriptor.subsurface = subsurface;
+        self.material_descriptor.absorption = absorption;
+        self
+    }
+
+    pub fn with_specular(mut self, specular: f32, specular_tint: Vec3) -> Self {
+        self.material_descriptor.specular = specular;
+        self.material_descriptor.specular_tint = specular_tint;
+        self
+    }
+
+    pub fn with_sheen(mut self, sheen: f32, sheen_tint: Vec3) -> Self {
+        self.material_descriptor.sheen = sheen;
+        self.material_descriptor.sheen_tint = sheen_tint;
+        self
+    }
+
+    pub fn with_clearcoat(mut self, clearcoat: f32, clearcoat_roughness: f32) -> Self {
+        self.material_descriptor.clearcoat = clearcoat;
+        self.material_descriptor.clearcoat_roughness = clearcoat_roughness;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32, rotation: f32) -> Self {
+        self.material_descriptor.anisotropic = anisotropy;
+        self.material_descriptor.anisotropy_rotation = rotation;
+        self
+    }
+
+    pub fn with_alpha_cutoff(mut self, alpha_cutoff: f32) -> Self {
+        self.material_descriptor.alpha_cutoff = alpha_cutoff;
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.material_descriptor.set_alpha_mode(alpha_mode);
+        self
+    }
+
+    /// Bind `texture` to `slot`, the texture can originate from any model.
+    pub fn with_texture(mut self, slot: MaterialTextureSlot, texture: &Texture) -> Self {
+        self.textures.retain(|(other_slot, _)| *other_slot != slot);
+        self.textures.push((slot, texture.clone()));
+        self
+    }
+}
+
+/// Pool texture of which only the mips needed at the current viewing distance are resident.
+/// The cpu side texture is kept around to upload dropped mips again once they are needed.
+struct StreamedTexture {
+    source: Texture,
+    srgb: bool,
+    texture: wgpu::Texture,
+    full_size: wgpu::Extent3d,
+    full_mip_level_count: u32,
+    /// Mip of `source` that is resident as mip 0 of `texture`.
+    resident_base_mip: u32,
+    /// Coarsest mip that can be used as base, limited by `MIN_STREAMED_TEXTURE_SIZE` and block compression.
+    max_base_mip: u32,
+    /// Finest mip requested since the last `update_residency`, `u32::MAX` when unused.
+    requested_base_mip: u32,
+    last_used_frame: u64,
+}
+
+impl StreamedTexture {
+    fn resident_bytes(&self) -> u64 {
+        mip_chain_bytes(
+            self.texture.size(),
+            self.texture.format(),
+            0,
+            self.texture.mip_level_count(),
+        )
+    }
+
+    fn bytes_at_base_mip(&self, base_mip: u32) -> u64 {
+        mip_chain_bytes(
+            self.full_size,
+            self.texture.format(),
+            base_mip,
+            self.full_mip_level_count - base_mip,
+        )
+    }
+}
+
 pub struct MaterialPool {
     material_descriptor_buffer: wgpu::Buffer,
     texture_transform_buffer: wgpu::Buffer,
     sampler: wgpu::Sampler,
+    textures: Vec<StreamedTexture>,
     texture_views: Vec<wgpu::TextureView>,
     texture_indices: HashMap<Uuid, usize>,
+    texture_bytes: u64,
+    frame_idx: u64,
 
     material_descriptors: Vec<MaterialDescriptor>,
     texture_transforms: Vec<TextureTransform>,
+    // Only materials and texture transforms changed since the last `write_materials` are uploaded
+    dirty_materials: Vec<u32>,
+    dirty_texture_transforms: Vec<u32>,
 
     bind_group_layout: wgpu::BindGroupLayout,
 }
@@ -138,11 +432,16 @@ impl MaterialPool {
             material_descriptor_buffer,
             texture_transform_buffer,
             sampler,
+            textures: Vec::new(),
             texture_views: Vec::new(),
             texture_indices: HashMap::new(),
+            texture_bytes: 0,
+            frame_idx: 0,
 
             material_descriptors: Vec::new(),
             texture_transforms: Vec::new(),
+            dirty_materials: Vec::new(),
+            dirty_texture_transforms: Vec::new(),
             bind_group_layout,
         }
     }
@@ -151,16 +450,59 @@ impl MaterialPool {
         &mut self,
         model_texture: &Texture,
         srgb: bool,
+        texture_budget_bytes: u64,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-    ) -> u32 {
-        let (_texture, texture_view) = model_texture.create_wgpu_texture(
-            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<u32> {
+        if self.texture_views.len() >= MAX_MATERIAL_POOL_TEXTURES {
+            bail!(
+                "Material pool ran out of textures, at most {} are supported.",
+                MAX_MATERIAL_POOL_TEXTURES
+            );
+        }
+
+        let (full_size, format, full_mip_level_count) = Self::source_layout(model_texture);
+        let max_base_mip = Self::max_base_mip(full_size, format, full_mip_level_count);
+
+        // Fall back to only the coarsest mips when the full texture doesn't fit
+        let full_bytes = mip_chain_bytes(full_size, format, 0, full_mip_level_count);
+        let base_mip = if self.make_room(full_bytes, texture_budget_bytes, device, command_encoder)
+        {
+            0
+        } else {
+            let tail_bytes = mip_chain_bytes(
+                full_size,
+                format,
+                max_base_mip,
+                full_mip_level_count - max_base_mip,
+            );
+            if !self.make_room(tail_bytes, texture_budget_bytes, device, command_encoder) {
+                bail!(
+                    "Texture of {} bytes does not fit in the texture budget of {} bytes.",
+                    tail_bytes,
+                    texture_budget_bytes
+                );
+            }
+            max_base_mip
+        };
+
+        let (texture, texture_view) =
+            Self::upload_texture(model_texture, srgb, base_mip, device, queue);
+        let streamed_texture = StreamedTexture {
+            source: model_texture.clone(),
             srgb,
-            device,
-            queue,
-        );
+            texture,
+            full_size,
+            full_mip_level_count,
+            resident_base_mip: base_mip,
+            max_base_mip,
+            requested_base_mip: u32::MAX,
+            last_used_frame: self.frame_idx,
+        };
 
+        self.texture_bytes += streamed_texture.resident_bytes();
+        self.textures.push(streamed_texture);
         self.texture_views.push(texture_view);
         let texture_idx = self.texture_views.len() - 1;
 
@@ -168,10 +510,343 @@ impl MaterialPool {
             uv_offset: model_texture.uv_offset().into(),
             uv_scale: model_texture.uv_scale().into(),
         });
+        self.dirty_texture_transforms.push(texture_idx as u32);
 
         self.texture_indices
             .insert(model_texture.uuid(), texture_idx);
-        texture_idx as u32
+        Ok(texture_idx as u32)
+    }
+
+    /// Size, format and mip count of the full mip chain of `source`.
+    fn source_layout(source: &Texture) -> (wgpu::Extent3d, wgpu::TextureFormat, u32) {
+        (
+            wgpu::Extent3d {
+                width: source.width(),
+                height: source.height(),
+                depth_or_array_layers: 1,
+            },
+            source.wgpu_format(),
+            source.mip_level_count(),
+        )
+    }
+
+    /// Upload `source` with `base_mip` as its finest mip, the mips before it are never read from the cpu side texture.
+    fn upload_texture(
+        source: &Texture,
+        srgb: bool,
+        base_mip: u32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let (full_size, format, full_mip_level_count) = Self::source_layout(source);
+
+        let texture = Self::create_streamed_texture(
+            full_size.mip_level_size(base_mip, wgpu::TextureDimension::D2),
+            full_mip_level_count - base_mip,
+            format,
+            srgb,
+            device,
+        );
+
+        // Mips are stored tightly packed from fine to coarse, skip straight to the first one needed
+        let data = &source.data()[mip_chain_bytes(full_size, format, 0, base_mip) as usize..];
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format.block_copy_size(None).unwrap_or(4);
+        let mut offset = 0;
+        for mip in 0..texture.mip_level_count() {
+            let mip_size = texture
+                .size()
+                .mip_level_size(mip, wgpu::TextureDimension::D2)
+                .physical_size(format);
+            let bytes_per_row = mip_size.width / block_width * block_size;
+            let rows = mip_size.height / block_height;
+
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: mip,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &data[offset..offset + (bytes_per_row * rows) as usize],
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(rows),
+                },
+                mip_size,
+            );
+            offset += (bytes_per_row * rows) as usize;
+        }
+
+        let texture_view = Self::create_streamed_texture_view(&texture, srgb);
+        (texture, texture_view)
+    }
+
+    fn create_streamed_texture(
+        size: wgpu::Extent3d,
+        mip_level_count: u32,
+        format: wgpu::TextureFormat,
+        srgb: bool,
+        device: &wgpu::Device,
+    ) -> wgpu::Texture {
+        let view_format = if srgb {
+            format.add_srgb_suffix()
+        } else {
+            format
+        };
+
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("terrarium::material_pool streamed_texture"),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: if view_format != format {
+                &[view_format]
+            } else {
+                &[]
+            },
+        })
+    }
+
+    fn create_streamed_texture_view(texture: &wgpu::Texture, srgb: bool) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(if srgb {
+                texture.format().add_srgb_suffix()
+            } else {
+                texture.format()
+            }),
+            ..Default::default()
+        })
+    }
+
+    /// Copy all mips of `texture` starting at `first_mip` into a new, smaller texture.
+    fn copy_mips(
+        texture: &wgpu::Texture,
+        first_mip: u32,
+        srgb: bool,
+        device: &wgpu::Device,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let format = texture.format();
+        let mip_level_count = texture.mip_level_count() - first_mip;
+
+        let streamed_texture = Self::create_streamed_texture(
+            texture
+                .size()
+                .mip_level_size(first_mip, wgpu::TextureDimension::D2),
+            mip_level_count,
+            format,
+            srgb,
+            device,
+        );
+
+        for mip in 0..mip_level_count {
+            command_encoder.copy_texture_to_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: first_mip + mip,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyTextureInfo {
+                    texture: &streamed_texture,
+                    mip_level: mip,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                texture
+                    .size()
+                    .mip_level_size(first_mip + mip, wgpu::TextureDimension::D2)
+                    .physical_size(format),
+            );
+        }
+
+        let texture_view = Self::create_streamed_texture_view(&streamed_texture, srgb);
+        (streamed_texture, texture_view)
+    }
+
+    fn max_base_mip(
+        full_size: wgpu::Extent3d,
+        format: wgpu::TextureFormat,
+        full_mip_level_count: u32,
+    ) -> u32 {
+        let (block_width, block_height) = format.block_dimensions();
+
+        // A streamed texture starts at its base mip, so that mip has to be a valid texture size on its own
+        (0..full_mip_level_count)
+            .take_while(|mip| {
+                let size = full_size.mip_level_size(*mip, wgpu::TextureDimension::D2);
+                size.width % block_width == 0
+                    && size.height % block_height == 0
+                    && size.width.min(size.height) >= MIN_STREAMED_TEXTURE_SIZE
+            })
+            .last()
+            .unwrap_or(0)
+    }
+
+    /// Drop mips of `texture_idx` up until `base_mip`, without having to upload anything.
+    fn trim_texture(
+        &mut self,
+        texture_idx: usize,
+        base_mip: u32,
+        device: &wgpu::Device,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let streamed_texture = &mut self.textures[texture_idx];
+        debug_assert!(base_mip > streamed_texture.resident_base_mip);
+
+        let (texture, texture_view) = Self::copy_mips(
+            &streamed_texture.texture,
+            base_mip - streamed_texture.resident_base_mip,
+            streamed_texture.srgb,
+            device,
+            command_encoder,
+        );
+
+        self.texture_bytes -= streamed_texture.resident_bytes();
+        streamed_texture.texture = texture;
+        streamed_texture.resident_base_mip = base_mip;
+        self.texture_bytes += streamed_texture.resident_bytes();
+        self.texture_views[texture_idx] = texture_view;
+    }
+
+    /// Trim textures until `bytes` more fit within `texture_budget_bytes`, returns false if that's impossible.
+    /// Textures unused this frame are trimmed first in least recently used order, followed by textures with more mips resident than requested.
+    fn make_room(
+        &mut self,
+        bytes: u64,
+        texture_budget_bytes: u64,
+        device: &wgpu::Device,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> bool {
+        while self.texture_bytes + bytes > texture_budget_bytes {
+            let least_recently_used = self
+                .textures
+                .iter()
+                .enumerate()
+                .filter(|(_, texture)| {
+                    texture.last_used_frame < self.frame_idx
+                        && texture.resident_base_mip < texture.max_base_mip
+                })
+                .min_by_key(|(_, texture)| texture.last_used_frame)
+                .map(|(i, texture)| (i, texture.max_base_mip));
+
+            let over_resident = || {
+                self.textures
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, texture)| {
+                        texture.requested_base_mip != u32::MAX
+                            && texture.requested_base_mip.min(texture.max_base_mip)
+                                > texture.resident_base_mip
+                    })
+                    .max_by_key(|(_, texture)| {
+                        texture.requested_base_mip.min(texture.max_base_mip)
+                            - texture.resident_base_mip
+                    })
+                    .map(|(i, texture)| (i, texture.requested_base_mip.min(texture.max_base_mip)))
+            };
+
+            let Some((texture_idx, base_mip)) = least_recently_used.or_else(over_resident) else {
+                return false;
+            };
+            self.trim_texture(texture_idx, base_mip, device, command_encoder);
+        }
+
+        true
+    }
+
+    /// Trim textures until they fit within `texture_budget_bytes`, returns false if that's impossible.
+    pub fn fit_texture_budget(
+        &mut self,
+        texture_budget_bytes: u64,
+        device: &wgpu::Device,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> bool {
+        self.make_room(0, texture_budget_bytes, device, command_encoder)
+    }
+
+    /// Request the textures of `material_idx` to be resident from `base_mip` onwards, see `update_residency`.
+    pub fn request_material(&mut self, material_idx: u32, base_mip: u32) {
+        let Some(material_descriptor) = self.material_descriptors.get(material_idx as usize) else {
+            return;
+        };
+
+        for texture_idx in material_descriptor.texture_indices() {
+            if let Some(texture) = self.textures.get_mut(texture_idx as usize) {
+                texture.requested_base_mip = texture.requested_base_mip.min(base_mip);
+                texture.last_used_frame = self.frame_idx;
+            }
+        }
+    }
+
+    /// Upload the mips requested through `request_material` since the last call, evicting mips of other textures to stay within budget.
+    /// Textures that don't fit remain at their current resolution until room is available.
+    pub fn update_residency(
+        &mut self,
+        texture_budget_bytes: u64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let mut texture_indices: Vec<usize> = (0..self.textures.len())
+            .filter(|i| {
+                let texture = &self.textures[*i];
+                texture.requested_base_mip.min(texture.max_base_mip) < texture.resident_base_mip
+            })
+            .collect();
+        texture_indices.sort_by_key(|i| self.textures[*i].requested_base_mip);
+
+        for texture_idx in texture_indices
+            .into_iter()
+            .take(MAX_TEXTURE_UPLOADS_PER_FRAME)
+        {
+            let texture = &self.textures[texture_idx];
+            let base_mip = texture.requested_base_mip.min(texture.max_base_mip);
+            let additional_bytes = texture
+                .bytes_at_base_mip(base_mip)
+                .saturating_sub(texture.resident_bytes());
+
+            if !self.make_room(
+                additional_bytes,
+                texture_budget_bytes,
+                device,
+                command_encoder,
+            ) {
+                continue;
+            }
+
+            let streamed_texture = &mut self.textures[texture_idx];
+            let (texture, texture_view) = Self::upload_texture(
+                &streamed_texture.source,
+                streamed_texture.srgb,
+                base_mip,
+                device,
+                queue,
+            );
+
+            self.texture_bytes -= streamed_texture.resident_bytes();
+            streamed_texture.texture = texture;
+            streamed_texture.resident_base_mip = base_mip;
+            self.texture_bytes += streamed_texture.resident_bytes();
+            self.texture_views[texture_idx] = texture_view;
+        }
+
+        for texture in &mut self.textures {
+            texture.requested_base_mip = u32::MAX;
+        }
+        self.frame_idx += 1;
+    }
+
+    /// Gpu memory currently taken up by resident texture mips.
+    pub fn texture_bytes(&self) -> u64 {
+        self.texture_bytes
     }
 
     pub fn material_count(&self) -> usize {
@@ -182,66 +857,198 @@ impl MaterialPool {
         &self.material_descriptors[i as usize]
     }
 
+    /// Whether any material changed since the last `write_materials`.
+    pub fn has_dirty_materials(&self) -> bool {
+        !self.dirty_materials.is_empty()
+    }
+
+    /// Mutable access to a material, it is uploaded again on the next `write_materials`.
     pub fn material_descriptor_mut(&mut self, i: u32) -> &mut MaterialDescriptor {
+        self.dirty_materials.push(i);
         &mut self.material_descriptors[i as usize]
     }
 
-    pub fn alloc_material(
+    /// Pool index of `texture`, uploading it first when it isn't part of the pool yet.
+    fn texture_index(
         &mut self,
-        model: &Model,
-        material: &Material,
+        texture: &Texture,
+        srgb: bool,
+        texture_budget_bytes: u64,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-    ) -> u32 {
-        let color_texture = if let Some(texture_idx) = &material.color_texture {
-            let texture = &model.textures[*texture_idx as usize];
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<u32> {
+        if let Some(texture_idx) = self.texture_indices.get(&texture.uuid()) {
+            Ok(*texture_idx as u32)
+        } else {
+            self.alloc_texture(
+                texture,
+                srgb,
+                texture_budget_bytes,
+                device,
+                queue,
+                command_encoder,
+            )
+        }
+    }
 
-            if let Some(texture_idx) = self.texture_indices.get(&texture.uuid()) {
-                *texture_idx as u32
-            } else {
-                self.alloc_texture(texture, true, device, queue)
-            }
+    /// Bind `texture` to `slot` of material `i`, or clear the slot when `None`.
+    pub fn set_material_texture(
+        &mut self,
+        i: u32,
+        slot: MaterialTextureSlot,
+        texture: Option<&Texture>,
+        texture_budget_bytes: u64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<()> {
+        if i as usize >= self.material_descriptors.len() {
+            bail!("Material {} does not exist.", i);
+        }
+
+        let texture_idx = if let Some(texture) = texture {
+            self.texture_index(
+                texture,
+                slot.is_srgb(),
+                texture_budget_bytes,
+                device,
+                queue,
+                command_encoder,
+            )?
         } else {
             u32::MAX
         };
 
-        let metallic_roughness_texture =
-            if let Some(texture_idx) = &material.metallic_roughness_texture {
-                let texture = &model.textures[*texture_idx as usize];
+        *self.material_descriptor_mut(i).texture_mut(slot) = texture_idx;
+        Ok(())
+    }
 
-                if let Some(texture_idx) = self.texture_indices.get(&texture.uuid()) {
-                    *texture_idx as u32
-                } else {
-                    self.alloc_texture(texture, false, device, queue)
-                }
-            } else {
-                u32::MAX
-            };
+    pub fn build_material(
+        &mut self,
+        builder: &MaterialBuilder,
+        texture_budget_bytes: u64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<u32> {
+        if self.material_descriptors.len() >= MAX_MATERIAL_POOL_MATERIALS {
+            bail!(
+                "Material pool ran out of materials, at most {} are supported.",
+                MAX_MATERIAL_POOL_MATERIALS
+            );
+        }
 
-        let normal_texture = if let Some(texture_idx) = &material.normal_texture {
-            let texture = &model.textures[*texture_idx as usize];
+        let mut material_descriptor = builder.material_descriptor;
+        for (slot, texture) in &builder.textures {
+            *material_descriptor.texture_mut(*slot) = self.texture_index(
+                texture,
+                slot.is_srgb(),
+                texture_budget_bytes,
+                device,
+                queue,
+                command_encoder,
+            )?;
+        }
 
-            if let Some(texture_idx) = self.texture_indices.get(&texture.uuid()) {
-                *texture_idx as u32
-            } else {
-                self.alloc_texture(texture, false, device, queue)
-            }
-        } else {
-            u32::MAX
-        };
+        self.material_descriptors.push(material_descriptor);
+        let material_idx = self.material_descriptors.len() as u32 - 1;
+        self.dirty_materials.push(material_idx);
+        Ok(material_idx)
+    }
 
-        let emission_texture = if let Some(texture_idx) = &material.emission_texture {
-            let texture = &model.textures[*texture_idx as usize];
+    pub fn alloc_material(
+        &mut self,
+        model: &Model,
+        material: &Material,
+        texture_budget_bytes: u64,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<u32> {
+        if self.material_descriptors.len() >= MAX_MATERIAL_POOL_MATERIALS {
+            bail!(
+                "Material pool ran out of materials, at most {} are supported.",
+                MAX_MATERIAL_POOL_MATERIALS
+            );
+        }
 
-            if let Some(texture_idx) = self.texture_indices.get(&texture.uuid()) {
-                *texture_idx as u32
+        let mut model_texture_index = |texture: Option<&Texture>, slot: MaterialTextureSlot| {
+            if let Some(texture) = texture {
+                self.texture_index(
+                    texture,
+                    slot.is_srgb(),
+                    texture_budget_bytes,
+                    device,
+                    queue,
+                    command_encoder,
+                )
             } else {
-                self.alloc_texture(texture, true, device, queue)
+                Ok(u32::MAX)
             }
-        } else {
-            u32::MAX
         };
 
+        let color_texture = model_texture_index(
+            material
+                .color_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::Color,
+        )?;
+        let metallic_roughness_texture = model_texture_index(
+            material
+                .metallic_roughness_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::MetallicRoughness,
+        )?;
+        let normal_texture = model_texture_index(
+            material
+                .normal_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::Normal,
+        )?;
+        let emission_texture = model_texture_index(
+            material
+                .emission_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::Emission,
+        )?;
+        let transmission_texture = model_texture_index(
+            material
+                .transmission_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::Transmission,
+        )?;
+        let sheen_texture = model_texture_index(
+            material
+                .sheen_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::Sheen,
+        )?;
+        let sheen_tint_texture = model_texture_index(
+            material
+                .sheen_tint_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::SheenTint,
+        )?;
+        let clearcoat_texture = model_texture_index(
+            material
+                .clearcoat_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::Clearcoat,
+        )?;
+        let clearcoat_roughness_texture = model_texture_index(
+            material
+                .clearcoat_roughness_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::ClearcoatRoughness,
+        )?;
+        let clearcoat_normal_texture = model_texture_index(
+            material
+                .clearcoat_normal_texture
+                .map(|texture_idx| &model.textures[texture_idx as usize]),
+            MaterialTextureSlot::ClearcoatNormal,
+        )?;
+
         let material_descriptor = MaterialDescriptor {
             color: material.color.into(),
             color_texture,
@@ -253,7 +1060,7 @@ impl MaterialPool {
             normal_texture,
             emission_texture,
             transmission: material.transmission,
-            transmission_texture: u32::MAX,
+            transmission_texture,
             eta: material.eta,
             subsurface: material.subsurface,
             absorption: material.absorption.into(),
@@ -263,39 +1070,57 @@ impl MaterialPool {
             sheen: material.sheen,
             sheen_tint: material.sheen_tint.into(),
             clearcoat: material.clearcoat,
-            clearcoat_texture: u32::MAX,
+            clearcoat_texture,
             clearcoat_roughness: material.clearcoat_roughness,
-            clearcoat_roughness_texture: u32::MAX,
+            clearcoat_roughness_texture,
             alpha_cutoff: material.alpha_cutoff,
-            sheen_texture: u32::MAX,
-            clearcoat_normal_texture: u32::MAX,
-            sheen_tint_texture: u32::MAX,
-            _padding0: 0,
-            _padding1: 0,
-            _padding2: 0,
+            sheen_texture,
+            clearcoat_normal_texture,
+            sheen_tint_texture,
+            alpha_mode: AlphaMode::from(material.alpha_mode) as u32,
+            // Models carry no anisotropy direction, their anisotropy follows the mesh tangents
+            anisotropy_texture: u32::MAX,
+            anisotropy_rotation: 0.0,
         };
 
         self.material_descriptors.push(material_descriptor);
-        self.material_descriptors.len() as u32 - 1
+        let material_idx = self.material_descriptors.len() as u32 - 1;
+        self.dirty_materials.push(material_idx);
+        Ok(material_idx)
     }
 
-    pub fn duplicate_material(&mut self, idx: u32) -> u32 {
-        let material_descriptor = self.material_descriptors[idx as usize].clone();
+    pub fn duplicate_material(&mut self, idx: u32) -> Result<u32> {
+        if self.material_descriptors.len() >= MAX_MATERIAL_POOL_MATERIALS {
+            bail!(
+                "Material pool ran out of materials, at most {} are supported.",
+                MAX_MATERIAL_POOL_MATERIALS
+            );
+        }
+
+        let material_descriptor = self.material_descriptors[idx as usize];
         self.material_descriptors.push(material_descriptor);
-        self.material_descriptors.len() as u32 - 1
+        let material_idx = self.material_descriptors.len() as u32 - 1;
+        self.dirty_materials.push(material_idx);
+        Ok(material_idx)
     }
 
-    pub fn write_materials(&self, queue: &wgpu::Queue) {
-        queue.write_buffer(
-            &self.material_descriptor_buffer,
-            0,
-            bytemuck::cast_slice(self.material_descriptors.as_slice()),
-        );
-        queue.write_buffer(
-            &self.texture_transform_buffer,
-            0,
-            bytemuck::cast_slice(self.texture_transforms.as_slice()),
-        );
+    /// Upload all materials and texture transforms changed since the last call.
+    pub fn write_materials(&mut self, queue: &wgpu::Queue) {
+        for range in dirty_ranges(&mut self.dirty_materials) {
+            queue.write_buffer(
+                &self.material_descriptor_buffer,
+                (range.start * std::mem::size_of::<MaterialDescriptor>()) as u64,
+                bytemuck::cast_slice(&self.material_descriptors[range]),
+            );
+        }
+
+        for range in dirty_ranges(&mut self.dirty_texture_transforms) {
+            queue.write_buffer(
+                &self.texture_transform_buffer,
+                (range.start * std::mem::size_of::<TextureTransform>()) as u64,
+                bytemuck::cast_slice(&self.texture_transforms[range]),
+            );
+        }
     }
 
     pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
@@ -348,3 +1173,35 @@ impl MaterialPool {
         callback(&bind_group);
     }
 }
+
+/// Drain `indices` into ranges of consecutive indices, to keep the number of writes down during large edits.
+fn dirty_ranges(indices: &mut Vec<u32>) -> Vec<Range<usize>> {
+    indices.sort_unstable();
+    indices.dedup();
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in indices.drain(..) {
+        let i = i as usize;
+        match ranges.last_mut() {
+            Some(range) if range.end == i => range.end += 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+#[test]
+fn srgb_texture_slots() {
+    let srgb_slots: Vec<MaterialTextureSlot> = MaterialTextureSlot::ALL
+        .into_iter()
+        .filter(MaterialTextureSlot::is_srgb)
+        .collect();
+    assert_eq!(
+        srgb_slots,
+        [
+            MaterialTextureSlot::Color,
+            MaterialTextureSlot::Emission,
+            MaterialTextureSlot::SheenTint
+        ]
+    );
+}
@@ -1,38 +1,116 @@
-use std::{iter, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    iter,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
+use anyhow::{bail, Context, Result};
+use culling::{
+    bounding_sphere, deformed_bounding_sphere, CullingParameters, CullingStats, Frustum,
+};
 use debug_lines::DebugLines;
-use glam::{Vec3, Vec4Swizzles};
+use emissive_lights::{EmissiveLights, MeshLightTriangles};
+use glam::{Mat4, Vec3, Vec4Swizzles};
+use linear_block_allocator::LinearBlockAllocation;
 use linear_transformed_cosines::LinearTransformedCosines;
-use material_pool::MaterialPool;
+use material_pool::{
+    AlphaMode, MaterialBuilder, MaterialDescriptor, MaterialPool, MaterialTextureSlot,
+};
+use morph_targets::MorphTargets;
+use punctual_lights::PunctualLights;
+use residency::{desired_base_mip, ResidencyStats, DEFAULT_RESIDENCY_BUDGET};
+use skinning::{SkinVertex, Skinning};
 use sky::Sky;
-use specs::Join;
+use specs::{Join, RunNow};
 use ugm::{
     material::Material,
     mesh::{Mesh, PackedVertex},
+    speedy::Readable,
+    texture::Texture,
     Model,
 };
-use vertex_pool::{VertexPool, VertexPoolAlloc, VertexPoolWriteData};
+use vertex_pool::{MorphTargetDelta, VertexPool, VertexPoolAlloc, VertexPoolWriteData};
 
 use crate::{
-    wgpu_util,
-    world::components::{AreaLightComponent, DynamicComponent, MeshComponent, TransformComponent},
+    render_passes::{
+        morph_target_pass::{self, MorphTargetPassParameters},
+        skinning_pass::{self, SkinningPassParameters},
+        sky_lut_pass::{self, SkyLutPassParameters},
+    },
+    wgpu_util::{self, PipelineDatabase},
+    world::{
+        animation::{AnimationClip, Skeleton},
+        bvh::MeshBvh,
+        components::{
+            AreaLightComponent, DirectionalLightComponent, DynamicComponent, LodCrossfade,
+            LodMeshComponent, LodMetric, MeshComponent, MeshSource, MorphWeightsComponent,
+            PointLightComponent, SkinnedMeshComponent, SpotLightComponent, TransformComponent,
+            VisibilityRangeComponent,
+        },
+        systems::{AnimationSystem, LodSystem, TransformPropagationSystem},
+        transform::FORWARD,
+    },
     xr::XrCameraState,
+    RenderPath,
 };
 
+/// Extension of the file containing the levels of detail of a model, stored next to it.
+pub const LOD_EXTENSION: &str = "lods";
+
 const MAX_STATIC_INSTANCES: usize = 1024 * 256;
 const MAX_DYNAMIC_INSTANCES: usize = 1024 * 16;
+/// Dither levels of a ray traced level of detail transition, each one selected through its own bit of the instance mask.
+/// Must match `LOD_DITHER_LEVELS` in rt_gbuffer_pass.wgsl.
+const LOD_DITHER_LEVELS: u32 = 7;
+
+/// Tlas instance mask covering the dither levels at which an instance with `lod_fade` is visible, see `RasterInstance::lod_fade`.
+/// The lowest bit is kept for instances that are not transitioning, so that rays of other passes still see them.
+fn lod_fade_mask(lod_fade: f32) -> u8 {
+    if lod_fade == 0.0 {
+        return 0xff;
+    }
+
+    (0..LOD_DITHER_LEVELS)
+        .filter(|level| {
+            let noise = (*level as f32 + 0.5) / LOD_DITHER_LEVELS as f32;
+            if lod_fade > 0.0 {
+                noise >= lod_fade
+            } else {
+                noise < -lod_fade
+            }
+        })
+        .fold(0, |mask, level| mask | (2 << level))
+}
 
+pub mod color_grading_lut;
+pub mod culling;
 pub mod debug_lines;
+pub mod emissive_lights;
+pub mod environment_map;
 pub mod gbuffer;
 mod linear_block_allocator;
 pub mod linear_transformed_cosines;
 pub mod material_pool;
+pub mod morph_targets;
+pub mod punctual_lights;
+pub mod residency;
+pub mod skinning;
 pub mod sky;
 pub mod vertex_pool;
 
 pub struct GpuModel {
     pub gpu_meshes: Vec<Option<Arc<GpuMesh>>>,
+    /// Coarser levels of detail for every mesh, ordered from finest to coarsest. Only loaded by `from_file`.
+    pub lod_gpu_meshes: Vec<Vec<Arc<GpuMesh>>>,
     pub gpu_materials: Vec<Arc<GpuMaterial>>,
+    /// Indices into `gpu_materials` for every mesh.
+    pub mesh_material_indices: Vec<Vec<u32>>,
+    /// One skeleton for every skin of the model.
+    pub skeletons: Vec<Arc<Skeleton>>,
+    pub animation_clips: Vec<Arc<AnimationClip>>,
+    /// File the model was loaded from, only known when created through `from_file`.
+    pub path: Option<PathBuf>,
 }
 
 impl GpuModel {
@@ -41,31 +119,315 @@ impl GpuModel {
         gpu_resources: &mut GpuResources,
         command_encoder: &mut wgpu::CommandEncoder,
         ctx: &wgpu_util::Context,
-    ) -> Self {
+    ) -> Result<Self> {
         let gpu_meshes: Vec<Option<Arc<GpuMesh>>> = model
             .meshes
             .iter()
-            .map(|mesh| gpu_resources.create_gpu_mesh(mesh, command_encoder, ctx))
-            .collect();
+            .map(|mesh| {
+                let opaque = mesh.material_indices.iter().all(|material_idx| {
+                    AlphaMode::from(model.materials[*material_idx as usize].alpha_mode)
+                        == AlphaMode::Opaque
+                });
+                gpu_resources.create_gpu_mesh(mesh, opaque, command_encoder, ctx)
+            })
+            .collect::<Result<_>>()?;
         let gpu_materials: Vec<Arc<GpuMaterial>> = model
             .materials
             .iter()
-            .map(|material| gpu_resources.create_gpu_material(model, material, ctx))
+            .map(|material| {
+                gpu_resources.create_gpu_material(model, material, command_encoder, ctx)
+            })
+            .collect::<Result<_>>()?;
+        let mesh_material_indices = model
+            .meshes
+            .iter()
+            .map(|mesh| mesh.material_indices.clone())
             .collect();
-
-        Self {
+        let skeletons = (0..model.skins.len())
+            .map(|skin_idx| Skeleton::from_model(model, skin_idx).map(Arc::new))
+            .collect::<Result<_>>()?;
+        let animation_clips = (0..model.animations.len())
+            .map(|animation_idx| AnimationClip::from_model(model, animation_idx).map(Arc::new))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            lod_gpu_meshes: vec![Vec::new(); gpu_meshes.len()],
             gpu_meshes,
             gpu_materials,
+            mesh_material_indices,
+            skeletons,
+            animation_clips,
+            path: None,
+        })
+    }
+
+    /// Read a ugm model from disk and upload it, meshes created from it keep a reference to `path`.
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        gpu_resources: &mut GpuResources,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let buffer = std::fs::read(path)
+            .with_context(|| format!("Failed to read model {}", path.display()))?;
+        let model = Model::read_from_buffer(&buffer)
+            .with_context(|| format!("Failed to parse model {}", path.display()))?;
+
+        let mut gpu_model = Self::new(&model, gpu_resources, command_encoder, ctx)
+            .with_context(|| format!("Failed to upload model {}", path.display()))?;
+        gpu_model.path = Some(path.to_path_buf());
+
+        // Levels of detail are generated offline into a separate file next to the model
+        let lod_path = path.with_extension(LOD_EXTENSION);
+        if lod_path.exists() {
+            let buffer = std::fs::read(&lod_path)
+                .with_context(|| format!("Failed to read lods {}", lod_path.display()))?;
+            let lod_meshes = Vec::<Vec<Mesh>>::read_from_buffer(&buffer)
+                .with_context(|| format!("Failed to parse lods {}", lod_path.display()))?;
+            if lod_meshes.len() != gpu_model.gpu_meshes.len() {
+                bail!(
+                    "Lods {} contain {} meshes, but the model contains {}.",
+                    lod_path.display(),
+                    lod_meshes.len(),
+                    gpu_model.gpu_meshes.len()
+                );
+            }
+
+            for (mesh_idx, lod_meshes) in lod_meshes.iter().enumerate() {
+                let Some(gpu_mesh) = &gpu_model.gpu_meshes[mesh_idx] else {
+                    continue;
+                };
+                let opaque = gpu_mesh.opaque;
+
+                for lod_mesh in lod_meshes {
+                    if let Some(lod_gpu_mesh) =
+                        gpu_resources.create_gpu_mesh(lod_mesh, opaque, command_encoder, ctx)?
+                    {
+                        gpu_model.lod_gpu_meshes[mesh_idx].push(lod_gpu_mesh);
+                    }
+                }
+            }
+        }
+
+        Ok(gpu_model)
+    }
+
+    /// Create a mesh component for one of the meshes of this model, `None` if the mesh is empty.
+    pub fn mesh_component(&self, mesh_idx: usize) -> Option<MeshComponent> {
+        let mesh = self.gpu_meshes[mesh_idx].clone()?;
+        let material_indices = &self.mesh_material_indices[mesh_idx];
+        let materials = material_indices
+            .iter()
+            .map(|material_idx| self.gpu_materials[*material_idx as usize].clone())
+            .collect();
+
+        let mut mesh_component = MeshComponent::new(mesh, materials);
+        if let Some(path) = &self.path {
+            mesh_component = mesh_component.with_source(MeshSource {
+                model_path: path.clone(),
+                mesh_idx: mesh_idx as u32,
+                material_indices: material_indices.clone(),
+            });
         }
+        Some(mesh_component)
+    }
+
+    /// Create the mesh and lod mesh components for one of the meshes of this model, `None` if the mesh is empty.
+    /// Only the levels that have a threshold are used, the finest level is the mesh itself.
+    pub fn lod_mesh_components(
+        &self,
+        mesh_idx: usize,
+        mut thresholds: Vec<f32>,
+        metric: LodMetric,
+    ) -> Option<(MeshComponent, LodMeshComponent)> {
+        let mesh_component = self.mesh_component(mesh_idx)?;
+
+        let mut meshes = vec![mesh_component.mesh.clone()];
+        meshes.extend(self.lod_gpu_meshes[mesh_idx].iter().cloned());
+        let num_lods = meshes.len().min(thresholds.len() + 1);
+        meshes.truncate(num_lods);
+        thresholds.truncate(num_lods - 1);
+
+        Some((
+            mesh_component,
+            LodMeshComponent::new(meshes, thresholds, metric),
+        ))
+    }
+
+    /// Create the mesh and skinned mesh components for one of the meshes of this model, deformed by skin `skin_idx`.
+    /// Every call allocates a new skinned mesh, `None` if the mesh is empty.
+    pub fn skinned_mesh_components(
+        &self,
+        mesh_idx: usize,
+        skin_idx: usize,
+        gpu_resources: &mut GpuResources,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> Result<Option<(MeshComponent, SkinnedMeshComponent)>> {
+        let Some(mut mesh_component) = self.mesh_component(mesh_idx) else {
+            return Ok(None);
+        };
+        let Some(skeleton) = self.skeletons.get(skin_idx) else {
+            bail!("Model does not contain skin {}.", skin_idx);
+        };
+
+        let bind_pose_mesh = mesh_component.mesh.clone();
+        mesh_component.mesh =
+            gpu_resources.create_deformable_gpu_mesh(&bind_pose_mesh, command_encoder, ctx)?;
+
+        Ok(Some((
+            mesh_component,
+            SkinnedMeshComponent::new(skeleton.clone(), bind_pose_mesh),
+        )))
+    }
+
+    /// Create the mesh and morph weights components for one of the meshes of this model, with all weights set to zero.
+    /// Every call allocates a new morphed mesh, `None` if the mesh is empty.
+    pub fn morphed_mesh_components(
+        &self,
+        mesh_idx: usize,
+        gpu_resources: &mut GpuResources,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> Result<Option<(MeshComponent, MorphWeightsComponent)>> {
+        let Some(mut mesh_component) = self.mesh_component(mesh_idx) else {
+            return Ok(None);
+        };
+
+        let bind_pose_mesh = mesh_component.mesh.clone();
+        mesh_component.mesh =
+            gpu_resources.create_deformable_gpu_mesh(&bind_pose_mesh, command_encoder, ctx)?;
+
+        Ok(Some((
+            mesh_component,
+            MorphWeightsComponent::new(bind_pose_mesh),
+        )))
+    }
+
+    pub fn animation_clip(&self, name: &str) -> Option<Arc<AnimationClip>> {
+        self.animation_clips
+            .iter()
+            .find(|animation_clip| animation_clip.name == name)
+            .cloned()
     }
 }
 
-#[derive(Debug, Clone)]
+/// Gpu side data of a mesh that is released when the mesh is evicted.
+#[derive(Debug)]
+struct MeshResidency {
+    vertex_pool_alloc: Option<VertexPoolAlloc>,
+    blas: Option<wgpu::Blas>,
+    /// Frame index of the last `GpuResources::update` that submitted an instance of the mesh.
+    last_used_frame: u64,
+}
+
+/// Cpu side copy of the vertex pool data of a mesh, used to rebuild it after it was evicted.
+struct MeshVertexData {
+    packed_vertices: Vec<PackedVertex>,
+    indices: Vec<u32>,
+    triangle_material_indices: Vec<u32>,
+}
+
+impl std::fmt::Debug for MeshVertexData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MeshVertexData")
+            .field("num_vertices", &self.packed_vertices.len())
+            .field("num_indices", &self.indices.len())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
 pub struct GpuMesh {
-    pub vertex_pool_alloc: VertexPoolAlloc,
-    pub blas: wgpu::Blas,
+    residency: Mutex<MeshResidency>,
+    /// Only kept for meshes without joint weights or morph targets, deformable meshes and their bind poses are never evicted.
+    vertex_data: Option<MeshVertexData>,
+    pub num_vertices: u32,
+    pub num_indices: u32,
+    /// Joint indices and weights of every vertex, only available for meshes with a skin.
+    pub skin_vertex_alloc: Option<LinearBlockAllocation>,
+    /// Per vertex deltas of every morph target in the vertex pool, only available for meshes with morph targets.
+    pub morph_target_alloc: Option<LinearBlockAllocation>,
+    pub num_morph_targets: u32,
+    /// Largest position delta of every morph target, grows the bounds of morphed instances for culling.
+    pub morph_target_extents: Vec<f32>,
+    /// Opaque meshes skip alpha testing during ray traversal, regardless of the alpha mode of their materials.
+    pub opaque: bool,
     pub bounds_min: Vec3,
     pub bounds_max: Vec3,
+    /// Triangle areas per material slot, turns instances of the mesh with emissive materials into lights.
+    pub light_triangles: Arc<MeshLightTriangles>,
+    /// Cpu side bvh used by `world::queries`, only built when enabled through `GpuResources::set_build_cpu_bvhs`.
+    /// Deformable meshes share the bvh of their bind pose.
+    pub cpu_bvh: Option<Arc<MeshBvh>>,
+}
+
+impl GpuMesh {
+    fn new_resident(
+        vertex_pool_alloc: VertexPoolAlloc,
+        blas: Option<wgpu::Blas>,
+        frame_idx: u64,
+    ) -> Mutex<MeshResidency> {
+        Mutex::new(MeshResidency {
+            vertex_pool_alloc: Some(vertex_pool_alloc),
+            blas,
+            last_used_frame: frame_idx,
+        })
+    }
+
+    /// Vertex pool allocation of the mesh, `GpuResources::update` makes every mesh it submits resident.
+    pub fn vertex_pool_alloc(&self) -> VertexPoolAlloc {
+        self.residency
+            .lock()
+            .unwrap()
+            .vertex_pool_alloc
+            .clone()
+            .expect("Mesh has been evicted from the vertex pool!")
+    }
+
+    /// Only available when the device supports ray tracing and the mesh is resident.
+    pub fn blas(&self) -> Option<wgpu::Blas> {
+        self.residency.lock().unwrap().blas.clone()
+    }
+
+    /// Evicted meshes are rebuilt from their cpu side data the next time an instance of them is submitted.
+    pub fn is_resident(&self) -> bool {
+        self.residency.lock().unwrap().vertex_pool_alloc.is_some()
+    }
+
+    pub fn last_used_frame(&self) -> u64 {
+        self.residency.lock().unwrap().last_used_frame
+    }
+
+    fn mark_used(&self, frame_idx: u64) {
+        self.residency.lock().unwrap().last_used_frame = frame_idx;
+    }
+
+    fn is_evictable(&self, frame_idx: u64) -> bool {
+        self.vertex_data.is_some() && self.is_resident() && self.last_used_frame() < frame_idx
+    }
+}
+
+fn blas_size_descriptor(
+    vertex_pool_alloc: &VertexPoolAlloc,
+    opaque: bool,
+) -> wgpu::BlasTriangleGeometrySizeDescriptor {
+    wgpu::BlasTriangleGeometrySizeDescriptor {
+        vertex_format: wgpu::VertexFormat::Float32x3,
+        vertex_count: (vertex_pool_alloc.vertex_alloc.end()
+            - vertex_pool_alloc.vertex_alloc.start()) as u32,
+        index_format: Some(wgpu::IndexFormat::Uint32),
+        index_count: Some(
+            (vertex_pool_alloc.index_alloc.end() - vertex_pool_alloc.index_alloc.start()) as u32,
+        ),
+        // Non-opaque geometry invokes candidate intersections for alpha testing
+        flags: if opaque {
+            wgpu::AccelerationStructureGeometryFlags::OPAQUE
+        } else {
+            wgpu::AccelerationStructureGeometryFlags::empty()
+        },
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -73,18 +435,62 @@ pub struct GpuMaterial {
     pub material_idx: u32,
 }
 
+/// Mesh instance drawn by the rasterized passes, the raster counterpart of a tlas instance.
+#[derive(Debug, Clone)]
+pub struct RasterInstance {
+    pub local_to_world: Mat4,
+    pub instance_idx: u32,
+    pub gpu_mesh: Arc<GpuMesh>,
+    /// Dither threshold of a level of detail transition, zero when the instance is not transitioning, see `LodCrossfade`.
+    pub lod_fade: f32,
+}
+
+/// Static mesh instance kept alive between updates, so that it can be patched in place when its entity changes.
+struct StaticInstance {
+    /// Index into the static tlas, stable for as long as the entity remains static.
+    slot: u32,
+    material_indices: Vec<u32>,
+    raster_instance: RasterInstance,
+    /// World space bounding sphere, cached as statics don't move.
+    bounds_center: Vec3,
+    bounds_radius: f32,
+}
+
 pub struct GpuResources {
     vertex_pool: VertexPool,
     material_pool: MaterialPool,
     linear_transformed_cosines: LinearTransformedCosines,
+    punctual_lights: PunctualLights,
+    emissive_lights: EmissiveLights,
     debug_lines: DebugLines,
-    static_tlas_package: wgpu::TlasPackage,
-    dynamic_tlas_package: wgpu::TlasPackage,
+    skinning: Skinning,
+    morph_targets: MorphTargets,
+    static_tlas_package: Option<wgpu::TlasPackage>,
+    dynamic_tlas_package: Option<wgpu::TlasPackage>,
     static_dirty: bool,
+    static_tlas_dirty: bool,
+    render_path: Option<RenderPath>,
     sky: Sky,
+    residency_budget: u64,
+    /// Incremented every `update`, orders meshes by their last use for eviction.
+    frame_idx: u64,
+    build_cpu_bvhs: bool,
+    culling_stats: CullingStats,
 
     dynamic_blas_instances: Vec<wgpu::TlasInstance>,
-    static_blas_instances: Vec<wgpu::TlasInstance>,
+    dynamic_raster_instances: Vec<RasterInstance>,
+    /// Dynamic mesh instances with blended materials, submitted in either render path.
+    dynamic_transparent_instances: Vec<RasterInstance>,
+    /// Entity of every dynamic instance, indexed by instance index.
+    dynamic_instance_entities: Vec<specs::Entity>,
+    static_instances: HashMap<specs::Entity, StaticInstance>,
+    /// Entity of every static instance, indexed by slot.
+    static_slot_entities: Vec<Option<specs::Entity>>,
+    /// Static instances using an emissive material, the only ones visited when submitting emissive lights.
+    emissive_static_entities: BTreeSet<specs::Entity>,
+    dirty_static_entities: Vec<specs::Entity>,
+    free_static_slots: BTreeSet<u32>,
+    next_static_slot: u32,
 
     gpu_meshes: Vec<Arc<GpuMesh>>,
     gpu_materials: Vec<Arc<GpuMaterial>>,
@@ -95,21 +501,35 @@ impl GpuResources {
         let vertex_pool = VertexPool::new(device);
         let material_pool = MaterialPool::new(device);
         let linear_transformed_cosines = LinearTransformedCosines::new(device, queue);
+        let punctual_lights = PunctualLights::new(device);
+        let emissive_lights = EmissiveLights::new(device);
         let debug_lines = DebugLines::new(device);
-
-        let static_tlas = device.create_tlas(&wgpu::CreateTlasDescriptor {
-            label: Some("terrarium::gpu_resources static_tlas"),
-            max_instances: (MAX_STATIC_INSTANCES) as u32,
-            flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
-            update_mode: wgpu::AccelerationStructureUpdateMode::Build,
-        });
-
-        let dynamic_tlas = device.create_tlas(&wgpu::CreateTlasDescriptor {
-            label: Some("terrarium::gpu_resources dynamic_tlas"),
-            max_instances: (MAX_DYNAMIC_INSTANCES) as u32,
-            flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
-            update_mode: wgpu::AccelerationStructureUpdateMode::Build,
-        });
+        let skinning = Skinning::new(device);
+        let morph_targets = MorphTargets::new(device);
+
+        let (static_tlas_package, dynamic_tlas_package) =
+            if RenderPath::from_features(device.features()) == RenderPath::RayTraced {
+                let static_tlas = device.create_tlas(&wgpu::CreateTlasDescriptor {
+                    label: Some("terrarium::gpu_resources static_tlas"),
+                    max_instances: (MAX_STATIC_INSTANCES) as u32,
+                    flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
+                    update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+                });
+
+                let dynamic_tlas = device.create_tlas(&wgpu::CreateTlasDescriptor {
+                    label: Some("terrarium::gpu_resources dynamic_tlas"),
+                    max_instances: (MAX_DYNAMIC_INSTANCES) as u32,
+                    flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
+                    update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+                });
+
+                (
+                    Some(wgpu::TlasPackage::new(static_tlas)),
+                    Some(wgpu::TlasPackage::new(dynamic_tlas)),
+                )
+            } else {
+                (None, None)
+            };
 
         let sky = Sky::new(device);
 
@@ -117,31 +537,120 @@ impl GpuResources {
             vertex_pool,
             material_pool,
             linear_transformed_cosines,
+            punctual_lights,
+            emissive_lights,
             debug_lines,
-            static_tlas_package: wgpu::TlasPackage::new(static_tlas),
-            dynamic_tlas_package: wgpu::TlasPackage::new(dynamic_tlas),
+            skinning,
+            morph_targets,
+            static_tlas_package,
+            dynamic_tlas_package,
             static_dirty: true,
+            static_tlas_dirty: true,
+            render_path: None,
             sky,
+            residency_budget: DEFAULT_RESIDENCY_BUDGET,
+            frame_idx: 0,
+            build_cpu_bvhs: false,
+            culling_stats: CullingStats::default(),
             dynamic_blas_instances: Vec::new(),
-            static_blas_instances: Vec::new(),
+            dynamic_raster_instances: Vec::new(),
+            dynamic_transparent_instances: Vec::new(),
+            dynamic_instance_entities: Vec::new(),
+            static_instances: HashMap::new(),
+            static_slot_entities: Vec::new(),
+            emissive_static_entities: BTreeSet::new(),
+            dirty_static_entities: Vec::new(),
+            free_static_slots: BTreeSet::new(),
+            next_static_slot: 0,
             gpu_meshes: Vec::new(),
             gpu_materials: Vec::new(),
         }
     }
 
+    /// Create a mesh in the vertex pool, `opaque` meshes skip alpha testing during ray traversal.
     pub fn create_gpu_mesh(
         &mut self,
         mesh: &Mesh,
+        opaque: bool,
         command_encoder: &mut wgpu::CommandEncoder,
         ctx: &wgpu_util::Context,
-    ) -> Option<Arc<GpuMesh>> {
+    ) -> Result<Option<Arc<GpuMesh>>> {
         if mesh.is_empty() {
-            return None;
+            return Ok(None);
         }
 
-        let vertex_pool_alloc = self
-            .vertex_pool
-            .alloc(mesh.packed_vertices.len() as u32, mesh.indices.len() as u32);
+        let num_vertices = mesh.packed_vertices.len() as u32;
+        let num_indices = mesh.indices.len() as u32;
+
+        if !self.fit_mesh_budget(
+            VertexPool::alloc_bytes(num_vertices, num_indices),
+            command_encoder,
+            &ctx.device,
+        ) {
+            bail!(
+                "Mesh of {} bytes does not fit in the residency budget of {} bytes.",
+                VertexPool::alloc_bytes(num_vertices, num_indices),
+                self.residency_budget
+            );
+        }
+
+        let skin_vertices: Option<Vec<SkinVertex>> = if mesh.joint_indices.is_empty() {
+            None
+        } else {
+            if mesh.joint_indices.len() != mesh.packed_vertices.len()
+                || mesh.joint_weights.len() != mesh.packed_vertices.len()
+            {
+                bail!(
+                    "Skinned mesh has {} vertices but {} joint indices and {} joint weights.",
+                    mesh.packed_vertices.len(),
+                    mesh.joint_indices.len(),
+                    mesh.joint_weights.len()
+                );
+            }
+
+            Some(
+                mesh.joint_indices
+                    .iter()
+                    .zip(&mesh.joint_weights)
+                    .map(|(joint_indices, joint_weights)| SkinVertex {
+                        joint_indices: joint_indices.map(|joint_idx| joint_idx as u32),
+                        joint_weights: *joint_weights,
+                    })
+                    .collect(),
+            )
+        };
+
+        // Deltas are stored target by target, normal and tangent deltas are optional
+        let mut morph_target_deltas: Vec<MorphTargetDelta> = Vec::new();
+        for (i, morph_target) in mesh.morph_targets.iter().enumerate() {
+            let num_deltas = mesh.packed_vertices.len();
+            if morph_target.position_deltas.len() != num_deltas
+                || !matches!(morph_target.normal_deltas.len(), 0 | n if n == num_deltas)
+                || !matches!(morph_target.tangent_deltas.len(), 0 | n if n == num_deltas)
+            {
+                bail!(
+                    "Morph target {} does not contain a delta for each of the {} vertices.",
+                    i,
+                    num_deltas
+                );
+            }
+
+            for j in 0..num_deltas {
+                morph_target_deltas.push(MorphTargetDelta::new(
+                    morph_target.position_deltas[j].into(),
+                    morph_target
+                        .normal_deltas
+                        .get(j)
+                        .map_or(Vec3::ZERO, |delta| (*delta).into()),
+                    morph_target
+                        .tangent_deltas
+                        .get(j)
+                        .map_or(Vec3::ZERO, |delta| (*delta).into()),
+                ));
+            }
+        }
+
+        let vertex_pool_alloc = self.vertex_pool.alloc(num_vertices, num_indices)?;
 
         self.vertex_pool.write_vertex_data(
             &VertexPoolWriteData {
@@ -153,76 +662,463 @@ impl GpuResources {
             &ctx.queue,
         );
 
-        let size_desc = wgpu::BlasTriangleGeometrySizeDescriptor {
-            vertex_format: wgpu::VertexFormat::Float32x3,
-            vertex_count: mesh.packed_vertices.len() as u32,
-            index_format: Some(wgpu::IndexFormat::Uint32),
-            index_count: Some(mesh.indices.len() as u32),
-            flags: wgpu::AccelerationStructureGeometryFlags::OPAQUE,
+        let skin_vertex_alloc = if let Some(skin_vertices) = &skin_vertices {
+            let skin_vertex_alloc = match self.skinning.alloc(num_vertices) {
+                Ok(skin_vertex_alloc) => skin_vertex_alloc,
+                Err(err) => {
+                    self.vertex_pool.free(&vertex_pool_alloc);
+                    return Err(err);
+                }
+            };
+            self.skinning
+                .write_skin_vertices(skin_vertices, &skin_vertex_alloc, &ctx.queue);
+            Some(skin_vertex_alloc)
+        } else {
+            None
+        };
+
+        let morph_target_alloc = if morph_target_deltas.is_empty() {
+            None
+        } else {
+            let morph_target_alloc = match self
+                .vertex_pool
+                .alloc_morph_target_deltas(morph_target_deltas.len() as u32)
+            {
+                Ok(morph_target_alloc) => morph_target_alloc,
+                Err(err) => {
+                    self.vertex_pool.free(&vertex_pool_alloc);
+                    if let Some(skin_vertex_alloc) = &skin_vertex_alloc {
+                        self.skinning.free(skin_vertex_alloc);
+                    }
+                    return Err(err);
+                }
+            };
+            self.vertex_pool.write_morph_target_deltas(
+                &morph_target_deltas,
+                &morph_target_alloc,
+                &ctx.queue,
+            );
+            Some(morph_target_alloc)
         };
 
-        let blas = ctx.device.create_blas(
+        let blas = self.supports_ray_tracing().then(|| {
+            self.create_blas(
+                &vertex_pool_alloc,
+                opaque,
+                wgpu::AccelerationStructureUpdateMode::Build,
+                &ctx.device,
+            )
+        });
+
+        // Deformations read from the bind pose every frame, so only meshes without any are evicted
+        let vertex_data =
+            (skin_vertex_alloc.is_none() && morph_target_alloc.is_none()).then(|| MeshVertexData {
+                packed_vertices: mesh.packed_vertices.clone(),
+                indices: mesh.indices.clone(),
+                triangle_material_indices: mesh.triangle_material_indices.clone(),
+            });
+
+        let gpu_mesh = Arc::new(GpuMesh {
+            residency: GpuMesh::new_resident(vertex_pool_alloc, blas, self.frame_idx),
+            vertex_data,
+            num_vertices,
+            num_indices,
+            skin_vertex_alloc,
+            morph_target_alloc,
+            num_morph_targets: mesh.morph_targets.len() as u32,
+            morph_target_extents: mesh
+                .morph_targets
+                .iter()
+                .map(|morph_target| {
+                    morph_target
+                        .position_deltas
+                        .iter()
+                        .map(|delta| Vec3::from(*delta).length())
+                        .fold(0.0, f32::max)
+                })
+                .collect(),
+            opaque,
+            bounds_min: mesh.bounds_min.into(),
+            bounds_max: mesh.bounds_max.into(),
+            light_triangles: Arc::new(MeshLightTriangles::from_mesh(mesh)),
+            cpu_bvh: self
+                .build_cpu_bvhs
+                .then(|| Arc::new(MeshBvh::from_mesh(mesh))),
+        });
+        if self.supports_ray_tracing() {
+            self.build_blases(iter::once(gpu_mesh.as_ref()), command_encoder);
+        }
+        self.gpu_meshes.push(gpu_mesh.clone());
+        Ok(Some(gpu_mesh))
+    }
+
+    /// Create a mesh for a single skinned or morphed entity to deform `bind_pose_mesh` into, initialized to the bind pose.
+    /// Its blas is refit every frame, assign it to the `MeshComponent` of the deformed entity.
+    pub fn create_deformable_gpu_mesh(
+        &mut self,
+        bind_pose_mesh: &GpuMesh,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> Result<Arc<GpuMesh>> {
+        if bind_pose_mesh.skin_vertex_alloc.is_none() && bind_pose_mesh.morph_target_alloc.is_none()
+        {
+            bail!("Cannot deform a mesh without joint weights or morph targets.");
+        }
+
+        let src_alloc = bind_pose_mesh.vertex_pool_alloc();
+        let num_vertices = bind_pose_mesh.num_vertices;
+        let num_indices = bind_pose_mesh.num_indices;
+
+        // Deformable meshes keep the vertices of the previous frame around for motion vectors
+        let alloc_bytes = VertexPool::alloc_bytes(num_vertices * 2, num_indices);
+        if !self.fit_mesh_budget(alloc_bytes, command_encoder, &ctx.device) {
+            bail!(
+                "Deformable mesh of {} bytes does not fit in the residency budget of {} bytes.",
+                alloc_bytes,
+                self.residency_budget
+            );
+        }
+
+        let vertex_pool_alloc = self
+            .vertex_pool
+            .alloc_deformable(num_vertices, num_indices)?;
+        self.vertex_pool.copy_vertex_data(
+            &src_alloc,
+            &vertex_pool_alloc,
+            &ctx.device,
+            command_encoder,
+        );
+
+        let blas = self.supports_ray_tracing().then(|| {
+            self.create_blas(
+                &vertex_pool_alloc,
+                bind_pose_mesh.opaque,
+                wgpu::AccelerationStructureUpdateMode::PreferUpdate,
+                &ctx.device,
+            )
+        });
+
+        let gpu_mesh = Arc::new(GpuMesh {
+            residency: GpuMesh::new_resident(vertex_pool_alloc, blas, self.frame_idx),
+            vertex_data: None,
+            num_vertices,
+            num_indices,
+            skin_vertex_alloc: None,
+            morph_target_alloc: None,
+            num_morph_targets: 0,
+            morph_target_extents: Vec::new(),
+            opaque: bind_pose_mesh.opaque,
+            bounds_min: bind_pose_mesh.bounds_min,
+            bounds_max: bind_pose_mesh.bounds_max,
+            light_triangles: bind_pose_mesh.light_triangles.clone(),
+            cpu_bvh: bind_pose_mesh.cpu_bvh.clone(),
+        });
+        if self.supports_ray_tracing() {
+            self.build_blases(iter::once(gpu_mesh.as_ref()), command_encoder);
+        }
+        self.gpu_meshes.push(gpu_mesh.clone());
+        Ok(gpu_mesh)
+    }
+
+    fn create_blas(
+        &self,
+        vertex_pool_alloc: &VertexPoolAlloc,
+        opaque: bool,
+        update_mode: wgpu::AccelerationStructureUpdateMode,
+        device: &wgpu::Device,
+    ) -> wgpu::Blas {
+        // Blases refit every frame favor build speed over trace speed
+        let flags = match update_mode {
+            wgpu::AccelerationStructureUpdateMode::Build => {
+                wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE
+            }
+            wgpu::AccelerationStructureUpdateMode::PreferUpdate => {
+                wgpu::AccelerationStructureFlags::PREFER_FAST_BUILD
+                    | wgpu::AccelerationStructureFlags::ALLOW_UPDATE
+            }
+        };
+
+        device.create_blas(
             &wgpu::CreateBlasDescriptor {
                 label: None,
-                flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
-                update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+                flags,
+                update_mode,
             },
             wgpu::BlasGeometrySizeDescriptors::Triangles {
-                descriptors: vec![size_desc.clone()],
+                descriptors: vec![blas_size_descriptor(vertex_pool_alloc, opaque)],
             },
-        );
+        )
+    }
 
-        let triangle_geometry = wgpu::BlasTriangleGeometry {
-            size: &size_desc,
-            vertex_buffer: self.vertex_pool.vertex_buffer(),
-            first_vertex: vertex_pool_alloc.vertex_alloc.start() as u32,
-            vertex_stride: std::mem::size_of::<PackedVertex>() as u64,
-            index_buffer: Some(self.vertex_pool.index_buffer()),
-            first_index: Some(vertex_pool_alloc.index_alloc.start() as u32),
-            transform_buffer: None,
-            transform_buffer_offset: None,
-        };
+    /// Build or refit the blases of meshes from the current contents of their vertex pool allocations.
+    fn build_blases<'a>(
+        &self,
+        gpu_meshes: impl Iterator<Item = &'a GpuMesh>,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let gpu_meshes: Vec<(wgpu::Blas, VertexPoolAlloc, bool)> = gpu_meshes
+            .map(|gpu_mesh| {
+                (
+                    gpu_mesh.blas().unwrap(),
+                    gpu_mesh.vertex_pool_alloc(),
+                    gpu_mesh.opaque,
+                )
+            })
+            .collect();
+        let size_descs: Vec<wgpu::BlasTriangleGeometrySizeDescriptor> = gpu_meshes
+            .iter()
+            .map(|(_, vertex_pool_alloc, opaque)| blas_size_descriptor(vertex_pool_alloc, *opaque))
+            .collect();
+
+        let build_entries: Vec<wgpu::BlasBuildEntry> = gpu_meshes
+            .iter()
+            .zip(&size_descs)
+            .map(
+                |((blas, vertex_pool_alloc, _), size_desc)| wgpu::BlasBuildEntry {
+                    blas,
+                    geometry: wgpu::BlasGeometries::TriangleGeometries(vec![
+                        wgpu::BlasTriangleGeometry {
+                            size: size_desc,
+                            vertex_buffer: self.vertex_pool.vertex_buffer(),
+                            first_vertex: vertex_pool_alloc.vertex_alloc.start() as u32,
+                            vertex_stride: std::mem::size_of::<PackedVertex>() as u64,
+                            index_buffer: Some(self.vertex_pool.index_buffer()),
+                            first_index: Some(vertex_pool_alloc.index_alloc.start() as u32),
+                            transform_buffer: None,
+                            transform_buffer_offset: None,
+                        },
+                    ]),
+                },
+            )
+            .collect();
+
+        command_encoder.build_acceleration_structures(build_entries.iter(), iter::empty());
+    }
+
+    /// Make room for `alloc_bytes` of new vertex pool allocations within the residency budget, returns false when they don't fit.
+    /// Meshes without an instance in the current frame are evicted first, least recently used first, texture mips are streamed out after that.
+    fn fit_mesh_budget(
+        &mut self,
+        alloc_bytes: u64,
+        command_encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+    ) -> bool {
+        let mut evictable_meshes: Vec<Arc<GpuMesh>> = self
+            .gpu_meshes
+            .iter()
+            .filter(|gpu_mesh| gpu_mesh.is_evictable(self.frame_idx))
+            .cloned()
+            .collect();
+        evictable_meshes.sort_by_key(|gpu_mesh| gpu_mesh.last_used_frame());
+
+        for gpu_mesh in evictable_meshes {
+            if self.vertex_pool.used_bytes() + alloc_bytes + self.material_pool.texture_bytes()
+                <= self.residency_budget
+            {
+                break;
+            }
+            self.evict_mesh(&gpu_mesh);
+        }
+
+        let mesh_bytes = self.vertex_pool.used_bytes() + alloc_bytes;
+        mesh_bytes <= self.residency_budget
+            && self.material_pool.fit_texture_budget(
+                self.residency_budget - mesh_bytes,
+                device,
+                command_encoder,
+            )
+    }
+
+    /// Release the vertex pool allocation and blas of a mesh, its cpu side data is kept to rebuild them on demand.
+    fn evict_mesh(&mut self, gpu_mesh: &GpuMesh) {
+        let mut residency = gpu_mesh.residency.lock().unwrap();
+        if let Some(vertex_pool_alloc) = residency.vertex_pool_alloc.take() {
+            self.vertex_pool.free(&vertex_pool_alloc);
+        }
+        residency.blas = None;
+    }
+
+    /// Mark a mesh as used during the current frame, rebuilding it from its cpu side data when it was evicted.
+    /// Returns false when the mesh does not fit within the residency budget anymore.
+    fn make_mesh_resident(
+        &mut self,
+        gpu_mesh: &GpuMesh,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> bool {
+        gpu_mesh.mark_used(self.frame_idx);
+        if gpu_mesh.is_resident() {
+            return true;
+        }
 
-        let build_entry = wgpu::BlasBuildEntry {
-            blas: &blas,
-            geometry: wgpu::BlasGeometries::TriangleGeometries(vec![triangle_geometry]),
+        let vertex_data = gpu_mesh
+            .vertex_data
+            .as_ref()
+            .expect("Only meshes with cpu side vertex data are evicted!");
+        if !self.fit_mesh_budget(
+            VertexPool::alloc_bytes(gpu_mesh.num_vertices, gpu_mesh.num_indices),
+            command_encoder,
+            &ctx.device,
+        ) {
+            return false;
+        }
+        let Ok(vertex_pool_alloc) = self
+            .vertex_pool
+            .alloc(gpu_mesh.num_vertices, gpu_mesh.num_indices)
+        else {
+            return false;
         };
 
-        command_encoder.build_acceleration_structures(iter::once(&build_entry), iter::empty());
+        self.vertex_pool.write_vertex_data(
+            &VertexPoolWriteData {
+                packed_vertices: &vertex_data.packed_vertices,
+                indices: &vertex_data.indices,
+                triangle_material_indices: &vertex_data.triangle_material_indices,
+            },
+            &vertex_pool_alloc,
+            &ctx.queue,
+        );
 
-        let gpu_mesh = Arc::new(GpuMesh {
-            vertex_pool_alloc,
-            blas,
-            bounds_min: mesh.bounds_min.into(),
-            bounds_max: mesh.bounds_max.into(),
+        let blas = self.supports_ray_tracing().then(|| {
+            self.create_blas(
+                &vertex_pool_alloc,
+                gpu_mesh.opaque,
+                wgpu::AccelerationStructureUpdateMode::Build,
+                &ctx.device,
+            )
         });
-        self.gpu_meshes.push(gpu_mesh.clone());
-        Some(gpu_mesh)
+        {
+            let mut residency = gpu_mesh.residency.lock().unwrap();
+            residency.vertex_pool_alloc = Some(vertex_pool_alloc);
+            residency.blas = blas;
+        }
+        if self.supports_ray_tracing() {
+            self.build_blases(iter::once(gpu_mesh), command_encoder);
+        }
+        true
     }
 
     pub fn create_gpu_material(
         &mut self,
         model: &Model,
         material: &Material,
+        command_encoder: &mut wgpu::CommandEncoder,
         ctx: &wgpu_util::Context,
-    ) -> Arc<GpuMaterial> {
-        let material_idx =
-            self.material_pool
-                .alloc_material(model, material, &ctx.device, &ctx.queue);
+    ) -> Result<Arc<GpuMaterial>> {
+        let texture_budget_bytes = self
+            .residency_budget
+            .saturating_sub(self.vertex_pool.used_bytes());
+        let material_idx = self.material_pool.alloc_material(
+            model,
+            material,
+            texture_budget_bytes,
+            &ctx.device,
+            &ctx.queue,
+            command_encoder,
+        )?;
+
+        let gpu_material = Arc::new(GpuMaterial { material_idx });
+        self.gpu_materials.push(gpu_material.clone());
+        Ok(gpu_material)
+    }
+
+    /// Create a material from code, see `MaterialBuilder`.
+    pub fn build_gpu_material(
+        &mut self,
+        builder: &MaterialBuilder,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> Result<Arc<GpuMaterial>> {
+        let texture_budget_bytes = self
+            .residency_budget
+            .saturating_sub(self.vertex_pool.used_bytes());
+        let material_idx = self.material_pool.build_material(
+            builder,
+            texture_budget_bytes,
+            &ctx.device,
+            &ctx.queue,
+            command_encoder,
+        )?;
 
         let gpu_material = Arc::new(GpuMaterial { material_idx });
         self.gpu_materials.push(gpu_material.clone());
-        gpu_material
+        Ok(gpu_material)
     }
 
-    pub fn duplicate_gpu_material(&mut self, gpu_material: &Arc<GpuMaterial>) -> Arc<GpuMaterial> {
+    /// Replace the texture bound to `slot` of `gpu_material`, or clear it when `texture` is `None`.
+    pub fn set_gpu_material_texture(
+        &mut self,
+        gpu_material: &GpuMaterial,
+        slot: MaterialTextureSlot,
+        texture: Option<&Texture>,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> Result<()> {
+        let texture_budget_bytes = self
+            .residency_budget
+            .saturating_sub(self.vertex_pool.used_bytes());
+        self.material_pool.set_material_texture(
+            gpu_material.material_idx,
+            slot,
+            texture,
+            texture_budget_bytes,
+            &ctx.device,
+            &ctx.queue,
+            command_encoder,
+        )
+    }
+
+    /// Parameters of `gpu_material`, changes are uploaded during the next `update`.
+    pub fn gpu_material_descriptor_mut(
+        &mut self,
+        gpu_material: &GpuMaterial,
+    ) -> &mut MaterialDescriptor {
+        self.material_pool
+            .material_descriptor_mut(gpu_material.material_idx)
+    }
+
+    pub fn duplicate_gpu_material(
+        &mut self,
+        gpu_material: &Arc<GpuMaterial>,
+    ) -> Result<Arc<GpuMaterial>> {
         let material_idx = self
             .material_pool
-            .duplicate_material(gpu_material.material_idx);
+            .duplicate_material(gpu_material.material_idx)?;
 
         let gpu_material = Arc::new(GpuMaterial { material_idx });
         self.gpu_materials.push(gpu_material.clone());
-        gpu_material
+        Ok(gpu_material)
+    }
+
+    /// Gpu memory available to meshes and textures, unused meshes are evicted and texture mips are streamed out to stay within it.
+    pub fn set_residency_budget(&mut self, budget_bytes: u64) {
+        self.residency_budget = budget_bytes;
+    }
+
+    /// Build a `MeshBvh` for every mesh created from now on, see `GpuMesh::cpu_bvh`.
+    pub fn set_build_cpu_bvhs(&mut self, build_cpu_bvhs: bool) {
+        self.build_cpu_bvhs = build_cpu_bvhs;
+    }
+
+    pub fn residency_stats(&self) -> ResidencyStats {
+        ResidencyStats {
+            budget_bytes: self.residency_budget,
+            texture_bytes: self.material_pool.texture_bytes(),
+            mesh_bytes: self.vertex_pool.used_bytes(),
+            evicted_mesh_bytes: self
+                .gpu_meshes
+                .iter()
+                .filter(|gpu_mesh| !gpu_mesh.is_resident())
+                .map(|gpu_mesh| {
+                    VertexPool::alloc_bytes(gpu_mesh.num_vertices, gpu_mesh.num_indices)
+                })
+                .sum(),
+        }
+    }
+
+    /// Instances and lights culled during the last `update`.
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culling_stats
     }
 
     pub fn vertex_pool(&self) -> &VertexPool {
@@ -245,6 +1141,14 @@ impl GpuResources {
         &mut self.linear_transformed_cosines
     }
 
+    pub fn punctual_lights(&self) -> &PunctualLights {
+        &self.punctual_lights
+    }
+
+    pub fn emissive_lights(&self) -> &EmissiveLights {
+        &self.emissive_lights
+    }
+
     pub fn debug_lines(&self) -> &DebugLines {
         &self.debug_lines
     }
@@ -253,12 +1157,59 @@ impl GpuResources {
         &mut self.debug_lines
     }
 
+    pub fn supports_ray_tracing(&self) -> bool {
+        self.static_tlas_package.is_some()
+    }
+
     pub fn static_tlas(&self) -> &wgpu::Tlas {
-        self.static_tlas_package.tlas()
+        self.static_tlas_package
+            .as_ref()
+            .expect("Ray tracing is not supported by this device.")
+            .tlas()
     }
 
     pub fn dynamic_tlas(&self) -> &wgpu::Tlas {
-        self.dynamic_tlas_package.tlas()
+        self.dynamic_tlas_package
+            .as_ref()
+            .expect("Ray tracing is not supported by this device.")
+            .tlas()
+    }
+
+    /// All static and dynamic mesh instances submitted during the last `update` using `RenderPath::Raster`.
+    pub fn raster_instances(&self) -> impl Iterator<Item = &RasterInstance> {
+        self.static_instances
+            .values()
+            .map(|static_instance| &static_instance.raster_instance)
+            .chain(self.dynamic_raster_instances.iter())
+    }
+
+    /// All static and dynamic mesh instances with at least one blended material, drawn by the transparent pass in either render path.
+    pub fn transparent_instances(&self) -> impl Iterator<Item = &RasterInstance> {
+        self.static_instances
+            .values()
+            .filter(|static_instance| {
+                self.has_blended_material(static_instance.material_indices.iter().copied())
+            })
+            .map(|static_instance| &static_instance.raster_instance)
+            .chain(self.dynamic_transparent_instances.iter())
+    }
+
+    fn has_blended_material(&self, mut material_indices: impl Iterator<Item = u32>) -> bool {
+        material_indices.any(|material_idx| {
+            self.material_pool
+                .material_descriptor(material_idx)
+                .alpha_mode()
+                == AlphaMode::Blend
+        })
+    }
+
+    fn has_emissive_material(&self, mut material_indices: impl Iterator<Item = u32>) -> bool {
+        material_indices.any(|material_idx| {
+            self.material_pool
+                .material_descriptor(material_idx)
+                .emission
+                != Vec3::ZERO
+        })
     }
 
     pub fn sky(&self) -> &Sky {
@@ -282,25 +1233,120 @@ impl GpuResources {
             if Arc::strong_count(gpu_mesh) == 1 {
                 gpu_mesh_indices_to_remove.push(i);
 
-                self.vertex_pool.free(&gpu_mesh.vertex_pool_alloc);
+                if let Some(vertex_pool_alloc) =
+                    &gpu_mesh.residency.lock().unwrap().vertex_pool_alloc
+                {
+                    self.vertex_pool.free(vertex_pool_alloc);
+                }
+                if let Some(skin_vertex_alloc) = &gpu_mesh.skin_vertex_alloc {
+                    self.skinning.free(skin_vertex_alloc);
+                }
+                if let Some(morph_target_alloc) = &gpu_mesh.morph_target_alloc {
+                    self.vertex_pool
+                        .free_morph_target_deltas(morph_target_alloc);
+                }
             }
         }
         vec_remove_multiple(&mut self.gpu_meshes, &mut gpu_mesh_indices_to_remove);
     }
 
+    /// Resynchronize all static entities during the next `update`.
+    /// Every static entity is visited, but only added, removed or changed instances are written to the gpu.
     pub fn mark_statics_dirty(&mut self) {
         self.static_dirty = true;
     }
 
+    /// Resynchronize a single static entity and its descendants during the next `update`.
+    /// Moved static entities are picked up by `update` itself, call this after adding, removing or editing the mesh of a static entity.
+    pub fn mark_static_entity_dirty(&mut self, entity: specs::Entity) {
+        self.dirty_static_entities.push(entity);
+    }
+
+    /// Entities of the dynamic instances submitted during the last `update`, indexed by instance index.
+    pub fn dynamic_instance_entities(&self) -> &[specs::Entity] {
+        &self.dynamic_instance_entities
+    }
+
+    /// Entity of a vertex pool instance index. Dynamic instances are looked up in `dynamic_instance_entities`,
+    /// which allows resolving indices of an earlier frame against the entities of that frame.
+    pub fn instance_entity(
+        &self,
+        instance_idx: u32,
+        dynamic_instance_entities: &[specs::Entity],
+    ) -> Option<specs::Entity> {
+        if (instance_idx as usize) < MAX_DYNAMIC_INSTANCES {
+            return dynamic_instance_entities
+                .get(instance_idx as usize)
+                .copied();
+        }
+
+        let slot = instance_idx - MAX_DYNAMIC_INSTANCES as u32;
+        self.static_slot_entities
+            .get(slot as usize)
+            .copied()
+            .flatten()
+    }
+
+    /// Index of the static tlas instance belonging to `entity`, stable until the entity is removed or becomes non-static.
+    pub fn static_tlas_instance_index(&self, entity: specs::Entity) -> Option<u32> {
+        self.static_instances
+            .get(&entity)
+            .map(|static_instance| static_instance.slot)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn update(
         &mut self,
         world: &specs::World,
         xr_camera_state: &XrCameraState,
+        render_path: RenderPath,
+        culling_parameters: &CullingParameters,
+        delta_time: f32,
         command_encoder: &mut wgpu::CommandEncoder,
-        queue: &wgpu::Queue,
+        ctx: &wgpu_util::Context,
+        pipeline_database: &mut PipelineDatabase,
     ) {
+        assert!(
+            render_path == RenderPath::Raster || self.supports_ray_tracing(),
+            "Cannot use RenderPath::RayTraced on a device without ray tracing support!"
+        );
+
         self.cleanup();
 
+        // Statics stay in use as long as their instance exists, only meshes without any instance this frame are evicted
+        self.frame_idx += 1;
+        for static_instance in self.static_instances.values() {
+            static_instance
+                .raster_instance
+                .gpu_mesh
+                .mark_used(self.frame_idx);
+        }
+
+        let mut transform_propagation_system = TransformPropagationSystem::default();
+        transform_propagation_system.run_now(world);
+        self.dirty_static_entities
+            .extend(transform_propagation_system.changed_static_entities);
+        AnimationSystem { delta_time }.run_now(world);
+
+        let mut lod_system = LodSystem {
+            camera_position: xr_camera_state.stage_translation,
+            projection_scale: xr_camera_state.view_to_clip_space[0].y_axis.y,
+            changed_static_entities: Vec::new(),
+        };
+        lod_system.run_now(world);
+        self.dirty_static_entities
+            .extend(lod_system.changed_static_entities);
+
+        // Statics are only submitted once for a single path, so switching requires a resubmit
+        if self.render_path != Some(render_path) {
+            self.render_path = Some(render_path);
+            self.clear_static_instances();
+            self.static_dirty = true;
+        }
+
+        let mut culling_stats = CullingStats::default();
+        let render_distance = culling_parameters.render_distance;
+
         {
             let (transform_storage, area_light_storage): (
                 specs::ReadStorage<'_, TransformComponent>,
@@ -312,8 +1358,9 @@ impl GpuResources {
                 let transform = transform_component.get_local_to_world_matrix(&transform_storage);
                 let translation = transform.w_axis.xyz();
 
+                let max_distance = culling_parameters.area_light_distance;
                 if translation.distance_squared(xr_camera_state.stage_translation)
-                    < (1500.0 * 1500.0)
+                    < (max_distance * max_distance)
                 {
                     let color = area_light_component.color * area_light_component.intensity;
 
@@ -323,53 +1370,278 @@ impl GpuResources {
                         area_light_component.range_bias_factor,
                         area_light_component.double_sided,
                     );
+                    culling_stats.submitted_lights += 1;
+                } else {
+                    culling_stats.culled_lights += 1;
                 }
             }
         }
 
-        self.static_blas_instances.clear();
-        if self.static_dirty {
-            let (transform_storage, mesh_storage): (
+        {
+            let (
+                transform_storage,
+                point_light_storage,
+                spot_light_storage,
+                directional_light_storage,
+            ): (
                 specs::ReadStorage<'_, TransformComponent>,
-                specs::ReadStorage<'_, MeshComponent>,
+                specs::ReadStorage<'_, PointLightComponent>,
+                specs::ReadStorage<'_, SpotLightComponent>,
+                specs::ReadStorage<'_, DirectionalLightComponent>,
             ) = world.system_data();
-            for (transform_component, mesh_component) in (&transform_storage, &mesh_storage).join()
+
+            for (transform_component, point_light_component) in
+                (&transform_storage, &point_light_storage).join()
             {
-                if !mesh_component.enabled || !transform_component.is_static() {
-                    continue;
+                let transform = transform_component.get_local_to_world_matrix(&transform_storage);
+                let translation = transform.w_axis.xyz();
+
+                let max_distance = render_distance + point_light_component.range;
+                if translation.distance_squared(xr_camera_state.stage_translation)
+                    < (max_distance * max_distance)
+                {
+                    if self.punctual_lights.submit_point_light(
+                        translation,
+                        point_light_component.range,
+                        point_light_component.color * point_light_component.intensity,
+                    ) {
+                        culling_stats.submitted_lights += 1;
+                    } else {
+                        culling_stats.overflowed_lights += 1;
+                    }
+                } else {
+                    culling_stats.culled_lights += 1;
                 }
+            }
 
+            for (transform_component, spot_light_component) in
+                (&transform_storage, &spot_light_storage).join()
+            {
                 let transform = transform_component.get_local_to_world_matrix(&transform_storage);
-                let transform4x3 = transform.transpose().to_cols_array()[..12]
-                    .try_into()
-                    .unwrap();
+                let translation = transform.w_axis.xyz();
 
-                let gpu_mesh = &mesh_component.mesh;
-                let blas = &gpu_mesh.blas;
-                let vertex_slice_index = gpu_mesh.vertex_pool_alloc.index;
+                let max_distance = render_distance + spot_light_component.range;
+                if translation.distance_squared(xr_camera_state.stage_translation)
+                    < (max_distance * max_distance)
+                {
+                    if self.punctual_lights.submit_spot_light(
+                        translation,
+                        transform.transform_vector3(FORWARD),
+                        spot_light_component.range,
+                        spot_light_component.color * spot_light_component.intensity,
+                        spot_light_component.inner_angle,
+                        spot_light_component.outer_angle,
+                    ) {
+                        culling_stats.submitted_lights += 1;
+                    } else {
+                        culling_stats.overflowed_lights += 1;
+                    }
+                } else {
+                    culling_stats.culled_lights += 1;
+                }
+            }
 
-                let instance_idx = self.vertex_pool.submit_slice_instance(
-                    transform,
-                    true,
-                    vertex_slice_index,
-                    &mesh_component.materials,
-                );
+            for (transform_component, directional_light_component) in
+                (&transform_storage, &directional_light_storage).join()
+            {
+                let transform = transform_component.get_local_to_world_matrix(&transform_storage);
 
-                let blas_instance = wgpu::TlasInstance::new(blas, transform4x3, instance_idx, 0xff);
+                // Directional lights are never culled, only dropped beyond the limit
+                if self.punctual_lights.submit_directional_light(
+                    transform.transform_vector3(FORWARD),
+                    directional_light_component.color * directional_light_component.intensity,
+                ) {
+                    culling_stats.submitted_lights += 1;
+                } else {
+                    culling_stats.overflowed_lights += 1;
+                }
+            }
+        }
+
+        if self.static_dirty || !self.dirty_static_entities.is_empty() {
+            let (entities, transform_storage, mesh_storage, lod_mesh_storage): (
+                specs::Entities<'_>,
+                specs::ReadStorage<'_, TransformComponent>,
+                specs::ReadStorage<'_, MeshComponent>,
+                specs::ReadStorage<'_, LodMeshComponent>,
+            ) = world.system_data();
+            let lod_fade = |entity: specs::Entity| {
+                lod_mesh_storage
+                    .get(entity)
+                    .and_then(|lod_mesh_component| lod_mesh_component.active_crossfade())
+                    .map_or(0.0, |lod_crossfade| -lod_crossfade.fade)
+            };
+
+            if self.static_dirty {
+                let mut synced_entities = HashSet::new();
+                for (entity, transform_component, mesh_component) in
+                    (&entities, &transform_storage, &mesh_storage).join()
+                {
+                    if !mesh_component.enabled || !transform_component.is_static() {
+                        continue;
+                    }
+
+                    // Instances of meshes that don't fit are removed along with those of removed entities
+                    if !self.make_mesh_resident(&mesh_component.mesh, command_encoder, ctx) {
+                        culling_stats.non_resident_instances += 1;
+                        continue;
+                    }
+
+                    let transform =
+                        transform_component.get_local_to_world_matrix(&transform_storage);
+                    if !self.sync_static_instance(
+                        entity,
+                        transform,
+                        mesh_component,
+                        lod_fade(entity),
+                        render_path,
+                    ) {
+                        culling_stats.overflowed_instances += 1;
+                    }
+                    synced_entities.insert(entity);
+                }
+
+                let removed_entities: Vec<specs::Entity> = self
+                    .static_instances
+                    .keys()
+                    .filter(|entity| !synced_entities.contains(entity))
+                    .copied()
+                    .collect();
+                for entity in removed_entities {
+                    self.remove_static_instance(entity);
+                }
+
+                self.dirty_static_entities.clear();
+            } else {
+                let mut visited_entities = HashSet::new();
+                let mut stack = std::mem::take(&mut self.dirty_static_entities);
+                while let Some(entity) = stack.pop() {
+                    if !visited_entities.insert(entity) {
+                        continue;
+                    }
+
+                    // Removed entities no longer have any components, which removes their instance
+                    match (transform_storage.get(entity), mesh_storage.get(entity)) {
+                        (Some(transform_component), Some(mesh_component))
+                            if mesh_component.enabled && transform_component.is_static() =>
+                        {
+                            let transform =
+                                transform_component.get_local_to_world_matrix(&transform_storage);
+                            if !self.make_mesh_resident(&mesh_component.mesh, command_encoder, ctx)
+                            {
+                                culling_stats.non_resident_instances += 1;
+                                self.remove_static_instance(entity);
+                            } else if !self.sync_static_instance(
+                                entity,
+                                transform,
+                                mesh_component,
+                                lod_fade(entity),
+                                render_path,
+                            ) {
+                                culling_stats.overflowed_instances += 1;
+                            }
+                        }
+                        _ => self.remove_static_instance(entity),
+                    }
+
+                    if let Some(transform_component) = transform_storage.get(entity) {
+                        stack.extend(transform_component.children());
+                    }
+                }
+            }
+        }
+
+        // Editing a material can turn the statics using it into lights or back
+        if self.material_pool.has_dirty_materials() {
+            self.emissive_static_entities = self
+                .static_instances
+                .iter()
+                .filter(|(_, static_instance)| {
+                    self.has_emissive_material(static_instance.material_indices.iter().copied())
+                })
+                .map(|(entity, _)| *entity)
+                .collect();
+        }
+
+        // Static instances persist between updates, but their emissive triangles are resubmitted every frame.
+        // Emissive lights only rebuild their alias table when the submitted instances change.
+        if render_path == RenderPath::RayTraced {
+            for entity in &self.emissive_static_entities {
+                let static_instance = &self.static_instances[entity];
+                if static_instance
+                    .bounds_center
+                    .distance(xr_camera_state.stage_translation)
+                    - static_instance.bounds_radius
+                    > render_distance
+                {
+                    continue;
+                }
 
-                self.static_blas_instances.push(blas_instance);
+                let raster_instance = &static_instance.raster_instance;
+                let gpu_mesh = &raster_instance.gpu_mesh;
+                self.emissive_lights.submit_instance(
+                    raster_instance.local_to_world,
+                    raster_instance.instance_idx,
+                    gpu_mesh,
+                    static_instance.material_indices.iter().map(|material_idx| {
+                        self.material_pool
+                            .material_descriptor(*material_idx)
+                            .emission
+                    }),
+                );
             }
         }
 
         self.dynamic_blas_instances.clear();
+        self.dynamic_raster_instances.clear();
+        self.dynamic_transparent_instances.clear();
+        self.dynamic_instance_entities.clear();
+        let mut deformed_gpu_meshes = Vec::new();
         {
-            let (transform_storage, mesh_storage, dynamic_storage): (
+            let (
+                entities,
+                transform_storage,
+                mesh_storage,
+                dynamic_storage,
+                visibility_range_storage,
+                lod_mesh_storage,
+                skinned_mesh_storage,
+                morph_weights_storage,
+            ): (
+                specs::Entities<'_>,
                 specs::ReadStorage<'_, TransformComponent>,
                 specs::ReadStorage<'_, MeshComponent>,
                 specs::ReadStorage<'_, DynamicComponent>,
+                specs::ReadStorage<'_, VisibilityRangeComponent>,
+                specs::ReadStorage<'_, LodMeshComponent>,
+                specs::ReadStorage<'_, SkinnedMeshComponent>,
+                specs::ReadStorage<'_, MorphWeightsComponent>,
             ) = world.system_data();
-            for (transform_component, mesh_component, _) in
-                (&transform_storage, &mesh_storage, &dynamic_storage).join()
+
+            let frustum = culling_parameters
+                .frustum_culling
+                .then(|| Frustum::new(&xr_camera_state.calculate_camera_data()));
+
+            for (
+                entity,
+                transform_component,
+                mesh_component,
+                _,
+                visibility_range_component,
+                lod_mesh_component,
+                skinned_mesh_component,
+                morph_weights_component,
+            ) in (
+                &entities,
+                &transform_storage,
+                &mesh_storage,
+                &dynamic_storage,
+                visibility_range_storage.maybe(),
+                lod_mesh_storage.maybe(),
+                skinned_mesh_storage.maybe(),
+                morph_weights_storage.maybe(),
+            )
+                .join()
             {
                 assert!(!transform_component.is_static(), "Detected a static TransformComponent on an entity containing the DynamicComponent!");
                 if !mesh_component.enabled {
@@ -377,71 +1649,589 @@ impl GpuResources {
                 }
 
                 let transform = transform_component.get_local_to_world_matrix(&transform_storage);
+
+                // Deformable meshes keep the bounds of their bind pose, which the deformation can leave
+                let (center, radius) =
+                    if skinned_mesh_component.is_some() || morph_weights_component.is_some() {
+                        let morph_padding = morph_weights_component.map_or(0.0, |morph_weights| {
+                            morph_weights
+                                .weights
+                                .iter()
+                                .zip(&morph_weights.bind_pose_mesh.morph_target_extents)
+                                .map(|(weight, extent)| weight.abs() * extent)
+                                .sum()
+                        });
+                        deformed_bounding_sphere(
+                            transform,
+                            mesh_component.mesh.bounds_min,
+                            mesh_component.mesh.bounds_max,
+                            morph_padding,
+                            skinned_mesh_component.map_or(&[][..], |skinned_mesh| {
+                                skinned_mesh.joint_matrices.as_slice()
+                            }),
+                        )
+                    } else {
+                        bounding_sphere(
+                            transform,
+                            mesh_component.mesh.bounds_min,
+                            mesh_component.mesh.bounds_max,
+                        )
+                    };
+                let distance =
+                    (center.distance(xr_camera_state.stage_translation) - radius).max(0.0);
+                if distance > render_distance {
+                    culling_stats.distance_culled_instances += 1;
+                    continue;
+                }
+                if visibility_range_component
+                    .is_some_and(|visibility_range| !visibility_range.contains(distance))
+                {
+                    culling_stats.range_culled_instances += 1;
+                    continue;
+                }
+                if frustum
+                    .as_ref()
+                    .is_some_and(|frustum| !frustum.intersects_sphere(center, radius))
+                {
+                    culling_stats.frustum_culled_instances += 1;
+                    continue;
+                }
+                if self.dynamic_instance_entities.len() >= MAX_DYNAMIC_INSTANCES {
+                    culling_stats.overflowed_instances += 1;
+                    continue;
+                }
+                if !self.make_mesh_resident(&mesh_component.mesh, command_encoder, ctx) {
+                    culling_stats.non_resident_instances += 1;
+                    continue;
+                }
+
+                // Only visible instances are deformed, culled ones keep the vertices of their last visible frame.
+                // Meshes not created through `create_deformable_gpu_mesh` have no vertices of their own and stay undeformed.
+                let prev_first_vertex =
+                    if skinned_mesh_component.is_some() || morph_weights_component.is_some() {
+                        mesh_component
+                            .mesh
+                            .vertex_pool_alloc()
+                            .prev_vertex_alloc
+                            .as_ref()
+                            .map(|prev_vertex_alloc| prev_vertex_alloc.start() as u32)
+                    } else {
+                        None
+                    };
+                if let Some(prev_first_vertex) = prev_first_vertex {
+                    if !self.submit_deformation(
+                        &mesh_component.mesh,
+                        prev_first_vertex,
+                        skinned_mesh_component,
+                        morph_weights_component,
+                    ) {
+                        culling_stats.deformation_overflowed_instances += 1;
+                        continue;
+                    }
+                    deformed_gpu_meshes.push(mesh_component.mesh.clone());
+                }
+                culling_stats.submitted_instances += 1;
+
                 let transform4x3 = transform.transpose().to_cols_array()[..12]
                     .try_into()
                     .unwrap();
 
                 let gpu_mesh = &mesh_component.mesh;
-                let blas = &gpu_mesh.blas;
-                let vertex_slice_index = gpu_mesh.vertex_pool_alloc.index;
+                let vertex_slice_index = gpu_mesh.vertex_pool_alloc().index;
 
                 let instance_idx = self.vertex_pool.submit_slice_instance(
+                    entity,
                     transform,
-                    false,
                     vertex_slice_index,
                     &mesh_component.materials,
                 );
+                self.dynamic_instance_entities.push(entity);
+
+                if self.has_blended_material(
+                    mesh_component
+                        .materials
+                        .iter()
+                        .map(|material| material.material_idx),
+                ) {
+                    self.dynamic_transparent_instances.push(RasterInstance {
+                        local_to_world: transform,
+                        instance_idx,
+                        gpu_mesh: gpu_mesh.clone(),
+                        lod_fade: 0.0,
+                    });
+                }
 
-                let blas_instance = wgpu::TlasInstance::new(blas, transform4x3, instance_idx, 0xff);
+                let lod_crossfade = lod_mesh_component
+                    .and_then(|lod_mesh_component| lod_mesh_component.active_crossfade());
+                let lod_fade = lod_crossfade.map_or(0.0, |lod_crossfade| -lod_crossfade.fade);
+
+                match render_path {
+                    RenderPath::RayTraced => {
+                        let blas = gpu_mesh.blas().unwrap();
+                        let blas_instance = wgpu::TlasInstance::new(
+                            &blas,
+                            transform4x3,
+                            instance_idx,
+                            lod_fade_mask(lod_fade),
+                        );
+
+                        self.dynamic_blas_instances.push(blas_instance);
+                        self.emissive_lights.submit_instance(
+                            transform,
+                            instance_idx,
+                            gpu_mesh,
+                            mesh_component.materials.iter().map(|material| {
+                                self.material_pool
+                                    .material_descriptor(material.material_idx)
+                                    .emission
+                            }),
+                        );
+                    }
+                    RenderPath::Raster => {
+                        self.dynamic_raster_instances.push(RasterInstance {
+                            local_to_world: transform,
+                            instance_idx,
+                            gpu_mesh: gpu_mesh.clone(),
+                            lod_fade,
+                        });
+                    }
+                }
 
-                self.dynamic_blas_instances.push(blas_instance);
+                // The other level of a transition is drawn as an additional instance
+                if let Some(lod_crossfade) = lod_crossfade {
+                    if !self.make_mesh_resident(&lod_crossfade.mesh, command_encoder, ctx) {
+                        culling_stats.non_resident_instances += 1;
+                    } else if !self.submit_lod_crossfade_instance(
+                        entity,
+                        transform,
+                        lod_crossfade,
+                        &mesh_component.materials,
+                        render_path,
+                    ) {
+                        culling_stats.overflowed_instances += 1;
+                    }
+                }
             }
-        }
 
-        let num_blas_instances = self.dynamic_blas_instances.len();
-        assert!(num_blas_instances <= MAX_DYNAMIC_INSTANCES);
-        let tlas_package_instances = self
-            .dynamic_tlas_package
-            .get_mut_slice(0..MAX_DYNAMIC_INSTANCES)
-            .unwrap();
-        for (i, instance) in self.dynamic_blas_instances.iter().enumerate() {
-            tlas_package_instances[i] = Some(instance.clone());
+            // Static instances hold a single level, the other level of a static transition is drawn as a dynamic instance
+            for (entity, transform_component, mesh_component, lod_mesh_component) in (
+                &entities,
+                &transform_storage,
+                &mesh_storage,
+                &lod_mesh_storage,
+            )
+                .join()
+            {
+                let Some(lod_crossfade) = lod_mesh_component.active_crossfade() else {
+                    continue;
+                };
+                if !mesh_component.enabled || !transform_component.is_static() {
+                    continue;
+                }
+
+                let transform = transform_component.get_local_to_world_matrix(&transform_storage);
+                if !self.make_mesh_resident(&lod_crossfade.mesh, command_encoder, ctx) {
+                    culling_stats.non_resident_instances += 1;
+                } else if !self.submit_lod_crossfade_instance(
+                    entity,
+                    transform,
+                    lod_crossfade,
+                    &mesh_component.materials,
+                    render_path,
+                ) {
+                    culling_stats.overflowed_instances += 1;
+                }
+            }
         }
-        for instance in tlas_package_instances.iter_mut().skip(num_blas_instances) {
-            *instance = None;
+
+        self.culling_stats = culling_stats;
+
+        if render_path == RenderPath::RayTraced {
+            self.update_tlas_instances();
         }
 
-        if self.static_dirty {
-            let num_blas_instances = self.static_blas_instances.len();
-            assert!(num_blas_instances <= MAX_STATIC_INSTANCES);
-            let tlas_package_instances = self
-                .static_tlas_package
-                .get_mut_slice(0..MAX_STATIC_INSTANCES)
-                .unwrap();
-            for (i, instance) in self.static_blas_instances.iter().enumerate() {
-                tlas_package_instances[i] = Some(instance.clone());
+        for static_instance in self.static_instances.values() {
+            let distance = (static_instance
+                .bounds_center
+                .distance(xr_camera_state.stage_translation)
+                - static_instance.bounds_radius)
+                .max(0.0);
+
+            let base_mip = desired_base_mip(distance);
+            for material_idx in &static_instance.material_indices {
+                self.material_pool.request_material(*material_idx, base_mip);
             }
-            for instance in tlas_package_instances.iter_mut().skip(num_blas_instances) {
-                *instance = None;
+        }
+        {
+            let (transform_storage, mesh_storage): (
+                specs::ReadStorage<'_, TransformComponent>,
+                specs::ReadStorage<'_, MeshComponent>,
+            ) = world.system_data();
+            for (transform_component, mesh_component) in (&transform_storage, &mesh_storage).join()
+            {
+                // Statics use the bounds cached in their instance
+                if !mesh_component.enabled || transform_component.is_static() {
+                    continue;
+                }
+
+                let transform = transform_component.get_local_to_world_matrix(&transform_storage);
+                let (center, radius) = bounding_sphere(
+                    transform,
+                    mesh_component.mesh.bounds_min,
+                    mesh_component.mesh.bounds_max,
+                );
+                let distance =
+                    (center.distance(xr_camera_state.stage_translation) - radius).max(0.0);
+
+                let base_mip = desired_base_mip(distance);
+                for material in &mesh_component.materials {
+                    self.material_pool
+                        .request_material(material.material_idx, base_mip);
+                }
             }
         }
+        self.material_pool.update_residency(
+            self.residency_budget
+                .saturating_sub(self.vertex_pool.used_bytes()),
+            &ctx.device,
+            &ctx.queue,
+            command_encoder,
+        );
 
+        let queue = &ctx.queue;
         self.vertex_pool.write_slices(queue);
         self.material_pool.write_materials(queue);
         self.linear_transformed_cosines.write_instances(queue);
+        self.punctual_lights.write_instances(queue);
+        self.emissive_lights.write_instances(queue);
         self.debug_lines.write_lines(queue);
+        self.morph_targets.write_instances(queue);
+        self.skinning.write_instances(queue);
+
+        morph_target_pass::encode(
+            &MorphTargetPassParameters {
+                vertex_pool: &self.vertex_pool,
+                morph_targets: &self.morph_targets,
+            },
+            &ctx.device,
+            command_encoder,
+            pipeline_database,
+        );
+        skinning_pass::encode(
+            &SkinningPassParameters {
+                vertex_pool: &self.vertex_pool,
+                skinning: &self.skinning,
+            },
+            &ctx.device,
+            command_encoder,
+            pipeline_database,
+        );
+
+        self.sky.constants.camera_altitude = xr_camera_state
+            .stage_translation
+            .dot(self.sky.constants.world_up);
+        let update_atmosphere_luts = self.sky.take_atmosphere_changed();
+        sky_lut_pass::encode(
+            &SkyLutPassParameters {
+                sky: &self.sky,
+                update_atmosphere_luts,
+            },
+            &ctx.device,
+            command_encoder,
+            pipeline_database,
+        );
+
+        // Deformed blases have to be refit before the dynamic tlas referencing them is built
+        if render_path == RenderPath::RayTraced && !deformed_gpu_meshes.is_empty() {
+            self.build_blases(
+                deformed_gpu_meshes.iter().map(|gpu_mesh| gpu_mesh.as_ref()),
+                command_encoder,
+            );
+        }
+
+        if render_path == RenderPath::RayTraced {
+            let mut tlases = vec![self.dynamic_tlas_package.as_ref().unwrap()];
+            if self.static_tlas_dirty {
+                tlases.push(self.static_tlas_package.as_ref().unwrap());
+            }
+            command_encoder.build_acceleration_structures(iter::empty(), tlases);
+            self.static_tlas_dirty = false;
+        }
+        self.static_dirty = false;
+    }
+
+    /// Write the instance of a static entity into its slot, skipped when nothing changed since the last sync.
+    fn sync_static_instance(
+        &mut self,
+        entity: specs::Entity,
+        transform: Mat4,
+        mesh_component: &MeshComponent,
+        lod_fade: f32,
+        render_path: RenderPath,
+    ) -> bool {
+        let gpu_mesh = &mesh_component.mesh;
+        let material_indices: Vec<u32> = mesh_component
+            .materials
+            .iter()
+            .map(|material| material.material_idx)
+            .collect();
+
+        let slot = if let Some(static_instance) = self.static_instances.get(&entity) {
+            if static_instance.raster_instance.local_to_world == transform
+                && Arc::ptr_eq(&static_instance.raster_instance.gpu_mesh, gpu_mesh)
+                && static_instance.material_indices == material_indices
+                && static_instance.raster_instance.lod_fade == lod_fade
+            {
+                return true;
+            }
+
+            static_instance.slot
+        } else {
+            let Some(slot) = self.alloc_static_slot() else {
+                return false;
+            };
+            slot
+        };
+
+        let instance_idx = self.vertex_pool.write_static_slice_instance(
+            slot,
+            gpu_mesh.vertex_pool_alloc().index,
+            &mesh_component.materials,
+        );
+
+        if render_path == RenderPath::RayTraced {
+            let transform4x3 = transform.transpose().to_cols_array()[..12]
+                .try_into()
+                .unwrap();
+            let blas = gpu_mesh.blas().unwrap();
+
+            self.static_tlas_package.as_mut().unwrap()[slot as usize] = Some(
+                wgpu::TlasInstance::new(&blas, transform4x3, instance_idx, lod_fade_mask(lod_fade)),
+            );
+            self.static_tlas_dirty = true;
+        }
 
-        let mut tlases = vec![&self.dynamic_tlas_package];
-        if self.static_dirty {
-            tlases.push(&self.static_tlas_package);
-            self.static_dirty = false;
+        let (bounds_center, bounds_radius) =
+            bounding_sphere(transform, gpu_mesh.bounds_min, gpu_mesh.bounds_max);
+        if self.has_emissive_material(material_indices.iter().copied()) {
+            self.emissive_static_entities.insert(entity);
+        } else {
+            self.emissive_static_entities.remove(&entity);
+        }
+        if self.static_slot_entities.len() <= slot as usize {
+            self.static_slot_entities.resize(slot as usize + 1, None);
+        }
+        self.static_slot_entities[slot as usize] = Some(entity);
+        self.static_instances.insert(
+            entity,
+            StaticInstance {
+                slot,
+                material_indices,
+                bounds_center,
+                bounds_radius,
+                raster_instance: RasterInstance {
+                    local_to_world: transform,
+                    instance_idx,
+                    gpu_mesh: gpu_mesh.clone(),
+                    lod_fade,
+                },
+            },
+        );
+        true
+    }
+
+    /// Submit the morph targets and skinning of a deformable instance, returns false when they don't fit into this frame.
+    /// Either both or neither are submitted, deforming only part of the way would leave a mix of poses behind.
+    fn submit_deformation(
+        &mut self,
+        gpu_mesh: &GpuMesh,
+        prev_first_vertex: u32,
+        skinned_mesh_component: Option<&SkinnedMeshComponent>,
+        morph_weights_component: Option<&MorphWeightsComponent>,
+    ) -> bool {
+        if skinned_mesh_component.is_some_and(|skinned_mesh_component| {
+            !self
+                .skinning
+                .has_capacity(skinned_mesh_component.joint_matrices.len())
+        }) {
+            return false;
+        }
+        if morph_weights_component.is_some_and(|morph_weights_component| {
+            !self
+                .morph_targets
+                .has_capacity(morph_weights_component.weights.len())
+        }) {
+            return false;
+        }
+
+        let dst_first_vertex = gpu_mesh.vertex_pool_alloc().vertex_alloc.start() as u32;
+
+        // Morph targets are applied in place first, skinning then continues from the morphed vertices
+        if let Some(morph_weights_component) = morph_weights_component {
+            let bind_pose_mesh = &morph_weights_component.bind_pose_mesh;
+            let vertex_alloc = bind_pose_mesh.vertex_pool_alloc().vertex_alloc;
+            self.morph_targets.submit_instance(
+                vertex_alloc.start() as u32,
+                dst_first_vertex,
+                prev_first_vertex,
+                (vertex_alloc.end() - vertex_alloc.start()) as u32,
+                bind_pose_mesh
+                    .morph_target_alloc
+                    .as_ref()
+                    .expect("Bind pose mesh of a MorphWeightsComponent has no morph targets!"),
+                &morph_weights_component.weights,
+            );
+        }
+
+        if let Some(skinned_mesh_component) = skinned_mesh_component {
+            let bind_pose_mesh = &skinned_mesh_component.bind_pose_mesh;
+            let vertex_alloc = bind_pose_mesh.vertex_pool_alloc().vertex_alloc;
+            let src_first_vertex = if morph_weights_component.is_some() {
+                dst_first_vertex
+            } else {
+                vertex_alloc.start() as u32
+            };
+            self.skinning.submit_instance(
+                src_first_vertex,
+                dst_first_vertex,
+                prev_first_vertex,
+                (vertex_alloc.end() - vertex_alloc.start()) as u32,
+                bind_pose_mesh
+                    .skin_vertex_alloc
+                    .as_ref()
+                    .expect("Bind pose mesh of a SkinnedMeshComponent has no joint weights!"),
+                &skinned_mesh_component.joint_matrices,
+            );
+        }
+
+        true
+    }
+
+    /// Submit the level a transition dithers in or out of as an additional dynamic instance, returns false when out of instances.
+    fn submit_lod_crossfade_instance(
+        &mut self,
+        entity: specs::Entity,
+        transform: Mat4,
+        lod_crossfade: &LodCrossfade,
+        materials: &[Arc<GpuMaterial>],
+        render_path: RenderPath,
+    ) -> bool {
+        if self.dynamic_instance_entities.len() >= MAX_DYNAMIC_INSTANCES {
+            return false;
+        }
+
+        let gpu_mesh = &lod_crossfade.mesh;
+        let instance_idx = self.vertex_pool.submit_slice_instance(
+            entity,
+            transform,
+            gpu_mesh.vertex_pool_alloc().index,
+            materials,
+        );
+        self.dynamic_instance_entities.push(entity);
+
+        match render_path {
+            RenderPath::RayTraced => {
+                let transform4x3 = transform.transpose().to_cols_array()[..12]
+                    .try_into()
+                    .unwrap();
+                self.dynamic_blas_instances.push(wgpu::TlasInstance::new(
+                    &gpu_mesh.blas().unwrap(),
+                    transform4x3,
+                    instance_idx,
+                    lod_fade_mask(lod_crossfade.fade),
+                ));
+            }
+            RenderPath::Raster => {
+                self.dynamic_raster_instances.push(RasterInstance {
+                    local_to_world: transform,
+                    instance_idx,
+                    gpu_mesh: gpu_mesh.clone(),
+                    lod_fade: lod_crossfade.fade,
+                });
+            }
+        }
+
+        true
+    }
+
+    fn remove_static_instance(&mut self, entity: specs::Entity) {
+        let Some(static_instance) = self.static_instances.remove(&entity) else {
+            return;
+        };
+
+        if let Some(static_tlas_package) = &mut self.static_tlas_package {
+            static_tlas_package[static_instance.slot as usize] = None;
+            self.static_tlas_dirty = true;
+        }
+        self.static_slot_entities[static_instance.slot as usize] = None;
+        self.emissive_static_entities.remove(&entity);
+        self.free_static_slots.insert(static_instance.slot);
+    }
+
+    fn clear_static_instances(&mut self) {
+        let entities: Vec<specs::Entity> = self.static_instances.keys().copied().collect();
+        for entity in entities {
+            self.remove_static_instance(entity);
+        }
+
+        self.free_static_slots.clear();
+        self.static_slot_entities.clear();
+        self.next_static_slot = 0;
+        self.static_tlas_dirty = true;
+    }
+
+    // Lowest free slots are reused first, wgpu only uploads tlas instances up to the highest modified index
+    /// Returns `None` when all `MAX_STATIC_INSTANCES` slots are in use.
+    fn alloc_static_slot(&mut self) -> Option<u32> {
+        if let Some(slot) = self.free_static_slots.pop_first() {
+            return Some(slot);
+        }
+
+        let slot = self.next_static_slot;
+        if slot as usize >= MAX_STATIC_INSTANCES {
+            return None;
+        }
+        self.next_static_slot += 1;
+        Some(slot)
+    }
+
+    fn update_tlas_instances(&mut self) {
+        let num_blas_instances = self.dynamic_blas_instances.len();
+        assert!(num_blas_instances <= MAX_DYNAMIC_INSTANCES);
+        let tlas_package_instances = self
+            .dynamic_tlas_package
+            .as_mut()
+            .unwrap()
+            .get_mut_slice(0..MAX_DYNAMIC_INSTANCES)
+            .unwrap();
+        for (i, instance) in self.dynamic_blas_instances.iter().enumerate() {
+            tlas_package_instances[i] = Some(instance.clone());
+        }
+        for instance in tlas_package_instances.iter_mut().skip(num_blas_instances) {
+            *instance = None;
         }
-        command_encoder.build_acceleration_structures(iter::empty(), tlases);
     }
 
     pub fn end_frame(&mut self, command_encoder: &mut wgpu::CommandEncoder) {
         self.vertex_pool.end_frame();
         self.linear_transformed_cosines.end_frame();
+        self.punctual_lights.end_frame();
+        self.emissive_lights.end_frame();
+        self.skinning.end_frame();
+        self.morph_targets.end_frame();
         self.debug_lines.end_frame(command_encoder);
     }
 }
+
+#[test]
+fn lod_fade_masks_are_complementary() {
+    assert_eq!(lod_fade_mask(0.0), 0xff);
+    for fade in [0.1, 0.3, 0.5, 0.9] {
+        let fading_out = lod_fade_mask(fade);
+        let fading_in = lod_fade_mask(-fade);
+        assert_eq!(fading_out & fading_in, 0);
+        assert_eq!(fading_out | fading_in, 0xfe);
+    }
+    assert_eq!(lod_fade_mask(1.0), 0);
+    assert_eq!(lod_fade_mask(-1.0), 0xfe);
+}
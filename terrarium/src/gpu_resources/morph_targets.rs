@@ -0,0 +1,150 @@
+use bytemuck::{Pod, Zeroable};
+
+use super::linear_block_allocator::LinearBlockAllocation;
+
+const MAX_MORPH_TARGET_WEIGHTS: usize = 1024 * 16;
+const MAX_MORPH_TARGET_INSTANCES: usize = 1024;
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct MorphTargetInstance {
+    src_first_vertex: u32,
+    dst_first_vertex: u32,
+    prev_first_vertex: u32,
+    num_vertices: u32,
+    first_morph_target_delta: u32,
+    num_morph_targets: u32,
+    first_weight: u32,
+    _padding0: u32,
+}
+
+/// Morph targets stored in `num_deltas` deltas of a mesh with `num_vertices`, every target holds a delta per vertex.
+fn num_morph_targets(num_vertices: u32, num_deltas: u64) -> u32 {
+    if num_vertices == 0 {
+        return 0;
+    }
+
+    (num_deltas / num_vertices as u64) as u32
+}
+
+/// Morph target weights and instances submitted for the current frame, the deltas themselves live in the `VertexPool`.
+/// Every instance blends the deltas on top of a bind pose vertex pool allocation into its own allocation, see `morph_target_pass`.
+pub struct MorphTargets {
+    weight_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+
+    weights: Vec<f32>,
+    instances: Vec<MorphTargetInstance>,
+    max_instance_vertices: u32,
+}
+
+impl MorphTargets {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let weight_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::morph_targets weights"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<f32>() * MAX_MORPH_TARGET_WEIGHTS) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::morph_targets instances"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<MorphTargetInstance>() * MAX_MORPH_TARGET_INSTANCES) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            weight_buffer,
+            instance_buffer,
+            weights: Vec::new(),
+            instances: Vec::new(),
+            max_instance_vertices: 0,
+        }
+    }
+
+    /// Whether another instance with up to `num_weights` weights fits into this frame.
+    pub fn has_capacity(&self, num_weights: usize) -> bool {
+        self.instances.len() < MAX_MORPH_TARGET_INSTANCES
+            && self.weights.len() + num_weights <= MAX_MORPH_TARGET_WEIGHTS
+    }
+
+    /// Blend the morph targets of `num_vertices` starting at `src_first_vertex` into `dst_first_vertex` of the vertex pool during the next morph target pass.
+    /// The previous contents of `dst_first_vertex` are kept at `prev_first_vertex`. Missing weights are treated as zero.
+    /// Returns false without submitting anything when the frame is out of capacity, see `has_capacity`.
+    pub fn submit_instance(
+        &mut self,
+        src_first_vertex: u32,
+        dst_first_vertex: u32,
+        prev_first_vertex: u32,
+        num_vertices: u32,
+        morph_target_alloc: &LinearBlockAllocation,
+        weights: &[f32],
+    ) -> bool {
+        let num_morph_targets = num_morph_targets(
+            num_vertices,
+            morph_target_alloc.end() - morph_target_alloc.start(),
+        );
+        let num_weights = (weights.len() as u32).min(num_morph_targets);
+
+        if !self.has_capacity(num_weights as usize) {
+            return false;
+        }
+
+        self.instances.push(MorphTargetInstance {
+            src_first_vertex,
+            dst_first_vertex,
+            prev_first_vertex,
+            num_vertices,
+            first_morph_target_delta: morph_target_alloc.start() as u32,
+            num_morph_targets: num_weights,
+            first_weight: self.weights.len() as u32,
+            _padding0: 0,
+        });
+        self.weights
+            .extend_from_slice(&weights[..num_weights as usize]);
+        self.max_instance_vertices = self.max_instance_vertices.max(num_vertices);
+
+        true
+    }
+
+    pub fn write_instances(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        queue.write_buffer(&self.weight_buffer, 0, bytemuck::cast_slice(&self.weights));
+    }
+
+    pub fn end_frame(&mut self) {
+        self.instances.clear();
+        self.weights.clear();
+        self.max_instance_vertices = 0;
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    /// Vertex count of the largest instance submitted this frame.
+    pub fn max_instance_vertices(&self) -> u32 {
+        self.max_instance_vertices
+    }
+
+    pub fn weight_buffer(&self) -> &wgpu::Buffer {
+        &self.weight_buffer
+    }
+
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        &self.instance_buffer
+    }
+}
+
+#[test]
+fn morph_target_counts() {
+    assert_eq!(num_morph_targets(100, 300), 3);
+    assert_eq!(num_morph_targets(100, 0), 0);
+    assert_eq!(num_morph_targets(0, 0), 0);
+    assert_eq!(num_morph_targets(0, 300), 0);
+}
@@ -0,0 +1,291 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+const MAX_POINT_LIGHTS: usize = 1024 * 16;
+const MAX_SPOT_LIGHTS: usize = 1024 * 16;
+const MAX_DIRECTIONAL_LIGHTS: usize = 64;
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct Constants {
+    point_light_count: u32,
+    spot_light_count: u32,
+    directional_light_count: u32,
+    _padding0: u32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct PointLightInstance {
+    position: Vec3,
+    range: f32,
+    color: Vec3,
+    _padding0: u32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct SpotLightInstance {
+    position: Vec3,
+    range: f32,
+    color: Vec3,
+    cos_inner_angle: f32,
+    direction: Vec3,
+    cos_outer_angle: f32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct DirectionalLightInstance {
+    direction: Vec3,
+    _padding0: u32,
+    color: Vec3,
+    _padding1: u32,
+}
+
+/// Gpu instances of all point, spot and directional lights, the punctual counterpart of `LinearTransformedCosines`.
+pub struct PunctualLights {
+    constants_buffer: wgpu::Buffer,
+    point_lights_buffer: wgpu::Buffer,
+    spot_lights_buffer: wgpu::Buffer,
+    directional_lights_buffer: wgpu::Buffer,
+    point_lights: Vec<PointLightInstance>,
+    spot_lights: Vec<SpotLightInstance>,
+    directional_lights: Vec<DirectionalLightInstance>,
+
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl PunctualLights {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let constants_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::punctual_lights constants"),
+            size: size_of::<Constants>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let point_lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::punctual_lights point_lights"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<PointLightInstance>() * MAX_POINT_LIGHTS) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let spot_lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::punctual_lights spot_lights"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<SpotLightInstance>() * MAX_SPOT_LIGHTS) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let directional_lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::punctual_lights directional_lights"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<DirectionalLightInstance>() * MAX_DIRECTIONAL_LIGHTS) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                storage_entry(3),
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: constants_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: point_lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spot_lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: directional_lights_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            constants_buffer,
+            point_lights_buffer,
+            spot_lights_buffer,
+            directional_lights_buffer,
+            point_lights: Vec::new(),
+            spot_lights: Vec::new(),
+            directional_lights: Vec::new(),
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn write_instances(&mut self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.constants_buffer,
+            0,
+            bytemuck::bytes_of(&Constants {
+                point_light_count: self.point_lights.len() as u32,
+                spot_light_count: self.spot_lights.len() as u32,
+                directional_light_count: self.directional_lights.len() as u32,
+                _padding0: 0,
+            }),
+        );
+
+        queue.write_buffer(
+            &self.point_lights_buffer,
+            0,
+            bytemuck::cast_slice(&self.point_lights),
+        );
+        queue.write_buffer(
+            &self.spot_lights_buffer,
+            0,
+            bytemuck::cast_slice(&self.spot_lights),
+        );
+        queue.write_buffer(
+            &self.directional_lights_buffer,
+            0,
+            bytemuck::cast_slice(&self.directional_lights),
+        );
+    }
+
+    /// Returns false without submitting anything when the frame is out of point lights.
+    pub fn submit_point_light(&mut self, position: Vec3, range: f32, color: Vec3) -> bool {
+        if self.point_lights.len() >= MAX_POINT_LIGHTS {
+            return false;
+        }
+
+        self.point_lights.push(PointLightInstance {
+            position,
+            range,
+            color,
+            _padding0: 0,
+        });
+        true
+    }
+
+    /// `direction` points away from the light, angles are in radians.
+    /// Returns false without submitting anything when the frame is out of spot lights.
+    pub fn submit_spot_light(
+        &mut self,
+        position: Vec3,
+        direction: Vec3,
+        range: f32,
+        color: Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> bool {
+        if self.spot_lights.len() >= MAX_SPOT_LIGHTS {
+            return false;
+        }
+
+        let outer_angle = outer_angle.max(inner_angle + 1e-4);
+
+        self.spot_lights.push(SpotLightInstance {
+            position,
+            range,
+            color,
+            cos_inner_angle: inner_angle.cos(),
+            direction: direction.normalize(),
+            cos_outer_angle: outer_angle.cos(),
+        });
+        true
+    }
+
+    /// `direction` is the direction light travels in, pointing away from the light.
+    /// Returns false without submitting anything when the frame is out of directional lights.
+    pub fn submit_directional_light(&mut self, direction: Vec3, color: Vec3) -> bool {
+        if self.directional_lights.len() >= MAX_DIRECTIONAL_LIGHTS {
+            return false;
+        }
+
+        self.directional_lights.push(DirectionalLightInstance {
+            direction: direction.normalize(),
+            _padding0: 0,
+            color,
+            _padding1: 0,
+        });
+        true
+    }
+
+    pub fn point_light_count(&self) -> usize {
+        self.point_lights.len()
+    }
+
+    /// Position and range of a point light submitted this frame.
+    pub fn point_light(&self, point_light_idx: usize) -> (Vec3, f32) {
+        let point_light = &self.point_lights[point_light_idx];
+        (point_light.position, point_light.range)
+    }
+
+    pub fn spot_light_count(&self) -> usize {
+        self.spot_lights.len()
+    }
+
+    /// Position, direction, range and cosine of the outer cone angle of a spot light submitted this frame.
+    pub fn spot_light(&self, spot_light_idx: usize) -> (Vec3, Vec3, f32, f32) {
+        let spot_light = &self.spot_lights[spot_light_idx];
+        (
+            spot_light.position,
+            spot_light.direction,
+            spot_light.range,
+            spot_light.cos_outer_angle,
+        )
+    }
+
+    pub fn directional_light_count(&self) -> usize {
+        self.directional_lights.len()
+    }
+
+    /// Direction light travels in of a directional light submitted this frame.
+    pub fn directional_light_direction(&self, directional_light_idx: usize) -> Vec3 {
+        self.directional_lights[directional_light_idx].direction
+    }
+
+    pub fn end_frame(&mut self) {
+        self.point_lights.clear();
+        self.spot_lights.clear();
+        self.directional_lights.clear();
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
@@ -0,0 +1,83 @@
+/// Default gpu memory budget shared by streamed textures and vertex pool allocations.
+/// Meshes without an instance in the current frame are evicted once it is exceeded, least recently used first.
+pub const DEFAULT_RESIDENCY_BUDGET: u64 = 1024 * 1024 * 1024 * 2;
+/// Distance from the camera up to which textures are fully resident, every doubling of the distance drops another mip.
+pub const TEXTURE_STREAMING_DISTANCE: f32 = 16.0;
+/// Limits the number of textures uploaded in a single frame to avoid hitches when large parts of a scene come into view.
+pub const MAX_TEXTURE_UPLOADS_PER_FRAME: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResidencyStats {
+    pub budget_bytes: u64,
+    pub texture_bytes: u64,
+    pub mesh_bytes: u64,
+    /// Vertex pool bytes of evicted meshes, not part of `used_bytes` until they are rebuilt.
+    pub evicted_mesh_bytes: u64,
+}
+
+impl ResidencyStats {
+    pub fn used_bytes(&self) -> u64 {
+        self.texture_bytes + self.mesh_bytes
+    }
+}
+
+/// Base mip level needed for a surface at `distance` from the camera.
+pub fn desired_base_mip(distance: f32) -> u32 {
+    if distance <= TEXTURE_STREAMING_DISTANCE {
+        0
+    } else {
+        (distance / TEXTURE_STREAMING_DISTANCE).log2().floor() as u32
+    }
+}
+
+/// Gpu memory taken up by `mip_count` mips of a 2d texture, starting at `first_mip`.
+pub(crate) fn mip_chain_bytes(
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+    first_mip: u32,
+    mip_count: u32,
+) -> u64 {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+
+    (first_mip..first_mip + mip_count)
+        .map(|mip| {
+            let mip_size = size
+                .mip_level_size(mip, wgpu::TextureDimension::D2)
+                .physical_size(format);
+            (mip_size.width / block_width) as u64
+                * (mip_size.height / block_height) as u64
+                * mip_size.depth_or_array_layers as u64
+                * block_size
+        })
+        .sum()
+}
+
+#[test]
+fn desired_base_mip_halves_per_distance_doubling() {
+    assert_eq!(desired_base_mip(0.0), 0);
+    assert_eq!(desired_base_mip(TEXTURE_STREAMING_DISTANCE), 0);
+    assert_eq!(desired_base_mip(TEXTURE_STREAMING_DISTANCE * 2.0), 1);
+    assert_eq!(desired_base_mip(TEXTURE_STREAMING_DISTANCE * 5.0), 2);
+}
+
+#[test]
+fn mip_chain_bytes_of_tail() {
+    let size = wgpu::Extent3d {
+        width: 256,
+        height: 256,
+        depth_or_array_layers: 1,
+    };
+
+    let format = wgpu::TextureFormat::Rgba8Unorm;
+    assert_eq!(mip_chain_bytes(size, format, 0, 1), 256 * 256 * 4);
+    assert_eq!(
+        mip_chain_bytes(size, format, 0, 9),
+        mip_chain_bytes(size, format, 0, 2) + mip_chain_bytes(size, format, 2, 7)
+    );
+
+    // Mips smaller than a block still take up a full block
+    let format = wgpu::TextureFormat::Bc1RgbaUnorm;
+    assert_eq!(mip_chain_bytes(size, format, 0, 1), 64 * 64 * 8);
+    assert_eq!(mip_chain_bytes(size, format, 8, 1), 8);
+}
@@ -0,0 +1,186 @@
+use anyhow::{bail, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+
+use super::linear_block_allocator::{LinearBlockAllocation, LinearBlockAllocator};
+
+const MAX_SKIN_VERTICES: usize = 1024 * 1024 * 4;
+const MAX_JOINT_MATRICES: usize = 1024 * 64;
+const MAX_SKINNING_INSTANCES: usize = 1024;
+
+/// Joints influencing a single vertex, weights are expected to sum up to one.
+#[derive(Pod, Debug, Clone, Copy, Zeroable)]
+#[repr(C)]
+pub struct SkinVertex {
+    pub joint_indices: [u32; 4],
+    pub joint_weights: [f32; 4],
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct SkinningInstance {
+    src_first_vertex: u32,
+    dst_first_vertex: u32,
+    num_vertices: u32,
+    first_skin_vertex: u32,
+    first_joint_matrix: u32,
+    prev_first_vertex: u32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+/// Joint weights of all skinned meshes and the skinning instances submitted for the current frame.
+/// Every instance deforms a bind pose vertex pool allocation into its own allocation, see `skinning_pass`.
+pub struct Skinning {
+    skin_vertex_buffer: wgpu::Buffer,
+    joint_matrix_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+
+    skin_vertex_allocator: LinearBlockAllocator,
+    joint_matrices: Vec<Mat4>,
+    instances: Vec<SkinningInstance>,
+    max_instance_vertices: u32,
+}
+
+impl Skinning {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let skin_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::skinning skin_vertices"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<SkinVertex>() * MAX_SKIN_VERTICES) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let joint_matrix_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::skinning joint_matrices"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<Mat4>() * MAX_JOINT_MATRICES) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::skinning instances"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<SkinningInstance>() * MAX_SKINNING_INSTANCES) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            skin_vertex_buffer,
+            joint_matrix_buffer,
+            instance_buffer,
+            skin_vertex_allocator: LinearBlockAllocator::new(MAX_SKIN_VERTICES as u64),
+            joint_matrices: Vec::new(),
+            instances: Vec::new(),
+            max_instance_vertices: 0,
+        }
+    }
+
+    pub fn alloc(&mut self, num_vertices: u32) -> Result<LinearBlockAllocation> {
+        let Some(alloc) = self.skin_vertex_allocator.allocate(num_vertices as u64) else {
+            bail!(
+                "Skinning ran out of skin vertices, {} requested with {} / {} in use.",
+                num_vertices,
+                self.skin_vertex_allocator.used_size(),
+                self.skin_vertex_allocator.total_size()
+            );
+        };
+
+        Ok(alloc)
+    }
+
+    pub fn free(&mut self, alloc: &LinearBlockAllocation) {
+        self.skin_vertex_allocator.free(alloc);
+    }
+
+    pub fn write_skin_vertices(
+        &self,
+        skin_vertices: &[SkinVertex],
+        alloc: &LinearBlockAllocation,
+        queue: &wgpu::Queue,
+    ) {
+        queue.write_buffer(
+            &self.skin_vertex_buffer,
+            alloc.start() * std::mem::size_of::<SkinVertex>() as u64,
+            bytemuck::cast_slice(skin_vertices),
+        );
+    }
+
+    /// Whether another instance with `num_joint_matrices` joints fits into this frame.
+    pub fn has_capacity(&self, num_joint_matrices: usize) -> bool {
+        self.instances.len() < MAX_SKINNING_INSTANCES
+            && self.joint_matrices.len() + num_joint_matrices <= MAX_JOINT_MATRICES
+    }
+
+    /// Skin `num_vertices` starting at `src_first_vertex` into `dst_first_vertex` of the vertex pool during the next skinning pass.
+    /// The previous contents of `dst_first_vertex` are kept at `prev_first_vertex`, unless skinning happens in place.
+    /// Returns false without submitting anything when the frame is out of capacity, see `has_capacity`.
+    pub fn submit_instance(
+        &mut self,
+        src_first_vertex: u32,
+        dst_first_vertex: u32,
+        prev_first_vertex: u32,
+        num_vertices: u32,
+        skin_vertex_alloc: &LinearBlockAllocation,
+        joint_matrices: &[Mat4],
+    ) -> bool {
+        if !self.has_capacity(joint_matrices.len()) {
+            return false;
+        }
+
+        self.instances.push(SkinningInstance {
+            src_first_vertex,
+            dst_first_vertex,
+            num_vertices,
+            first_skin_vertex: skin_vertex_alloc.start() as u32,
+            first_joint_matrix: self.joint_matrices.len() as u32,
+            prev_first_vertex,
+            _padding0: 0,
+            _padding1: 0,
+        });
+        self.joint_matrices.extend_from_slice(joint_matrices);
+        self.max_instance_vertices = self.max_instance_vertices.max(num_vertices);
+
+        true
+    }
+
+    pub fn write_instances(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        queue.write_buffer(
+            &self.joint_matrix_buffer,
+            0,
+            bytemuck::cast_slice(&self.joint_matrices),
+        );
+    }
+
+    pub fn end_frame(&mut self) {
+        self.instances.clear();
+        self.joint_matrices.clear();
+        self.max_instance_vertices = 0;
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    /// Vertex count of the largest instance submitted this frame.
+    pub fn max_instance_vertices(&self) -> u32 {
+        self.max_instance_vertices
+    }
+
+    pub fn skin_vertex_buffer(&self) -> &wgpu::Buffer {
+        &self.skin_vertex_buffer
+    }
+
+    pub fn joint_matrix_buffer(&self) -> &wgpu::Buffer {
+        &self.joint_matrix_buffer
+    }
+
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        &self.instance_buffer
+    }
+}
@@ -1,13 +1,20 @@
 use bytemuck::{Pod, Zeroable};
+use std::f32::consts::TAU;
+
 use glam::Vec3;
 use wgpu::util::DeviceExt;
 
+use super::environment_map::EnvironmentMap;
+
 #[derive(Debug, Pod, Clone, Copy, Zeroable)]
 #[repr(C)]
 pub struct SunInfo {
+    /// Direction the sun light travels in.
     pub direction: Vec3,
     pub size: f32,
+    /// Color of the sun light reaching the ground, `TimeOfDay` derives it from the transmittance of the atmosphere.
     pub color: Vec3,
+    /// Illuminance of the sun above the atmosphere, which lights the sky.
     pub intensity: f32,
 }
 
@@ -60,47 +67,202 @@ impl Default for SunInfo {
     }
 }
 
-#[derive(Debug, Pod, Clone, Copy, Zeroable)]
+/// Physically based atmosphere made up of Rayleigh and Mie scattering and ozone absorption.
+/// Distances are in kilometers and coefficients per kilometer, defaults describe the atmosphere of earth.
+#[derive(Debug, Pod, Clone, Copy, Zeroable, PartialEq)]
 #[repr(C)]
 pub struct AtmosphereInfo {
-    pub inscattering_color: Vec3,
-    pub density: f32,
-    pub density_noise_scale: f32,
-    pub density_noise_min: f32,
-    pub density_noise_max: f32,
+    pub rayleigh_scattering: Vec3,
+    pub rayleigh_scale_height: f32,
+    pub mie_scattering: Vec3,
+    pub mie_scale_height: f32,
+    pub mie_absorption: Vec3,
+    /// Asymmetry of the Mie phase function, positive values scatter forward.
+    pub mie_anisotropy: f32,
+    pub ozone_absorption: Vec3,
+    /// Altitude of the peak of the tent shaped ozone layer.
+    pub ozone_center_height: f32,
+    pub ground_albedo: Vec3,
+    pub ozone_width: f32,
+    pub planet_radius: f32,
+    pub atmosphere_height: f32,
+    /// Multiplier on world space distances in aerial perspective, exaggerates haze in scenes smaller than the real world.
+    pub aerial_perspective_scale: f32,
     pub _padding0: u32,
 }
 
 impl Default for AtmosphereInfo {
     fn default() -> Self {
         Self {
-            inscattering_color: Vec3::new(135.0 / 255.0, 206.0 / 255.0, 235.0 / 255.0).normalize(),
-            density: 0.005,
-            density_noise_scale: 0.4,
-            density_noise_min: 0.5,
-            density_noise_max: 1.0,
+            rayleigh_scattering: Vec3::new(5.802, 13.558, 33.1) * 1e-3,
+            rayleigh_scale_height: 8.0,
+            mie_scattering: Vec3::splat(3.996e-3),
+            mie_scale_height: 1.2,
+            mie_absorption: Vec3::splat(4.4e-3),
+            mie_anisotropy: 0.8,
+            ozone_absorption: Vec3::new(0.65, 1.881, 0.085) * 1e-3,
+            ozone_center_height: 25.0,
+            ground_albedo: Vec3::splat(0.3),
+            ozone_width: 30.0,
+            planet_radius: 6360.0,
+            atmosphere_height: 100.0,
+            aerial_perspective_scale: 1.0,
             _padding0: 0,
         }
     }
 }
 
 impl AtmosphereInfo {
+    /// Extinction coefficient at an altitude above the ground, matches `AtmosphereInfo::medium` in `shared/sky.wgsl`.
+    fn extinction(&self, altitude: f32) -> Vec3 {
+        let altitude = altitude.max(0.0);
+        let rayleigh_density = (-altitude / self.rayleigh_scale_height).exp();
+        let mie_density = (-altitude / self.mie_scale_height).exp();
+        let ozone_density =
+            (1.0 - (altitude - self.ozone_center_height).abs() / (self.ozone_width * 0.5)).max(0.0);
+
+        self.rayleigh_scattering * rayleigh_density
+            + (self.mie_scattering + self.mie_absorption) * mie_density
+            + self.ozone_absorption * ozone_density
+    }
+
+    /// Transmittance from `altitude` kilometers above the ground to the top of the atmosphere, zero when the ground is in the way.
+    pub fn transmittance(&self, altitude: f32, cos_zenith: f32) -> Vec3 {
+        const SAMPLE_COUNT: u32 = 64;
+
+        let view_height = self.planet_radius + altitude.max(0.0);
+        let cos_horizon = -(1.0 - (self.planet_radius / view_height).powi(2))
+            .max(0.0)
+            .sqrt();
+        if cos_zenith < cos_horizon {
+            return Vec3::ZERO;
+        }
+
+        let top_radius = self.planet_radius + self.atmosphere_height;
+        let origin = Vec3::new(0.0, view_height, 0.0);
+        let direction = Vec3::new(
+            (1.0 - cos_zenith * cos_zenith).max(0.0).sqrt(),
+            cos_zenith,
+            0.0,
+        );
+        let b = view_height * cos_zenith;
+        let ray_length = (-b
+            + (b * b - view_height * view_height + top_radius * top_radius)
+                .max(0.0)
+                .sqrt())
+        .max(0.0);
+
+        let step_size = ray_length / SAMPLE_COUNT as f32;
+        let mut optical_depth = Vec3::ZERO;
+        for i in 0..SAMPLE_COUNT {
+            let position = origin + direction * (i as f32 + 0.5) * step_size;
+            optical_depth += self.extinction(position.length() - self.planet_radius) * step_size;
+        }
+
+        (-optical_depth).exp()
+    }
+
     #[cfg(feature = "egui")]
     pub fn egui(&mut self, ui: &mut egui::Ui) {
-        let mut inscattering_color = self.inscattering_color.to_array();
-        ui.color_edit_button_rgb(&mut inscattering_color)
-            .labelled_by(ui.label("Inscattering Color").id);
-        self.inscattering_color = Vec3::from_array(inscattering_color);
+        fn coefficients(ui: &mut egui::Ui, label: &str, value: &mut Vec3) {
+            // Shown per thousand kilometers to keep the drag values readable
+            let mut scaled = (*value * 1e3).to_array();
+            ui.horizontal(|ui| {
+                ui.label(label);
+                for (component, prefix) in scaled.iter_mut().zip(["r: ", "g: ", "b: "]) {
+                    ui.add(
+                        egui::DragValue::new(component)
+                            .speed(0.01)
+                            .range(0.0..=100.0)
+                            .prefix(prefix),
+                    );
+                }
+            });
+            *value = Vec3::from_array(scaled) * 1e-3;
+        }
 
-        ui.add(egui::Slider::new(&mut self.density, 0.0..=0.3).text("Density"));
+        coefficients(ui, "Rayleigh Scattering", &mut self.rayleigh_scattering);
+        ui.add(
+            egui::Slider::new(&mut self.rayleigh_scale_height, 0.1..=20.0)
+                .text("Rayleigh Scale Height"),
+        );
+        coefficients(ui, "Mie Scattering", &mut self.mie_scattering);
+        coefficients(ui, "Mie Absorption", &mut self.mie_absorption);
+        ui.add(egui::Slider::new(&mut self.mie_scale_height, 0.1..=10.0).text("Mie Scale Height"));
+        ui.add(egui::Slider::new(&mut self.mie_anisotropy, 0.0..=0.99).text("Mie Anisotropy"));
+        coefficients(ui, "Ozone Absorption", &mut self.ozone_absorption);
+
+        let mut ground_albedo = self.ground_albedo.to_array();
+        ui.color_edit_button_rgb(&mut ground_albedo)
+            .labelled_by(ui.label("Ground Albedo").id);
+        self.ground_albedo = Vec3::from_array(ground_albedo);
 
         ui.add(
-            egui::Slider::new(&mut self.density_noise_scale, 0.0..=1.0).text("Density Noise Scale"),
+            egui::Slider::new(&mut self.aerial_perspective_scale, 0.0..=100.0)
+                .logarithmic(true)
+                .text("Aerial Perspective Scale"),
         );
-        ui.add(egui::Slider::new(&mut self.density_noise_min, 0.0..=1.0).text("Density Noise Min"));
-        ui.add(egui::Slider::new(&mut self.density_noise_max, 0.0..=1.0).text("Density Noise Max"));
-        self.density_noise_min = self.density_noise_min.min(self.density_noise_max - 0.001);
-        self.density_noise_max = self.density_noise_max.max(self.density_noise_min + 0.001);
+    }
+}
+
+/// Sun position at a location on earth, see `TimeOfDay::apply`.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeOfDay {
+    /// Latitude in degrees, positive on the northern hemisphere.
+    pub latitude: f32,
+    /// Day of the year, starting at 0 for the first of January.
+    pub day_of_year: u32,
+    /// Local solar time in hours, the sun reaches its highest point at 12.
+    pub hour: f32,
+    /// World space direction pointing north.
+    pub north: Vec3,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            latitude: 52.0,
+            day_of_year: 172,
+            hour: 15.0,
+            north: Vec3::NEG_Z,
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// Direction from the ground towards the sun, below the horizon at night.
+    pub fn direction_to_sun(&self, world_up: Vec3) -> Vec3 {
+        let latitude = self.latitude.to_radians();
+        // Approximation of the solar declination, accurate to about a degree
+        let declination =
+            (-23.44f32).to_radians() * (TAU / 365.0 * (self.day_of_year as f32 + 10.0)).cos();
+        let hour_angle = (15.0 * (self.hour - 12.0)).to_radians();
+
+        let up = world_up.normalize();
+        let north = (self.north - up * self.north.dot(up)).normalize();
+        let east = north.cross(up);
+
+        let elevation = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+        let east_component = -declination.cos() * hour_angle.sin();
+        let north_component = latitude.cos() * declination.sin()
+            - latitude.sin() * declination.cos() * hour_angle.cos();
+
+        (east * east_component + north * north_component + up * elevation).normalize()
+    }
+
+    /// Point the sun along the time of day and color it by the transmittance of `atmosphere` at ground level, its size and intensity are left untouched.
+    pub fn apply(&self, sun: &mut SunInfo, world_up: Vec3, atmosphere: &AtmosphereInfo) {
+        let direction_to_sun = self.direction_to_sun(world_up);
+        sun.direction = -direction_to_sun;
+        sun.color = atmosphere.transmittance(0.0, direction_to_sun.dot(world_up.normalize()));
+    }
+
+    #[cfg(feature = "egui")]
+    pub fn egui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.latitude, -90.0..=90.0).text("Latitude"));
+        ui.add(egui::Slider::new(&mut self.day_of_year, 0..=364).text("Day Of Year"));
+        ui.add(egui::Slider::new(&mut self.hour, 0.0..=24.0).text("Hour"));
     }
 }
 
@@ -110,33 +272,160 @@ pub struct SkyConstants {
     pub sun: SunInfo,
     pub atmosphere: AtmosphereInfo,
     pub world_up: Vec3,
+    /// Height of the camera above the ground in world space, updated by `GpuResources::update`.
+    pub camera_altitude: f32,
+    /// Multiplier on the radiance of the environment map set through `Sky::set_environment_map`.
+    pub environment_intensity: f32,
+    /// Mip count of the prefiltered specular environment, zero without an environment map.
+    environment_specular_mip_count: u32,
     _padding0: u32,
+    _padding1: u32,
 }
 
+const TRANSMITTANCE_LUT_RESOLUTION: (u32, u32) = (256, 64);
+const MULTI_SCATTERING_LUT_RESOLUTION: (u32, u32) = (32, 32);
+const SKY_VIEW_LUT_RESOLUTION: (u32, u32) = (192, 108);
+
+/// Sky constants and the atmosphere luts built by `sky_lut_pass`.
+/// Transmittance and multi-scattering only depend on the atmosphere, the sky view lut is rebuilt every frame.
+/// An environment map replaces the atmosphere as the sky and lights the scene through image based lighting.
 pub struct Sky {
     bind_group_layout: wgpu::BindGroupLayout,
+    transmittance_lut: wgpu::Texture,
+    multi_scattering_lut: wgpu::Texture,
+    sky_view_lut: wgpu::Texture,
+    lut_sampler: wgpu::Sampler,
+    environment_map: Option<EnvironmentMap>,
+    empty_environment_map: EnvironmentMap,
+    environment_sampler: wgpu::Sampler,
+    /// Atmosphere the transmittance and multi-scattering luts were last built for.
+    lut_atmosphere: Option<AtmosphereInfo>,
     pub constants: SkyConstants,
 }
 
 impl Sky {
     pub fn new(device: &wgpu::Device) -> Self {
+        let lut_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let cube_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                multisampled: false,
+            },
+            count: None,
+        };
+
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                lut_entry(1),
+                lut_entry(2),
+                lut_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                cube_entry(5),
+                cube_entry(6),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
                 },
-                count: None,
-            }],
+            ],
+        });
+
+        let create_lut = |label: &str, (width, height): (u32, u32)| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+                view_formats: &[],
+            })
+        };
+
+        let transmittance_lut = create_lut(
+            "terrarium::sky transmittance_lut",
+            TRANSMITTANCE_LUT_RESOLUTION,
+        );
+        let multi_scattering_lut = create_lut(
+            "terrarium::sky multi_scattering_lut",
+            MULTI_SCATTERING_LUT_RESOLUTION,
+        );
+        let sky_view_lut = create_lut("terrarium::sky sky_view_lut", SKY_VIEW_LUT_RESOLUTION);
+
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let environment_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
 
         Self {
             bind_group_layout,
-            constants: Default::default(),
+            transmittance_lut,
+            multi_scattering_lut,
+            sky_view_lut,
+            lut_sampler,
+            environment_map: None,
+            empty_environment_map: EnvironmentMap::empty(device),
+            environment_sampler,
+            lut_atmosphere: None,
+            constants: SkyConstants {
+                environment_intensity: 1.0,
+                ..Default::default()
+            },
         }
     }
 
@@ -144,20 +433,118 @@ impl Sky {
         &self.bind_group_layout
     }
 
+    pub fn transmittance_lut(&self) -> &wgpu::Texture {
+        &self.transmittance_lut
+    }
+
+    pub fn multi_scattering_lut(&self) -> &wgpu::Texture {
+        &self.multi_scattering_lut
+    }
+
+    pub fn sky_view_lut(&self) -> &wgpu::Texture {
+        &self.sky_view_lut
+    }
+
+    pub fn lut_sampler(&self) -> &wgpu::Sampler {
+        &self.lut_sampler
+    }
+
+    pub fn environment_map(&self) -> Option<&EnvironmentMap> {
+        self.environment_map.as_ref()
+    }
+
+    /// Use an environment map for the sky, reflection misses and ambient lighting instead of the atmosphere.
+    /// The sun keeps lighting the scene directly, aerial perspective still uses the atmosphere.
+    pub fn set_environment_map(&mut self, environment_map: Option<EnvironmentMap>) {
+        self.environment_map = environment_map;
+    }
+
+    /// Whether the atmosphere changed since the transmittance and multi-scattering luts were last built, marking them as up to date.
+    pub(crate) fn take_atmosphere_changed(&mut self) -> bool {
+        let changed = self.lut_atmosphere != Some(self.constants.atmosphere);
+        self.lut_atmosphere = Some(self.constants.atmosphere);
+        changed
+    }
+
     pub fn bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
+        let environment_map = self
+            .environment_map
+            .as_ref()
+            .unwrap_or(&self.empty_environment_map);
+
+        let mut constants = self.constants;
+        constants.environment_specular_mip_count = if self.environment_map.is_some() {
+            environment_map.specular_texture().mip_level_count()
+        } else {
+            0
+        };
         let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("terrarium::sky constants"),
-            contents: bytemuck::bytes_of(&self.constants),
+            contents: bytemuck::bytes_of(&constants),
             usage: wgpu::BufferUsages::UNIFORM,
         });
 
+        let transmittance_lut_view = self
+            .transmittance_lut
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let multi_scattering_lut_view = self
+            .multi_scattering_lut
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let sky_view_lut_view = self
+            .sky_view_lut
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let cube_view_descriptor = wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        };
+        let environment_radiance_view = environment_map
+            .radiance_texture()
+            .create_view(&cube_view_descriptor);
+        let environment_specular_view = environment_map
+            .specular_texture()
+            .create_view(&cube_view_descriptor);
+
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &self.bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: constants.as_entire_binding(),
-            }],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: constants.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&transmittance_lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&multi_scattering_lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&sky_view_lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&self.lut_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&environment_radiance_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(&environment_specular_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: environment_map.irradiance_sh_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::Sampler(&self.environment_sampler),
+                },
+            ],
         })
     }
 }
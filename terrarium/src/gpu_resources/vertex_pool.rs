@@ -1,7 +1,8 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
+use anyhow::{bail, Result};
 use bytemuck::{Pod, Zeroable};
-use glam::Mat4;
+use glam::{Mat4, Vec3};
 use ugm::mesh::PackedVertex;
 
 use super::{
@@ -13,6 +14,7 @@ const MAX_VERTEX_POOL_VERTICES: usize = 1024 * 1024 * 24;
 const MAX_VERTEX_POOL_INDICES: usize = 1024 * 1024 * 64;
 const MAX_VERTEX_POOL_SLICES: usize = 1024 * 8;
 const MAX_MATERIALS_PER_INSTANCE: usize = 64;
+const MAX_MORPH_TARGET_DELTAS: usize = 1024 * 1024 * 2;
 
 pub struct VertexPoolWriteData<'a> {
     pub packed_vertices: &'a [PackedVertex],
@@ -20,10 +22,37 @@ pub struct VertexPoolWriteData<'a> {
     pub triangle_material_indices: &'a [u32],
 }
 
+/// Offset of a single vertex for a single morph target.
+#[derive(Pod, Debug, Clone, Copy, Zeroable)]
+#[repr(C)]
+pub struct MorphTargetDelta {
+    position: Vec3,
+    _padding0: u32,
+    normal: Vec3,
+    _padding1: u32,
+    tangent: Vec3,
+    _padding2: u32,
+}
+
+impl MorphTargetDelta {
+    pub fn new(position: Vec3, normal: Vec3, tangent: Vec3) -> Self {
+        Self {
+            position,
+            _padding0: 0,
+            normal,
+            _padding1: 0,
+            tangent,
+            _padding2: 0,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VertexPoolAlloc {
     pub vertex_alloc: LinearBlockAllocation,
     pub index_alloc: LinearBlockAllocation,
+    /// Vertices of the previous frame, only available for deformable allocations.
+    pub prev_vertex_alloc: Option<LinearBlockAllocation>,
     pub index: u32,
 }
 
@@ -34,6 +63,11 @@ pub struct VertexPoolSlice {
     num_vertices: u32,
     first_index: u32,
     num_indices: u32,
+    /// Equal to `first_vertex` unless the slice is deformed on the gpu, used for motion vectors.
+    prev_first_vertex: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
 }
 
 impl VertexPoolSlice {
@@ -43,16 +77,15 @@ impl VertexPoolSlice {
             num_vertices,
             first_index,
             num_indices,
+            prev_first_vertex: first_vertex,
+            _padding0: 0,
+            _padding1: 0,
+            _padding2: 0,
         }
     }
 
     fn new_unallocated() -> Self {
-        Self {
-            first_vertex: 0,
-            num_vertices: 0,
-            first_index: 0,
-            num_indices: 0,
-        }
+        Self::new(0, 0, 0, 0)
     }
 
     fn is_allocated(&self) -> bool {
@@ -68,16 +101,23 @@ pub struct VertexPool {
     object_to_world_buffer: wgpu::Buffer,
     material_index_buffer: wgpu::Buffer,
     vertex_slice_index_buffer: wgpu::Buffer,
+    morph_target_delta_buffer: wgpu::Buffer,
 
     vertex_allocator: LinearBlockAllocator,
     index_allocator: LinearBlockAllocator,
+    morph_target_delta_allocator: LinearBlockAllocator,
     slices: Box<[VertexPoolSlice]>,
     delta_object_to_world_inv: Vec<Mat4>,
-    prev_object_to_world: Vec<Mat4>,
+    /// Transforms of the entities submitted during the previous and current frame, keyed by entity
+    /// since submission order changes with culling.
+    prev_object_to_world: HashMap<specs::Entity, Mat4>,
+    object_to_world: HashMap<specs::Entity, Mat4>,
     static_material_indices: Vec<u32>,
     dynamic_material_indices: Vec<u32>,
     static_vertex_slice_indices: Vec<u32>,
     dynamic_vertex_slice_indices: Vec<u32>,
+    // Static instances persist between frames, only slots written since the last `write_slices` are uploaded
+    dirty_static_slots: Vec<u32>,
 
     frame_idx: u32,
 
@@ -93,6 +133,7 @@ impl VertexPool {
             usage: wgpu::BufferUsages::BLAS_INPUT
                 | wgpu::BufferUsages::VERTEX
                 | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -103,6 +144,7 @@ impl VertexPool {
             usage: wgpu::BufferUsages::BLAS_INPUT
                 | wgpu::BufferUsages::INDEX
                 | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
                 | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -110,7 +152,9 @@ impl VertexPool {
             label: Some("terrarium::vertex_pool triangle_material_indices"),
             mapped_at_creation: false,
             size: (std::mem::size_of::<u32>() * MAX_VERTEX_POOL_INDICES / 3) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
         });
 
         let slices_buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -145,8 +189,17 @@ impl VertexPool {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
+        let morph_target_delta_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::vertex_pool morph_target_deltas"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<MorphTargetDelta>() * MAX_MORPH_TARGET_DELTAS) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
         let vertex_allocator = LinearBlockAllocator::new(MAX_VERTEX_POOL_VERTICES as u64);
         let index_allocator = LinearBlockAllocator::new(MAX_VERTEX_POOL_INDICES as u64);
+        let morph_target_delta_allocator =
+            LinearBlockAllocator::new(MAX_MORPH_TARGET_DELTAS as u64);
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
@@ -232,17 +285,21 @@ impl VertexPool {
             object_to_world_buffer,
             material_index_buffer,
             vertex_slice_index_buffer,
+            morph_target_delta_buffer,
 
             vertex_allocator,
             index_allocator,
+            morph_target_delta_allocator,
             slices: vec![VertexPoolSlice::new_unallocated(); MAX_VERTEX_POOL_SLICES]
                 .into_boxed_slice(),
             delta_object_to_world_inv: Vec::new(),
-            prev_object_to_world: vec![Mat4::IDENTITY; MAX_DYNAMIC_INSTANCES],
+            prev_object_to_world: HashMap::new(),
+            object_to_world: HashMap::new(),
             static_material_indices: Vec::new(),
             dynamic_material_indices: Vec::new(),
             static_vertex_slice_indices: Vec::new(),
             dynamic_vertex_slice_indices: Vec::new(),
+            dirty_static_slots: Vec::new(),
             frame_idx: 0,
             bind_group_layout,
         }
@@ -275,6 +332,91 @@ impl VertexPool {
         );
     }
 
+    /// Copy the vertices, indices and triangle material indices of `src` into `dst`, which must be of equal size.
+    /// The previous frame vertices of a deformable `dst` are initialized to the vertices of `src` as well.
+    pub fn copy_vertex_data(
+        &self,
+        src: &VertexPoolAlloc,
+        dst: &VertexPoolAlloc,
+        device: &wgpu::Device,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) {
+        let num_vertices = src.vertex_alloc.end() - src.vertex_alloc.start();
+        let num_indices = src.index_alloc.end() - src.index_alloc.start();
+        assert_eq!(
+            num_vertices,
+            dst.vertex_alloc.end() - dst.vertex_alloc.start()
+        );
+        assert_eq!(num_indices, dst.index_alloc.end() - dst.index_alloc.start());
+
+        let copies = [
+            (
+                &self.vertex_buffer,
+                std::mem::size_of::<PackedVertex>() as u64,
+                src.vertex_alloc.start(),
+                dst.vertex_alloc.start(),
+                num_vertices,
+            ),
+            (
+                &self.vertex_buffer,
+                std::mem::size_of::<PackedVertex>() as u64,
+                src.vertex_alloc.start(),
+                dst.prev_vertex_alloc
+                    .as_ref()
+                    .map_or(0, |prev_vertex_alloc| prev_vertex_alloc.start()),
+                dst.prev_vertex_alloc.as_ref().map_or(0, |_| num_vertices),
+            ),
+            (
+                &self.index_buffer,
+                std::mem::size_of::<u32>() as u64,
+                src.index_alloc.start(),
+                dst.index_alloc.start(),
+                num_indices,
+            ),
+            (
+                &self.triangle_material_index_buffer,
+                std::mem::size_of::<u32>() as u64,
+                src.index_alloc.start() / 3,
+                dst.index_alloc.start() / 3,
+                num_indices / 3,
+            ),
+        ];
+
+        // Copies within a single buffer are not allowed, go through an intermediate buffer instead
+        let staging_size = copies
+            .iter()
+            .map(|(_, stride, _, _, count)| stride * count)
+            .max()
+            .unwrap();
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("terrarium::vertex_pool copy_staging"),
+            mapped_at_creation: false,
+            size: staging_size,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        });
+
+        for (buffer, stride, src_start, dst_start, count) in copies {
+            if count == 0 {
+                continue;
+            }
+
+            command_encoder.copy_buffer_to_buffer(
+                buffer,
+                src_start * stride,
+                &staging_buffer,
+                0,
+                count * stride,
+            );
+            command_encoder.copy_buffer_to_buffer(
+                &staging_buffer,
+                0,
+                buffer,
+                dst_start * stride,
+                count * stride,
+            );
+        }
+    }
+
     pub fn write_slices(&mut self, queue: &wgpu::Queue) {
         queue.write_buffer(&self.slices_buffer, 0, bytemuck::cast_slice(&self.slices));
 
@@ -289,91 +431,151 @@ impl VertexPool {
             0,
             bytemuck::cast_slice(&self.dynamic_material_indices),
         );
-        if !self.static_material_indices.is_empty() {
-            queue.write_buffer(
-                &self.material_index_buffer,
-                (size_of::<u32>() * MAX_MATERIALS_PER_INSTANCE * MAX_DYNAMIC_INSTANCES) as u64,
-                bytemuck::cast_slice(&self.static_material_indices),
-            );
-        }
 
         queue.write_buffer(
             &self.vertex_slice_index_buffer,
             0,
             bytemuck::cast_slice(&self.dynamic_vertex_slice_indices),
         );
-        if !self.static_vertex_slice_indices.is_empty() {
+
+        self.write_dirty_static_slots(queue);
+    }
+
+    fn write_dirty_static_slots(&mut self, queue: &wgpu::Queue) {
+        self.dirty_static_slots.sort_unstable();
+        self.dirty_static_slots.dedup();
+
+        // Merge consecutive slots into ranges to keep the number of writes down during large edits
+        let mut i = 0;
+        while i < self.dirty_static_slots.len() {
+            let first = self.dirty_static_slots[i] as usize;
+            let mut last = first;
+            while i + 1 < self.dirty_static_slots.len()
+                && self.dirty_static_slots[i + 1] as usize == last + 1
+            {
+                last += 1;
+                i += 1;
+            }
+            i += 1;
+
+            queue.write_buffer(
+                &self.material_index_buffer,
+                (size_of::<u32>() * MAX_MATERIALS_PER_INSTANCE * (MAX_DYNAMIC_INSTANCES + first))
+                    as u64,
+                bytemuck::cast_slice(
+                    &self.static_material_indices[first * MAX_MATERIALS_PER_INSTANCE
+                        ..(last + 1) * MAX_MATERIALS_PER_INSTANCE],
+                ),
+            );
             queue.write_buffer(
                 &self.vertex_slice_index_buffer,
-                (size_of::<u32>() * MAX_DYNAMIC_INSTANCES) as u64,
-                bytemuck::cast_slice(&self.static_vertex_slice_indices),
+                (size_of::<u32>() * (MAX_DYNAMIC_INSTANCES + first)) as u64,
+                bytemuck::cast_slice(&self.static_vertex_slice_indices[first..=last]),
             );
         }
+
+        self.dirty_static_slots.clear();
     }
 
-    pub fn submit_slice_instance(
+    /// Write a static instance into `static_slot`, static instances persist until overwritten.
+    /// Returns the instance index to be used as custom index of the matching tlas instance.
+    pub fn write_static_slice_instance(
         &mut self,
-        transform: Mat4,
-        is_static: bool,
+        static_slot: u32,
         vertex_slice_index: u32,
         materials: &[Arc<GpuMaterial>],
     ) -> u32 {
         assert!(materials.len() <= MAX_MATERIALS_PER_INSTANCE);
+        assert!((static_slot as usize) < MAX_STATIC_INSTANCES);
 
-        if is_static {
-            for material in materials {
-                self.static_material_indices.push(material.material_idx);
-            }
-            for _ in 0..(MAX_MATERIALS_PER_INSTANCE - materials.len()) {
-                self.static_material_indices.push(0);
-            }
-
-            self.static_vertex_slice_indices.push(vertex_slice_index);
+        let slot = static_slot as usize;
+        if self.static_vertex_slice_indices.len() <= slot {
+            self.static_vertex_slice_indices.resize(slot + 1, 0);
+            self.static_material_indices
+                .resize((slot + 1) * MAX_MATERIALS_PER_INSTANCE, 0);
+        }
 
-            (MAX_DYNAMIC_INSTANCES + self.static_vertex_slice_indices.len()) as u32 - 1
-        } else {
-            let i = self.delta_object_to_world_inv.len();
-            assert!(i < MAX_DYNAMIC_INSTANCES);
-            let delta = transform * self.prev_object_to_world[i].inverse();
-            self.delta_object_to_world_inv.push(delta.inverse());
-            self.prev_object_to_world[i] = transform;
+        let material_indices = &mut self.static_material_indices
+            [slot * MAX_MATERIALS_PER_INSTANCE..(slot + 1) * MAX_MATERIALS_PER_INSTANCE];
+        material_indices.fill(0);
+        for (material_index, material) in material_indices.iter_mut().zip(materials) {
+            *material_index = material.material_idx;
+        }
 
-            for material in materials {
-                self.dynamic_material_indices.push(material.material_idx);
-            }
-            for _ in 0..(MAX_MATERIALS_PER_INSTANCE - materials.len()) {
-                self.dynamic_material_indices.push(0);
-            }
+        self.static_vertex_slice_indices[slot] = vertex_slice_index;
+        self.dirty_static_slots.push(static_slot);
 
-            self.dynamic_vertex_slice_indices.push(vertex_slice_index);
+        (MAX_DYNAMIC_INSTANCES + slot) as u32
+    }
 
-            self.dynamic_vertex_slice_indices.len() as u32 - 1
+    /// Submit a dynamic instance of `entity` for the current frame, see `write_static_slice_instance` for statics.
+    /// Entities that weren't submitted during the previous frame start without motion.
+    pub fn submit_slice_instance(
+        &mut self,
+        entity: specs::Entity,
+        transform: Mat4,
+        vertex_slice_index: u32,
+        materials: &[Arc<GpuMaterial>],
+    ) -> u32 {
+        assert!(materials.len() <= MAX_MATERIALS_PER_INSTANCE);
+        assert!(self.delta_object_to_world_inv.len() < MAX_DYNAMIC_INSTANCES);
+
+        let prev_transform = self
+            .prev_object_to_world
+            .get(&entity)
+            .copied()
+            .unwrap_or(transform);
+        let delta = transform * prev_transform.inverse();
+        self.delta_object_to_world_inv.push(delta.inverse());
+        self.object_to_world.insert(entity, transform);
+
+        for material in materials {
+            self.dynamic_material_indices.push(material.material_idx);
+        }
+        for _ in 0..(MAX_MATERIALS_PER_INSTANCE - materials.len()) {
+            self.dynamic_material_indices.push(0);
         }
+
+        self.dynamic_vertex_slice_indices.push(vertex_slice_index);
+
+        self.dynamic_vertex_slice_indices.len() as u32 - 1
     }
 
     pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.prev_object_to_world, &mut self.object_to_world);
+        self.object_to_world.clear();
         self.delta_object_to_world_inv.clear();
-        self.static_material_indices.clear();
         self.dynamic_material_indices.clear();
-        self.static_vertex_slice_indices.clear();
         self.dynamic_vertex_slice_indices.clear();
 
         self.frame_idx += 1;
     }
 
-    pub fn alloc(&mut self, num_vertices: u32, num_indices: u32) -> VertexPoolAlloc {
-        let slice_idx = self
-            .first_available_slice_idx()
-            .expect("Vertex pool ran out of slices!");
-
-        let vertex_alloc = self
-            .vertex_allocator
-            .allocate(num_vertices as u64)
-            .expect("Failed to allocate vertices.");
-        let index_alloc = self
-            .index_allocator
-            .allocate(num_indices as u64)
-            .expect("Failed to allocate indices.");
+    pub fn alloc(&mut self, num_vertices: u32, num_indices: u32) -> Result<VertexPoolAlloc> {
+        let Some(slice_idx) = self.first_available_slice_idx() else {
+            bail!(
+                "Vertex pool ran out of slices, at most {} are supported.",
+                MAX_VERTEX_POOL_SLICES
+            );
+        };
+
+        let Some(vertex_alloc) = self.vertex_allocator.allocate(num_vertices as u64) else {
+            bail!(
+                "Vertex pool ran out of vertices, {} requested with {} / {} in use.",
+                num_vertices,
+                self.vertex_allocator.used_size(),
+                self.vertex_allocator.total_size()
+            );
+        };
+        let Some(index_alloc) = self.index_allocator.allocate(num_indices as u64) else {
+            self.vertex_allocator.free(&vertex_alloc);
+            bail!(
+                "Vertex pool ran out of indices, {} requested with {} / {} in use.",
+                num_indices,
+                self.index_allocator.used_size(),
+                self.index_allocator.total_size()
+            );
+        };
 
         let slice = VertexPoolSlice::new(
             vertex_alloc.start() as u32,
@@ -383,17 +585,92 @@ impl VertexPool {
         );
         self.slices[slice_idx] = slice;
 
-        VertexPoolAlloc {
+        Ok(VertexPoolAlloc {
             vertex_alloc,
             index_alloc,
+            prev_vertex_alloc: None,
             index: slice_idx as u32,
-        }
+        })
+    }
+
+    /// Allocate a slice whose vertices are rewritten on the gpu every frame, keeping the vertices of the previous frame around for motion vectors.
+    pub fn alloc_deformable(
+        &mut self,
+        num_vertices: u32,
+        num_indices: u32,
+    ) -> Result<VertexPoolAlloc> {
+        let mut alloc = self.alloc(num_vertices, num_indices)?;
+
+        let Some(prev_vertex_alloc) = self.vertex_allocator.allocate(num_vertices as u64) else {
+            self.free(&alloc);
+            bail!(
+                "Vertex pool ran out of vertices, {} requested with {} / {} in use.",
+                num_vertices,
+                self.vertex_allocator.used_size(),
+                self.vertex_allocator.total_size()
+            );
+        };
+
+        self.slices[alloc.index as usize].prev_first_vertex = prev_vertex_alloc.start() as u32;
+        alloc.prev_vertex_alloc = Some(prev_vertex_alloc);
+        Ok(alloc)
+    }
+
+    pub fn alloc_morph_target_deltas(&mut self, num_deltas: u32) -> Result<LinearBlockAllocation> {
+        let Some(alloc) = self
+            .morph_target_delta_allocator
+            .allocate(num_deltas as u64)
+        else {
+            bail!(
+                "Vertex pool ran out of morph target deltas, {} requested with {} / {} in use.",
+                num_deltas,
+                self.morph_target_delta_allocator.used_size(),
+                self.morph_target_delta_allocator.total_size()
+            );
+        };
+
+        Ok(alloc)
+    }
+
+    pub fn write_morph_target_deltas(
+        &self,
+        deltas: &[MorphTargetDelta],
+        alloc: &LinearBlockAllocation,
+        queue: &wgpu::Queue,
+    ) {
+        queue.write_buffer(
+            &self.morph_target_delta_buffer,
+            alloc.start() * std::mem::size_of::<MorphTargetDelta>() as u64,
+            bytemuck::cast_slice(deltas),
+        );
+    }
+
+    pub fn free_morph_target_deltas(&mut self, alloc: &LinearBlockAllocation) {
+        self.morph_target_delta_allocator.free(alloc);
+    }
+
+    /// Gpu memory taken up by a mesh with `num_vertices` and `num_indices` once allocated.
+    pub fn alloc_bytes(num_vertices: u32, num_indices: u32) -> u64 {
+        (std::mem::size_of::<PackedVertex>() as u64) * num_vertices as u64
+            + (std::mem::size_of::<u32>() as u64) * (num_indices as u64 + num_indices as u64 / 3)
+    }
+
+    /// Gpu memory currently taken up by allocated vertices, indices, triangle material indices and morph target deltas.
+    pub fn used_bytes(&self) -> u64 {
+        (std::mem::size_of::<PackedVertex>() as u64) * self.vertex_allocator.used_size()
+            + (std::mem::size_of::<u32>() as u64)
+                * (self.index_allocator.used_size() + self.index_allocator.used_size() / 3)
+            + (std::mem::size_of::<MorphTargetDelta>() as u64)
+                * self.morph_target_delta_allocator.used_size()
     }
 
     pub fn free(&mut self, alloc: &VertexPoolAlloc) {
         self.vertex_allocator.free(&alloc.vertex_alloc);
         self.index_allocator.free(&alloc.index_alloc);
-        self.slices[alloc.index as usize].num_vertices = 0;
+        if let Some(prev_vertex_alloc) = &alloc.prev_vertex_alloc {
+            self.vertex_allocator.free(prev_vertex_alloc);
+        }
+        self.slices[alloc.index as usize] = VertexPoolSlice::new_unallocated();
     }
 
     fn first_available_slice_idx(&self) -> Option<usize> {
@@ -454,4 +731,8 @@ impl VertexPool {
     pub fn index_buffer(&self) -> &wgpu::Buffer {
         &self.index_buffer
     }
+
+    pub fn morph_target_delta_buffer(&self) -> &wgpu::Buffer {
+        &self.morph_target_delta_buffer
+    }
 }
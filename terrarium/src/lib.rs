@@ -1,30 +1,47 @@
-use std::num::NonZeroU32;
+use std::{
+    fmt,
+    num::NonZeroU32,
+    sync::{Arc, OnceLock},
+};
 
 use glam::{UVec2, Vec3};
 use gpu_resources::{
+    color_grading_lut::ColorGradingLut,
+    culling::CullingParameters,
     gbuffer::Gbuffer,
-    sky::{AtmosphereInfo, SunInfo},
+    sky::{AtmosphereInfo, SunInfo, TimeOfDay},
     GpuResources,
 };
+use helpers::timer::Timer;
+use render_graph::{BuiltinPass, RenderGraph, RenderGraphContext, ScheduledPass};
 use render_passes::{
+    auto_exposure_pass::{self, AutoExposurePassParameters},
     blit_pass::{self, BlitPassParameters},
     bloom_pass::{self, BloomPassParameters},
     build_frustum_pass::{self, BuildFrustumPassParameters},
-    color_correction_pass::{self, ColorCorrectionPassParameters},
+    color_correction_pass::{self, ColorCorrectionPassParameters, ToneMapper},
     debug_line_pass::{self, DebugLinePassParameters},
+    gbuffer_pass::{self, GbufferPassParameters},
+    global_illumination_pass::{self, GlobalIlluminationPassParameters},
     ltc_cull_pass::{self, LtcCullPassParameters},
     ltc_lighting_pass::{self, LtcLightingPassParameters},
-    mirror_reflection_pass::{self, MirrorReflectionPassParameters},
+    pick_pass::{self, PickPassParameters, PickResult},
+    reflection_pass::{self, ReflectionPassParameters},
     rt_gbuffer_pass::{self, RtGbufferPassParameters},
     shade_pass::{self, ShadePassParameters, ShadingMode},
+    shadow_pass::{self, ShadowPassParameters},
+    ssao_pass::{self, SsaoPassParameters},
     taa_pass::{self, TaaPassParameters},
+    transparent_pass::{self, TransparentPassParameters},
 };
+use wgpu::util::DeviceExt;
 use world::transform::UP;
 use xr::XrCameraState;
 
 pub mod app_loop;
 pub mod gpu_resources;
 pub mod helpers;
+pub mod render_graph;
 pub mod render_passes;
 pub mod wgpu_util;
 pub mod world;
@@ -39,18 +56,27 @@ struct SizedResources {
     resolution: UVec2,
     render_resolution: UVec2,
     lighting_resolution: UVec2,
+    reflection_resolution: UVec2,
+    global_illumination_resolution: UVec2,
     render_resolution_scale: f32,
     lighting_resolution_scale: f32,
+    reflection_resolution_scale: f32,
+    global_illumination_resolution_scale: f32,
 
-    reflection_counter_buffer: [wgpu::Buffer; 2],
-    reflection_pid_buffer: [wgpu::Buffer; 2],
     frustum_buffer: wgpu::Buffer,
     ltc_instance_index_buffer: wgpu::Buffer,
     ltc_instance_grid_texture_view: wgpu::TextureView,
     gbuffer: Gbuffer,
+    depth_texture: wgpu::Texture,
     shading_texture: [wgpu::Texture; 2],
     lighting_texture: wgpu::Texture,
     reflection_texture: wgpu::Texture,
+    reflection_trace_texture: wgpu::Texture,
+    reflection_history_texture: [wgpu::Texture; 2],
+    global_illumination_texture: wgpu::Texture,
+    global_illumination_trace_texture: wgpu::Texture,
+    global_illumination_history_texture: [wgpu::Texture; 2],
+    ambient_occlusion_texture: [wgpu::Texture; 2],
 }
 
 impl SizedResources {
@@ -58,6 +84,8 @@ impl SizedResources {
         resolution: UVec2,
         render_resolution_scale: f32,
         lighting_resolution_scale: f32,
+        reflection_resolution_scale: f32,
+        global_illumination_resolution_scale: f32,
         device: &wgpu::Device,
     ) -> Self {
         let render_resolution = UVec2::new(
@@ -68,8 +96,17 @@ impl SizedResources {
             (render_resolution.x as f32 * lighting_resolution_scale).ceil() as u32,
             (render_resolution.y as f32 * lighting_resolution_scale).ceil() as u32,
         );
+        let reflection_resolution = UVec2::new(
+            (render_resolution.x as f32 * reflection_resolution_scale).ceil() as u32,
+            (render_resolution.y as f32 * reflection_resolution_scale).ceil() as u32,
+        );
+        let global_illumination_resolution = UVec2::new(
+            (render_resolution.x as f32 * global_illumination_resolution_scale).ceil() as u32,
+            (render_resolution.y as f32 * global_illumination_resolution_scale).ceil() as u32,
+        );
 
         let gbuffer = Gbuffer::new(render_resolution, device);
+        let depth_texture = gbuffer_pass::create_depth_texture(render_resolution, device);
 
         let shading_texture = std::array::from_fn(|i| {
             device.create_texture(&wgpu::TextureDescriptor {
@@ -85,6 +122,7 @@ impl SizedResources {
                 format: wgpu::TextureFormat::Rgba16Float,
                 usage: wgpu::TextureUsages::RENDER_ATTACHMENT
                     | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST
                     | wgpu::TextureUsages::TEXTURE_BINDING
                     | wgpu::TextureUsages::STORAGE_BINDING,
                 view_formats: &[],
@@ -104,34 +142,52 @@ impl SizedResources {
             format: wgpu::TextureFormat::Rgba16Float,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT
                 | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST
                 | wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::STORAGE_BINDING,
             view_formats: &[],
         });
 
-        let reflection_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("terrarium::reflection"),
-            size: wgpu::Extent3d {
-                width: render_resolution.x,
-                height: render_resolution.y,
-                depth_or_array_layers: 2,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::COPY_SRC
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
+        let reflection_texture = reflection_pass::create_reflection_texture(
+            "terrarium::reflection",
+            reflection_resolution,
+            device,
+        );
+        let reflection_trace_texture = reflection_pass::create_reflection_texture(
+            "terrarium::reflection_trace",
+            reflection_resolution,
+            device,
+        );
+        let reflection_history_texture = std::array::from_fn(|i| {
+            reflection_pass::create_reflection_texture(
+                &format!("terrarium::reflection_history {}", i),
+                reflection_resolution,
+                device,
+            )
         });
 
-        let reflection_counter_buffer = std::array::from_fn(|_| {
-            rt_gbuffer_pass::create_reflection_counter_buffer(resolution, device)
+        let global_illumination_texture =
+            global_illumination_pass::create_global_illumination_texture(
+                "terrarium::global_illumination",
+                global_illumination_resolution,
+                device,
+            );
+        let global_illumination_trace_texture =
+            global_illumination_pass::create_global_illumination_texture(
+                "terrarium::global_illumination_trace",
+                global_illumination_resolution,
+                device,
+            );
+        let global_illumination_history_texture = std::array::from_fn(|i| {
+            global_illumination_pass::create_global_illumination_texture(
+                &format!("terrarium::global_illumination_history {}", i),
+                global_illumination_resolution,
+                device,
+            )
         });
-        let reflection_pid_buffer = std::array::from_fn(|_| {
-            rt_gbuffer_pass::create_reflection_pid_buffer(resolution, device)
+
+        let ambient_occlusion_texture = std::array::from_fn(|_| {
+            ssao_pass::create_ambient_occlusion_texture(render_resolution, device)
         });
 
         let frustum_buffer = build_frustum_pass::create_frustum_buffer(lighting_resolution, device);
@@ -147,34 +203,137 @@ impl SizedResources {
             resolution,
             render_resolution,
             lighting_resolution,
+            reflection_resolution,
+            global_illumination_resolution,
             render_resolution_scale,
             lighting_resolution_scale,
+            reflection_resolution_scale,
+            global_illumination_resolution_scale,
 
-            reflection_counter_buffer,
-            reflection_pid_buffer,
             frustum_buffer,
             ltc_instance_index_buffer,
             ltc_instance_grid_texture_view,
             gbuffer,
+            depth_texture,
             shading_texture,
             lighting_texture,
             reflection_texture,
+            reflection_trace_texture,
+            reflection_history_texture,
+            global_illumination_texture,
+            global_illumination_trace_texture,
+            global_illumination_history_texture,
+            ambient_occlusion_texture,
+        }
+    }
+
+    /// Clear every texture that is reprojected into the next frame.
+    fn clear_history(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        let history_textures = self
+            .shading_texture
+            .iter()
+            .chain(&self.reflection_history_texture)
+            .chain(&self.global_illumination_history_texture)
+            .chain(&self.ambient_occlusion_texture);
+        for texture in history_textures {
+            command_encoder.clear_texture(texture, &wgpu::ImageSubresourceRange::default());
+        }
+    }
+
+    fn textures(&self, frame_idx: u32) -> [(&'static str, &wgpu::Texture); 5] {
+        [
+            (
+                render_graph::SHADING_TEXTURE,
+                &self.shading_texture[frame_idx as usize % 2],
+            ),
+            (render_graph::LIGHTING_TEXTURE, &self.lighting_texture),
+            (render_graph::REFLECTION_TEXTURE, &self.reflection_texture),
+            (
+                render_graph::GLOBAL_ILLUMINATION_TEXTURE,
+                &self.global_illumination_texture,
+            ),
+            (
+                render_graph::AMBIENT_OCCLUSION_TEXTURE,
+                &self.ambient_occlusion_texture[0],
+            ),
+        ]
+    }
+}
+
+fn create_array_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        array_layer_count: Some(2),
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
+
+/// Selects how primary visibility and shadows are resolved.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPath {
+    /// Hardware ray traced gbuffer, shadows, reflections, global illumination and emissive lights, requires `Renderer::ray_tracing_features`.
+    #[default]
+    RayTraced,
+    /// Rasterized gbuffer with shadow maps, supported by every device. Reflections, global illumination and emissive lights are unavailable.
+    Raster,
+}
+
+impl RenderPath {
+    /// The most capable path supported by a device with the given features.
+    pub fn from_features(features: wgpu::Features) -> Self {
+        if features.contains(Renderer::ray_tracing_features()) {
+            Self::RayTraced
+        } else {
+            Self::Raster
         }
     }
 }
 
+impl fmt::Display for RenderPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::RayTraced => "Ray Traced",
+            Self::Raster => "Raster",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 pub struct RenderSettings {
+    pub render_path: RenderPath,
     pub render_resolution_scale: f32,
     pub shading_mode: ShadingMode,
     pub render_distance: f32,
+    /// Area lights further away from the camera are culled, independent of the render distance.
+    pub area_light_distance: f32,
+    /// Cull dynamic instances outside the view, which also removes them from shadows and reflections.
+    pub enable_frustum_culling: bool,
     pub ambient_factor: f32,
     pub enable_lighting: bool,
     pub enable_shadows: bool,
     pub shadow_bias: f32,
     pub lighting_range_bias: f32,
     pub lighting_resolution_scale: f32,
+    /// Emissive triangles considered per pixel when lighting the scene with emissive materials, zero disables it.
+    /// Only supported by `RenderPath::RayTraced`.
+    pub emissive_light_candidates: u32,
     pub enable_reflections: bool,
+    /// Surfaces rougher than this fall back to the prefiltered environment instead of tracing reflections.
     pub reflection_max_roughness: f32,
+    /// Scale of the reflection resolution relative to the render resolution.
+    pub reflection_resolution_scale: f32,
+    /// Trace a bounce of diffuse light, replacing the constant ambient term.
+    pub enable_global_illumination: bool,
+    /// Scale of the global illumination resolution relative to the render resolution.
+    pub global_illumination_resolution_scale: f32,
+    /// Screen space ambient occlusion, off by default as it darkens scenes that were lit without it.
+    pub enable_ambient_occlusion: bool,
+    pub ambient_occlusion_radius: f32,
+    pub ambient_occlusion_intensity: f32,
+    pub ambient_occlusion_sample_count: u32,
+    /// Depth difference below which samples don't occlude, hides self occlusion on flat surfaces.
+    pub ambient_occlusion_bias: f32,
     pub enable_debug_lines: bool,
     pub apply_mipmaps: bool,
     pub apply_normal_maps: bool,
@@ -183,25 +342,48 @@ pub struct RenderSettings {
     pub bloom_radius: f32,
     pub enable_emissive_stabilization: bool,
     pub enable_taa: bool,
+    pub tone_mapper: ToneMapper,
+    /// Exposure compensation in stops.
+    pub exposure_compensation: f32,
+    pub enable_auto_exposure: bool,
+    pub auto_exposure_speed: f32,
+    /// Blend between the tonemapped image and the color grading lut set through `Renderer::set_color_grading_lut`.
+    pub color_grading_intensity: f32,
     pub sun: SunInfo,
+    /// Drives the direction and color of `sun` when set.
+    pub time_of_day: Option<TimeOfDay>,
     pub atmosphere: AtmosphereInfo,
+    /// Multiplier on the environment map set through `Sky::set_environment_map`.
+    pub environment_intensity: f32,
     pub world_up: Vec3,
 }
 
 impl Default for RenderSettings {
     fn default() -> Self {
         Self {
+            render_path: RenderPath::RayTraced,
             render_resolution_scale: 1.0,
             shading_mode: ShadingMode::Full,
             render_distance: 1000.0,
+            area_light_distance: 1500.0,
+            enable_frustum_culling: false,
             ambient_factor: 0.1,
             enable_lighting: true,
             enable_shadows: true,
             shadow_bias: 0.1,
             lighting_range_bias: 0.0,
             lighting_resolution_scale: 0.9,
+            emissive_light_candidates: 8,
             enable_reflections: true,
-            reflection_max_roughness: 0.2,
+            reflection_max_roughness: 1.0,
+            reflection_resolution_scale: 0.5,
+            enable_global_illumination: false,
+            global_illumination_resolution_scale: 0.5,
+            enable_ambient_occlusion: false,
+            ambient_occlusion_radius: 0.5,
+            ambient_occlusion_intensity: 1.0,
+            ambient_occlusion_sample_count: 16,
+            ambient_occlusion_bias: 0.025,
             enable_debug_lines: true,
             apply_mipmaps: true,
             apply_normal_maps: true,
@@ -210,8 +392,15 @@ impl Default for RenderSettings {
             bloom_radius: 1.0,
             enable_emissive_stabilization: true,
             enable_taa: true,
+            tone_mapper: ToneMapper::Reinhard,
+            exposure_compensation: 0.0,
+            enable_auto_exposure: false,
+            auto_exposure_speed: 1.5,
+            color_grading_intensity: 1.0,
             sun: SunInfo::default(),
+            time_of_day: None,
             atmosphere: AtmosphereInfo::default(),
+            environment_intensity: 1.0,
             world_up: UP,
         }
     }
@@ -221,6 +410,13 @@ impl RenderSettings {
     #[cfg(feature = "egui")]
     pub fn egui(&mut self, ui: &mut egui::Ui) {
         ui.heading("Shading");
+        egui::ComboBox::from_label("Render Path")
+            .selected_text(self.render_path.to_string())
+            .show_ui(ui, |ui| {
+                for path in [RenderPath::RayTraced, RenderPath::Raster] {
+                    ui.selectable_value(&mut self.render_path, path, path.to_string());
+                }
+            });
         ui.add(
             egui::Slider::new(&mut self.render_resolution_scale, 0.4..=1.0)
                 .text("Resolution Scale"),
@@ -239,11 +435,18 @@ impl RenderSettings {
                     ShadingMode::Fog,
                     ShadingMode::Reflection,
                     ShadingMode::SimpleLighting,
+                    ShadingMode::AmbientOcclusion,
+                    ShadingMode::Indirect,
                 ] {
                     ui.selectable_value(&mut self.shading_mode, mode, mode.to_string());
                 }
             });
         ui.add(egui::Slider::new(&mut self.render_distance, 0.0..=10000.0).text("Render Distance"));
+        ui.add(
+            egui::Slider::new(&mut self.area_light_distance, 0.0..=10000.0)
+                .text("Area Light Distance"),
+        );
+        ui.checkbox(&mut self.enable_frustum_culling, "Frustum Culling");
         ui.add(egui::Slider::new(&mut self.ambient_factor, 0.0..=1.0).text("Ambient Factor"));
         ui.checkbox(&mut self.enable_debug_lines, "Debug Lines");
         ui.checkbox(&mut self.apply_mipmaps, "Mipmapping");
@@ -259,6 +462,10 @@ impl RenderSettings {
         ui.add(egui::Slider::new(&mut self.lighting_range_bias, 0.0..=0.3).text("Range Bias"));
         ui.checkbox(&mut self.enable_shadows, "Shadows");
         ui.add(egui::Slider::new(&mut self.shadow_bias, 0.0..=1.0).text("Shadow Bias"));
+        ui.add(
+            egui::Slider::new(&mut self.emissive_light_candidates, 0..=32)
+                .text("Emissive Candidates"),
+        );
         ui.separator();
 
         ui.heading("Reflections");
@@ -266,15 +473,54 @@ impl RenderSettings {
         ui.add(
             egui::Slider::new(&mut self.reflection_max_roughness, 0.0..=1.0).text("Max Roughness"),
         );
+        ui.add(
+            egui::Slider::new(&mut self.reflection_resolution_scale, 0.25..=1.0)
+                .text("Resolution Scale"),
+        );
+        ui.separator();
+
+        ui.heading("Global Illumination");
+        ui.checkbox(&mut self.enable_global_illumination, "Enable");
+        ui.add(
+            egui::Slider::new(&mut self.global_illumination_resolution_scale, 0.25..=1.0)
+                .text("Resolution Scale"),
+        );
+        ui.separator();
+
+        ui.heading("Ambient Occlusion");
+        ui.checkbox(&mut self.enable_ambient_occlusion, "Enable");
+        ui.add(egui::Slider::new(&mut self.ambient_occlusion_radius, 0.01..=5.0).text("Radius"));
+        ui.add(
+            egui::Slider::new(&mut self.ambient_occlusion_intensity, 0.0..=1.0).text("Intensity"),
+        );
+        ui.add(egui::Slider::new(&mut self.ambient_occlusion_sample_count, 1..=64).text("Samples"));
+        ui.add(egui::Slider::new(&mut self.ambient_occlusion_bias, 0.0..=0.2).text("Bias"));
         ui.separator();
 
         ui.heading("Sun");
+        let mut enable_time_of_day = self.time_of_day.is_some();
+        ui.checkbox(&mut enable_time_of_day, "Time Of Day");
+        if enable_time_of_day != self.time_of_day.is_some() {
+            self.time_of_day = enable_time_of_day.then(TimeOfDay::default);
+        }
+        if let Some(time_of_day) = &mut self.time_of_day {
+            time_of_day.egui(ui);
+            time_of_day.apply(&mut self.sun, self.world_up, &self.atmosphere);
+        }
         self.sun.egui(ui);
         ui.separator();
         ui.heading("Atmosphere");
         self.atmosphere.egui(ui);
         ui.separator();
 
+        ui.heading("Environment");
+        ui.add(
+            egui::Slider::new(&mut self.environment_intensity, 0.0..=10.0)
+                .logarithmic(true)
+                .text("Intensity"),
+        );
+        ui.separator();
+
         ui.heading("Bloom");
         ui.checkbox(&mut self.enable_bloom, "Enable");
         ui.add(egui::Slider::new(&mut self.bloom_intensity, 0.0..=1.0).text("Intensity"));
@@ -286,6 +532,33 @@ impl RenderSettings {
 
         ui.heading("Taa");
         ui.checkbox(&mut self.enable_taa, "Enable");
+        ui.separator();
+
+        ui.heading("Tonemapping");
+        egui::ComboBox::from_label("Tone Mapper")
+            .selected_text(self.tone_mapper.to_string())
+            .show_ui(ui, |ui| {
+                for tone_mapper in [
+                    ToneMapper::Reinhard,
+                    ToneMapper::AcesFitted,
+                    ToneMapper::Agx,
+                    ToneMapper::PbrNeutral,
+                ] {
+                    ui.selectable_value(
+                        &mut self.tone_mapper,
+                        tone_mapper,
+                        tone_mapper.to_string(),
+                    );
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.exposure_compensation, -8.0..=8.0).text("Exposure"));
+        ui.checkbox(&mut self.enable_auto_exposure, "Auto Exposure");
+        ui.add(
+            egui::Slider::new(&mut self.auto_exposure_speed, 0.1..=10.0).text("Adaptation Speed"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.color_grading_intensity, 0.0..=1.0).text("Color Grading"),
+        );
     }
 }
 
@@ -300,21 +573,233 @@ pub struct RenderParameters<'a> {
     pub gizmo_draw_data: Option<transform_gizmo::GizmoDrawData>,
 }
 
+pub struct RenderToImageParameters<'a> {
+    pub render_settings: &'a RenderSettings,
+    /// Rendered as is, the caller is responsible for setting up both views and their projections.
+    pub xr_camera_state: &'a XrCameraState,
+    pub world: &'a specs::World,
+    pub gpu_resources: &'a mut GpuResources,
+    /// Time step used for animation and exposure adaptation, instead of the time passed since the previous frame.
+    pub delta_time: f32,
+    /// Discard temporal history (TAA, auto exposure and denoisers) before rendering,
+    /// making the result independent of previously rendered frames.
+    pub reset_history: bool,
+}
+
+/// Tightly packed `Rgba8UnormSrgb` pixels for both views, read back from the gpu.
+pub struct RenderedImage {
+    pub resolution: UVec2,
+    pub views: [Vec<u8>; 2],
+}
+
+/// Closest surface hit by a ray passed to `Renderer::pick`.
+#[derive(Debug, Clone, Copy)]
+pub struct PickHit {
+    pub entity: specs::Entity,
+    pub position: Vec3,
+    /// Geometric normal facing the ray origin.
+    pub normal: Vec3,
+    /// Index into the material pool.
+    pub material_idx: u32,
+    pub distance: f32,
+}
+
+/// Identifies a ray passed to `Renderer::pick`, matching it with its `PickResponse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PickId(u64);
+
+/// Outcome of the pick `id`, `hit` is `None` when the ray hit nothing or could not be traced.
+#[derive(Debug, Clone, Copy)]
+pub struct PickResponse {
+    pub id: PickId,
+    pub hit: Option<PickHit>,
+}
+
+/// Pick traced during an earlier frame, waiting for its result to be read back.
+struct PendingPick {
+    id: PickId,
+    dynamic_instance_entities: Vec<specs::Entity>,
+    map_requested: bool,
+    /// Set by the `map_async` callback, false when mapping failed.
+    mapped: Arc<OnceLock<bool>>,
+}
+
 pub struct Renderer {
     sized_resources: SizedResources,
+    supported_render_path: RenderPath,
+    shadow_map_texture: wgpu::Texture,
+    shadow_map_buffer: wgpu::Buffer,
+    profiler: wgpu_util::GpuProfiler,
+    render_graph: RenderGraph,
+    luminance_histogram_buffer: wgpu::Buffer,
+    exposure_buffer: wgpu::Buffer,
+    color_grading_lut: Option<ColorGradingLut>,
+    identity_color_grading_lut: ColorGradingLut,
+    frame_timer: Timer,
     frame_idx: u32,
+    pick_ray: Option<(PickId, Vec3, Vec3)>,
+    next_pick_id: u64,
+    pending_pick: Option<PendingPick>,
+    pick_response: Option<PickResponse>,
+    pick_result_buffer: wgpu::Buffer,
+    pick_readback_buffer: wgpu::Buffer,
 }
 
 impl Renderer {
     pub fn new(resolution: UVec2, ctx: &wgpu_util::Context) -> Self {
-        let sized_resources = SizedResources::new(resolution, 1.0, 1.0, &ctx.device);
+        let sized_resources = SizedResources::new(resolution, 1.0, 1.0, 1.0, 1.0, &ctx.device);
+        let shadow_map_texture = shadow_pass::create_shadow_map_texture(&ctx.device);
+        let shadow_map_buffer = shadow_pass::create_shadow_map_buffer(&ctx.device);
+        let render_graph = RenderGraph::new(
+            sized_resources.resolution,
+            sized_resources.render_resolution,
+            sized_resources.lighting_resolution,
+            sized_resources.reflection_resolution,
+            sized_resources.global_illumination_resolution,
+        );
 
         Self {
             sized_resources,
+            supported_render_path: RenderPath::from_features(ctx.device.features()),
+            shadow_map_texture,
+            shadow_map_buffer,
+            profiler: wgpu_util::GpuProfiler::new(&ctx.device, &ctx.queue),
+            render_graph,
+            luminance_histogram_buffer: auto_exposure_pass::create_histogram_buffer(&ctx.device),
+            exposure_buffer: auto_exposure_pass::create_exposure_buffer(&ctx.device),
+            color_grading_lut: None,
+            identity_color_grading_lut: ColorGradingLut::identity(&ctx.device, &ctx.queue),
+            frame_timer: Timer::new(),
             frame_idx: 0,
+            pick_ray: None,
+            next_pick_id: 0,
+            pending_pick: None,
+            pick_response: None,
+            pick_result_buffer: pick_pass::create_result_buffer(&ctx.device),
+            pick_readback_buffer: pick_pass::create_readback_buffer(&ctx.device),
         }
     }
 
+    /// Per pass timings of `render`, disabled until enabled through `profiler_mut`.
+    pub fn profiler(&self) -> &wgpu_util::GpuProfiler {
+        &self.profiler
+    }
+
+    pub fn profiler_mut(&mut self) -> &mut wgpu_util::GpuProfiler {
+        &mut self.profiler
+    }
+
+    /// Custom passes and the textures they share, encoded around the built-in passes by `render`.
+    pub fn render_graph(&self) -> &RenderGraph {
+        &self.render_graph
+    }
+
+    pub fn render_graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.render_graph
+    }
+
+    /// Built-in or render graph texture by name, as written by the last `render`.
+    /// The render target is owned by the caller and not available here.
+    pub fn texture(&self, name: &str) -> Option<&wgpu::Texture> {
+        self.sized_resources
+            .textures(self.frame_idx.wrapping_sub(1))
+            .into_iter()
+            .find(|(builtin_name, _)| *builtin_name == name)
+            .map(|(_, texture)| texture)
+            .or_else(|| self.render_graph.texture(name))
+    }
+
+    /// Lut applied after tonemapping, scaled by `RenderSettings::color_grading_intensity`. `None` leaves colors ungraded.
+    pub fn set_color_grading_lut(&mut self, color_grading_lut: Option<ColorGradingLut>) {
+        self.color_grading_lut = color_grading_lut;
+    }
+
+    /// The render path that is actually used for the requested one, falls back to `RenderPath::Raster` when ray tracing is unsupported.
+    pub fn effective_render_path(&self, render_path: RenderPath) -> RenderPath {
+        if self.supported_render_path == RenderPath::Raster {
+            RenderPath::Raster
+        } else {
+            render_path
+        }
+    }
+
+    /// Trace a ray, for example from `XrCameraData::generate_ray`, against the scene during a following `render`.
+    /// Results arrive asynchronously through `pick_response`, typically a frame later, tagged with the returned id.
+    /// A ray that has not been traced yet is replaced by the next call and never receives a response.
+    /// Only the ray traced render path supports picking, other paths respond without a hit.
+    pub fn pick(&mut self, origin: Vec3, direction: Vec3) -> PickId {
+        let id = PickId(self.next_pick_id);
+        self.next_pick_id += 1;
+        self.pick_ray = Some((id, origin, direction.normalize()));
+        id
+    }
+
+    /// Response to the most recent pick read back from the gpu, compare its id with the one returned by `pick`.
+    pub fn pick_response(&self) -> Option<PickResponse> {
+        self.pick_response
+    }
+
+    /// Map the readback buffer of a pick submitted during an earlier frame and resolve its hit once mapped.
+    fn read_back_pick(&mut self, gpu_resources: &GpuResources, device: &wgpu::Device) {
+        let Some(pending_pick) = &mut self.pending_pick else {
+            return;
+        };
+
+        if !pending_pick.map_requested {
+            let mapped = pending_pick.mapped.clone();
+            self.pick_readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = mapped.set(result.is_ok());
+                });
+            pending_pick.map_requested = true;
+        }
+
+        let _ = device.poll(wgpu::PollType::Poll);
+        let Some(&mapped) = pending_pick.mapped.get() else {
+            return;
+        };
+
+        // A failed readback responds without a hit, leaving the buffer free for the next pick
+        if !mapped {
+            self.pick_response = Some(PickResponse {
+                id: pending_pick.id,
+                hit: None,
+            });
+            self.pending_pick = None;
+            return;
+        }
+
+        let pick_result: PickResult = {
+            let data = self.pick_readback_buffer.slice(..).get_mapped_range();
+            bytemuck::pod_read_unaligned(&data)
+        };
+        self.pick_readback_buffer.unmap();
+
+        // Entities removed since the pick was traced resolve to no hit
+        let hit = if pick_result.hit != 0 {
+            gpu_resources
+                .instance_entity(
+                    pick_result.instance_idx,
+                    &pending_pick.dynamic_instance_entities,
+                )
+                .map(|entity| PickHit {
+                    entity,
+                    position: pick_result.position,
+                    normal: pick_result.normal,
+                    material_idx: pick_result.material_idx,
+                    distance: pick_result.distance,
+                })
+        } else {
+            None
+        };
+        self.pick_response = Some(PickResponse {
+            id: pending_pick.id,
+            hit,
+        });
+        self.pending_pick = None;
+    }
+
     pub fn render(
         &mut self,
         parameters: &mut RenderParameters,
@@ -322,280 +807,839 @@ impl Renderer {
         ctx: &wgpu_util::Context,
         pipeline_database: &mut wgpu_util::PipelineDatabase,
     ) {
+        let delta_time = self.frame_timer.elapsed();
+        self.frame_timer.reset();
+
+        self.render_with_delta_time(
+            parameters,
+            delta_time,
+            command_encoder,
+            ctx,
+            pipeline_database,
+        );
+    }
+
+    fn render_with_delta_time(
+        &mut self,
+        parameters: &mut RenderParameters,
+        delta_time: f32,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+        pipeline_database: &mut wgpu_util::PipelineDatabase,
+    ) {
+        self.profiler.begin_frame(&ctx.device);
+
         if parameters.render_settings.render_resolution_scale
             != self.sized_resources.render_resolution_scale
             || parameters.render_settings.lighting_resolution_scale
                 != self.sized_resources.lighting_resolution_scale
+            || parameters.render_settings.reflection_resolution_scale
+                != self.sized_resources.reflection_resolution_scale
+            || parameters
+                .render_settings
+                .global_illumination_resolution_scale
+                != self.sized_resources.global_illumination_resolution_scale
         {
             self.sized_resources = SizedResources::new(
                 self.sized_resources.resolution,
                 parameters.render_settings.render_resolution_scale,
                 parameters.render_settings.lighting_resolution_scale,
+                parameters.render_settings.reflection_resolution_scale,
+                parameters
+                    .render_settings
+                    .global_illumination_resolution_scale,
                 &ctx.device,
             );
+            self.resize_render_graph(&ctx.device);
         }
 
-        parameters.gpu_resources.sky_mut().constants.sun = parameters.render_settings.sun;
+        let mut sun = parameters.render_settings.sun;
+        if let Some(time_of_day) = &parameters.render_settings.time_of_day {
+            time_of_day.apply(
+                &mut sun,
+                parameters.render_settings.world_up,
+                &parameters.render_settings.atmosphere,
+            );
+        }
+        parameters.gpu_resources.sky_mut().constants.sun = sun;
         parameters.gpu_resources.sky_mut().constants.atmosphere =
             parameters.render_settings.atmosphere;
         parameters.gpu_resources.sky_mut().constants.world_up = parameters.render_settings.world_up;
+        parameters
+            .gpu_resources
+            .sky_mut()
+            .constants
+            .environment_intensity = parameters.render_settings.environment_intensity;
 
         parameters
             .gpu_resources
             .linear_transformed_cosines_mut()
             .range_bias = parameters.render_settings.lighting_range_bias;
 
+        let render_path = self.effective_render_path(parameters.render_settings.render_path);
+
+        let scope = self
+            .profiler
+            .begin_scope("gpu_resources_update", command_encoder);
         parameters.gpu_resources.update(
             parameters.world,
             parameters.xr_camera_state,
-            command_encoder,
-            &ctx.queue,
-        );
-
-        rt_gbuffer_pass::encode(
-            &RtGbufferPassParameters {
-                resolution: self.sized_resources.render_resolution,
-                mipmapping: parameters.render_settings.apply_mipmaps,
-                normal_mapping: parameters.render_settings.apply_normal_maps,
-                reflection_max_roughness: parameters.render_settings.reflection_max_roughness,
+            render_path,
+            &CullingParameters {
                 render_distance: parameters.render_settings.render_distance,
-                gpu_resources: parameters.gpu_resources,
-                xr_camera_buffer: parameters.xr_camera_buffer,
-                gbuffer: &self.sized_resources.gbuffer,
-                reflection_counter_buffer: &self.sized_resources.reflection_counter_buffer,
-                reflection_pid_buffer: &self.sized_resources.reflection_pid_buffer,
+                area_light_distance: parameters.render_settings.area_light_distance,
+                frustum_culling: parameters.render_settings.enable_frustum_culling,
             },
-            &ctx.device,
+            delta_time,
             command_encoder,
+            ctx,
             pipeline_database,
         );
+        self.profiler.end_scope(scope, command_encoder);
 
-        build_frustum_pass::encode(
-            &BuildFrustumPassParameters {
-                resolution: self.sized_resources.render_resolution,
-                lighting_resolution: self.sized_resources.lighting_resolution,
-                gbuffer: &self.sized_resources.gbuffer,
-                xr_camera_buffer: parameters.xr_camera_buffer,
-                frustum_buffer: &self.sized_resources.frustum_buffer,
-            },
-            &ctx.device,
-            command_encoder,
-            pipeline_database,
-        );
+        self.read_back_pick(parameters.gpu_resources, &ctx.device);
+        if render_path == RenderPath::RayTraced && self.pending_pick.is_none() {
+            if let Some((id, origin, direction)) = self.pick_ray.take() {
+                let scope = self.profiler.begin_scope("pick_pass", command_encoder);
+                pick_pass::encode(
+                    &PickPassParameters {
+                        origin,
+                        direction,
+                        max_distance: parameters.render_settings.render_distance,
+                        gpu_resources: parameters.gpu_resources,
+                        result_buffer: &self.pick_result_buffer,
+                        readback_buffer: &self.pick_readback_buffer,
+                    },
+                    &ctx.device,
+                    command_encoder,
+                    pipeline_database,
+                );
+                self.profiler.end_scope(scope, command_encoder);
 
-        ltc_cull_pass::encode(
-            &LtcCullPassParameters {
-                resolution: self.sized_resources.lighting_resolution,
-                gpu_resources: parameters.gpu_resources,
-                frustum_buffer: &self.sized_resources.frustum_buffer,
-                ltc_instance_index_buffer: &self.sized_resources.ltc_instance_index_buffer,
-                ltc_instance_grid_texture_view: &self
-                    .sized_resources
-                    .ltc_instance_grid_texture_view,
+                self.pending_pick = Some(PendingPick {
+                    id,
+                    dynamic_instance_entities: parameters
+                        .gpu_resources
+                        .dynamic_instance_entities()
+                        .to_vec(),
+                    map_requested: false,
+                    mapped: Arc::new(OnceLock::new()),
+                });
+            }
+        } else if render_path == RenderPath::Raster {
+            if let Some((id, _, _)) = self.pick_ray.take() {
+                self.pick_response = Some(PickResponse { id, hit: None });
+            }
+        }
+
+        for scheduled in self.render_graph.schedule() {
+            match scheduled {
+                ScheduledPass::Builtin(pass) => self.encode_builtin_pass(
+                    pass,
+                    render_path,
+                    delta_time,
+                    parameters,
+                    command_encoder,
+                    ctx,
+                    pipeline_database,
+                ),
+                ScheduledPass::User(pass_idx) => self.encode_render_graph_pass(
+                    pass_idx,
+                    render_path,
+                    parameters,
+                    command_encoder,
+                    ctx,
+                    pipeline_database,
+                ),
+            }
+        }
+
+        parameters.gpu_resources.end_frame(command_encoder);
+        self.profiler.end_frame(command_encoder);
+        self.frame_idx += 1;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn encode_builtin_pass(
+        &mut self,
+        pass: BuiltinPass,
+        render_path: RenderPath,
+        delta_time: f32,
+        parameters: &mut RenderParameters,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+        pipeline_database: &mut wgpu_util::PipelineDatabase,
+    ) {
+        let shading_texture = &self.sized_resources.shading_texture[self.frame_idx as usize % 2];
+        let prev_shading_texture =
+            &self.sized_resources.shading_texture[(self.frame_idx as usize + 1) % 2];
+
+        match pass {
+            BuiltinPass::Gbuffer => match render_path {
+                RenderPath::RayTraced => {
+                    let scope = self
+                        .profiler
+                        .begin_scope("rt_gbuffer_pass", command_encoder);
+                    rt_gbuffer_pass::encode(
+                        &RtGbufferPassParameters {
+                            resolution: self.sized_resources.render_resolution,
+                            mipmapping: parameters.render_settings.apply_mipmaps,
+                            normal_mapping: parameters.render_settings.apply_normal_maps,
+                            render_distance: parameters.render_settings.render_distance,
+                            gpu_resources: parameters.gpu_resources,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                            gbuffer: &self.sized_resources.gbuffer,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+                RenderPath::Raster => {
+                    let scope = self.profiler.begin_scope("gbuffer_pass", command_encoder);
+                    gbuffer_pass::encode(
+                        &GbufferPassParameters {
+                            resolution: self.sized_resources.render_resolution,
+                            mipmapping: parameters.render_settings.apply_mipmaps,
+                            normal_mapping: parameters.render_settings.apply_normal_maps,
+                            gpu_resources: parameters.gpu_resources,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                            gbuffer: &self.sized_resources.gbuffer,
+                            depth_texture: &self.sized_resources.depth_texture,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
             },
-            &ctx.device,
+            BuiltinPass::BuildFrustum => {
+                let scope = self
+                    .profiler
+                    .begin_scope("build_frustum_pass", command_encoder);
+                build_frustum_pass::encode(
+                    &BuildFrustumPassParameters {
+                        resolution: self.sized_resources.render_resolution,
+                        lighting_resolution: self.sized_resources.lighting_resolution,
+                        gbuffer: &self.sized_resources.gbuffer,
+                        xr_camera_buffer: parameters.xr_camera_buffer,
+                        frustum_buffer: &self.sized_resources.frustum_buffer,
+                    },
+                    &ctx.device,
+                    command_encoder,
+                    pipeline_database,
+                );
+                self.profiler.end_scope(scope, command_encoder);
+            }
+            BuiltinPass::LtcCull => {
+                let scope = self.profiler.begin_scope("ltc_cull_pass", command_encoder);
+                ltc_cull_pass::encode(
+                    &LtcCullPassParameters {
+                        resolution: self.sized_resources.lighting_resolution,
+                        gpu_resources: parameters.gpu_resources,
+                        frustum_buffer: &self.sized_resources.frustum_buffer,
+                        ltc_instance_index_buffer: &self.sized_resources.ltc_instance_index_buffer,
+                        ltc_instance_grid_texture_view: &self
+                            .sized_resources
+                            .ltc_instance_grid_texture_view,
+                    },
+                    &ctx.device,
+                    command_encoder,
+                    pipeline_database,
+                );
+                self.profiler.end_scope(scope, command_encoder);
+            }
+            BuiltinPass::Shadow => {
+                if parameters.render_settings.enable_lighting
+                    && render_path == RenderPath::Raster
+                    && parameters.render_settings.enable_shadows
+                {
+                    let scope = self.profiler.begin_scope("shadow_pass", command_encoder);
+                    shadow_pass::encode(
+                        &ShadowPassParameters {
+                            camera_position: parameters.xr_camera_state.stage_translation,
+                            shadow_bias: parameters.render_settings.shadow_bias,
+                            render_distance: parameters.render_settings.render_distance,
+                            gpu_resources: parameters.gpu_resources,
+                            shadow_map_texture: &self.shadow_map_texture,
+                            shadow_map_buffer: &self.shadow_map_buffer,
+                        },
+                        &ctx.device,
+                        &ctx.queue,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+            }
+            BuiltinPass::LtcLighting => {
+                if parameters.render_settings.enable_lighting {
+                    let lighting_view = create_array_view(&self.sized_resources.lighting_texture);
+                    let shadow_map_view =
+                        self.shadow_map_texture
+                            .create_view(&wgpu::TextureViewDescriptor {
+                                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                                ..Default::default()
+                            });
+
+                    let scope = self
+                        .profiler
+                        .begin_scope("ltc_lighting_pass", command_encoder);
+                    ltc_lighting_pass::encode(
+                        &LtcLightingPassParameters {
+                            resolution: self.sized_resources.render_resolution,
+                            lighting_resolution: self.sized_resources.lighting_resolution,
+                            shadows: parameters.render_settings.enable_shadows,
+                            shadow_bias: parameters.render_settings.shadow_bias,
+                            frame_idx: self.frame_idx,
+                            emissive_light_candidates: parameters
+                                .render_settings
+                                .emissive_light_candidates,
+                            render_path,
+                            gpu_resources: parameters.gpu_resources,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                            gbuffer: &self.sized_resources.gbuffer,
+                            ltc_instance_index_buffer: &self
+                                .sized_resources
+                                .ltc_instance_index_buffer,
+                            ltc_instance_grid_texture_view: &self
+                                .sized_resources
+                                .ltc_instance_grid_texture_view,
+                            dst_view: &lighting_view,
+                            shadow_map_view: &shadow_map_view,
+                            shadow_map_buffer: &self.shadow_map_buffer,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                } else {
+                    command_encoder.clear_texture(
+                        &self.sized_resources.lighting_texture,
+                        &wgpu::ImageSubresourceRange::default(),
+                    );
+                }
+            }
+            BuiltinPass::Reflection => {
+                // Reflections are traced against the acceleration structures
+                if parameters.render_settings.enable_reflections
+                    && render_path == RenderPath::RayTraced
+                {
+                    let scope = self
+                        .profiler
+                        .begin_scope("reflection_pass", command_encoder);
+                    reflection_pass::encode(
+                        &ReflectionPassParameters {
+                            resolution: self.sized_resources.render_resolution,
+                            reflection_resolution: self.sized_resources.reflection_resolution,
+                            frame_idx: self.frame_idx,
+                            ambient_factor: parameters.render_settings.ambient_factor,
+                            render_distance: parameters.render_settings.render_distance,
+                            reflection_max_roughness: parameters
+                                .render_settings
+                                .reflection_max_roughness,
+                            gpu_resources: parameters.gpu_resources,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                            gbuffer: &self.sized_resources.gbuffer,
+                            trace_texture: &self.sized_resources.reflection_trace_texture,
+                            history_textures: &self.sized_resources.reflection_history_texture,
+                            dst_texture: &self.sized_resources.reflection_texture,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                } else {
+                    command_encoder.clear_texture(
+                        &self.sized_resources.reflection_texture,
+                        &wgpu::ImageSubresourceRange::default(),
+                    );
+                    // Prevents stale history from ghosting in once reflections are enabled again
+                    for history_texture in &self.sized_resources.reflection_history_texture {
+                        command_encoder.clear_texture(
+                            history_texture,
+                            &wgpu::ImageSubresourceRange::default(),
+                        );
+                    }
+                }
+            }
+            BuiltinPass::GlobalIllumination => {
+                // Diffuse rays are traced against the acceleration structures
+                if parameters.render_settings.enable_global_illumination
+                    && render_path == RenderPath::RayTraced
+                {
+                    let scope = self
+                        .profiler
+                        .begin_scope("global_illumination_pass", command_encoder);
+                    global_illumination_pass::encode(
+                        &GlobalIlluminationPassParameters {
+                            resolution: self.sized_resources.render_resolution,
+                            global_illumination_resolution: self
+                                .sized_resources
+                                .global_illumination_resolution,
+                            frame_idx: self.frame_idx,
+                            ambient_factor: parameters.render_settings.ambient_factor,
+                            render_distance: parameters.render_settings.render_distance,
+                            emissive_lights: parameters.render_settings.enable_lighting
+                                && parameters.render_settings.emissive_light_candidates > 0,
+                            gpu_resources: parameters.gpu_resources,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                            gbuffer: &self.sized_resources.gbuffer,
+                            trace_texture: &self.sized_resources.global_illumination_trace_texture,
+                            history_textures: &self
+                                .sized_resources
+                                .global_illumination_history_texture,
+                            dst_texture: &self.sized_resources.global_illumination_texture,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                } else {
+                    command_encoder.clear_texture(
+                        &self.sized_resources.global_illumination_texture,
+                        &wgpu::ImageSubresourceRange::default(),
+                    );
+                    // Prevents stale history from ghosting in once global illumination is enabled again
+                    for history_texture in &self.sized_resources.global_illumination_history_texture
+                    {
+                        command_encoder.clear_texture(
+                            history_texture,
+                            &wgpu::ImageSubresourceRange::default(),
+                        );
+                    }
+                }
+            }
+            BuiltinPass::AmbientOcclusion => {
+                if parameters.render_settings.enable_ambient_occlusion {
+                    let scope = self.profiler.begin_scope("ssao_pass", command_encoder);
+                    ssao_pass::encode(
+                        &SsaoPassParameters {
+                            resolution: self.sized_resources.render_resolution,
+                            frame_idx: self.frame_idx,
+                            sample_count: parameters.render_settings.ambient_occlusion_sample_count,
+                            radius: parameters.render_settings.ambient_occlusion_radius,
+                            intensity: parameters.render_settings.ambient_occlusion_intensity,
+                            bias: parameters.render_settings.ambient_occlusion_bias,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                            gbuffer: &self.sized_resources.gbuffer,
+                            ambient_occlusion_textures: &self
+                                .sized_resources
+                                .ambient_occlusion_texture,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+            }
+            BuiltinPass::Shade => {
+                let scope = self.profiler.begin_scope("shade_pass", command_encoder);
+                shade_pass::encode(
+                    &ShadePassParameters {
+                        resolution: self.sized_resources.render_resolution,
+                        shading_mode: parameters.render_settings.shading_mode,
+                        ambient_factor: parameters.render_settings.ambient_factor,
+                        // Nothing is traced when reflections are off, so every surface keeps the environment specular
+                        reflection_max_roughness: if parameters.render_settings.enable_reflections
+                            && render_path == RenderPath::RayTraced
+                        {
+                            parameters.render_settings.reflection_max_roughness
+                        } else {
+                            0.0
+                        },
+                        ambient_occlusion: parameters.render_settings.enable_ambient_occlusion,
+                        global_illumination: parameters.render_settings.enable_global_illumination
+                            && render_path == RenderPath::RayTraced,
+                        gpu_resources: parameters.gpu_resources,
+                        xr_camera_buffer: parameters.xr_camera_buffer,
+                        gbuffer: &self.sized_resources.gbuffer,
+                        lighting_view: &create_array_view(&self.sized_resources.lighting_texture),
+                        reflection_view: &create_array_view(
+                            &self.sized_resources.reflection_texture,
+                        ),
+                        ambient_occlusion_view: &create_array_view(
+                            &self.sized_resources.ambient_occlusion_texture[0],
+                        ),
+                        global_illumination_view: &create_array_view(
+                            &self.sized_resources.global_illumination_texture,
+                        ),
+                        dst_view: &create_array_view(shading_texture),
+                    },
+                    &ctx.device,
+                    command_encoder,
+                    pipeline_database,
+                );
+                self.profiler.end_scope(scope, command_encoder);
+            }
+            BuiltinPass::Transparent => {
+                let scope = self
+                    .profiler
+                    .begin_scope("transparent_pass", command_encoder);
+                transparent_pass::encode(
+                    &TransparentPassParameters {
+                        resolution: self.sized_resources.render_resolution,
+                        ambient_factor: parameters.render_settings.ambient_factor,
+                        camera_position: parameters.xr_camera_state.stage_translation,
+                        gpu_resources: parameters.gpu_resources,
+                        xr_camera_buffer: parameters.xr_camera_buffer,
+                        gbuffer: &self.sized_resources.gbuffer,
+                        dst_view: &create_array_view(shading_texture),
+                        target_format: wgpu::TextureFormat::Rgba16Float,
+                    },
+                    &ctx.device,
+                    command_encoder,
+                    pipeline_database,
+                );
+                self.profiler.end_scope(scope, command_encoder);
+            }
+            BuiltinPass::Taa => {
+                if parameters.render_settings.enable_taa {
+                    let scope = self.profiler.begin_scope("taa_pass", command_encoder);
+                    taa_pass::encode(
+                        &TaaPassParameters {
+                            resolution: self.sized_resources.render_resolution,
+                            color_texture_view: &create_array_view(shading_texture),
+                            prev_color_texture_view: &create_array_view(prev_shading_texture),
+                            gbuffer: &self.sized_resources.gbuffer,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+            }
+            BuiltinPass::Blit => {
+                let scope = self.profiler.begin_scope("blit_pass", command_encoder);
+                blit_pass::encode(
+                    &BlitPassParameters {
+                        src_view: &create_array_view(shading_texture),
+                        dst_view: &create_array_view(parameters.render_target),
+                        multiview: Some(NonZeroU32::new(2).unwrap()),
+                        view_index_override: None,
+                        target_format: wgpu::TextureFormat::Rgba16Float,
+                    },
+                    &ctx.device,
+                    command_encoder,
+                    pipeline_database,
+                );
+                self.profiler.end_scope(scope, command_encoder);
+            }
+            BuiltinPass::Bloom => {
+                if parameters.render_settings.enable_bloom {
+                    let scope = self.profiler.begin_scope("bloom_pass", command_encoder);
+                    bloom_pass::encode(
+                        &BloomPassParameters {
+                            intensity: parameters.render_settings.bloom_intensity,
+                            radius: parameters.render_settings.bloom_radius,
+                            initial_color_texture: shading_texture,
+                            color_texture: parameters.render_target,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+            }
+            BuiltinPass::AutoExposure => {
+                if parameters.render_settings.enable_auto_exposure {
+                    let scope = self
+                        .profiler
+                        .begin_scope("auto_exposure_pass", command_encoder);
+                    auto_exposure_pass::encode(
+                        &AutoExposurePassParameters {
+                            resolution: self.sized_resources.resolution,
+                            delta_time,
+                            adaptation_speed: parameters.render_settings.auto_exposure_speed,
+                            color_texture_view: &create_array_view(parameters.render_target),
+                            histogram_buffer: &self.luminance_histogram_buffer,
+                            exposure_buffer: &self.exposure_buffer,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+            }
+            BuiltinPass::DebugLines => {
+                if parameters.render_settings.enable_debug_lines {
+                    let scope = self
+                        .profiler
+                        .begin_scope("debug_line_pass", command_encoder);
+                    debug_line_pass::encode(
+                        &DebugLinePassParameters {
+                            gpu_resources: parameters.gpu_resources,
+                            xr_camera_buffer: parameters.xr_camera_buffer,
+                            dst_view: &create_array_view(parameters.render_target),
+                            target_format: wgpu::TextureFormat::Rgba16Float,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+            }
+            BuiltinPass::Gizmo =>
+            {
+                #[cfg(feature = "transform-gizmo")]
+                if let Some(gizmo_draw_data) = &parameters.gizmo_draw_data {
+                    use crate::render_passes::gizmo_pass::{self, GizmoPassParameters};
+
+                    let scope = self.profiler.begin_scope("gizmo_pass", command_encoder);
+                    gizmo_pass::encode(
+                        &GizmoPassParameters {
+                            resolution: self.sized_resources.resolution,
+                            gizmo_draw_data,
+                            dst_view: &create_array_view(parameters.render_target),
+                            target_format: wgpu::TextureFormat::Rgba16Float,
+                        },
+                        &ctx.device,
+                        command_encoder,
+                        pipeline_database,
+                    );
+                    self.profiler.end_scope(scope, command_encoder);
+                }
+            }
+            BuiltinPass::ColorCorrection => {
+                let scope = self
+                    .profiler
+                    .begin_scope("color_correction_pass", command_encoder);
+                color_correction_pass::encode(
+                    &ColorCorrectionPassParameters {
+                        resolution: self.sized_resources.resolution,
+                        tone_mapper: parameters.render_settings.tone_mapper,
+                        exposure_compensation: parameters.render_settings.exposure_compensation,
+                        enable_auto_exposure: parameters.render_settings.enable_auto_exposure,
+                        color_grading_intensity: parameters.render_settings.color_grading_intensity,
+                        color_grading_lut: self
+                            .color_grading_lut
+                            .as_ref()
+                            .unwrap_or(&self.identity_color_grading_lut),
+                        exposure_buffer: &self.exposure_buffer,
+                        color_texture_view: &create_array_view(parameters.render_target),
+                    },
+                    &ctx.device,
+                    command_encoder,
+                    pipeline_database,
+                );
+                self.profiler.end_scope(scope, command_encoder);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn encode_render_graph_pass(
+        &mut self,
+        pass_idx: usize,
+        render_path: RenderPath,
+        parameters: &mut RenderParameters,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+        pipeline_database: &mut wgpu_util::PipelineDatabase,
+    ) {
+        let (pass, mut textures) = self.render_graph.pass_and_textures(pass_idx);
+        textures.extend(self.sized_resources.textures(self.frame_idx));
+        textures.insert(render_graph::RENDER_TARGET, parameters.render_target);
+
+        let scope = self.profiler.begin_scope(pass.name(), command_encoder);
+        pass.encode(&mut RenderGraphContext {
+            resolution: self.sized_resources.resolution,
+            render_resolution: self.sized_resources.render_resolution,
+            lighting_resolution: self.sized_resources.lighting_resolution,
+            reflection_resolution: self.sized_resources.reflection_resolution,
+            global_illumination_resolution: self.sized_resources.global_illumination_resolution,
+            render_path,
+            render_settings: parameters.render_settings,
+            xr_camera_state: parameters.xr_camera_state,
+            xr_camera_buffer: parameters.xr_camera_buffer,
+            gpu_resources: parameters.gpu_resources,
+            gbuffer: &self.sized_resources.gbuffer,
+            device: &ctx.device,
+            queue: &ctx.queue,
             command_encoder,
             pipeline_database,
-        );
+            textures,
+        });
+        self.profiler.end_scope(scope, command_encoder);
+    }
 
-        let lighting_view =
-            self.sized_resources
-                .lighting_texture
-                .create_view(&wgpu::TextureViewDescriptor {
-                    dimension: Some(wgpu::TextureViewDimension::D2Array),
-                    ..Default::default()
-                });
+    /// Render the world into an owned render target and read the result back, without requiring a window or surface.
+    /// Submits and waits on the gpu, intended for offscreen use such as regression tests or thumbnails.
+    pub fn render_to_image(
+        &mut self,
+        parameters: &mut RenderToImageParameters,
+        ctx: &wgpu_util::Context,
+        pipeline_database: &mut wgpu_util::PipelineDatabase,
+    ) -> RenderedImage {
+        let resolution = self.sized_resources.resolution;
 
-        if parameters.render_settings.enable_lighting {
-            ltc_lighting_pass::encode(
-                &LtcLightingPassParameters {
-                    resolution: self.sized_resources.render_resolution,
-                    lighting_resolution: self.sized_resources.lighting_resolution,
-                    shadows: parameters.render_settings.enable_shadows,
-                    shadow_bias: parameters.render_settings.shadow_bias,
-                    gpu_resources: parameters.gpu_resources,
-                    xr_camera_buffer: parameters.xr_camera_buffer,
-                    gbuffer: &self.sized_resources.gbuffer,
-                    ltc_instance_index_buffer: &self.sized_resources.ltc_instance_index_buffer,
-                    ltc_instance_grid_texture_view: &self
-                        .sized_resources
-                        .ltc_instance_grid_texture_view,
-                    dst_view: &lighting_view,
-                },
-                &ctx.device,
-                command_encoder,
-                pipeline_database,
-            );
-        } else {
-            command_encoder.clear_texture(
-                &self.sized_resources.lighting_texture,
-                &wgpu::ImageSubresourceRange::default(),
-            );
-        }
+        let render_target = Self::create_render_target(resolution, &ctx.device);
 
-        let reflection_view =
-            self.sized_resources
-                .reflection_texture
-                .create_view(&wgpu::TextureViewDescriptor {
-                    dimension: Some(wgpu::TextureViewDimension::D2Array),
-                    ..Default::default()
-                });
+        let mut command_encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        if parameters.render_settings.enable_reflections {
-            mirror_reflection_pass::encode(
-                &MirrorReflectionPassParameters {
-                    resolution: self.sized_resources.render_resolution,
-                    reflection_resolution: self.sized_resources.render_resolution, // TODO!
-                    ambient_factor: parameters.render_settings.ambient_factor,
-                    render_distance: parameters.render_settings.render_distance,
-                    gpu_resources: parameters.gpu_resources,
-                    xr_camera_buffer: parameters.xr_camera_buffer,
-                    gbuffer: &self.sized_resources.gbuffer,
-                    reflection_counter_buffer: &self.sized_resources.reflection_counter_buffer,
-                    reflection_pid_buffer: &self.sized_resources.reflection_pid_buffer,
-                    dst_view: &reflection_view,
-                },
-                &ctx.device,
-                command_encoder,
-                pipeline_database,
-            );
-        } else {
-            command_encoder.clear_texture(
-                &self.sized_resources.reflection_texture,
-                &wgpu::ImageSubresourceRange::default(),
-            );
+        if parameters.reset_history {
+            self.reset_history(&mut command_encoder);
         }
 
-        let shading_view = self.sized_resources.shading_texture[self.frame_idx as usize % 2]
-            .create_view(&wgpu::TextureViewDescriptor {
-                dimension: Some(wgpu::TextureViewDimension::D2Array),
-                array_layer_count: Some(2),
-                mip_level_count: Some(1),
-                ..Default::default()
-            });
-        let prev_shading_view = self.sized_resources.shading_texture
-            [(self.frame_idx as usize + 1) % 2]
-            .create_view(&wgpu::TextureViewDescriptor {
-                dimension: Some(wgpu::TextureViewDimension::D2Array),
-                array_layer_count: Some(2),
-                mip_level_count: Some(1),
-                ..Default::default()
+        let xr_camera_data = parameters.xr_camera_state.calculate_camera_data();
+        let xr_camera_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("terrarium::xr_camera"),
+                contents: bytemuck::bytes_of(&[xr_camera_data, xr_camera_data]),
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
             });
 
-        shade_pass::encode(
-            &ShadePassParameters {
-                resolution: self.sized_resources.render_resolution,
-                shading_mode: parameters.render_settings.shading_mode,
-                ambient_factor: parameters.render_settings.ambient_factor,
-                reflection_max_roughness: parameters.render_settings.reflection_max_roughness,
+        self.render_with_delta_time(
+            &mut RenderParameters {
+                render_settings: parameters.render_settings,
+                xr_camera_state: parameters.xr_camera_state,
+                xr_camera_buffer: &xr_camera_buffer,
+                render_target: &render_target,
+                world: parameters.world,
                 gpu_resources: parameters.gpu_resources,
-                xr_camera_buffer: parameters.xr_camera_buffer,
-                gbuffer: &self.sized_resources.gbuffer,
-                lighting_view: &lighting_view,
-                reflection_view: &reflection_view,
-                dst_view: &shading_view,
+                #[cfg(feature = "transform-gizmo")]
+                gizmo_draw_data: None,
             },
-            &ctx.device,
-            command_encoder,
+            parameters.delta_time,
+            &mut command_encoder,
+            ctx,
             pipeline_database,
         );
 
-        if parameters.render_settings.enable_taa {
-            taa_pass::encode(
-                &TaaPassParameters {
-                    resolution: self.sized_resources.render_resolution,
-                    color_texture_view: &shading_view,
-                    prev_color_texture_view: &prev_shading_view,
-                    gbuffer: &self.sized_resources.gbuffer,
-                    xr_camera_buffer: parameters.xr_camera_buffer,
-                },
-                &ctx.device,
-                command_encoder,
-                pipeline_database,
-            );
-        }
+        let srgb_texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("terrarium::render_to_image"),
+            size: wgpu::Extent3d {
+                width: resolution.x,
+                height: resolution.y,
+                depth_or_array_layers: 2,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
 
-        let render_target_view =
-            parameters
-                .render_target
-                .create_view(&wgpu::TextureViewDescriptor {
-                    dimension: Some(wgpu::TextureViewDimension::D2Array),
-                    array_layer_count: Some(2),
-                    mip_level_count: Some(1),
-                    ..Default::default()
-                });
+        let render_target_view = render_target.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            array_layer_count: Some(2),
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        let srgb_view = srgb_texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            array_layer_count: Some(2),
+            ..Default::default()
+        });
 
         blit_pass::encode(
             &BlitPassParameters {
-                src_view: &shading_view,
-                dst_view: &render_target_view,
+                src_view: &render_target_view,
+                dst_view: &srgb_view,
                 multiview: Some(NonZeroU32::new(2).unwrap()),
                 view_index_override: None,
-                target_format: wgpu::TextureFormat::Rgba16Float,
+                target_format: wgpu::TextureFormat::Rgba8UnormSrgb,
             },
             &ctx.device,
-            command_encoder,
+            &mut command_encoder,
             pipeline_database,
         );
 
-        if parameters.render_settings.enable_bloom {
-            bloom_pass::encode(
-                &BloomPassParameters {
-                    intensity: parameters.render_settings.bloom_intensity,
-                    radius: parameters.render_settings.bloom_radius,
-                    initial_color_texture: &self.sized_resources.shading_texture
-                        [self.frame_idx as usize % 2],
-                    color_texture: parameters.render_target,
-                },
-                &ctx.device,
-                command_encoder,
-                pipeline_database,
-            );
-        }
+        let unpadded_bytes_per_row = resolution.x * 4;
+        let padded_bytes_per_row =
+            unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
 
-        if parameters.render_settings.enable_debug_lines {
-            debug_line_pass::encode(
-                &DebugLinePassParameters {
-                    gpu_resources: parameters.gpu_resources,
-                    xr_camera_buffer: parameters.xr_camera_buffer,
-                    dst_view: &render_target_view,
-                    target_format: wgpu::TextureFormat::Rgba16Float,
+        let staging_buffers: [wgpu::Buffer; 2] = std::array::from_fn(|view_index| {
+            let staging_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("terrarium::render_to_image staging"),
+                size: (padded_bytes_per_row * resolution.y) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+
+            command_encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &srgb_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: view_index as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &staging_buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded_bytes_per_row),
+                        rows_per_image: Some(resolution.y),
+                    },
+                },
+                wgpu::Extent3d {
+                    width: resolution.x,
+                    height: resolution.y,
+                    depth_or_array_layers: 1,
                 },
-                &ctx.device,
-                command_encoder,
-                pipeline_database,
             );
-        }
 
-        #[cfg(feature = "transform-gizmo")]
-        if let Some(gizmo_draw_data) = &parameters.gizmo_draw_data {
-            use crate::render_passes::gizmo_pass::{self, GizmoPassParameters};
+            staging_buffer
+        });
 
-            gizmo_pass::encode(
-                &GizmoPassParameters {
-                    resolution: self.sized_resources.resolution,
-                    gizmo_draw_data,
-                    dst_view: &render_target_view,
-                    target_format: wgpu::TextureFormat::Rgba16Float,
-                },
-                &ctx.device,
-                command_encoder,
-                pipeline_database,
-            );
-        }
+        ctx.queue.submit(Some(command_encoder.finish()));
 
-        color_correction_pass::encode(
-            &ColorCorrectionPassParameters {
-                resolution: self.sized_resources.resolution,
-                color_texture_view: &render_target_view,
-            },
-            &ctx.device,
-            command_encoder,
-            pipeline_database,
-        );
+        let views = std::array::from_fn(|view_index| {
+            let padded: Vec<u8> =
+                wgpu_util::readback_buffer(&staging_buffers[view_index], &ctx.device);
 
-        parameters.gpu_resources.end_frame(command_encoder);
-        self.frame_idx += 1;
+            padded
+                .chunks_exact(padded_bytes_per_row as usize)
+                .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+                .copied()
+                .collect()
+        });
+
+        RenderedImage { resolution, views }
+    }
+
+    /// Create a render target matching what `render` expects, including the mip chain used by bloom.
+    pub fn create_render_target(resolution: UVec2, device: &wgpu::Device) -> wgpu::Texture {
+        let mip_level_count =
+            (((resolution.x.max(resolution.y) as f32).log2()).floor() + 1.0) as u32;
+
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("terrarium::render_target"),
+            size: wgpu::Extent3d {
+                width: resolution.x,
+                height: resolution.y,
+                depth_or_array_layers: 2,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING,
+            view_formats: &[],
+        })
     }
 
     pub fn resize(&mut self, resolution: UVec2, ctx: &wgpu_util::Context) {
@@ -603,8 +1647,32 @@ impl Renderer {
             resolution,
             self.sized_resources.render_resolution_scale,
             self.sized_resources.lighting_resolution_scale,
+            self.sized_resources.reflection_resolution_scale,
+            self.sized_resources.global_illumination_resolution_scale,
             &ctx.device,
         );
+        self.resize_render_graph(&ctx.device);
+    }
+
+    /// Discard all temporal history, so that the next frame is rendered as if it was the first one.
+    pub fn reset_history(&mut self, command_encoder: &mut wgpu::CommandEncoder) {
+        self.sized_resources.clear_history(command_encoder);
+
+        // Auto exposure snaps to its target when no previous exposure is available
+        command_encoder.clear_buffer(&self.exposure_buffer, 0, None);
+        self.frame_idx = 0;
+        self.frame_timer.reset();
+    }
+
+    fn resize_render_graph(&mut self, device: &wgpu::Device) {
+        self.render_graph.resize(
+            self.sized_resources.resolution,
+            self.sized_resources.render_resolution,
+            self.sized_resources.lighting_resolution,
+            self.sized_resources.reflection_resolution,
+            self.sized_resources.global_illumination_resolution,
+            device,
+        );
     }
 
     pub fn required_features() -> wgpu::Features {
@@ -613,11 +1681,149 @@ impl Renderer {
             | wgpu::Features::TEXTURE_BINDING_ARRAY
             | wgpu::Features::TEXTURE_COMPRESSION_BC
             | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
-            | wgpu::Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE
-            | wgpu::Features::EXPERIMENTAL_RAY_QUERY
             | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
             | wgpu::Features::FLOAT32_FILTERABLE
             | wgpu::Features::CLEAR_TEXTURE
             | wgpu::Features::POLYGON_MODE_LINE
     }
+
+    /// Features that are enabled when available, without ray tracing the renderer falls back to `RenderPath::Raster`.
+    /// Timestamp queries are only used by the profiler to time passes on the gpu.
+    pub fn optional_features() -> wgpu::Features {
+        Self::ray_tracing_features()
+            | wgpu::Features::TIMESTAMP_QUERY
+            | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS
+    }
+
+    pub fn ray_tracing_features() -> wgpu::Features {
+        wgpu::Features::EXPERIMENTAL_RAY_TRACING_ACCELERATION_STRUCTURE
+            | wgpu::Features::EXPERIMENTAL_RAY_QUERY
+    }
+}
+
+#[test]
+#[ignore = "requires a vulkan adapter supporting the required features"]
+fn render_to_image_is_reproducible() {
+    use gpu_resources::material_pool::MaterialBuilder;
+    use specs::{Builder, WorldExt};
+    use world::components::{
+        AnimationPlayerComponent, AreaLightComponent, DirectionalLightComponent, DynamicComponent,
+        LodMeshComponent, MeshComponent, MorphWeightsComponent, PointLightComponent,
+        SkinnedMeshComponent, SpotLightComponent, TransformComponent, VisibilityRangeComponent,
+    };
+    use world::transform::{Transform, FORWARD, RIGHT};
+
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::VULKAN,
+        ..Default::default()
+    });
+    let adapter = futures::executor::block_on(
+        instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+    )
+    .expect("No vulkan adapter available.");
+    assert!(
+        adapter.features().contains(Renderer::required_features()),
+        "The vulkan adapter does not support the required features."
+    );
+
+    let ctx = futures::executor::block_on(wgpu_util::Context::init_headless(
+        Renderer::optional_features(),
+        Renderer::required_features(),
+        wgpu::DownlevelCapabilities::default(),
+        adapter.limits(),
+        false,
+        true,
+    ));
+    let mut pipeline_database = wgpu_util::PipelineDatabase::new();
+    let mut renderer = Renderer::new(UVec2::new(64, 64), &ctx);
+    let mut gpu_resources = GpuResources::new(&ctx.device, &ctx.queue);
+
+    let mut world = specs::World::new();
+    world.register::<TransformComponent>();
+    world.register::<MeshComponent>();
+    world.register::<AreaLightComponent>();
+    world.register::<PointLightComponent>();
+    world.register::<SpotLightComponent>();
+    world.register::<DirectionalLightComponent>();
+    world.register::<DynamicComponent>();
+    world.register::<SkinnedMeshComponent>();
+    world.register::<AnimationPlayerComponent>();
+    world.register::<MorphWeightsComponent>();
+    world.register::<VisibilityRangeComponent>();
+    world.register::<LodMeshComponent>();
+
+    // A quad in front of the camera, lit by a point light in between
+    let mut packed_vertices = vec![<ugm::mesh::PackedVertex as bytemuck::Zeroable>::zeroed(); 4];
+    for (packed_vertex, corner) in
+        packed_vertices
+            .iter_mut()
+            .zip([-RIGHT - UP, RIGHT - UP, RIGHT + UP, -RIGHT + UP])
+    {
+        packed_vertex.position = (corner + FORWARD * 3.0).into();
+    }
+    let mesh = ugm::mesh::Mesh {
+        packed_vertices,
+        indices: vec![0, 1, 2, 0, 2, 3],
+        triangle_material_indices: vec![0, 0],
+        material_indices: vec![0],
+        ..Default::default()
+    };
+
+    let mut command_encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    let gpu_mesh = gpu_resources
+        .create_gpu_mesh(&mesh, true, &mut command_encoder, &ctx)
+        .unwrap()
+        .unwrap();
+    let gpu_material = gpu_resources
+        .build_gpu_material(
+            &MaterialBuilder::new().with_color(Vec3::new(0.8, 0.4, 0.2)),
+            &mut command_encoder,
+            &ctx,
+        )
+        .unwrap();
+    ctx.queue.submit(Some(command_encoder.finish()));
+
+    world
+        .create_entity()
+        .with(TransformComponent::new(Transform::default(), true))
+        .with(MeshComponent::new(gpu_mesh, vec![gpu_material]))
+        .build();
+    world
+        .create_entity()
+        .with(TransformComponent::new(
+            Transform::new(FORWARD * 1.5 + UP, glam::Quat::IDENTITY, Vec3::ONE),
+            false,
+        ))
+        .with(PointLightComponent::new(Vec3::ONE, 10.0, 10.0))
+        .build();
+
+    let mut xr_camera_state = XrCameraState::new(0.1, 100.0, false);
+    xr_camera_state.default_stage_to_view_space();
+    xr_camera_state.view_to_clip_space =
+        [glam::Mat4::perspective_rh(60.0_f32.to_radians(), 1.0, 0.1, 100.0); 2];
+
+    let render_settings = RenderSettings::default();
+    let mut render = |renderer: &mut Renderer| {
+        renderer.render_to_image(
+            &mut RenderToImageParameters {
+                render_settings: &render_settings,
+                xr_camera_state: &xr_camera_state,
+                world: &world,
+                gpu_resources: &mut gpu_resources,
+                delta_time: 1.0 / 60.0,
+                reset_history: true,
+            },
+            &ctx,
+            &mut pipeline_database,
+        )
+    };
+
+    let first = render(&mut renderer);
+    let second = render(&mut renderer);
+    assert_eq!(first.resolution, UVec2::new(64, 64));
+    // Guards against comparing two empty images
+    assert!(first.views[0].iter().any(|byte| *byte != first.views[0][0]));
+    assert_eq!(first.views, second.views);
 }
@@ -0,0 +1,684 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use glam::UVec2;
+
+use crate::{
+    gpu_resources::{gbuffer::Gbuffer, GpuResources},
+    wgpu_util::PipelineDatabase,
+    xr::XrCameraState,
+    RenderPath, RenderSettings,
+};
+
+/// Final shaded color of the built-in passes at render resolution, before it is blitted into the render target.
+pub const SHADING_TEXTURE: &str = "shading";
+/// Direct lighting at lighting resolution.
+pub const LIGHTING_TEXTURE: &str = "lighting";
+/// Denoised traced reflections at reflection resolution.
+pub const REFLECTION_TEXTURE: &str = "reflection";
+/// Denoised incoming diffuse radiance at global illumination resolution, without the albedo of the surface it arrives at.
+pub const GLOBAL_ILLUMINATION_TEXTURE: &str = "global_illumination";
+/// Denoised ambient occlusion factor at render resolution, one where unoccluded.
+pub const AMBIENT_OCCLUSION_TEXTURE: &str = "ambient_occlusion";
+/// The target passed through `RenderParameters`, at output resolution.
+pub const RENDER_TARGET: &str = "render_target";
+/// Gbuffer contents, not a texture that can be looked up by name but available through `RenderGraphContext::gbuffer`.
+pub const GBUFFER: &str = "gbuffer";
+
+const BUILTIN_TEXTURES: [&str; 6] = [
+    SHADING_TEXTURE,
+    LIGHTING_TEXTURE,
+    REFLECTION_TEXTURE,
+    GLOBAL_ILLUMINATION_TEXTURE,
+    AMBIENT_OCCLUSION_TEXTURE,
+    RENDER_TARGET,
+];
+
+/// Passes encoded by the renderer itself, in execution order. Passes that are disabled through `RenderSettings` or the render path keep their place in the graph, but encode nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinPass {
+    /// Ray traced or rasterized gbuffer, depending on the render path.
+    Gbuffer,
+    BuildFrustum,
+    LtcCull,
+    Shadow,
+    LtcLighting,
+    Reflection,
+    /// One bounce of ray traced diffuse light, only on the ray traced path.
+    GlobalIllumination,
+    AmbientOcclusion,
+    Shade,
+    /// Forward shaded blended materials, composited on top of the shaded image.
+    Transparent,
+    Taa,
+    Blit,
+    Bloom,
+    AutoExposure,
+    DebugLines,
+    Gizmo,
+    ColorCorrection,
+}
+
+impl BuiltinPass {
+    pub const ALL: [Self; 17] = [
+        Self::Gbuffer,
+        Self::BuildFrustum,
+        Self::LtcCull,
+        Self::Shadow,
+        Self::LtcLighting,
+        Self::Reflection,
+        Self::GlobalIllumination,
+        Self::AmbientOcclusion,
+        Self::Shade,
+        Self::Transparent,
+        Self::Taa,
+        Self::Blit,
+        Self::Bloom,
+        Self::AutoExposure,
+        Self::DebugLines,
+        Self::Gizmo,
+        Self::ColorCorrection,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gbuffer => "gbuffer_pass",
+            Self::BuildFrustum => "build_frustum_pass",
+            Self::LtcCull => "ltc_cull_pass",
+            Self::Shadow => "shadow_pass",
+            Self::LtcLighting => "ltc_lighting_pass",
+            Self::Reflection => "reflection_pass",
+            Self::GlobalIllumination => "global_illumination_pass",
+            Self::AmbientOcclusion => "ssao_pass",
+            Self::Shade => "shade_pass",
+            Self::Transparent => "transparent_pass",
+            Self::Taa => "taa_pass",
+            Self::Blit => "blit_pass",
+            Self::Bloom => "bloom_pass",
+            Self::AutoExposure => "auto_exposure_pass",
+            Self::DebugLines => "debug_line_pass",
+            Self::Gizmo => "gizmo_pass",
+            Self::ColorCorrection => "color_correction_pass",
+        }
+    }
+
+    pub fn reads(&self) -> &'static [&'static str] {
+        match self {
+            Self::Gbuffer | Self::LtcCull | Self::Shadow => &[],
+            Self::BuildFrustum
+            | Self::LtcLighting
+            | Self::Reflection
+            | Self::GlobalIllumination
+            | Self::AmbientOcclusion => &[GBUFFER],
+            Self::Shade => &[
+                GBUFFER,
+                LIGHTING_TEXTURE,
+                REFLECTION_TEXTURE,
+                GLOBAL_ILLUMINATION_TEXTURE,
+                AMBIENT_OCCLUSION_TEXTURE,
+            ],
+            Self::Transparent | Self::Taa => &[GBUFFER, SHADING_TEXTURE],
+            Self::Blit => &[SHADING_TEXTURE],
+            Self::Bloom => &[SHADING_TEXTURE, RENDER_TARGET],
+            Self::AutoExposure | Self::DebugLines | Self::Gizmo | Self::ColorCorrection => {
+                &[RENDER_TARGET]
+            }
+        }
+    }
+
+    pub fn writes(&self) -> &'static [&'static str] {
+        match self {
+            Self::Gbuffer => &[GBUFFER],
+            Self::BuildFrustum | Self::LtcCull | Self::Shadow | Self::AutoExposure => &[],
+            Self::LtcLighting => &[LIGHTING_TEXTURE],
+            Self::Reflection => &[REFLECTION_TEXTURE],
+            Self::GlobalIllumination => &[GLOBAL_ILLUMINATION_TEXTURE],
+            Self::AmbientOcclusion => &[AMBIENT_OCCLUSION_TEXTURE],
+            Self::Shade | Self::Transparent | Self::Taa => &[SHADING_TEXTURE],
+            Self::Blit | Self::Bloom | Self::DebugLines | Self::Gizmo | Self::ColorCorrection => {
+                &[RENDER_TARGET]
+            }
+        }
+    }
+}
+
+/// Where a user pass is inserted relative to a built-in one. Multiple passes on the same anchor run in insertion order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassAnchor {
+    Before(BuiltinPass),
+    After(BuiltinPass),
+}
+
+/// A pass registered through `RenderGraph::insert_pass`.
+/// Reads and writes name textures of the graph, reads must be written by an earlier pass.
+pub trait RenderGraphPass {
+    fn name(&self) -> &str;
+
+    fn reads(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn writes(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn encode(&mut self, context: &mut RenderGraphContext);
+}
+
+/// Resolution a graph texture follows, textures are recreated when the followed resolution changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderGraphResolution {
+    /// Resolution of the render target.
+    Output,
+    /// Output resolution scaled by `RenderSettings::render_resolution_scale`.
+    Render,
+    /// Render resolution scaled by `RenderSettings::lighting_resolution_scale`.
+    Lighting,
+    /// Render resolution scaled by `RenderSettings::reflection_resolution_scale`.
+    Reflection,
+    /// Render resolution scaled by `RenderSettings::global_illumination_resolution_scale`.
+    GlobalIllumination,
+    Fixed(UVec2),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderGraphTextureDescriptor {
+    pub resolution: RenderGraphResolution,
+    pub format: wgpu::TextureFormat,
+    pub mip_level_count: u32,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl RenderGraphTextureDescriptor {
+    pub fn new(resolution: RenderGraphResolution, format: wgpu::TextureFormat) -> Self {
+        Self {
+            resolution,
+            format,
+            mip_level_count: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::STORAGE_BINDING,
+        }
+    }
+}
+
+struct RenderGraphTexture {
+    descriptor: RenderGraphTextureDescriptor,
+    texture: wgpu::Texture,
+}
+
+struct RegisteredPass {
+    anchor: PassAnchor,
+    pass: Box<dyn RenderGraphPass>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScheduledPass {
+    Builtin(BuiltinPass),
+    User(usize),
+}
+
+/// Orders user passes around the built-in ones and owns the textures they share.
+pub struct RenderGraph {
+    passes: Vec<RegisteredPass>,
+    textures: HashMap<String, RenderGraphTexture>,
+    resolution: UVec2,
+    render_resolution: UVec2,
+    lighting_resolution: UVec2,
+    reflection_resolution: UVec2,
+    global_illumination_resolution: UVec2,
+}
+
+impl RenderGraph {
+    pub(crate) fn new(
+        resolution: UVec2,
+        render_resolution: UVec2,
+        lighting_resolution: UVec2,
+        reflection_resolution: UVec2,
+        global_illumination_resolution: UVec2,
+    ) -> Self {
+        Self {
+            passes: Vec::new(),
+            textures: HashMap::new(),
+            resolution,
+            render_resolution,
+            lighting_resolution,
+            reflection_resolution,
+            global_illumination_resolution,
+        }
+    }
+
+    /// Create a texture with two array layers, one for each view, that passes can access by name.
+    pub fn create_texture(
+        &mut self,
+        name: &str,
+        descriptor: RenderGraphTextureDescriptor,
+        device: &wgpu::Device,
+    ) -> Result<()> {
+        if BUILTIN_TEXTURES.contains(&name) || name == GBUFFER || self.textures.contains_key(name) {
+            bail!("Render graph texture \"{}\" already exists.", name);
+        }
+
+        let texture = self.allocate_texture(name, &descriptor, device);
+        self.textures.insert(
+            name.to_owned(),
+            RenderGraphTexture {
+                descriptor,
+                texture,
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove a texture created through `create_texture`, fails when a registered pass still uses it.
+    pub fn remove_texture(&mut self, name: &str) -> Result<()> {
+        if let Some(pass) = self.passes.iter().find(|registered| {
+            registered.pass.reads().contains(&name) || registered.pass.writes().contains(&name)
+        }) {
+            bail!(
+                "Render graph texture \"{}\" is still used by pass \"{}\".",
+                name,
+                pass.pass.name()
+            );
+        }
+
+        self.textures.remove(name);
+        Ok(())
+    }
+
+    /// Texture created through `create_texture`, built-in textures are available through `Renderer::texture`.
+    pub fn texture(&self, name: &str) -> Option<&wgpu::Texture> {
+        self.textures.get(name).map(|texture| &texture.texture)
+    }
+
+    /// Insert a pass relative to a built-in one.
+    /// Fails when the name is taken, when it accesses unknown textures or when it reads a texture no earlier pass writes.
+    pub fn insert_pass(
+        &mut self,
+        anchor: PassAnchor,
+        pass: Box<dyn RenderGraphPass>,
+    ) -> Result<()> {
+        let name = pass.name();
+        if BuiltinPass::ALL
+            .iter()
+            .any(|builtin| builtin.name() == name)
+            || self
+                .passes
+                .iter()
+                .any(|registered| registered.pass.name() == name)
+        {
+            bail!("Render graph pass \"{}\" already exists.", name);
+        }
+
+        for resource in pass.reads().into_iter().chain(pass.writes()) {
+            if !self.has_resource(resource) {
+                bail!(
+                    "Render graph pass \"{}\" accesses unknown texture \"{}\".",
+                    name,
+                    resource
+                );
+            }
+        }
+
+        self.passes.push(RegisteredPass { anchor, pass });
+        if let Err(err) = self.validate() {
+            self.passes.pop();
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Remove a pass inserted through `insert_pass`.
+    /// Fails when no such pass exists or when a later pass reads a texture only this pass writes.
+    pub fn remove_pass(&mut self, name: &str) -> Result<Box<dyn RenderGraphPass>> {
+        let Some(idx) = self
+            .passes
+            .iter()
+            .position(|registered| registered.pass.name() == name)
+        else {
+            bail!("Render graph pass \"{}\" does not exist.", name);
+        };
+
+        let registered = self.passes.remove(idx);
+        if let Err(err) = self.validate() {
+            self.passes.insert(idx, registered);
+            return Err(err);
+        }
+
+        Ok(registered.pass)
+    }
+
+    /// Names of all passes in execution order, including built-in ones.
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.schedule()
+            .into_iter()
+            .map(|scheduled| match scheduled {
+                ScheduledPass::Builtin(builtin) => builtin.name(),
+                ScheduledPass::User(idx) => self.passes[idx].pass.name(),
+            })
+            .collect()
+    }
+
+    fn has_resource(&self, name: &str) -> bool {
+        name == GBUFFER || BUILTIN_TEXTURES.contains(&name) || self.textures.contains_key(name)
+    }
+
+    fn validate(&self) -> Result<()> {
+        // The render target is written outside of the graph, by whoever presents it
+        let mut written = HashSet::from([RENDER_TARGET]);
+
+        for scheduled in self.schedule() {
+            let (name, reads, writes) = match scheduled {
+                ScheduledPass::Builtin(builtin) => (
+                    builtin.name(),
+                    builtin.reads().to_vec(),
+                    builtin.writes().to_vec(),
+                ),
+                ScheduledPass::User(idx) => {
+                    let pass = &self.passes[idx].pass;
+                    (pass.name(), pass.reads(), pass.writes())
+                }
+            };
+
+            if let Some(resource) = reads.iter().find(|read| !written.contains(*read)) {
+                bail!(
+                    "Render graph pass \"{}\" reads \"{}\" before any pass writes it.",
+                    name,
+                    resource
+                );
+            }
+            written.extend(writes);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn schedule(&self) -> Vec<ScheduledPass> {
+        let anchored = |anchor: PassAnchor| {
+            self.passes
+                .iter()
+                .enumerate()
+                .filter(move |(_, registered)| registered.anchor == anchor)
+                .map(|(idx, _)| ScheduledPass::User(idx))
+        };
+
+        let mut schedule = Vec::new();
+        for builtin in BuiltinPass::ALL {
+            schedule.extend(anchored(PassAnchor::Before(builtin)));
+            schedule.push(ScheduledPass::Builtin(builtin));
+            schedule.extend(anchored(PassAnchor::After(builtin)));
+        }
+        schedule
+    }
+
+    pub(crate) fn pass_and_textures(
+        &mut self,
+        idx: usize,
+    ) -> (&mut dyn RenderGraphPass, HashMap<&str, &wgpu::Texture>) {
+        let textures = self
+            .textures
+            .iter()
+            .map(|(name, texture)| (name.as_str(), &texture.texture))
+            .collect();
+        (self.passes[idx].pass.as_mut(), textures)
+    }
+
+    /// Recreate all textures that follow a resolution which changed.
+    pub(crate) fn resize(
+        &mut self,
+        resolution: UVec2,
+        render_resolution: UVec2,
+        lighting_resolution: UVec2,
+        reflection_resolution: UVec2,
+        global_illumination_resolution: UVec2,
+        device: &wgpu::Device,
+    ) {
+        self.resolution = resolution;
+        self.render_resolution = render_resolution;
+        self.lighting_resolution = lighting_resolution;
+        self.reflection_resolution = reflection_resolution;
+        self.global_illumination_resolution = global_illumination_resolution;
+
+        let names: Vec<String> = self.textures.keys().cloned().collect();
+        for name in names {
+            let descriptor = self.textures[&name].descriptor;
+            let size = self.texture_size(&descriptor);
+            let texture = &self.textures[&name].texture;
+            if texture.width() != size.x || texture.height() != size.y {
+                let texture = self.allocate_texture(&name, &descriptor, device);
+                self.textures.get_mut(&name).unwrap().texture = texture;
+            }
+        }
+    }
+
+    fn texture_size(&self, descriptor: &RenderGraphTextureDescriptor) -> UVec2 {
+        match descriptor.resolution {
+            RenderGraphResolution::Output => self.resolution,
+            RenderGraphResolution::Render => self.render_resolution,
+            RenderGraphResolution::Lighting => self.lighting_resolution,
+            RenderGraphResolution::Reflection => self.reflection_resolution,
+            RenderGraphResolution::GlobalIllumination => self.global_illumination_resolution,
+            RenderGraphResolution::Fixed(resolution) => resolution,
+        }
+    }
+
+    fn allocate_texture(
+        &self,
+        name: &str,
+        descriptor: &RenderGraphTextureDescriptor,
+        device: &wgpu::Device,
+    ) -> wgpu::Texture {
+        let size = self.texture_size(descriptor);
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&format!("terrarium::render_graph {}", name)),
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 2,
+            },
+            mip_level_count: descriptor.mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: descriptor.format,
+            usage: descriptor.usage,
+            view_formats: &[],
+        })
+    }
+}
+
+/// Everything a user pass has access to while encoding.
+pub struct RenderGraphContext<'a> {
+    pub resolution: UVec2,
+    pub render_resolution: UVec2,
+    pub lighting_resolution: UVec2,
+    pub reflection_resolution: UVec2,
+    pub global_illumination_resolution: UVec2,
+    pub render_path: RenderPath,
+    pub render_settings: &'a RenderSettings,
+    pub xr_camera_state: &'a XrCameraState,
+    pub xr_camera_buffer: &'a wgpu::Buffer,
+    pub gpu_resources: &'a GpuResources,
+    pub gbuffer: &'a Gbuffer,
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub command_encoder: &'a mut wgpu::CommandEncoder,
+    pub pipeline_database: &'a mut PipelineDatabase,
+    pub(crate) textures: HashMap<&'a str, &'a wgpu::Texture>,
+}
+
+impl RenderGraphContext<'_> {
+    /// Built-in or graph texture by name, panics when it doesn't exist.
+    pub fn texture(&self, name: &str) -> &wgpu::Texture {
+        self.textures
+            .get(name)
+            .unwrap_or_else(|| panic!("Render graph texture \"{}\" does not exist.", name))
+    }
+
+    /// View of the first mip of both array layers of a texture.
+    pub fn texture_view(&self, name: &str) -> wgpu::TextureView {
+        self.texture(name)
+            .create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                array_layer_count: Some(2),
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+    }
+}
+
+#[cfg(test)]
+struct TestPass {
+    name: &'static str,
+    reads: Vec<&'static str>,
+    writes: Vec<&'static str>,
+}
+
+#[cfg(test)]
+impl RenderGraphPass for TestPass {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn reads(&self) -> Vec<&str> {
+        self.reads.clone()
+    }
+
+    fn writes(&self) -> Vec<&str> {
+        self.writes.clone()
+    }
+
+    fn encode(&mut self, _context: &mut RenderGraphContext) {}
+}
+
+#[cfg(test)]
+fn test_pass(
+    name: &'static str,
+    reads: &[&'static str],
+    writes: &[&'static str],
+) -> Box<dyn RenderGraphPass> {
+    Box::new(TestPass {
+        name,
+        reads: reads.to_vec(),
+        writes: writes.to_vec(),
+    })
+}
+
+#[test]
+fn schedule_places_passes_around_anchors() {
+    let mut render_graph =
+        RenderGraph::new(UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE);
+    render_graph
+        .insert_pass(
+            PassAnchor::After(BuiltinPass::Shade),
+            test_pass("after_shade", &[SHADING_TEXTURE], &[SHADING_TEXTURE]),
+        )
+        .unwrap();
+    render_graph
+        .insert_pass(
+            PassAnchor::Before(BuiltinPass::Shade),
+            test_pass("before_shade", &[GBUFFER], &[]),
+        )
+        .unwrap();
+    render_graph
+        .insert_pass(
+            PassAnchor::After(BuiltinPass::Shade),
+            test_pass("after_shade_2", &[], &[]),
+        )
+        .unwrap();
+
+    let pass_names = render_graph.pass_names();
+    assert_eq!(pass_names.len(), BuiltinPass::ALL.len() + 3);
+    let shade = pass_names
+        .iter()
+        .position(|name| *name == BuiltinPass::Shade.name())
+        .unwrap();
+    assert_eq!(
+        pass_names[shade - 1..shade + 3],
+        ["before_shade", "shade_pass", "after_shade", "after_shade_2"]
+    );
+}
+
+#[test]
+fn validate_rejects_missing_producers() {
+    let mut render_graph =
+        RenderGraph::new(UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE);
+
+    // The shading texture is first written by the shade pass
+    assert!(render_graph
+        .insert_pass(
+            PassAnchor::Before(BuiltinPass::Shade),
+            test_pass("early", &[SHADING_TEXTURE], &[]),
+        )
+        .is_err());
+    assert!(render_graph
+        .insert_pass(
+            PassAnchor::Before(BuiltinPass::Gbuffer),
+            test_pass("unknown", &["unknown"], &[]),
+        )
+        .is_err());
+    assert_eq!(render_graph.pass_names().len(), BuiltinPass::ALL.len());
+}
+
+#[test]
+fn validate_rejects_cycles() {
+    let mut render_graph =
+        RenderGraph::new(UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE);
+    render_graph
+        .insert_pass(
+            PassAnchor::After(BuiltinPass::Gbuffer),
+            test_pass("producer", &[], &[LIGHTING_TEXTURE]),
+        )
+        .unwrap();
+
+    // Each pass reads what the other one writes, whichever runs first reads before any write
+    assert!(render_graph
+        .insert_pass(
+            PassAnchor::After(BuiltinPass::Gbuffer),
+            test_pass("a", &[REFLECTION_TEXTURE], &[GLOBAL_ILLUMINATION_TEXTURE]),
+        )
+        .is_err());
+    render_graph
+        .insert_pass(
+            PassAnchor::After(BuiltinPass::Gbuffer),
+            test_pass("b", &[LIGHTING_TEXTURE], &[REFLECTION_TEXTURE]),
+        )
+        .unwrap();
+    assert!(render_graph
+        .insert_pass(
+            PassAnchor::Before(BuiltinPass::Gbuffer),
+            test_pass("c", &[REFLECTION_TEXTURE], &[LIGHTING_TEXTURE]),
+        )
+        .is_err());
+}
+
+#[test]
+fn remove_pass_keeps_graph_valid() {
+    let mut render_graph =
+        RenderGraph::new(UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE, UVec2::ONE);
+    render_graph
+        .insert_pass(
+            PassAnchor::After(BuiltinPass::Gbuffer),
+            test_pass("producer", &[], &[LIGHTING_TEXTURE]),
+        )
+        .unwrap();
+    render_graph
+        .insert_pass(
+            PassAnchor::Before(BuiltinPass::LtcLighting),
+            test_pass("consumer", &[LIGHTING_TEXTURE], &[]),
+        )
+        .unwrap();
+
+    assert!(render_graph.remove_pass("producer").is_err());
+    assert!(render_graph.remove_pass("missing").is_err());
+    assert_eq!(
+        render_graph.pass_names()[..3],
+        ["gbuffer_pass", "producer", "build_frustum_pass"]
+    );
+
+    assert!(render_graph.remove_pass("consumer").is_ok());
+    assert!(render_graph.remove_pass("producer").is_ok());
+    assert_eq!(render_graph.pass_names().len(), BuiltinPass::ALL.len());
+}
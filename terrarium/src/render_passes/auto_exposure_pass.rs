@@ -0,0 +1,280 @@
+use bytemuck::{Pod, Zeroable};
+use glam::UVec2;
+use wgpu::util::DeviceExt;
+use wgsl_includes::include_wgsl;
+
+use crate::wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase};
+
+const NUM_BINS: u32 = 256;
+const MIN_LOG_LUMINANCE: f32 = -10.0;
+const LOG_LUMINANCE_RANGE: f32 = 22.0;
+
+pub fn create_histogram_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("terrarium::auto_exposure histogram"),
+        usage: wgpu::BufferUsages::STORAGE,
+        size: (size_of::<u32>() as u32 * NUM_BINS) as u64,
+        mapped_at_creation: false,
+    })
+}
+
+/// Holds the adapted average scene luminance, zero until the first auto exposure pass has run.
+pub fn create_exposure_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("terrarium::auto_exposure exposure"),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        size: size_of::<f32>() as u64,
+        mapped_at_creation: false,
+    })
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct Constants {
+    resolution: UVec2,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    delta_time: f32,
+    adaptation_speed: f32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+pub struct AutoExposurePassParameters<'a> {
+    pub resolution: UVec2,
+    pub delta_time: f32,
+    pub adaptation_speed: f32,
+    pub color_texture_view: &'a wgpu::TextureView,
+    pub histogram_buffer: &'a wgpu::Buffer,
+    pub exposure_buffer: &'a wgpu::Buffer,
+}
+
+pub fn encode(
+    parameters: &AutoExposurePassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::auto_exposure constants"),
+        contents: bytemuck::bytes_of(&Constants {
+            resolution: parameters.resolution,
+            min_log_luminance: MIN_LOG_LUMINANCE,
+            log_luminance_range: LOG_LUMINANCE_RANGE,
+            delta_time: parameters.delta_time,
+            adaptation_speed: parameters.adaptation_speed,
+            _padding0: 0,
+            _padding1: 0,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    encode_histogram(
+        parameters,
+        &constants,
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+    encode_average(
+        parameters,
+        &constants,
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+}
+
+fn encode_histogram(
+    parameters: &AutoExposurePassParameters,
+    constants: &wgpu::Buffer,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/auto_exposure_histogram_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::auto_exposure_histogram"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::auto_exposure_histogram"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Texture {
+                                    sample_type: wgpu::TextureSampleType::Float {
+                                        filterable: false,
+                                    },
+                                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(parameters.color_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: parameters.histogram_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("terrarium::auto_exposure_histogram"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.insert_debug_marker("terrarium::auto_exposure_histogram");
+        cpass.dispatch_workgroups(
+            parameters.resolution.x.div_ceil(16),
+            parameters.resolution.y.div_ceil(16),
+            1,
+        );
+    }
+}
+
+fn encode_average(
+    parameters: &AutoExposurePassParameters,
+    constants: &wgpu::Buffer,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/auto_exposure_average_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::auto_exposure_average"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::auto_exposure_average"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: parameters.histogram_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: parameters.exposure_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("terrarium::auto_exposure_average"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.insert_debug_marker("terrarium::auto_exposure_average");
+        cpass.dispatch_workgroups(1, 1, 1);
+    }
+}
@@ -1,20 +1,62 @@
+use std::fmt;
+
 use bytemuck::{Pod, Zeroable};
-use glam::UVec2;
+use glam::{UVec2, Vec3};
 use wgpu::util::DeviceExt;
 use wgsl_includes::include_wgsl;
 
-use crate::wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase};
+use crate::{
+    gpu_resources::color_grading_lut::ColorGradingLut,
+    wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase},
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ToneMapper {
+    #[default]
+    Reinhard,
+    AcesFitted,
+    Agx,
+    PbrNeutral,
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Reinhard => "Reinhard",
+            Self::AcesFitted => "ACES Fitted",
+            Self::Agx => "AgX",
+            Self::PbrNeutral => "Khronos PBR Neutral",
+        };
+        write!(f, "{}", name)
+    }
+}
 
 #[derive(Pod, Clone, Copy, Zeroable)]
 #[repr(C)]
 struct Constants {
     resolution: UVec2,
+    tone_mapper: u32,
+    auto_exposure: u32,
+    exposure: f32,
+    color_grading_intensity: f32,
+    lut_size: u32,
     _padding0: u32,
+    lut_domain_min: Vec3,
     _padding1: u32,
+    lut_domain_max: Vec3,
+    _padding2: u32,
 }
 
 pub struct ColorCorrectionPassParameters<'a> {
     pub resolution: UVec2,
+    pub tone_mapper: ToneMapper,
+    /// Exposure compensation in stops, applied on top of auto exposure when enabled.
+    pub exposure_compensation: f32,
+    pub enable_auto_exposure: bool,
+    pub color_grading_intensity: f32,
+    pub color_grading_lut: &'a ColorGradingLut,
+    pub exposure_buffer: &'a wgpu::Buffer,
     pub color_texture_view: &'a wgpu::TextureView,
 }
 
@@ -61,6 +103,34 @@ pub fn encode(
                                 },
                                 count: None,
                             },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 3,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Texture {
+                                    sample_type: wgpu::TextureSampleType::Float {
+                                        filterable: true,
+                                    },
+                                    view_dimension: wgpu::TextureViewDimension::D3,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 4,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                                count: None,
+                            },
                         ],
                     },
                 )],
@@ -73,12 +143,29 @@ pub fn encode(
         label: Some("terrarium::color_correction constants"),
         contents: bytemuck::bytes_of(&Constants {
             resolution: parameters.resolution,
+            tone_mapper: parameters.tone_mapper as u32,
+            auto_exposure: parameters.enable_auto_exposure as u32,
+            exposure: parameters.exposure_compensation.exp2(),
+            color_grading_intensity: parameters.color_grading_intensity,
+            lut_size: parameters.color_grading_lut.size(),
             _padding0: 0,
+            lut_domain_min: parameters.color_grading_lut.domain_min(),
             _padding1: 0,
+            lut_domain_max: parameters.color_grading_lut.domain_max(),
+            _padding2: 0,
         }),
         usage: wgpu::BufferUsages::UNIFORM,
     });
 
+    let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
     let bind_group_layout = pipeline.get_bind_group_layout(0);
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: None,
@@ -92,6 +179,20 @@ pub fn encode(
                 binding: 1,
                 resource: wgpu::BindingResource::TextureView(parameters.color_texture_view),
             },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: parameters.exposure_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(
+                    parameters.color_grading_lut.texture_view(),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&lut_sampler),
+            },
         ],
     });
 
@@ -0,0 +1,487 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+use wgsl_includes::include_wgsl;
+
+use crate::wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase};
+
+/// Importance samples taken per texel of each prefiltered specular mip.
+const PREFILTER_SAMPLE_COUNT: u32 = 64;
+/// Face resolution of the radiance mip sampled when projecting onto spherical harmonics.
+const IRRADIANCE_SOURCE_RESOLUTION: u32 = 32;
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct CubemapConstants {
+    source_is_cubemap: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct PrefilterConstants {
+    roughness: f32,
+    sample_count: u32,
+    radiance_resolution: u32,
+    _padding0: u32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct IrradianceConstants {
+    lod: f32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+pub struct EnvironmentMapPassParameters<'a> {
+    /// Rgba32Float source image, a single equirectangular layer or six cubemap faces.
+    pub source_texture: &'a wgpu::Texture,
+    pub source_is_cubemap: bool,
+    pub radiance_texture: &'a wgpu::Texture,
+    pub specular_texture: &'a wgpu::Texture,
+    pub irradiance_sh_buffer: &'a wgpu::Buffer,
+}
+
+pub fn encode(
+    parameters: &EnvironmentMapPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    encode_cubemap(parameters, device, command_encoder, pipeline_database);
+    for mip in 1..parameters.radiance_texture.mip_level_count() {
+        encode_downsample(parameters, mip, device, command_encoder, pipeline_database);
+    }
+    for mip in 0..parameters.specular_texture.mip_level_count() {
+        encode_prefilter(parameters, mip, device, command_encoder, pipeline_database);
+    }
+    encode_irradiance(parameters, device, command_encoder, pipeline_database);
+}
+
+fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn texture_entry(
+    binding: u32,
+    filterable: bool,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable },
+            view_dimension,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+fn storage_cube_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: wgpu::TextureFormat::Rgba16Float,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+        },
+        count: None,
+    }
+}
+
+/// View of all six faces of a single mip, cubemaps can't be written to as cubes.
+fn face_array_view(texture: &wgpu::Texture, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        min_filter: wgpu::FilterMode::Linear,
+        mag_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+fn dispatch_faces(
+    label: &str,
+    pipeline: &wgpu::ComputePipeline,
+    bind_group: &wgpu::BindGroup,
+    resolution: u32,
+    command_encoder: &mut wgpu::CommandEncoder,
+) {
+    let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some(label),
+        timestamp_writes: None,
+    });
+    cpass.set_pipeline(pipeline);
+    cpass.set_bind_group(0, bind_group, &[]);
+    cpass.insert_debug_marker(label);
+    cpass.dispatch_workgroups(resolution.div_ceil(8), resolution.div_ceil(8), 6);
+}
+
+fn encode_cubemap(
+    parameters: &EnvironmentMapPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/environment_map_cubemap_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::environment_map_cubemap"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::environment_map_cubemap"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            uniform_entry(0),
+                            texture_entry(1, false, wgpu::TextureViewDimension::D2Array),
+                            storage_cube_entry(2),
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::environment_map_cubemap constants"),
+        contents: bytemuck::bytes_of(&CubemapConstants {
+            source_is_cubemap: parameters.source_is_cubemap as u32,
+            _padding0: 0,
+            _padding1: 0,
+            _padding2: 0,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let source_view = parameters
+        .source_texture
+        .create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+    let radiance_view = face_array_view(parameters.radiance_texture, 0);
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&source_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&radiance_view),
+            },
+        ],
+    });
+
+    dispatch_faces(
+        "terrarium::environment_map_cubemap",
+        &pipeline,
+        &bind_group,
+        parameters.radiance_texture.width(),
+        command_encoder,
+    );
+}
+
+fn encode_downsample(
+    parameters: &EnvironmentMapPassParameters,
+    mip: u32,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/environment_map_downsample_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::environment_map_downsample"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::environment_map_downsample"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            texture_entry(0, false, wgpu::TextureViewDimension::D2Array),
+                            storage_cube_entry(1),
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let src_view = face_array_view(parameters.radiance_texture, mip - 1);
+    let dst_view = face_array_view(parameters.radiance_texture, mip);
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&dst_view),
+            },
+        ],
+    });
+
+    dispatch_faces(
+        "terrarium::environment_map_downsample",
+        &pipeline,
+        &bind_group,
+        (parameters.radiance_texture.width() >> mip).max(1),
+        command_encoder,
+    );
+}
+
+fn encode_prefilter(
+    parameters: &EnvironmentMapPassParameters,
+    mip: u32,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/environment_map_prefilter_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::environment_map_prefilter"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::environment_map_prefilter"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            uniform_entry(0),
+                            texture_entry(1, true, wgpu::TextureViewDimension::Cube),
+                            sampler_entry(2),
+                            storage_cube_entry(3),
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    // Roughness increases linearly with the mip, the sky samples it the same way in `Sky::environment_radiance`
+    let mip_count = parameters.specular_texture.mip_level_count();
+    let roughness = mip as f32 / (mip_count - 1).max(1) as f32;
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::environment_map_prefilter constants"),
+        contents: bytemuck::bytes_of(&PrefilterConstants {
+            roughness,
+            sample_count: PREFILTER_SAMPLE_COUNT,
+            radiance_resolution: parameters.radiance_texture.width(),
+            _padding0: 0,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let radiance_view = cube_view(parameters.radiance_texture);
+    let specular_view = face_array_view(parameters.specular_texture, mip);
+    let sampler = create_sampler(device);
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&radiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&specular_view),
+            },
+        ],
+    });
+
+    dispatch_faces(
+        "terrarium::environment_map_prefilter",
+        &pipeline,
+        &bind_group,
+        (parameters.specular_texture.width() >> mip).max(1),
+        command_encoder,
+    );
+}
+
+fn encode_irradiance(
+    parameters: &EnvironmentMapPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/environment_map_irradiance_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::environment_map_irradiance"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::environment_map_irradiance"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            uniform_entry(0),
+                            texture_entry(1, true, wgpu::TextureViewDimension::Cube),
+                            sampler_entry(2),
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 3,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    // Irradiance is low frequency, a small mip keeps the sample count low without aliasing
+    let radiance_resolution = parameters.radiance_texture.width();
+    let lod = (radiance_resolution as f32 / IRRADIANCE_SOURCE_RESOLUTION as f32)
+        .log2()
+        .max(0.0);
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::environment_map_irradiance constants"),
+        contents: bytemuck::bytes_of(&IrradianceConstants {
+            lod,
+            _padding0: 0,
+            _padding1: 0,
+            _padding2: 0,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let radiance_view = cube_view(parameters.radiance_texture);
+    let sampler = create_sampler(device);
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&radiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: parameters.irradiance_sh_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("terrarium::environment_map_irradiance"),
+        timestamp_writes: None,
+    });
+    cpass.set_pipeline(&pipeline);
+    cpass.set_bind_group(0, &bind_group, &[]);
+    cpass.insert_debug_marker("terrarium::environment_map_irradiance");
+    cpass.dispatch_workgroups(1, 1, 1);
+}
@@ -1,30 +1,63 @@
 use std::num::NonZeroU32;
 
 use bytemuck::{Pod, Zeroable};
-use glam::Mat4;
-use specs::Join;
-use ugm::mesh::PackedVertex;
+use glam::{Mat4, UVec2, Vec4};
+use wgpu::util::DeviceExt;
 use wgsl_includes::include_wgsl;
 
 use crate::{
-    gpu_resources::GpuResources,
+    gpu_resources::{
+        gbuffer::{Gbuffer, GBUFFER_FORMATS},
+        GpuResources,
+    },
     wgpu_util::PipelineDatabase,
-    world::components::{MeshComponent, TransformComponent},
 };
 
+pub fn create_depth_texture(resolution: UVec2, device: &wgpu::Device) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("terrarium::gbuffer_pass depth"),
+        size: wgpu::Extent3d {
+            width: resolution.x,
+            height: resolution.y,
+            depth_or_array_layers: 2,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    })
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct Constants {
+    resolution: UVec2,
+    mipmapping: u32,
+    normal_mapping: u32,
+}
+
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 struct PushConstant {
     local_to_world_space: Mat4,
-    inv_trans_local_to_world_space: Mat4,
+    /// Columns of a mat3x3, which are padded to 16 bytes. Keeps the push constant within 128 bytes.
+    inv_trans_local_to_world_space: [Vec4; 3],
+    lod_fade: f32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
 }
 
+/// Rasterized counterpart of the rt_gbuffer pass, fills the same gbuffer for all `RasterInstance`s.
 pub struct GbufferPassParameters<'a> {
-    pub world: &'a specs::World,
+    pub resolution: UVec2,
+    pub mipmapping: bool,
+    pub normal_mapping: bool,
     pub gpu_resources: &'a GpuResources,
     pub xr_camera_buffer: &'a wgpu::Buffer,
-    pub dst_view: &'a wgpu::TextureView,
-    pub target_format: wgpu::TextureFormat,
+    pub gbuffer: &'a Gbuffer,
     pub depth_texture: &'a wgpu::Texture,
 }
 
@@ -34,43 +67,6 @@ pub fn encode(
     command_encoder: &mut wgpu::CommandEncoder,
     pipeline_database: &mut PipelineDatabase,
 ) {
-    let vertex_buffer_layout = wgpu::VertexBufferLayout {
-        array_stride: std::mem::size_of::<PackedVertex>() as wgpu::BufferAddress,
-        step_mode: wgpu::VertexStepMode::Vertex,
-        attributes: &[
-            wgpu::VertexAttribute {
-                // Position
-                format: wgpu::VertexFormat::Float32x3,
-                offset: 0,
-                shader_location: 0,
-            },
-            wgpu::VertexAttribute {
-                // Normal
-                format: wgpu::VertexFormat::Uint32,
-                offset: 3 * std::mem::size_of::<f32>() as u64,
-                shader_location: 1,
-            },
-            wgpu::VertexAttribute {
-                // Texcoord
-                format: wgpu::VertexFormat::Float32x2,
-                offset: 4 * std::mem::size_of::<f32>() as u64,
-                shader_location: 2,
-            },
-            wgpu::VertexAttribute {
-                // Tangent
-                format: wgpu::VertexFormat::Uint32,
-                offset: 6 * std::mem::size_of::<f32>() as u64,
-                shader_location: 3,
-            },
-            wgpu::VertexAttribute {
-                // Tangent handiness
-                format: wgpu::VertexFormat::Float32,
-                offset: 7 * std::mem::size_of::<f32>() as u64,
-                shader_location: 4,
-            },
-        ],
-    };
-
     let depth_stencil = Some(wgpu::DepthStencilState {
         format: parameters.depth_texture.format(),
         depth_write_enabled: true,
@@ -79,27 +75,26 @@ pub fn encode(
         bias: wgpu::DepthBiasState::default(),
     });
 
+    let targets = GBUFFER_FORMATS.map(|format| Some(format.into()));
+
     let shader =
         pipeline_database.shader_from_src(device, include_wgsl!("../../shaders/gbuffer_pass.wgsl"));
     let pipeline = pipeline_database.render_pipeline(
         device,
         wgpu::RenderPipelineDescriptor {
-            label: Some(&format!(
-                "terrarium::gbuffer_pass {:?}",
-                parameters.target_format
-            )),
+            label: Some("terrarium::gbuffer_pass"),
             layout: None,
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: Some("vs_main"),
-                buffers: &[vertex_buffer_layout],
+                buffers: &[],
                 compilation_options: Default::default(),
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
                 entry_point: Some("fs_main"),
                 compilation_options: Default::default(),
-                targets: &[Some(parameters.target_format.into())],
+                targets: &targets,
             }),
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
@@ -118,16 +113,28 @@ pub fn encode(
                 bind_group_layouts: &[
                     &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                         label: None,
-                        entries: &[wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::VERTEX,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
                             },
-                            count: None,
-                        }],
+                        ],
                     }),
                     parameters.gpu_resources.vertex_pool().bind_group_layout(),
                     parameters.gpu_resources.material_pool().bind_group_layout(),
@@ -140,14 +147,30 @@ pub fn encode(
         },
     );
 
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::gbuffer_pass constants"),
+        contents: bytemuck::bytes_of(&Constants {
+            resolution: parameters.resolution,
+            mipmapping: parameters.mipmapping as u32,
+            normal_mapping: parameters.normal_mapping as u32,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
     let bind_group_layout = pipeline.get_bind_group_layout(0);
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: None,
         layout: &bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: parameters.xr_camera_buffer.as_entire_binding(),
-        }],
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: parameters.xr_camera_buffer.as_entire_binding(),
+            },
+        ],
     });
 
     let depth_view = parameters
@@ -160,15 +183,8 @@ pub fn encode(
 
     {
         let mut rpass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: parameters.dst_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            label: Some("terrarium::gbuffer_pass"),
+            color_attachments: &parameters.gbuffer.color_attachments(),
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &depth_view,
                 depth_ops: Some(wgpu::Operations {
@@ -181,6 +197,7 @@ pub fn encode(
             occlusion_query_set: None,
         });
         rpass.set_pipeline(&pipeline);
+        rpass.insert_debug_marker("terrarium::gbuffer_pass");
 
         rpass.set_bind_group(0, &bind_group, &[]);
         rpass.set_bind_group(
@@ -196,40 +213,30 @@ pub fn encode(
             },
         );
 
-        let vertex_pool = parameters.gpu_resources.vertex_pool();
-        rpass.set_vertex_buffer(0, vertex_pool.vertex_buffer().slice(..));
-        rpass.set_index_buffer(
-            vertex_pool.index_buffer().slice(..),
-            wgpu::IndexFormat::Uint32,
-        );
-
-        let (transform_storage, mesh_storage): (
-            specs::ReadStorage<'_, TransformComponent>,
-            specs::ReadStorage<'_, MeshComponent>,
-        ) = parameters.world.system_data();
-
-        for (transform_component, mesh_component) in (&transform_storage, &mesh_storage).join() {
-            let vertex_pool_alloc = &mesh_component.mesh.vertex_pool_alloc;
-            let local_to_world_space =
-                transform_component.get_local_to_world_matrix(&transform_storage);
-            let inv_trans_local_to_world_space = transform_component
-                .get_local_to_world_matrix(&transform_storage)
-                .inverse()
-                .transpose();
+        // Vertices are pulled from the vertex pool in the shader, instance_index selects the vertex pool instance
+        for instance in parameters.gpu_resources.raster_instances() {
+            let num_indices = instance.gpu_mesh.num_indices;
+            let inv_trans_local_to_world = instance.local_to_world.inverse().transpose();
 
             rpass.set_push_constants(
                 wgpu::ShaderStages::VERTEX,
                 0,
                 bytemuck::bytes_of(&PushConstant {
-                    local_to_world_space,
-                    inv_trans_local_to_world_space,
+                    local_to_world_space: instance.local_to_world,
+                    inv_trans_local_to_world_space: [
+                        inv_trans_local_to_world.x_axis,
+                        inv_trans_local_to_world.y_axis,
+                        inv_trans_local_to_world.z_axis,
+                    ],
+                    lod_fade: instance.lod_fade,
+                    _padding0: 0,
+                    _padding1: 0,
+                    _padding2: 0,
                 }),
             );
-            rpass.draw_indexed(
-                vertex_pool_alloc.index_alloc.start() as u32
-                    ..vertex_pool_alloc.index_alloc.end() as u32,
-                vertex_pool_alloc.vertex_alloc.start() as i32,
-                0..1,
+            rpass.draw(
+                0..num_indices,
+                instance.instance_idx..instance.instance_idx + 1,
             );
         }
     }
@@ -0,0 +1,546 @@
+use bytemuck::{Pod, Zeroable};
+use glam::UVec2;
+use wgpu::util::DeviceExt;
+use wgsl_includes::include_wgsl;
+
+use crate::{
+    gpu_resources::{gbuffer::Gbuffer, GpuResources},
+    wgpu_util::{
+        empty_bind_group, empty_bind_group_layout, ComputePipelineDescriptorExtensions,
+        PipelineDatabase,
+    },
+};
+
+pub fn create_global_illumination_texture(
+    label: &str,
+    resolution: UVec2,
+    device: &wgpu::Device,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: resolution.x,
+            height: resolution.y,
+            depth_or_array_layers: 2,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    })
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct Constants {
+    resolution: UVec2,
+    global_illumination_resolution: UVec2,
+    ambient_factor: f32,
+    view_index: u32,
+    render_distance: f32,
+    frame_idx: u32,
+    emissive_lights: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct DenoiseConstants {
+    resolution: UVec2,
+    global_illumination_resolution: UVec2,
+}
+
+pub struct GlobalIlluminationPassParameters<'a> {
+    pub resolution: UVec2,
+    pub global_illumination_resolution: UVec2,
+    pub frame_idx: u32,
+    pub ambient_factor: f32,
+    pub render_distance: f32,
+    /// Whether the lighting pass samples emissive triangles, hits on them then only carry the light they reflect.
+    pub emissive_lights: bool,
+    pub gpu_resources: &'a GpuResources,
+    pub xr_camera_buffer: &'a wgpu::Buffer,
+    pub gbuffer: &'a Gbuffer,
+    /// A single cosine distributed sample per texel, alpha is negative for sky texels.
+    pub trace_texture: &'a wgpu::Texture,
+    /// Temporal accumulation ping-pong textures, alpha holds the history length.
+    pub history_textures: &'a [wgpu::Texture; 2],
+    /// Denoised incoming diffuse radiance, all textures are at global illumination resolution.
+    pub dst_texture: &'a wgpu::Texture,
+}
+
+fn create_array_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    })
+}
+
+fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn texture_entry(binding: u32, filterable: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable },
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn storage_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: wgpu::TextureFormat::Rgba16Float,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+        },
+        count: None,
+    }
+}
+
+pub fn encode(
+    parameters: &GlobalIlluminationPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let trace_view = create_array_view(parameters.trace_texture);
+    let history_views: [wgpu::TextureView; 2] =
+        std::array::from_fn(|i| create_array_view(&parameters.history_textures[i]));
+    let dst_view = create_array_view(parameters.dst_texture);
+
+    let history_idx = parameters.frame_idx as usize % 2;
+
+    encode_trace(
+        parameters,
+        &trace_view,
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+    encode_temporal(
+        parameters,
+        &trace_view,
+        &history_views[(history_idx + 1) % 2],
+        &history_views[history_idx],
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+    encode_spatial(
+        parameters,
+        &history_views[history_idx],
+        &dst_view,
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+}
+
+fn encode_trace(
+    parameters: &GlobalIlluminationPassParameters,
+    dst_view: &wgpu::TextureView,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/global_illumination_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::global_illumination"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::global_illumination"),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::AccelerationStructure {
+                                    vertex_return: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 3,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::AccelerationStructure {
+                                    vertex_return: false,
+                                },
+                                count: None,
+                            },
+                            storage_entry(4),
+                        ],
+                    }),
+                    parameters.gpu_resources.vertex_pool().bind_group_layout(),
+                    parameters.gpu_resources.material_pool().bind_group_layout(),
+                    parameters.gpu_resources.sky().bind_group_layout(),
+                    parameters.gbuffer.bind_group_layout(),
+                    parameters
+                        .gpu_resources
+                        .linear_transformed_cosines()
+                        .bind_group_layout(),
+                    parameters
+                        .gpu_resources
+                        .emissive_lights()
+                        .bind_group_layout(),
+                    parameters
+                        .gpu_resources
+                        .punctual_lights()
+                        .bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    for view_index in 0..2 {
+        let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("terrarium::global_illumination constants"),
+            contents: bytemuck::bytes_of(&Constants {
+                resolution: parameters.resolution,
+                global_illumination_resolution: parameters.global_illumination_resolution,
+                ambient_factor: parameters.ambient_factor,
+                view_index,
+                render_distance: parameters.render_distance,
+                frame_idx: parameters.frame_idx,
+                emissive_lights: parameters.emissive_lights as u32,
+                _padding0: 0,
+                _padding1: 0,
+                _padding2: 0,
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = pipeline.get_bind_group_layout(0);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: constants.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: parameters.xr_camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::AccelerationStructure(
+                        parameters.gpu_resources.static_tlas(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::AccelerationStructure(
+                        parameters.gpu_resources.dynamic_tlas(),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(dst_view),
+                },
+            ],
+        });
+
+        {
+            let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("terrarium::global_illumination"),
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&pipeline);
+            cpass.set_bind_group(0, &bind_group, &[]);
+            cpass.set_bind_group(
+                1,
+                &parameters.gpu_resources.vertex_pool().bind_group(device),
+                &[],
+            );
+            parameters.gpu_resources.material_pool().bind_group(
+                pipeline.get_bind_group_layout(2),
+                device,
+                |bind_group| {
+                    cpass.set_bind_group(2, bind_group, &[]);
+                },
+            );
+            cpass.set_bind_group(3, &parameters.gpu_resources.sky().bind_group(device), &[]);
+            cpass.set_bind_group(4, parameters.gbuffer.bind_group(), &[]);
+            cpass.set_bind_group(
+                5,
+                parameters
+                    .gpu_resources
+                    .linear_transformed_cosines()
+                    .bind_group(),
+                &[],
+            );
+            cpass.set_bind_group(
+                6,
+                parameters.gpu_resources.emissive_lights().bind_group(),
+                &[],
+            );
+            cpass.set_bind_group(
+                7,
+                parameters.gpu_resources.punctual_lights().bind_group(),
+                &[],
+            );
+            cpass.insert_debug_marker("terrarium::global_illumination");
+            cpass.dispatch_workgroups(
+                parameters.global_illumination_resolution.x.div_ceil(8),
+                parameters.global_illumination_resolution.y.div_ceil(8),
+                1,
+            );
+        }
+    }
+}
+
+fn encode_temporal(
+    parameters: &GlobalIlluminationPassParameters,
+    src_view: &wgpu::TextureView,
+    history_view: &wgpu::TextureView,
+    dst_view: &wgpu::TextureView,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/global_illumination_temporal_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::global_illumination_temporal"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::global_illumination_temporal"),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            uniform_entry(0),
+                            texture_entry(1, false),
+                            texture_entry(2, true),
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 3,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                                count: None,
+                            },
+                            storage_entry(4),
+                        ],
+                    }),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    parameters.gbuffer.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::global_illumination_temporal constants"),
+        contents: bytemuck::bytes_of(&DenoiseConstants {
+            resolution: parameters.resolution,
+            global_illumination_resolution: parameters.global_illumination_resolution,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let history_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        min_filter: wgpu::FilterMode::Linear,
+        mag_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(history_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&history_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(dst_view),
+            },
+        ],
+    });
+
+    {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("terrarium::global_illumination_temporal"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.set_bind_group(1, empty_bind_group(device), &[]);
+        cpass.set_bind_group(2, empty_bind_group(device), &[]);
+        cpass.set_bind_group(3, empty_bind_group(device), &[]);
+        cpass.set_bind_group(4, parameters.gbuffer.bind_group(), &[]);
+        cpass.insert_debug_marker("terrarium::global_illumination_temporal");
+        cpass.dispatch_workgroups(
+            parameters.global_illumination_resolution.x.div_ceil(16),
+            parameters.global_illumination_resolution.y.div_ceil(16),
+            1,
+        );
+    }
+}
+
+fn encode_spatial(
+    parameters: &GlobalIlluminationPassParameters,
+    src_view: &wgpu::TextureView,
+    dst_view: &wgpu::TextureView,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/global_illumination_spatial_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::global_illumination_spatial"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::global_illumination_spatial"),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[uniform_entry(0), texture_entry(1, false), storage_entry(2)],
+                    }),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    parameters.gbuffer.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::global_illumination_spatial constants"),
+        contents: bytemuck::bytes_of(&DenoiseConstants {
+            resolution: parameters.resolution,
+            global_illumination_resolution: parameters.global_illumination_resolution,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(dst_view),
+            },
+        ],
+    });
+
+    {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("terrarium::global_illumination_spatial"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.set_bind_group(1, empty_bind_group(device), &[]);
+        cpass.set_bind_group(2, empty_bind_group(device), &[]);
+        cpass.set_bind_group(3, empty_bind_group(device), &[]);
+        cpass.set_bind_group(4, parameters.gbuffer.bind_group(), &[]);
+        cpass.insert_debug_marker("terrarium::global_illumination_spatial");
+        cpass.dispatch_workgroups(
+            parameters.global_illumination_resolution.x.div_ceil(16),
+            parameters.global_illumination_resolution.y.div_ceil(16),
+            1,
+        );
+    }
+}
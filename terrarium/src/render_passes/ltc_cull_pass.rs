@@ -149,6 +149,10 @@ pub fn encode(
                         .linear_transformed_cosines()
                         .bind_group_layout(),
                     parameters.gpu_resources.debug_lines().bind_group_layout(),
+                    parameters
+                        .gpu_resources
+                        .punctual_lights()
+                        .bind_group_layout(),
                 ],
                 push_constant_ranges: &[],
             })
@@ -231,6 +235,11 @@ pub fn encode(
             &[],
         );
         cpass.set_bind_group(6, parameters.gpu_resources.debug_lines().bind_group(), &[]);
+        cpass.set_bind_group(
+            7,
+            parameters.gpu_resources.punctual_lights().bind_group(),
+            &[],
+        );
         cpass.insert_debug_marker("terrarium::ltc_cull");
         cpass.dispatch_workgroups(
             parameters
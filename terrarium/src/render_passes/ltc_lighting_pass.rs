@@ -5,7 +5,11 @@ use wgsl_includes::include_wgsl;
 
 use crate::{
     gpu_resources::{gbuffer::Gbuffer, GpuResources},
-    wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase},
+    wgpu_util::{
+        empty_bind_group, empty_bind_group_layout, ComputePipelineDescriptorExtensions,
+        PipelineDatabase,
+    },
+    RenderPath,
 };
 
 use super::build_frustum_pass;
@@ -17,8 +21,8 @@ struct Constants {
     lighting_resolution: UVec2,
     shadows: u32,
     shadow_bias: f32,
-    _padding0: u32,
-    _padding1: u32,
+    frame_idx: u32,
+    emissive_light_candidates: u32,
 }
 
 pub struct LtcLightingPassParameters<'a> {
@@ -26,12 +30,19 @@ pub struct LtcLightingPassParameters<'a> {
     pub lighting_resolution: UVec2,
     pub shadows: bool,
     pub shadow_bias: f32,
+    pub frame_idx: u32,
+    /// Emissive triangles considered per pixel, zero disables lighting by emissive materials.
+    /// Only supported by `RenderPath::RayTraced`.
+    pub emissive_light_candidates: u32,
+    pub render_path: RenderPath,
     pub gpu_resources: &'a GpuResources,
     pub xr_camera_buffer: &'a wgpu::Buffer,
     pub gbuffer: &'a Gbuffer,
     pub ltc_instance_index_buffer: &'a wgpu::Buffer,
     pub ltc_instance_grid_texture_view: &'a wgpu::TextureView,
     pub dst_view: &'a wgpu::TextureView,
+    pub shadow_map_view: &'a wgpu::TextureView,
+    pub shadow_map_buffer: &'a wgpu::Buffer,
 }
 
 pub fn encode(
@@ -40,90 +51,149 @@ pub fn encode(
     command_encoder: &mut wgpu::CommandEncoder,
     pipeline_database: &mut PipelineDatabase,
 ) {
-    let shader = pipeline_database.shader_from_src(
-        device,
-        include_wgsl!("../../shaders/ltc_lighting_pass.wgsl"),
-    );
+    // The raster path has no acceleration structures to trace shadow rays against, it samples shadow maps instead
+    let (shader, label) = match parameters.render_path {
+        RenderPath::RayTraced => (
+            pipeline_database.shader_from_src(
+                device,
+                include_wgsl!("../../shaders/ltc_lighting_pass.wgsl"),
+            ),
+            "terrarium::ltc_lighting",
+        ),
+        RenderPath::Raster => (
+            pipeline_database.shader_from_src(
+                device,
+                include_wgsl!("../../shaders/ltc_lighting_raster_pass.wgsl"),
+            ),
+            "terrarium::ltc_lighting_raster",
+        ),
+    };
+
+    let shadow_entries = match parameters.render_path {
+        RenderPath::RayTraced => vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::AccelerationStructure {
+                    vertex_return: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::AccelerationStructure {
+                    vertex_return: false,
+                },
+                count: None,
+            },
+        ],
+        RenderPath::Raster => vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ],
+    };
+
+    // Emissive triangles are only sampled when shadow rays can be traced towards them
+    let emissive_light_bind_group_layout = match parameters.render_path {
+        RenderPath::RayTraced => parameters
+            .gpu_resources
+            .emissive_lights()
+            .bind_group_layout(),
+        RenderPath::Raster => empty_bind_group_layout(device),
+    };
+
     let pipeline = pipeline_database.compute_pipeline(
         device,
         wgpu::ComputePipelineDescriptor {
-            label: Some("terrarium::ltc_lighting"),
+            label: Some(label),
             ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
         },
         || {
+            let mut entries = vec![
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadWrite,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadOnly,
+                        format: wgpu::TextureFormat::Rg32Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ];
+            entries.extend_from_slice(&shadow_entries);
+
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("terrarium::ltc_lighting"),
+                label: Some(label),
                 bind_group_layouts: &[
                     &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                         label: None,
-                        entries: &[
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 0,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::Buffer {
-                                    ty: wgpu::BufferBindingType::Uniform,
-                                    has_dynamic_offset: false,
-                                    min_binding_size: None,
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 1,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::Buffer {
-                                    ty: wgpu::BufferBindingType::Uniform,
-                                    has_dynamic_offset: false,
-                                    min_binding_size: None,
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 2,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::AccelerationStructure {
-                                    vertex_return: false,
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 3,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::AccelerationStructure {
-                                    vertex_return: false,
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 4,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::StorageTexture {
-                                    access: wgpu::StorageTextureAccess::ReadWrite,
-                                    format: wgpu::TextureFormat::Rgba16Float,
-                                    view_dimension: wgpu::TextureViewDimension::D2Array,
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 5,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::Buffer {
-                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                                    has_dynamic_offset: false,
-                                    min_binding_size: None,
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 6,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::StorageTexture {
-                                    access: wgpu::StorageTextureAccess::ReadOnly,
-                                    format: wgpu::TextureFormat::Rg32Uint,
-                                    view_dimension: wgpu::TextureViewDimension::D2,
-                                },
-                                count: None,
-                            },
-                        ],
+                        entries: &entries,
                     }),
                     parameters.gpu_resources.vertex_pool().bind_group_layout(),
                     parameters.gpu_resources.material_pool().bind_group_layout(),
@@ -133,6 +203,11 @@ pub fn encode(
                         .gpu_resources
                         .linear_transformed_cosines()
                         .bind_group_layout(),
+                    emissive_light_bind_group_layout,
+                    parameters
+                        .gpu_resources
+                        .punctual_lights()
+                        .bind_group_layout(),
                 ],
                 push_constant_ranges: &[],
             })
@@ -146,57 +221,85 @@ pub fn encode(
             lighting_resolution: parameters.lighting_resolution,
             shadows: parameters.shadows as u32,
             shadow_bias: parameters.shadow_bias,
-            _padding0: 0,
-            _padding1: 0,
+            frame_idx: parameters.frame_idx,
+            emissive_light_candidates: parameters.emissive_light_candidates,
         }),
         usage: wgpu::BufferUsages::UNIFORM,
     });
 
-    let bind_group_layout = pipeline.get_bind_group_layout(0);
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: None,
-        layout: &bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: constants.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: parameters.xr_camera_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
+    let shadow_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("terrarium::ltc_lighting shadow_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        compare: Some(wgpu::CompareFunction::LessEqual),
+        ..Default::default()
+    });
+
+    let mut entries = vec![
+        wgpu::BindGroupEntry {
+            binding: 0,
+            resource: constants.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 1,
+            resource: parameters.xr_camera_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 4,
+            resource: wgpu::BindingResource::TextureView(parameters.dst_view),
+        },
+        wgpu::BindGroupEntry {
+            binding: 5,
+            resource: parameters.ltc_instance_index_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+            binding: 6,
+            resource: wgpu::BindingResource::TextureView(parameters.ltc_instance_grid_texture_view),
+        },
+    ];
+    match parameters.render_path {
+        RenderPath::RayTraced => {
+            entries.push(wgpu::BindGroupEntry {
                 binding: 2,
                 resource: wgpu::BindingResource::AccelerationStructure(
                     parameters.gpu_resources.static_tlas(),
                 ),
-            },
-            wgpu::BindGroupEntry {
+            });
+            entries.push(wgpu::BindGroupEntry {
                 binding: 3,
                 resource: wgpu::BindingResource::AccelerationStructure(
                     parameters.gpu_resources.dynamic_tlas(),
                 ),
-            },
-            wgpu::BindGroupEntry {
-                binding: 4,
-                resource: wgpu::BindingResource::TextureView(parameters.dst_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 5,
-                resource: parameters.ltc_instance_index_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 6,
-                resource: wgpu::BindingResource::TextureView(
-                    parameters.ltc_instance_grid_texture_view,
-                ),
-            },
-        ],
+            });
+        }
+        RenderPath::Raster => {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(parameters.shadow_map_view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: parameters.shadow_map_buffer.as_entire_binding(),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::Sampler(&shadow_sampler),
+            });
+        }
+    }
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &entries,
     });
 
     {
         let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("terrarium::ltc_lighting"),
+            label: Some(label),
             timestamp_writes: None,
         });
         cpass.set_pipeline(&pipeline);
@@ -223,7 +326,17 @@ pub fn encode(
                 .bind_group(),
             &[],
         );
-        cpass.insert_debug_marker("terrarium::ltc_lighting");
+        let emissive_light_bind_group = match parameters.render_path {
+            RenderPath::RayTraced => parameters.gpu_resources.emissive_lights().bind_group(),
+            RenderPath::Raster => empty_bind_group(device),
+        };
+        cpass.set_bind_group(6, emissive_light_bind_group, &[]);
+        cpass.set_bind_group(
+            7,
+            parameters.gpu_resources.punctual_lights().bind_group(),
+            &[],
+        );
+        cpass.insert_debug_marker(label);
         cpass.dispatch_workgroups(
             parameters
                 .lighting_resolution
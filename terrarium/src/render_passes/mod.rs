@@ -1,19 +1,27 @@
+pub mod auto_exposure_pass;
 pub mod blit_pass;
 pub mod bloom_pass;
+pub mod build_frustum_pass;
 pub mod color_correction_pass;
 pub mod debug_line_pass;
 pub mod debug_pass;
-pub mod gbuffer_pass;
-pub mod rt_gbuffer_pass;
-pub mod shade_pass;
-// pub mod shadow_pass;
-// pub mod ssao_pass;
-pub mod build_frustum_pass;
 pub mod emissive_stabilization_pass;
+pub mod environment_map_pass;
+pub mod gbuffer_pass;
+pub mod global_illumination_pass;
 pub mod ltc_cull_pass;
 pub mod ltc_lighting_pass;
-pub mod mirror_reflection_pass;
+pub mod morph_target_pass;
+pub mod pick_pass;
+pub mod reflection_pass;
+pub mod rt_gbuffer_pass;
+pub mod shade_pass;
+pub mod shadow_pass;
+pub mod skinning_pass;
+pub mod sky_lut_pass;
+pub mod ssao_pass;
 pub mod taa_pass;
+pub mod transparent_pass;
 pub mod write_indirect_args_pass;
 
 #[cfg(feature = "transform-gizmo")]
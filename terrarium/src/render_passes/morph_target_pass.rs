@@ -0,0 +1,135 @@
+use wgsl_includes::include_wgsl;
+
+use crate::{
+    gpu_resources::{morph_targets::MorphTargets, vertex_pool::VertexPool},
+    wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase},
+};
+
+pub struct MorphTargetPassParameters<'a> {
+    pub vertex_pool: &'a VertexPool,
+    pub morph_targets: &'a MorphTargets,
+}
+
+pub fn encode(
+    parameters: &MorphTargetPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    if parameters.morph_targets.instance_count() == 0 {
+        return;
+    }
+
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/morph_target_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::morph_target"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::morph_target"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 3,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: parameters.vertex_pool.vertex_buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: parameters
+                    .vertex_pool
+                    .morph_target_delta_buffer()
+                    .as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: parameters.morph_targets.weight_buffer().as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: parameters
+                    .morph_targets
+                    .instance_buffer()
+                    .as_entire_binding(),
+            },
+        ],
+    });
+
+    {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("terrarium::morph_target"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.insert_debug_marker("terrarium::morph_target");
+        cpass.dispatch_workgroups(
+            parameters
+                .morph_targets
+                .max_instance_vertices()
+                .div_ceil(64),
+            parameters.morph_targets.instance_count(),
+            1,
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+use wgsl_includes::include_wgsl;
+
+use crate::{
+    gpu_resources::GpuResources,
+    wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase},
+};
+
+/// Closest hit of a pick ray as written by the gpu, `hit` is zero when nothing was hit.
+#[derive(Pod, Debug, Clone, Copy, Zeroable)]
+#[repr(C)]
+pub struct PickResult {
+    pub position: Vec3,
+    pub distance: f32,
+    pub normal: Vec3,
+    pub instance_idx: u32,
+    pub material_idx: u32,
+    pub hit: u32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+pub fn create_result_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("terrarium::pick result"),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        size: size_of::<PickResult>() as u64,
+        mapped_at_creation: false,
+    })
+}
+
+pub fn create_readback_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("terrarium::pick readback"),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        size: size_of::<PickResult>() as u64,
+        mapped_at_creation: false,
+    })
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct Constants {
+    origin: Vec3,
+    max_distance: f32,
+    direction: Vec3,
+    _padding0: u32,
+}
+
+/// Traces a single ray against the static and dynamic tlas and copies the closest hit into `readback_buffer`.
+pub struct PickPassParameters<'a> {
+    pub origin: Vec3,
+    /// Normalized, hit distances are measured along it.
+    pub direction: Vec3,
+    pub max_distance: f32,
+    pub gpu_resources: &'a GpuResources,
+    pub result_buffer: &'a wgpu::Buffer,
+    pub readback_buffer: &'a wgpu::Buffer,
+}
+
+pub fn encode(
+    parameters: &PickPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader =
+        pipeline_database.shader_from_src(device, include_wgsl!("../../shaders/pick_pass.wgsl"));
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::pick"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::pick"),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::AccelerationStructure {
+                                    vertex_return: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::AccelerationStructure {
+                                    vertex_return: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 3,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                        ],
+                    }),
+                    parameters.gpu_resources.vertex_pool().bind_group_layout(),
+                    parameters.gpu_resources.material_pool().bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::pick constants"),
+        contents: bytemuck::bytes_of(&Constants {
+            origin: parameters.origin,
+            max_distance: parameters.max_distance,
+            direction: parameters.direction,
+            _padding0: 0,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::AccelerationStructure(
+                    parameters.gpu_resources.static_tlas(),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::AccelerationStructure(
+                    parameters.gpu_resources.dynamic_tlas(),
+                ),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: parameters.result_buffer.as_entire_binding(),
+            },
+        ],
+    });
+
+    {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("terrarium::pick"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.set_bind_group(
+            1,
+            &parameters.gpu_resources.vertex_pool().bind_group(device),
+            &[],
+        );
+        parameters.gpu_resources.material_pool().bind_group(
+            pipeline.get_bind_group_layout(2),
+            device,
+            |bind_group| {
+                cpass.set_bind_group(2, bind_group, &[]);
+            },
+        );
+        cpass.insert_debug_marker("terrarium::pick");
+        cpass.dispatch_workgroups(1, 1, 1);
+    }
+
+    command_encoder.copy_buffer_to_buffer(
+        parameters.result_buffer,
+        0,
+        parameters.readback_buffer,
+        0,
+        size_of::<PickResult>() as u64,
+    );
+}
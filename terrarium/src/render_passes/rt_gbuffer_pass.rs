@@ -11,47 +11,26 @@ use crate::{
     },
 };
 
-pub fn create_reflection_counter_buffer(_resolution: UVec2, device: &wgpu::Device) -> wgpu::Buffer {
-    device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("terrarium::rt_gbuffer reflection_counter"),
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        size: size_of::<u32>() as u64,
-        mapped_at_creation: false,
-    })
-}
-
-pub fn create_reflection_pid_buffer(resolution: UVec2, device: &wgpu::Device) -> wgpu::Buffer {
-    device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("terrarium::rt_gbuffer reflection_pid"),
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        size: (size_of::<u32>() as u32 * resolution.x * resolution.y * 2) as u64,
-        mapped_at_creation: false,
-    })
-}
-
 #[derive(Pod, Clone, Copy, Zeroable)]
 #[repr(C)]
 struct Constants {
     resolution: UVec2,
     mipmapping: u32,
     normal_mapping: u32,
-    reflection_max_roughness: f32,
     view_index: u32,
     render_distance: f32,
     _padding0: u32,
+    _padding1: u32,
 }
 
 pub struct RtGbufferPassParameters<'a> {
     pub resolution: UVec2,
     pub mipmapping: bool,
     pub normal_mapping: bool,
-    pub reflection_max_roughness: f32,
     pub render_distance: f32,
     pub gpu_resources: &'a GpuResources,
     pub xr_camera_buffer: &'a wgpu::Buffer,
     pub gbuffer: &'a Gbuffer,
-    pub reflection_counter_buffer: &'a [wgpu::Buffer; 2],
-    pub reflection_pid_buffer: &'a [wgpu::Buffer; 2],
 }
 
 pub fn encode(
@@ -111,26 +90,6 @@ pub fn encode(
                                 },
                                 count: None,
                             },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 4,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::Buffer {
-                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                                    has_dynamic_offset: false,
-                                    min_binding_size: None,
-                                },
-                                count: None,
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 5,
-                                visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::Buffer {
-                                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                                    has_dynamic_offset: false,
-                                    min_binding_size: None,
-                                },
-                                count: None,
-                            },
                         ],
                     }),
                     parameters.gpu_resources.vertex_pool().bind_group_layout(),
@@ -150,10 +109,10 @@ pub fn encode(
                 resolution: parameters.resolution,
                 mipmapping: parameters.mipmapping as u32,
                 normal_mapping: parameters.normal_mapping as u32,
-                reflection_max_roughness: parameters.reflection_max_roughness,
                 view_index,
                 render_distance: parameters.render_distance,
                 _padding0: 0,
+                _padding1: 0,
             }),
             usage: wgpu::BufferUsages::UNIFORM,
         });
@@ -183,25 +142,9 @@ pub fn encode(
                         parameters.gpu_resources.dynamic_tlas(),
                     ),
                 },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: parameters.reflection_counter_buffer[view_index as usize]
-                        .as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 5,
-                    resource: parameters.reflection_pid_buffer[view_index as usize]
-                        .as_entire_binding(),
-                },
             ],
         });
 
-        command_encoder.clear_buffer(
-            &parameters.reflection_counter_buffer[view_index as usize],
-            0,
-            None,
-        );
-
         {
             let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
                 label: Some("terrarium::rt_gbuffer"),
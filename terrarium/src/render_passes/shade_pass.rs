@@ -26,6 +26,9 @@ pub enum ShadingMode {
     Fog,
     Reflection,
     SimpleLighting,
+    AmbientOcclusion,
+    /// All indirect light reaching the surface, ray traced when global illumination is enabled.
+    Indirect,
 }
 
 impl fmt::Display for ShadingMode {
@@ -41,6 +44,8 @@ impl fmt::Display for ShadingMode {
             Self::Fog => "Fog",
             Self::Reflection => "Reflection",
             Self::SimpleLighting => "Simple Lighting",
+            Self::AmbientOcclusion => "Ambient Occlusion",
+            Self::Indirect => "Indirect",
         };
         write!(f, "{}", name)
     }
@@ -53,9 +58,9 @@ struct Constants {
     shading_mode: u32,
     ambient_factor: f32,
     reflection_max_roughness: f32,
+    ambient_occlusion: u32,
+    global_illumination: u32,
     _padding0: u32,
-    _padding1: u32,
-    _padding2: u32,
 }
 
 pub struct ShadePassParameters<'a> {
@@ -63,11 +68,17 @@ pub struct ShadePassParameters<'a> {
     pub shading_mode: ShadingMode,
     pub ambient_factor: f32,
     pub reflection_max_roughness: f32,
+    /// Scale the ambient term by `ambient_occlusion_view`, which is left unread otherwise.
+    pub ambient_occlusion: bool,
+    /// Replace the diffuse ambient term by `global_illumination_view`, which is left unread otherwise.
+    pub global_illumination: bool,
     pub gpu_resources: &'a GpuResources,
     pub xr_camera_buffer: &'a wgpu::Buffer,
     pub gbuffer: &'a Gbuffer,
     pub lighting_view: &'a wgpu::TextureView,
     pub reflection_view: &'a wgpu::TextureView,
+    pub ambient_occlusion_view: &'a wgpu::TextureView,
+    pub global_illumination_view: &'a wgpu::TextureView,
     pub dst_view: &'a wgpu::TextureView,
 }
 
@@ -152,6 +163,30 @@ pub fn encode(
                                 ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                                 count: None,
                             },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 8,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Texture {
+                                    sample_type: wgpu::TextureSampleType::Float {
+                                        filterable: true,
+                                    },
+                                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 9,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Texture {
+                                    sample_type: wgpu::TextureSampleType::Float {
+                                        filterable: true,
+                                    },
+                                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
                         ],
                     }),
                     parameters.gpu_resources.vertex_pool().bind_group_layout(),
@@ -175,9 +210,9 @@ pub fn encode(
             shading_mode: parameters.shading_mode as u32,
             ambient_factor: parameters.ambient_factor,
             reflection_max_roughness: parameters.reflection_max_roughness,
+            ambient_occlusion: parameters.ambient_occlusion as u32,
+            global_illumination: parameters.global_illumination as u32,
             _padding0: 0,
-            _padding1: 0,
-            _padding2: 0,
         }),
         usage: wgpu::BufferUsages::UNIFORM,
     });
@@ -220,6 +255,14 @@ pub fn encode(
                 binding: 7,
                 resource: wgpu::BindingResource::Sampler(&sampler),
             },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(parameters.ambient_occlusion_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(parameters.global_illumination_view),
+            },
         ],
     });
 
@@ -1,194 +1,456 @@
-// use bytemuck::{Pod, Zeroable};
-// use glam::UVec2;
-// use wgpu::util::DeviceExt;
-// use wgsl_includes::include_wgsl;
-
-// use crate::{
-//     gpu_resources::GpuResources,
-//     wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase},
-// };
-
-// #[derive(Pod, Clone, Copy, Zeroable)]
-// #[repr(C)]
-// struct Constants {
-//     resolution: UVec2,
-//     shadow_resolution: UVec2,
-//     seed: u32,
-//     _padding0: u32,
-//     _padding1: u32,
-//     _padding2: u32,
-// }
-
-// pub struct ShadowPassParameters<'a> {
-//     pub resolution: UVec2,
-//     pub shadow_resolution: UVec2,
-//     pub seed: u32,
-//     pub gpu_resources: &'a GpuResources,
-//     pub xr_camera_buffer: &'a wgpu::Buffer,
-//     pub gbuffer: &'a [wgpu::Buffer; 2],
-//     pub shadow_texture_view: &'a wgpu::TextureView,
-// }
-
-// pub fn encode(
-//     parameters: &ShadowPassParameters,
-//     device: &wgpu::Device,
-//     command_encoder: &mut wgpu::CommandEncoder,
-//     pipeline_database: &mut PipelineDatabase,
-// ) {
-//     let shader =
-//         pipeline_database.shader_from_src(device, include_wgsl!("../../shaders/shadow_pass.wgsl"));
-//     let pipeline = pipeline_database.compute_pipeline(
-//         device,
-//         wgpu::ComputePipelineDescriptor {
-//             label: Some("terrarium::shadow"),
-//             ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
-//         },
-//         || {
-//             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-//                 label: Some("terrarium::shadow"),
-//                 bind_group_layouts: &[
-//                     &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-//                         label: None,
-//                         entries: &[
-//                             wgpu::BindGroupLayoutEntry {
-//                                 binding: 0,
-//                                 visibility: wgpu::ShaderStages::COMPUTE,
-//                                 ty: wgpu::BindingType::Buffer {
-//                                     ty: wgpu::BufferBindingType::Uniform,
-//                                     has_dynamic_offset: false,
-//                                     min_binding_size: None,
-//                                 },
-//                                 count: None,
-//                             },
-//                             wgpu::BindGroupLayoutEntry {
-//                                 binding: 1,
-//                                 visibility: wgpu::ShaderStages::COMPUTE,
-//                                 ty: wgpu::BindingType::Buffer {
-//                                     ty: wgpu::BufferBindingType::Uniform,
-//                                     has_dynamic_offset: false,
-//                                     min_binding_size: None,
-//                                 },
-//                                 count: None,
-//                             },
-//                             wgpu::BindGroupLayoutEntry {
-//                                 binding: 2,
-//                                 visibility: wgpu::ShaderStages::COMPUTE,
-//                                 ty: wgpu::BindingType::AccelerationStructure,
-//                                 count: None,
-//                             },
-//                             wgpu::BindGroupLayoutEntry {
-//                                 binding: 3,
-//                                 visibility: wgpu::ShaderStages::COMPUTE,
-//                                 ty: wgpu::BindingType::Buffer {
-//                                     ty: wgpu::BufferBindingType::Storage { read_only: true },
-//                                     has_dynamic_offset: false,
-//                                     min_binding_size: None,
-//                                 },
-//                                 count: None,
-//                             },
-//                             wgpu::BindGroupLayoutEntry {
-//                                 binding: 4,
-//                                 visibility: wgpu::ShaderStages::COMPUTE,
-//                                 ty: wgpu::BindingType::Buffer {
-//                                     ty: wgpu::BufferBindingType::Storage { read_only: true },
-//                                     has_dynamic_offset: false,
-//                                     min_binding_size: None,
-//                                 },
-//                                 count: None,
-//                             },
-//                             wgpu::BindGroupLayoutEntry {
-//                                 binding: 5,
-//                                 visibility: wgpu::ShaderStages::COMPUTE,
-//                                 ty: wgpu::BindingType::StorageTexture {
-//                                     access: wgpu::StorageTextureAccess::ReadWrite,
-//                                     format: wgpu::TextureFormat::R16Float,
-//                                     view_dimension: wgpu::TextureViewDimension::D2Array,
-//                                 },
-//                                 count: None,
-//                             },
-//                         ],
-//                     }),
-//                     parameters.gpu_resources.vertex_pool().bind_group_layout(),
-//                     parameters.gpu_resources.material_pool().bind_group_layout(),
-//                     parameters.gpu_resources.sky().bind_group_layout(),
-//                 ],
-//                 push_constant_ranges: &[],
-//             })
-//         },
-//     );
-
-//     let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-//         label: Some("terrarium::shadow constants"),
-//         contents: bytemuck::bytes_of(&Constants {
-//             resolution: parameters.resolution,
-//             shadow_resolution: parameters.shadow_resolution,
-//             seed: parameters.seed,
-//             _padding0: 0,
-//             _padding1: 0,
-//             _padding2: 0,
-//         }),
-//         usage: wgpu::BufferUsages::UNIFORM,
-//     });
-
-//     let bind_group_layout = pipeline.get_bind_group_layout(0);
-//     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-//         label: None,
-//         layout: &bind_group_layout,
-//         entries: &[
-//             wgpu::BindGroupEntry {
-//                 binding: 0,
-//                 resource: constants.as_entire_binding(),
-//             },
-//             wgpu::BindGroupEntry {
-//                 binding: 1,
-//                 resource: parameters.xr_camera_buffer.as_entire_binding(),
-//             },
-//             wgpu::BindGroupEntry {
-//                 binding: 2,
-//                 resource: wgpu::BindingResource::AccelerationStructure(
-//                     parameters.gpu_resources.tlas(),
-//                 ),
-//             },
-//             wgpu::BindGroupEntry {
-//                 binding: 3,
-//                 resource: parameters.gbuffer[0].as_entire_binding(),
-//             },
-//             wgpu::BindGroupEntry {
-//                 binding: 4,
-//                 resource: parameters.gbuffer[1].as_entire_binding(),
-//             },
-//             wgpu::BindGroupEntry {
-//                 binding: 5,
-//                 resource: wgpu::BindingResource::TextureView(parameters.shadow_texture_view),
-//             },
-//         ],
-//     });
-
-//     {
-//         let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-//             label: Some("terrarium::shadow"),
-//             timestamp_writes: None,
-//         });
-//         cpass.set_pipeline(&pipeline);
-//         cpass.set_bind_group(0, &bind_group, &[]);
-//         cpass.set_bind_group(
-//             1,
-//             &parameters.gpu_resources.vertex_pool().bind_group(device),
-//             &[],
-//         );
-//         parameters.gpu_resources.material_pool().bind_group(
-//             pipeline.get_bind_group_layout(2),
-//             device,
-//             |bind_group| {
-//                 cpass.set_bind_group(2, bind_group, &[]);
-//             },
-//         );
-//         cpass.set_bind_group(3, &parameters.gpu_resources.sky().bind_group(device), &[]);
-//         cpass.insert_debug_marker("terrarium::shadow");
-//         cpass.dispatch_workgroups(
-//             parameters.shadow_resolution.x.div_ceil(16),
-//             parameters.shadow_resolution.y.div_ceil(16),
-//             1,
-//         );
-//     }
-// }
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4Swizzles};
+use wgsl_includes::include_wgsl;
+
+use crate::{
+    gpu_resources::{culling::bounding_sphere, GpuResources},
+    wgpu_util::{empty_bind_group, empty_bind_group_layout, PipelineDatabase},
+};
+
+/// Number of perspective shadow map layers shared by area, spot and point lights each frame, must match ltc_lighting_raster_pass.wgsl.
+/// Area and spot lights take a single layer, point lights take one per cube face. Layers go to the lights closest to the camera,
+/// lights that no longer fit into the budget remain unshadowed.
+pub const MAX_SHADOW_MAPS: usize = 16;
+/// Number of cascades rendered for the first directional light, must match ltc_lighting_raster_pass.wgsl.
+/// Any further directional lights remain unshadowed.
+pub const MAX_SHADOW_CASCADES: usize = 4;
+pub const SHADOW_MAP_RESOLUTION: u32 = 1024;
+
+const SHADOW_MAP_LAYERS: usize = MAX_SHADOW_MAPS + MAX_SHADOW_CASCADES;
+
+/// Field of view of the perspective projection used by area light shadow maps, covers most of the hemisphere an area light emits into.
+/// Also caps the field of view of spot light shadow maps.
+const SHADOW_MAP_FOV_DEGREES: f32 = 150.0;
+
+/// Ratio between the radii of consecutive cascades, the last cascade covers the whole render distance.
+const SHADOW_CASCADE_SCALE: f32 = 4.0;
+
+/// Offset along the geometric normal applied before sampling a perspective shadow map, cascades scale theirs with their texel size.
+const SHADOW_MAP_NORMAL_OFFSET: f32 = 0.02;
+
+// Must match shared/punctual_lights.wgsl
+const LIGHT_TYPE_AREA: u32 = 0;
+const LIGHT_TYPE_POINT: u32 = 1;
+const LIGHT_TYPE_SPOT: u32 = 2;
+const LIGHT_TYPE_SHIFT: u32 = 30;
+
+fn encode_light_index(light_type: u32, light_idx: u32) -> u32 {
+    (light_type << LIGHT_TYPE_SHIFT) | light_idx
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct ShadowMap {
+    world_to_clip_space: Mat4,
+    /// Encoded light index for perspective shadow maps, directional light index for cascades.
+    encoded_light_index: u32,
+    normal_offset: f32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct PushConstant {
+    world_to_clip_space: Mat4,
+    local_to_world_space: Mat4,
+}
+
+#[derive(Clone, Copy)]
+enum ShadowedLight {
+    Area {
+        ltc_instance_idx: u32,
+        transform: Mat4,
+    },
+    Point {
+        point_light_idx: u32,
+        position: Vec3,
+        range: f32,
+    },
+    Spot {
+        spot_light_idx: u32,
+        position: Vec3,
+        direction: Vec3,
+        range: f32,
+        cos_outer_angle: f32,
+    },
+}
+
+impl ShadowedLight {
+    fn position(&self) -> Vec3 {
+        match self {
+            Self::Area { transform, .. } => transform.w_axis.xyz(),
+            Self::Point { position, .. } | Self::Spot { position, .. } => *position,
+        }
+    }
+
+    fn layer_count(&self) -> usize {
+        match self {
+            Self::Point { .. } => 6,
+            Self::Area { .. } | Self::Spot { .. } => 1,
+        }
+    }
+}
+
+pub fn create_shadow_map_texture(device: &wgpu::Device) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("terrarium::shadow_pass shadow_map"),
+        size: wgpu::Extent3d {
+            width: SHADOW_MAP_RESOLUTION,
+            height: SHADOW_MAP_RESOLUTION,
+            depth_or_array_layers: SHADOW_MAP_LAYERS as u32,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+pub fn create_shadow_map_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("terrarium::shadow_pass shadow_maps"),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        size: (size_of::<ShadowMap>() * SHADOW_MAP_LAYERS) as u64,
+        mapped_at_creation: false,
+    })
+}
+
+/// Radius of each cascade around the camera, from the nearest to the farthest.
+fn shadow_cascade_radii(render_distance: f32) -> [f32; MAX_SHADOW_CASCADES] {
+    std::array::from_fn(|i| {
+        render_distance * SHADOW_CASCADE_SCALE.powi(i as i32 + 1 - MAX_SHADOW_CASCADES as i32)
+    })
+}
+
+/// Orthographic projection of a sphere around `center` along `direction`, covering casters up to `caster_distance` in front of it.
+/// The projection moves in whole texels so that shadow edges don't shimmer as the camera moves.
+fn shadow_cascade_world_to_clip_space(
+    direction: Vec3,
+    center: Vec3,
+    radius: f32,
+    caster_distance: f32,
+) -> Mat4 {
+    let world_to_view_space =
+        Mat4::look_to_rh(Vec3::ZERO, direction, direction.any_orthonormal_vector());
+
+    let texel_size = radius * 2.0 / SHADOW_MAP_RESOLUTION as f32;
+    let center_vs = world_to_view_space.transform_point3(center);
+    let center_vs = Vec3::new(
+        (center_vs.x / texel_size).floor() * texel_size,
+        (center_vs.y / texel_size).floor() * texel_size,
+        center_vs.z,
+    );
+
+    let view_to_clip_space = Mat4::orthographic_rh(
+        center_vs.x - radius,
+        center_vs.x + radius,
+        center_vs.y - radius,
+        center_vs.y + radius,
+        -center_vs.z - caster_distance,
+        -center_vs.z + radius,
+    );
+    view_to_clip_space * world_to_view_space
+}
+
+/// Renders shadow maps for the lights closest to the camera, used by the raster path in place of shadow rays.
+/// Area lights are approximated by a perspective projection from their center facing the direction they emit into,
+/// spot lights by a projection covering their outer cone, point lights by the six faces of a cube.
+/// The first directional light gets `MAX_SHADOW_CASCADES` orthographic cascades centered on the camera.
+pub struct ShadowPassParameters<'a> {
+    pub camera_position: Vec3,
+    pub shadow_bias: f32,
+    pub render_distance: f32,
+    pub gpu_resources: &'a GpuResources,
+    pub shadow_map_texture: &'a wgpu::Texture,
+    pub shadow_map_buffer: &'a wgpu::Buffer,
+}
+
+pub fn encode(
+    parameters: &ShadowPassParameters,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let linear_transformed_cosines = parameters.gpu_resources.linear_transformed_cosines();
+    let punctual_lights = parameters.gpu_resources.punctual_lights();
+
+    let mut shadowed_lights: Vec<ShadowedLight> = (0..linear_transformed_cosines.instance_count())
+        .map(|i| ShadowedLight::Area {
+            ltc_instance_idx: i as u32,
+            transform: linear_transformed_cosines.instance_transform(i),
+        })
+        .chain((0..punctual_lights.point_light_count()).map(|i| {
+            let (position, range) = punctual_lights.point_light(i);
+            ShadowedLight::Point {
+                point_light_idx: i as u32,
+                position,
+                range,
+            }
+        }))
+        .chain((0..punctual_lights.spot_light_count()).map(|i| {
+            let (position, direction, range, cos_outer_angle) = punctual_lights.spot_light(i);
+            ShadowedLight::Spot {
+                spot_light_idx: i as u32,
+                position,
+                direction,
+                range,
+                cos_outer_angle,
+            }
+        }))
+        .collect();
+    shadowed_lights.sort_by(|a, b| {
+        let a = a.position().distance_squared(parameters.camera_position);
+        let b = b.position().distance_squared(parameters.camera_position);
+        a.total_cmp(&b)
+    });
+
+    let z_near = parameters.shadow_bias.max(0.05);
+    let perspective =
+        |fov: f32, z_far: f32| Mat4::perspective_rh(fov, 1.0, z_near, z_far.max(z_near * 2.0));
+
+    let mut shadow_maps = [ShadowMap {
+        world_to_clip_space: Mat4::IDENTITY,
+        encoded_light_index: u32::MAX,
+        normal_offset: SHADOW_MAP_NORMAL_OFFSET,
+        _padding0: 0,
+        _padding1: 0,
+    }; SHADOW_MAP_LAYERS];
+    // Layers to render along with the sphere their casters must intersect, if the light has a limited range
+    let mut rendered_layers: Vec<(usize, Option<(Vec3, f32)>)> = Vec::new();
+
+    for shadowed_light in &shadowed_lights {
+        if rendered_layers.len() + shadowed_light.layer_count() > MAX_SHADOW_MAPS {
+            // A point light might not fit anymore while a light taking a single layer still does
+            continue;
+        }
+
+        match *shadowed_light {
+            ShadowedLight::Area {
+                ltc_instance_idx,
+                transform,
+            } => {
+                // Area lights emit towards their local -y axis
+                let world_to_view_space = Mat4::look_to_rh(
+                    transform.w_axis.xyz(),
+                    -transform.y_axis.xyz().normalize(),
+                    transform.z_axis.xyz().normalize(),
+                );
+
+                let layer = rendered_layers.len();
+                shadow_maps[layer].world_to_clip_space = perspective(
+                    SHADOW_MAP_FOV_DEGREES.to_radians(),
+                    parameters.render_distance,
+                ) * world_to_view_space;
+                shadow_maps[layer].encoded_light_index =
+                    encode_light_index(LIGHT_TYPE_AREA, ltc_instance_idx);
+                rendered_layers.push((layer, None));
+            }
+            ShadowedLight::Point {
+                point_light_idx,
+                position,
+                range,
+            } => {
+                // Every face covers a quarter turn, together they cover all directions around the light
+                for direction in [
+                    Vec3::X,
+                    Vec3::NEG_X,
+                    Vec3::Y,
+                    Vec3::NEG_Y,
+                    Vec3::Z,
+                    Vec3::NEG_Z,
+                ] {
+                    let world_to_view_space =
+                        Mat4::look_to_rh(position, direction, direction.any_orthonormal_vector());
+
+                    let layer = rendered_layers.len();
+                    shadow_maps[layer].world_to_clip_space =
+                        perspective(90f32.to_radians(), range) * world_to_view_space;
+                    shadow_maps[layer].encoded_light_index =
+                        encode_light_index(LIGHT_TYPE_POINT, point_light_idx);
+                    rendered_layers.push((layer, Some((position, range))));
+                }
+            }
+            ShadowedLight::Spot {
+                spot_light_idx,
+                position,
+                direction,
+                range,
+                cos_outer_angle,
+            } => {
+                let world_to_view_space =
+                    Mat4::look_to_rh(position, direction, direction.any_orthonormal_vector());
+                let fov = (cos_outer_angle.clamp(-1.0, 1.0).acos() * 2.0)
+                    .clamp(1f32.to_radians(), SHADOW_MAP_FOV_DEGREES.to_radians());
+
+                let layer = rendered_layers.len();
+                shadow_maps[layer].world_to_clip_space =
+                    perspective(fov, range) * world_to_view_space;
+                shadow_maps[layer].encoded_light_index =
+                    encode_light_index(LIGHT_TYPE_SPOT, spot_light_idx);
+                rendered_layers.push((layer, Some((position, range))));
+            }
+        }
+    }
+
+    if punctual_lights.directional_light_count() > 0 {
+        let direction = punctual_lights.directional_light_direction(0);
+
+        for (cascade, radius) in shadow_cascade_radii(parameters.render_distance)
+            .into_iter()
+            .enumerate()
+        {
+            let layer = MAX_SHADOW_MAPS + cascade;
+            shadow_maps[layer].world_to_clip_space = shadow_cascade_world_to_clip_space(
+                direction,
+                parameters.camera_position,
+                radius,
+                parameters.render_distance,
+            );
+            shadow_maps[layer].encoded_light_index = 0;
+            // Texels get larger with every cascade, so does the offset needed to move out of the surface
+            shadow_maps[layer].normal_offset =
+                (radius * 2.0 / SHADOW_MAP_RESOLUTION as f32 * 1.5).max(SHADOW_MAP_NORMAL_OFFSET);
+            rendered_layers.push((layer, None));
+        }
+    }
+
+    queue.write_buffer(
+        parameters.shadow_map_buffer,
+        0,
+        bytemuck::cast_slice(&shadow_maps),
+    );
+
+    let shader =
+        pipeline_database.shader_from_src(device, include_wgsl!("../../shaders/shadow_pass.wgsl"));
+    let pipeline = pipeline_database.render_pipeline(
+        device,
+        wgpu::RenderPipelineDescriptor {
+            label: Some("terrarium::shadow_pass"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: parameters.shadow_map_texture.format(),
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::shadow_pass"),
+                bind_group_layouts: &[
+                    empty_bind_group_layout(device),
+                    parameters.gpu_resources.vertex_pool().bind_group_layout(),
+                ],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::VERTEX,
+                    range: 0..size_of::<PushConstant>() as u32,
+                }],
+            })
+        },
+    );
+
+    let vertex_pool_bind_group = parameters.gpu_resources.vertex_pool().bind_group(device);
+
+    for (layer, caster_bounds) in rendered_layers {
+        let shadow_map = &shadow_maps[layer];
+
+        let depth_view = parameters
+            .shadow_map_texture
+            .create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer as u32,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+
+        let mut rpass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("terrarium::shadow_pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&pipeline);
+        rpass.insert_debug_marker("terrarium::shadow_pass");
+
+        rpass.set_bind_group(0, empty_bind_group(device), &[]);
+        rpass.set_bind_group(1, &vertex_pool_bind_group, &[]);
+
+        for instance in parameters.gpu_resources.raster_instances() {
+            if let Some((light_position, range)) = caster_bounds {
+                let (center, radius) = bounding_sphere(
+                    instance.local_to_world,
+                    instance.gpu_mesh.bounds_min,
+                    instance.gpu_mesh.bounds_max,
+                );
+                if center.distance(light_position) > range + radius {
+                    continue;
+                }
+            }
+
+            let num_indices = instance.gpu_mesh.num_indices;
+
+            rpass.set_push_constants(
+                wgpu::ShaderStages::VERTEX,
+                0,
+                bytemuck::bytes_of(&PushConstant {
+                    world_to_clip_space: shadow_map.world_to_clip_space,
+                    local_to_world_space: instance.local_to_world,
+                }),
+            );
+            rpass.draw(
+                0..num_indices,
+                instance.instance_idx..instance.instance_idx + 1,
+            );
+        }
+    }
+}
+
+#[test]
+fn shadow_cascades_cover_the_render_distance() {
+    let radii = shadow_cascade_radii(100.0);
+    assert!((radii[MAX_SHADOW_CASCADES - 1] - 100.0).abs() < 1e-4);
+    assert!(radii.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let camera_position = Vec3::new(12.3, 4.5, -6.7);
+    let direction = Vec3::new(0.3, -1.0, 0.2).normalize();
+    for radius in radii {
+        let world_to_clip_space =
+            shadow_cascade_world_to_clip_space(direction, camera_position, radius, 100.0);
+        let position_ndc = world_to_clip_space.project_point3(camera_position);
+        assert!(position_ndc.x.abs() < 1.0 && position_ndc.y.abs() < 1.0);
+        assert!(position_ndc.z > 0.0 && position_ndc.z < 1.0);
+    }
+}
@@ -0,0 +1,350 @@
+use wgpu::util::DeviceExt;
+use wgsl_includes::include_wgsl;
+
+use crate::{
+    gpu_resources::sky::Sky,
+    wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase},
+};
+
+pub struct SkyLutPassParameters<'a> {
+    pub sky: &'a Sky,
+    /// Also rebuild the transmittance and multi-scattering luts, which only depend on the atmosphere.
+    pub update_atmosphere_luts: bool,
+}
+
+pub fn encode(
+    parameters: &SkyLutPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::sky_lut constants"),
+        contents: bytemuck::bytes_of(&parameters.sky.constants),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    if parameters.update_atmosphere_luts {
+        encode_transmittance(
+            parameters,
+            &constants,
+            device,
+            command_encoder,
+            pipeline_database,
+        );
+        encode_multi_scattering(
+            parameters,
+            &constants,
+            device,
+            command_encoder,
+            pipeline_database,
+        );
+    }
+
+    encode_sky_view(
+        parameters,
+        &constants,
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+}
+
+fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+fn lut_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+fn storage_lut_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: wgpu::TextureFormat::Rgba16Float,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn dispatch(
+    label: &str,
+    pipeline: &wgpu::ComputePipeline,
+    bind_group: &wgpu::BindGroup,
+    lut: &wgpu::Texture,
+    workgroup_size: u32,
+    command_encoder: &mut wgpu::CommandEncoder,
+) {
+    let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some(label),
+        timestamp_writes: None,
+    });
+    cpass.set_pipeline(pipeline);
+    cpass.set_bind_group(0, bind_group, &[]);
+    cpass.insert_debug_marker(label);
+    cpass.dispatch_workgroups(
+        lut.width().div_ceil(workgroup_size),
+        lut.height().div_ceil(workgroup_size),
+        1,
+    );
+}
+
+fn encode_transmittance(
+    parameters: &SkyLutPassParameters,
+    constants: &wgpu::Buffer,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/sky_transmittance_lut_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::sky_transmittance_lut"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::sky_transmittance_lut"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[uniform_entry(0), storage_lut_entry(1)],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let transmittance_lut_view = parameters
+        .sky
+        .transmittance_lut()
+        .create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&transmittance_lut_view),
+            },
+        ],
+    });
+
+    dispatch(
+        "terrarium::sky_transmittance_lut",
+        &pipeline,
+        &bind_group,
+        parameters.sky.transmittance_lut(),
+        16,
+        command_encoder,
+    );
+}
+
+fn encode_multi_scattering(
+    parameters: &SkyLutPassParameters,
+    constants: &wgpu::Buffer,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/sky_multi_scattering_lut_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::sky_multi_scattering_lut"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::sky_multi_scattering_lut"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            uniform_entry(0),
+                            lut_entry(1),
+                            sampler_entry(2),
+                            storage_lut_entry(3),
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let transmittance_lut_view = parameters
+        .sky
+        .transmittance_lut()
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    let multi_scattering_lut_view = parameters
+        .sky
+        .multi_scattering_lut()
+        .create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&transmittance_lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(parameters.sky.lut_sampler()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&multi_scattering_lut_view),
+            },
+        ],
+    });
+
+    dispatch(
+        "terrarium::sky_multi_scattering_lut",
+        &pipeline,
+        &bind_group,
+        parameters.sky.multi_scattering_lut(),
+        8,
+        command_encoder,
+    );
+}
+
+fn encode_sky_view(
+    parameters: &SkyLutPassParameters,
+    constants: &wgpu::Buffer,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database.shader_from_src(
+        device,
+        include_wgsl!("../../shaders/sky_view_lut_pass.wgsl"),
+    );
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::sky_view_lut"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::sky_view_lut"),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            uniform_entry(0),
+                            lut_entry(1),
+                            lut_entry(2),
+                            sampler_entry(3),
+                            storage_lut_entry(4),
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let transmittance_lut_view = parameters
+        .sky
+        .transmittance_lut()
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    let multi_scattering_lut_view = parameters
+        .sky
+        .multi_scattering_lut()
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    let sky_view_lut_view = parameters
+        .sky
+        .sky_view_lut()
+        .create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&transmittance_lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&multi_scattering_lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(parameters.sky.lut_sampler()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&sky_view_lut_view),
+            },
+        ],
+    });
+
+    dispatch(
+        "terrarium::sky_view_lut",
+        &pipeline,
+        &bind_group,
+        parameters.sky.sky_view_lut(),
+        16,
+        command_encoder,
+    );
+}
@@ -1,36 +1,66 @@
 use bytemuck::{Pod, Zeroable};
-use glam::UVec2;
+use glam::{IVec2, UVec2};
 use wgpu::util::DeviceExt;
 use wgsl_includes::include_wgsl;
 
-use crate::wgpu_util::{ComputePipelineDescriptorExtensions, PipelineDatabase};
+use crate::{
+    gpu_resources::gbuffer::Gbuffer,
+    wgpu_util::{
+        empty_bind_group, empty_bind_group_layout, ComputePipelineDescriptorExtensions,
+        PipelineDatabase,
+    },
+};
+
+pub fn create_ambient_occlusion_texture(resolution: UVec2, device: &wgpu::Device) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("terrarium::ssao ambient_occlusion"),
+        size: wgpu::Extent3d {
+            width: resolution.x,
+            height: resolution.y,
+            depth_or_array_layers: 2,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R32Float,
+        usage: wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    })
+}
 
 #[derive(Pod, Clone, Copy, Zeroable)]
 #[repr(C)]
 struct Constants {
     resolution: UVec2,
-    shadow_resolution: UVec2,
-    seed: u32,
+    frame_idx: u32,
     sample_count: u32,
     radius: f32,
     intensity: f32,
     bias: f32,
     _padding0: u32,
-    _padding1: u32,
-    _padding2: u32,
+}
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct BlurConstants {
+    resolution: UVec2,
+    direction: IVec2,
 }
 
 pub struct SsaoPassParameters<'a> {
     pub resolution: UVec2,
-    pub shadow_resolution: UVec2,
-    pub seed: u32,
+    pub frame_idx: u32,
     pub sample_count: u32,
     pub radius: f32,
     pub intensity: f32,
     pub bias: f32,
-    pub shadow_texture_view: &'a wgpu::TextureView,
     pub xr_camera_buffer: &'a wgpu::Buffer,
-    pub gbuffer: &'a [wgpu::Buffer; 2],
+    pub gbuffer: &'a Gbuffer,
+    /// Ping-pong textures for the blur, the denoised result ends up in the first one.
+    pub ambient_occlusion_textures: &'a [wgpu::Texture; 2],
 }
 
 pub fn encode(
@@ -39,8 +69,49 @@ pub fn encode(
     command_encoder: &mut wgpu::CommandEncoder,
     pipeline_database: &mut PipelineDatabase,
 ) {
-    let shader = pipeline_database
-        .shader_from_src(device, include_wgsl!("../../shaders/ssao_pass.wgsl"));
+    let views: [wgpu::TextureView; 2] = std::array::from_fn(|i| {
+        parameters.ambient_occlusion_textures[i].create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        })
+    });
+
+    encode_ssao(
+        parameters,
+        &views[0],
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+    encode_blur(
+        parameters,
+        IVec2::X,
+        &views[0],
+        &views[1],
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+    encode_blur(
+        parameters,
+        IVec2::Y,
+        &views[1],
+        &views[0],
+        device,
+        command_encoder,
+        pipeline_database,
+    );
+}
+
+fn encode_ssao(
+    parameters: &SsaoPassParameters,
+    dst_view: &wgpu::TextureView,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader =
+        pipeline_database.shader_from_src(device, include_wgsl!("../../shaders/ssao_pass.wgsl"));
     let pipeline = pipeline_database.compute_pipeline(
         device,
         wgpu::ComputePipelineDescriptor {
@@ -50,8 +121,8 @@ pub fn encode(
         || {
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("terrarium::ssao"),
-                bind_group_layouts: &[&device.create_bind_group_layout(
-                    &wgpu::BindGroupLayoutDescriptor {
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                         label: None,
                         entries: &[
                             wgpu::BindGroupLayoutEntry {
@@ -77,54 +148,152 @@ pub fn encode(
                             wgpu::BindGroupLayoutEntry {
                                 binding: 2,
                                 visibility: wgpu::ShaderStages::COMPUTE,
-                                ty: wgpu::BindingType::Buffer {
-                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                                    has_dynamic_offset: false,
-                                    min_binding_size: None,
+                                ty: wgpu::BindingType::StorageTexture {
+                                    access: wgpu::StorageTextureAccess::WriteOnly,
+                                    format: wgpu::TextureFormat::R32Float,
+                                    view_dimension: wgpu::TextureViewDimension::D2Array,
                                 },
                                 count: None,
                             },
+                        ],
+                    }),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    parameters.gbuffer.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            })
+        },
+    );
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::ssao constants"),
+        contents: bytemuck::bytes_of(&Constants {
+            resolution: parameters.resolution,
+            frame_idx: parameters.frame_idx,
+            sample_count: parameters.sample_count,
+            radius: parameters.radius,
+            intensity: parameters.intensity,
+            bias: parameters.bias,
+            _padding0: 0,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: parameters.xr_camera_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(dst_view),
+            },
+        ],
+    });
+
+    {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("terrarium::ssao"),
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&pipeline);
+        cpass.set_bind_group(0, &bind_group, &[]);
+        cpass.set_bind_group(1, empty_bind_group(device), &[]);
+        cpass.set_bind_group(2, empty_bind_group(device), &[]);
+        cpass.set_bind_group(3, empty_bind_group(device), &[]);
+        cpass.set_bind_group(4, parameters.gbuffer.bind_group(), &[]);
+        cpass.insert_debug_marker("terrarium::ssao");
+        cpass.dispatch_workgroups(
+            parameters.resolution.x.div_ceil(16),
+            parameters.resolution.y.div_ceil(16),
+            1,
+        );
+    }
+}
+
+fn encode_blur(
+    parameters: &SsaoPassParameters,
+    direction: IVec2,
+    src_view: &wgpu::TextureView,
+    dst_view: &wgpu::TextureView,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let shader = pipeline_database
+        .shader_from_src(device, include_wgsl!("../../shaders/ssao_blur_pass.wgsl"));
+    let pipeline = pipeline_database.compute_pipeline(
+        device,
+        wgpu::ComputePipelineDescriptor {
+            label: Some("terrarium::ssao_blur"),
+            ..wgpu::ComputePipelineDescriptor::partial_default(&shader)
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::ssao_blur"),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
                             wgpu::BindGroupLayoutEntry {
-                                binding: 3,
+                                binding: 0,
                                 visibility: wgpu::ShaderStages::COMPUTE,
                                 ty: wgpu::BindingType::Buffer {
-                                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                    ty: wgpu::BufferBindingType::Uniform,
                                     has_dynamic_offset: false,
                                     min_binding_size: None,
                                 },
                                 count: None,
                             },
                             wgpu::BindGroupLayoutEntry {
-                                binding: 4,
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::COMPUTE,
+                                ty: wgpu::BindingType::Texture {
+                                    sample_type: wgpu::TextureSampleType::Float {
+                                        filterable: false,
+                                    },
+                                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
                                 visibility: wgpu::ShaderStages::COMPUTE,
                                 ty: wgpu::BindingType::StorageTexture {
-                                    access: wgpu::StorageTextureAccess::ReadWrite,
-                                    format: wgpu::TextureFormat::R16Float,
+                                    access: wgpu::StorageTextureAccess::WriteOnly,
+                                    format: wgpu::TextureFormat::R32Float,
                                     view_dimension: wgpu::TextureViewDimension::D2Array,
                                 },
                                 count: None,
                             },
                         ],
-                    },
-                )],
+                    }),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    parameters.gbuffer.bind_group_layout(),
+                ],
                 push_constant_ranges: &[],
             })
         },
     );
 
     let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("terrarium::ssao constants"),
-        contents: bytemuck::bytes_of(&Constants {
+        label: Some("terrarium::ssao_blur constants"),
+        contents: bytemuck::bytes_of(&BlurConstants {
             resolution: parameters.resolution,
-            shadow_resolution: parameters.shadow_resolution,
-            seed: parameters.seed,
-            sample_count: parameters.sample_count,
-            radius: parameters.radius,
-            intensity: parameters.intensity,
-            bias: parameters.bias,
-            _padding0: 0,
-            _padding1: 0,
-            _padding2: 0,
+            direction,
         }),
         usage: wgpu::BufferUsages::UNIFORM,
     });
@@ -140,34 +309,30 @@ pub fn encode(
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: parameters.xr_camera_buffer.as_entire_binding(),
+                resource: wgpu::BindingResource::TextureView(src_view),
             },
             wgpu::BindGroupEntry {
                 binding: 2,
-                resource: parameters.gbuffer[0].as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: parameters.gbuffer[1].as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 4,
-                resource: wgpu::BindingResource::TextureView(parameters.shadow_texture_view),
+                resource: wgpu::BindingResource::TextureView(dst_view),
             },
         ],
     });
 
     {
         let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-            label: Some("terrarium::ssao"),
+            label: Some("terrarium::ssao_blur"),
             timestamp_writes: None,
         });
         cpass.set_pipeline(&pipeline);
         cpass.set_bind_group(0, &bind_group, &[]);
-        cpass.insert_debug_marker("terrarium::ssao");
+        cpass.set_bind_group(1, empty_bind_group(device), &[]);
+        cpass.set_bind_group(2, empty_bind_group(device), &[]);
+        cpass.set_bind_group(3, empty_bind_group(device), &[]);
+        cpass.set_bind_group(4, parameters.gbuffer.bind_group(), &[]);
+        cpass.insert_debug_marker("terrarium::ssao_blur");
         cpass.dispatch_workgroups(
-            parameters.shadow_resolution.x.div_ceil(16),
-            parameters.shadow_resolution.y.div_ceil(16),
+            parameters.resolution.x.div_ceil(16),
+            parameters.resolution.y.div_ceil(16),
             1,
         );
     }
@@ -0,0 +1,262 @@
+use std::num::NonZeroU32;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, UVec2, Vec3};
+use wgpu::util::DeviceExt;
+use wgsl_includes::include_wgsl;
+
+use crate::{
+    gpu_resources::{gbuffer::Gbuffer, GpuResources, RasterInstance},
+    wgpu_util::{empty_bind_group, empty_bind_group_layout, PipelineDatabase},
+};
+
+#[derive(Pod, Clone, Copy, Zeroable)]
+#[repr(C)]
+struct Constants {
+    resolution: UVec2,
+    ambient_factor: f32,
+    _padding0: u32,
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct PushConstant {
+    local_to_world_space: Mat4,
+    inv_trans_local_to_world_space: Mat4,
+}
+
+/// Forward shades the blended triangles of all transparent instances on top of the shaded image, sorted back to front.
+/// Used by both render paths, occlusion is resolved against the depth stored in the gbuffer.
+pub struct TransparentPassParameters<'a> {
+    pub resolution: UVec2,
+    pub ambient_factor: f32,
+    /// Instances are sorted by the distance of their bounds center to this position.
+    pub camera_position: Vec3,
+    pub gpu_resources: &'a GpuResources,
+    pub xr_camera_buffer: &'a wgpu::Buffer,
+    pub gbuffer: &'a Gbuffer,
+    pub dst_view: &'a wgpu::TextureView,
+    pub target_format: wgpu::TextureFormat,
+}
+
+pub fn encode(
+    parameters: &TransparentPassParameters,
+    device: &wgpu::Device,
+    command_encoder: &mut wgpu::CommandEncoder,
+    pipeline_database: &mut PipelineDatabase,
+) {
+    let mut instances: Vec<(f32, &RasterInstance)> = parameters
+        .gpu_resources
+        .transparent_instances()
+        .map(|instance| {
+            let bounds_center = (instance.gpu_mesh.bounds_min + instance.gpu_mesh.bounds_max) * 0.5;
+            let distance = instance
+                .local_to_world
+                .transform_point3(bounds_center)
+                .distance(parameters.camera_position);
+            (distance, instance)
+        })
+        .collect();
+    if instances.is_empty() {
+        return;
+    }
+    instances.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let shader = pipeline_database
+        .shader_from_src(device, include_wgsl!("../../shaders/transparent_pass.wgsl"));
+    let pipeline = pipeline_database.render_pipeline(
+        device,
+        wgpu::RenderPipelineDescriptor {
+            label: Some("terrarium::transparent_pass"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                // Alpha is left untouched, the shading texture stores the emission flag in it
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: parameters.target_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: Some(NonZeroU32::new(2).unwrap()),
+            cache: None,
+        },
+        || {
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("terrarium::transparent_pass"),
+                bind_group_layouts: &[
+                    &device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: None,
+                        entries: &[
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 0,
+                                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 1,
+                                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                                ty: wgpu::BindingType::Buffer {
+                                    ty: wgpu::BufferBindingType::Uniform,
+                                    has_dynamic_offset: false,
+                                    min_binding_size: None,
+                                },
+                                count: None,
+                            },
+                            wgpu::BindGroupLayoutEntry {
+                                binding: 2,
+                                visibility: wgpu::ShaderStages::FRAGMENT,
+                                ty: wgpu::BindingType::Texture {
+                                    sample_type: wgpu::TextureSampleType::Float {
+                                        filterable: false,
+                                    },
+                                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                                    multisampled: false,
+                                },
+                                count: None,
+                            },
+                        ],
+                    }),
+                    parameters.gpu_resources.vertex_pool().bind_group_layout(),
+                    parameters.gpu_resources.material_pool().bind_group_layout(),
+                    parameters.gpu_resources.sky().bind_group_layout(),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    empty_bind_group_layout(device),
+                    parameters
+                        .gpu_resources
+                        .punctual_lights()
+                        .bind_group_layout(),
+                ],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::VERTEX,
+                    range: 0..size_of::<PushConstant>() as u32,
+                }],
+            })
+        },
+    );
+
+    let constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("terrarium::transparent_pass constants"),
+        contents: bytemuck::bytes_of(&Constants {
+            resolution: parameters.resolution,
+            ambient_factor: parameters.ambient_factor,
+            _padding0: 0,
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: constants.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: parameters.xr_camera_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(
+                    parameters.gbuffer.position_and_depth_view(),
+                ),
+            },
+        ],
+    });
+
+    {
+        let mut rpass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("terrarium::transparent_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: parameters.dst_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rpass.set_pipeline(&pipeline);
+        rpass.insert_debug_marker("terrarium::transparent_pass");
+
+        rpass.set_bind_group(0, &bind_group, &[]);
+        rpass.set_bind_group(
+            1,
+            &parameters.gpu_resources.vertex_pool().bind_group(device),
+            &[],
+        );
+        parameters.gpu_resources.material_pool().bind_group(
+            pipeline.get_bind_group_layout(2),
+            device,
+            |bind_group| {
+                rpass.set_bind_group(2, bind_group, &[]);
+            },
+        );
+        rpass.set_bind_group(3, &parameters.gpu_resources.sky().bind_group(device), &[]);
+        rpass.set_bind_group(4, empty_bind_group(device), &[]);
+        rpass.set_bind_group(5, empty_bind_group(device), &[]);
+        rpass.set_bind_group(6, empty_bind_group(device), &[]);
+        rpass.set_bind_group(
+            7,
+            parameters.gpu_resources.punctual_lights().bind_group(),
+            &[],
+        );
+
+        // Back to front per instance, triangles within an instance are not sorted
+        for (_, instance) in instances {
+            let num_indices = instance.gpu_mesh.num_indices;
+
+            rpass.set_push_constants(
+                wgpu::ShaderStages::VERTEX,
+                0,
+                bytemuck::bytes_of(&PushConstant {
+                    local_to_world_space: instance.local_to_world,
+                    inv_trans_local_to_world_space: instance.local_to_world.inverse().transpose(),
+                }),
+            );
+            rpass.draw(
+                0..num_indices,
+                instance.instance_idx..instance.instance_idx + 1,
+            );
+        }
+    }
+}
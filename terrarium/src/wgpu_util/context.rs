@@ -61,10 +61,62 @@ impl Context {
         });
         surface.pre_adapter(&instance, window);
 
+        Self::init_with_instance(
+            instance,
+            false,
+            optional_features,
+            required_features,
+            required_downlevel_capabilities,
+            required_limits,
+        )
+        .await
+    }
+
+    /// Create a context without a window or surface, used for offscreen rendering.
+    ///
+    /// Set `force_fallback_adapter` to pick a software adapter (e.g. lavapipe), which is useful for running on machines without a gpu.
+    pub async fn init_headless(
+        optional_features: Features,
+        required_features: Features,
+        required_downlevel_capabilities: DownlevelCapabilities,
+        required_limits: Limits,
+        force_fallback_adapter: bool,
+        no_gpu_validation: bool,
+    ) -> Self {
+        let mut flags = wgpu::InstanceFlags::DEBUG;
+        if !no_gpu_validation {
+            flags |= wgpu::InstanceFlags::VALIDATION;
+        }
+
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::VULKAN,
+            flags,
+            backend_options: wgpu::BackendOptions::default(),
+        });
+
+        Self::init_with_instance(
+            instance,
+            force_fallback_adapter,
+            optional_features,
+            required_features,
+            required_downlevel_capabilities,
+            required_limits,
+        )
+        .await
+    }
+
+    async fn init_with_instance(
+        instance: wgpu::Instance,
+        force_fallback_adapter: bool,
+        optional_features: Features,
+        required_features: Features,
+        required_downlevel_capabilities: DownlevelCapabilities,
+        required_limits: Limits,
+    ) -> Self {
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
+                force_fallback_adapter,
                 compatible_surface: None,
             })
             .await
@@ -112,7 +164,8 @@ impl Context {
     }
 
     pub(crate) fn init_with_xr(
-        required_features: wgpu::Features,
+        optional_features: wgpu::Features,
+        mut required_features: wgpu::Features,
         required_limits: wgpu::Limits,
         no_gpu_validation: bool,
     ) -> Result<Self> {
@@ -241,6 +294,7 @@ impl Context {
         let wgpu_exposed_adapter = wgpu_vk_instance
             .expose_adapter(vk_physical_device)
             .context("failed to expose adapter")?;
+        required_features |= optional_features & wgpu_exposed_adapter.features;
 
         let enabled_extensions = wgpu_exposed_adapter
             .adapter
@@ -261,12 +315,14 @@ impl Context {
             };
 
             // TODO: derive from gpu features
-            let device_extensions = [
-                c"VK_KHR_swapchain",
-                c"VK_KHR_acceleration_structure",
-                c"VK_KHR_ray_query",
-                c"VK_KHR_buffer_device_address",
-            ];
+            let mut device_extensions = vec![c"VK_KHR_swapchain"];
+            if required_features.contains(wgpu::Features::EXPERIMENTAL_RAY_QUERY) {
+                device_extensions.extend([
+                    c"VK_KHR_acceleration_structure",
+                    c"VK_KHR_ray_query",
+                    c"VK_KHR_buffer_device_address",
+                ]);
+            }
             let device_extensions_cchar: Vec<_> =
                 device_extensions.iter().map(|s| s.as_ptr()).collect();
 
@@ -4,10 +4,12 @@ use std::future::IntoFuture;
 
 pub mod context;
 pub mod pipeline_database;
+pub mod profiler;
 pub mod surface;
 
 pub use context::*;
 pub use pipeline_database::PipelineDatabase;
+pub use profiler::GpuProfiler;
 pub use surface::Surface;
 
 pub trait ComputePipelineDescriptorExtensions<'a> {
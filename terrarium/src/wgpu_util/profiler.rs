@@ -0,0 +1,513 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write as _,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+
+/// Maximum number of scopes timed on the gpu per frame, scopes past this limit only record cpu timings.
+pub const MAX_PROFILER_SCOPES: u32 = 128;
+/// Number of frames a timestamp readback can lag behind before its timings are dropped.
+const PROFILER_FRAMES_IN_FLIGHT: usize = 4;
+/// Number of frames averaged by `ProfilerScopeTimings`.
+const PROFILER_AVERAGE_FRAMES: usize = 60;
+/// Limits the size of a chrome trace when recording is never stopped.
+const MAX_TRACE_EVENTS: usize = 1024 * 1024;
+
+/// Rolling average timings of a single named scope, in milliseconds.
+#[derive(Debug, Clone, Default)]
+pub struct ProfilerScopeTimings {
+    pub name: String,
+    pub cpu_ms: f32,
+    /// Only available when the device supports timestamp queries inside encoders.
+    pub gpu_ms: Option<f32>,
+}
+
+/// Rolling average timings of the last frames, in milliseconds.
+#[derive(Debug, Clone, Default)]
+pub struct ProfilerTimings {
+    pub frame_cpu_ms: f32,
+    pub frame_gpu_ms: Option<f32>,
+    /// Scopes in the order they were first encountered.
+    pub scopes: Vec<ProfilerScopeTimings>,
+}
+
+/// Returned by `GpuProfiler::begin_scope`, pass it back to `GpuProfiler::end_scope`.
+#[must_use]
+pub struct ProfilerScope {
+    scope_idx: Option<usize>,
+}
+
+struct RollingAverage {
+    samples: VecDeque<f32>,
+    sum: f32,
+}
+
+impl RollingAverage {
+    fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(PROFILER_AVERAGE_FRAMES),
+            sum: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        if self.samples.len() == PROFILER_AVERAGE_FRAMES {
+            self.sum -= self.samples.pop_front().unwrap();
+        }
+        self.samples.push_back(sample);
+        self.sum += sample;
+    }
+
+    fn average(&self) -> Option<f32> {
+        (!self.samples.is_empty()).then(|| self.sum / self.samples.len() as f32)
+    }
+}
+
+struct ScopeAverages {
+    name: String,
+    cpu: RollingAverage,
+    gpu: RollingAverage,
+}
+
+struct FrameScope {
+    name: String,
+    /// Microseconds since the profiler was created.
+    cpu_start_us: f64,
+    cpu_ms: f32,
+    query_idx: Option<u32>,
+}
+
+/// Scopes of a frame of which the timestamps are being read back.
+struct PendingFrame {
+    readback_buffer: wgpu::Buffer,
+    mapped: Arc<AtomicBool>,
+    in_use: bool,
+    map_requested: bool,
+    cpu_start_us: f64,
+    scopes: Vec<FrameScope>,
+}
+
+struct TraceEvent {
+    name: String,
+    category: &'static str,
+    start_us: f64,
+    duration_us: f64,
+}
+
+/// Opt-in frame profiler, times named scopes on the cpu and, when supported, on the gpu through timestamp queries.
+/// Gpu timings become available a few frames later, once their readback has completed.
+pub struct GpuProfiler {
+    enabled: bool,
+    epoch: Instant,
+    timestamp_period: f32,
+    query_set: Option<wgpu::QuerySet>,
+    resolve_buffer: Option<wgpu::Buffer>,
+    pending_frames: Vec<PendingFrame>,
+    frame_idx: usize,
+
+    frame_start: Option<Instant>,
+    frame_scopes: Vec<FrameScope>,
+    next_query_idx: u32,
+
+    frame_cpu: RollingAverage,
+    frame_gpu: RollingAverage,
+    scope_averages: Vec<ScopeAverages>,
+    scope_indices: HashMap<String, usize>,
+
+    recording_trace: bool,
+    trace_events: Vec<TraceEvent>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let supports_timestamps = device.features().contains(
+            wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS,
+        );
+
+        let (query_set, resolve_buffer, pending_frames) = if supports_timestamps {
+            let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("terrarium::profiler"),
+                ty: wgpu::QueryType::Timestamp,
+                count: MAX_PROFILER_SCOPES * 2,
+            });
+
+            let size = (std::mem::size_of::<u64>() as u32 * MAX_PROFILER_SCOPES * 2) as u64;
+            let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("terrarium::profiler resolve"),
+                mapped_at_creation: false,
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            });
+
+            let pending_frames = (0..PROFILER_FRAMES_IN_FLIGHT)
+                .map(|i| PendingFrame {
+                    readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some(&format!("terrarium::profiler readback {}", i)),
+                        mapped_at_creation: false,
+                        size,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    }),
+                    mapped: Arc::new(AtomicBool::new(false)),
+                    in_use: false,
+                    map_requested: false,
+                    cpu_start_us: 0.0,
+                    scopes: Vec::new(),
+                })
+                .collect();
+
+            (Some(query_set), Some(resolve_buffer), pending_frames)
+        } else {
+            (None, None, Vec::new())
+        };
+
+        Self {
+            enabled: false,
+            epoch: Instant::now(),
+            timestamp_period: queue.get_timestamp_period(),
+            query_set,
+            resolve_buffer,
+            pending_frames,
+            frame_idx: 0,
+            frame_start: None,
+            frame_scopes: Vec::new(),
+            next_query_idx: 0,
+            frame_cpu: RollingAverage::new(),
+            frame_gpu: RollingAverage::new(),
+            scope_averages: Vec::new(),
+            scope_indices: HashMap::new(),
+            recording_trace: false,
+            trace_events: Vec::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether scopes are also timed on the gpu, requires `TIMESTAMP_QUERY` and `TIMESTAMP_QUERY_INSIDE_ENCODERS`.
+    pub fn supports_gpu_timings(&self) -> bool {
+        self.query_set.is_some()
+    }
+
+    fn elapsed_us(&self, instant: Instant) -> f64 {
+        instant.duration_since(self.epoch).as_secs_f64() * 1_000_000.0
+    }
+
+    /// Start a new frame, also collects the gpu timings of earlier frames that finished reading back.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        if !self.enabled {
+            return;
+        }
+
+        self.read_back_frames(device);
+
+        self.frame_start = Some(Instant::now());
+        self.frame_scopes.clear();
+        self.next_query_idx = 0;
+    }
+
+    /// Start timing a scope, encoded commands between this and `end_scope` are attributed to `name`.
+    pub fn begin_scope(
+        &mut self,
+        name: &str,
+        command_encoder: &mut wgpu::CommandEncoder,
+    ) -> ProfilerScope {
+        if !self.enabled || self.frame_start.is_none() {
+            return ProfilerScope { scope_idx: None };
+        }
+
+        let query_idx = match &self.query_set {
+            Some(query_set) if self.next_query_idx < MAX_PROFILER_SCOPES * 2 => {
+                command_encoder.write_timestamp(query_set, self.next_query_idx);
+                self.next_query_idx += 2;
+                Some(self.next_query_idx - 2)
+            }
+            _ => None,
+        };
+
+        self.frame_scopes.push(FrameScope {
+            name: name.to_owned(),
+            cpu_start_us: self.elapsed_us(Instant::now()),
+            cpu_ms: 0.0,
+            query_idx,
+        });
+
+        ProfilerScope {
+            scope_idx: Some(self.frame_scopes.len() - 1),
+        }
+    }
+
+    pub fn end_scope(&mut self, scope: ProfilerScope, command_encoder: &mut wgpu::CommandEncoder) {
+        let Some(scope_idx) = scope.scope_idx else {
+            return;
+        };
+
+        let cpu_end_us = self.elapsed_us(Instant::now());
+        let frame_scope = &mut self.frame_scopes[scope_idx];
+        frame_scope.cpu_ms = ((cpu_end_us - frame_scope.cpu_start_us) / 1000.0) as f32;
+
+        if let (Some(query_set), Some(query_idx)) = (&self.query_set, frame_scope.query_idx) {
+            command_encoder.write_timestamp(query_set, query_idx + 1);
+        }
+    }
+
+    /// Finish the frame, resolves its timestamp queries into `command_encoder` for readback once submitted.
+    pub fn end_frame(&mut self, command_encoder: &mut wgpu::CommandEncoder) {
+        let Some(frame_start) = self.frame_start.take() else {
+            return;
+        };
+
+        let frame_start_us = self.elapsed_us(frame_start);
+        let frame_cpu_ms = frame_start.elapsed().as_secs_f32() * 1000.0;
+        self.frame_cpu.push(frame_cpu_ms);
+
+        let frame_scopes = std::mem::take(&mut self.frame_scopes);
+        for frame_scope in &frame_scopes {
+            let scope_idx = self.scope_idx(&frame_scope.name);
+            self.scope_averages[scope_idx].cpu.push(frame_scope.cpu_ms);
+        }
+
+        if self.recording_trace {
+            self.push_trace_event("frame", "cpu", frame_start_us, frame_cpu_ms as f64 * 1000.0);
+            for frame_scope in &frame_scopes {
+                self.push_trace_event(
+                    &frame_scope.name,
+                    "cpu",
+                    frame_scope.cpu_start_us,
+                    frame_scope.cpu_ms as f64 * 1000.0,
+                );
+            }
+        }
+
+        let (Some(query_set), Some(resolve_buffer)) = (&self.query_set, &self.resolve_buffer)
+        else {
+            return;
+        };
+        if self.next_query_idx == 0 {
+            return;
+        }
+
+        // Drop the timings of this frame when all readback buffers are still waiting on the gpu
+        let pending_frame_idx = self.frame_idx % PROFILER_FRAMES_IN_FLIGHT;
+        let pending_frame = &mut self.pending_frames[pending_frame_idx];
+        if pending_frame.in_use {
+            return;
+        }
+        self.frame_idx += 1;
+
+        command_encoder.resolve_query_set(query_set, 0..self.next_query_idx, resolve_buffer, 0);
+        command_encoder.copy_buffer_to_buffer(
+            resolve_buffer,
+            0,
+            &pending_frame.readback_buffer,
+            0,
+            (std::mem::size_of::<u64>() as u32 * self.next_query_idx) as u64,
+        );
+
+        pending_frame.in_use = true;
+        pending_frame.map_requested = false;
+        pending_frame.cpu_start_us = frame_start_us;
+        pending_frame.scopes = frame_scopes;
+    }
+
+    /// Map the readback buffers of submitted frames and process the ones that finished mapping.
+    /// Frames resolved in `end_frame` are guaranteed to be submitted by the time the next frame begins.
+    fn read_back_frames(&mut self, device: &wgpu::Device) {
+        if self.pending_frames.is_empty() {
+            return;
+        }
+
+        for pending_frame in &mut self.pending_frames {
+            if pending_frame.in_use && !pending_frame.map_requested {
+                let mapped = pending_frame.mapped.clone();
+                pending_frame.readback_buffer.slice(..).map_async(
+                    wgpu::MapMode::Read,
+                    move |result| {
+                        mapped.store(result.is_ok(), Ordering::Release);
+                    },
+                );
+                pending_frame.map_requested = true;
+            }
+        }
+
+        let _ = device.poll(wgpu::PollType::Poll);
+
+        for pending_frame_idx in 0..self.pending_frames.len() {
+            let pending_frame = &mut self.pending_frames[pending_frame_idx];
+            if !pending_frame.in_use || !pending_frame.mapped.swap(false, Ordering::Acquire) {
+                continue;
+            }
+
+            let timestamps: Vec<u64> = {
+                let data = pending_frame.readback_buffer.slice(..).get_mapped_range();
+                bytemuck::cast_slice(&data).to_vec()
+            };
+            pending_frame.readback_buffer.unmap();
+            pending_frame.in_use = false;
+
+            let cpu_start_us = pending_frame.cpu_start_us;
+            let scopes = std::mem::take(&mut pending_frame.scopes);
+            self.process_gpu_timestamps(cpu_start_us, &scopes, &timestamps);
+        }
+    }
+
+    fn process_gpu_timestamps(
+        &mut self,
+        cpu_start_us: f64,
+        scopes: &[FrameScope],
+        timestamps: &[u64],
+    ) {
+        let timestamp_period = self.timestamp_period as f64;
+        let to_us = move |ticks: u64| ticks as f64 * timestamp_period / 1000.0;
+
+        let mut first_timestamp = u64::MAX;
+        let mut last_timestamp = 0;
+        for scope in scopes {
+            if let Some(query_idx) = scope.query_idx {
+                first_timestamp = first_timestamp.min(timestamps[query_idx as usize]);
+                last_timestamp = last_timestamp.max(timestamps[query_idx as usize + 1]);
+            }
+        }
+        if first_timestamp >= last_timestamp {
+            return;
+        }
+
+        self.frame_gpu
+            .push((to_us(last_timestamp - first_timestamp) / 1000.0) as f32);
+
+        for scope in scopes {
+            let Some(query_idx) = scope.query_idx else {
+                continue;
+            };
+            let start = timestamps[query_idx as usize];
+            let end = timestamps[query_idx as usize + 1].max(start);
+
+            let scope_idx = self.scope_idx(&scope.name);
+            self.scope_averages[scope_idx]
+                .gpu
+                .push((to_us(end - start) / 1000.0) as f32);
+
+            // Gpu timestamps have their own time base, align the first gpu scope with the start of the cpu frame
+            if self.recording_trace {
+                self.push_trace_event(
+                    &scope.name,
+                    "gpu",
+                    cpu_start_us + to_us(start - first_timestamp),
+                    to_us(end - start),
+                );
+            }
+        }
+    }
+
+    fn scope_idx(&mut self, name: &str) -> usize {
+        if let Some(scope_idx) = self.scope_indices.get(name) {
+            return *scope_idx;
+        }
+
+        self.scope_averages.push(ScopeAverages {
+            name: name.to_owned(),
+            cpu: RollingAverage::new(),
+            gpu: RollingAverage::new(),
+        });
+        self.scope_indices
+            .insert(name.to_owned(), self.scope_averages.len() - 1);
+        self.scope_averages.len() - 1
+    }
+
+    /// Rolling averages over the last frames of every scope.
+    pub fn timings(&self) -> ProfilerTimings {
+        ProfilerTimings {
+            frame_cpu_ms: self.frame_cpu.average().unwrap_or(0.0),
+            frame_gpu_ms: self.frame_gpu.average(),
+            scopes: self
+                .scope_averages
+                .iter()
+                .map(|scope| ProfilerScopeTimings {
+                    name: scope.name.clone(),
+                    cpu_ms: scope.cpu.average().unwrap_or(0.0),
+                    gpu_ms: scope.gpu.average(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Start collecting scopes for `export_chrome_trace`, discarding anything recorded before.
+    pub fn start_trace(&mut self) {
+        self.recording_trace = true;
+        self.trace_events.clear();
+    }
+
+    pub fn stop_trace(&mut self) {
+        self.recording_trace = false;
+    }
+
+    fn push_trace_event(
+        &mut self,
+        name: &str,
+        category: &'static str,
+        start_us: f64,
+        duration_us: f64,
+    ) {
+        if self.trace_events.len() < MAX_TRACE_EVENTS {
+            self.trace_events.push(TraceEvent {
+                name: name.to_owned(),
+                category,
+                start_us,
+                duration_us,
+            });
+        }
+    }
+
+    /// Write the recorded scopes as a chrome trace, which can be opened in `chrome://tracing` or Perfetto.
+    pub fn export_chrome_trace<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let mut json = String::from("{\"traceEvents\":[");
+        for (i, event) in self.trace_events.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+
+            let tid = if event.category == "gpu" { 1 } else { 0 };
+            write!(
+                json,
+                "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":{}}}",
+                escape_json(&event.name),
+                event.category,
+                event.start_us,
+                event.duration_us,
+                tid
+            )?;
+        }
+        json.push_str("],\"displayTimeUnit\":\"ms\"}");
+
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write chrome trace {}", path.display()))
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use glam::{Mat4, Quat, Vec3, Vec4};
+use ugm::{
+    animation::{AnimationInterpolation, AnimationProperty},
+    Model,
+};
+
+/// Local translation, rotation and scale of a single joint.
+#[derive(Debug, Clone, Copy)]
+pub struct JointPose {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl JointPose {
+    fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// Joint hierarchy of a skin, joint indices match the joint indices stored in the vertices of skinned meshes.
+/// Joint transforms are relative to the root of the model, place skinned entities at the root of the model they originate from.
+#[derive(Debug)]
+pub struct Skeleton {
+    joint_node_indices: Vec<u32>,
+    node_joint_indices: HashMap<u32, u32>,
+    parent_joint_indices: Vec<Option<u32>>,
+    /// Transforms of non-joint nodes in between a joint and its parent joint, or the model root.
+    parent_offsets: Vec<Mat4>,
+    inverse_bind_matrices: Vec<Mat4>,
+    rest_pose: Vec<JointPose>,
+    /// Joints ordered parents first, so that global transforms can be resolved in a single pass.
+    evaluation_order: Vec<u32>,
+}
+
+impl Skeleton {
+    pub fn from_model(model: &Model, skin_idx: usize) -> Result<Self> {
+        let Some(skin) = model.skins.get(skin_idx) else {
+            bail!("Model does not contain skin {}.", skin_idx);
+        };
+        if skin.inverse_bind_matrices.len() != skin.joint_node_indices.len() {
+            bail!(
+                "Skin {} has {} joints but {} inverse bind matrices.",
+                skin_idx,
+                skin.joint_node_indices.len(),
+                skin.inverse_bind_matrices.len()
+            );
+        }
+
+        let mut parent_node_indices = vec![None; model.nodes.len()];
+        for (node_idx, node) in model.nodes.iter().enumerate() {
+            for child_node_idx in &node.child_node_indices {
+                parent_node_indices[*child_node_idx as usize] = Some(node_idx as u32);
+            }
+        }
+
+        let node_joint_indices: HashMap<u32, u32> = skin
+            .joint_node_indices
+            .iter()
+            .enumerate()
+            .map(|(joint_idx, node_idx)| (*node_idx, joint_idx as u32))
+            .collect();
+
+        let num_joints = skin.joint_node_indices.len();
+        let mut parent_joint_indices = Vec::with_capacity(num_joints);
+        let mut parent_offsets = Vec::with_capacity(num_joints);
+        let mut depths = Vec::with_capacity(num_joints);
+        for node_idx in &skin.joint_node_indices {
+            let mut parent_offset = Mat4::IDENTITY;
+            let mut parent_joint_idx = None;
+            let mut depth = 0;
+
+            let mut parent_node_idx = parent_node_indices[*node_idx as usize];
+            while let Some(node_idx) = parent_node_idx {
+                if let Some(joint_idx) = node_joint_indices.get(&node_idx) {
+                    if parent_joint_idx.is_none() {
+                        parent_joint_idx = Some(*joint_idx);
+                    }
+                    depth += 1;
+                } else if parent_joint_idx.is_none() {
+                    parent_offset =
+                        Mat4::from_cols_array(&model.nodes[node_idx as usize].transform)
+                            * parent_offset;
+                }
+                parent_node_idx = parent_node_indices[node_idx as usize];
+            }
+
+            parent_joint_indices.push(parent_joint_idx);
+            parent_offsets.push(parent_offset);
+            depths.push(depth);
+        }
+
+        let mut evaluation_order: Vec<u32> = (0..num_joints as u32).collect();
+        evaluation_order.sort_by_key(|joint_idx| depths[*joint_idx as usize]);
+
+        Ok(Self {
+            joint_node_indices: skin.joint_node_indices.clone(),
+            node_joint_indices,
+            parent_joint_indices,
+            parent_offsets,
+            inverse_bind_matrices: skin
+                .inverse_bind_matrices
+                .iter()
+                .map(Mat4::from_cols_array)
+                .collect(),
+            rest_pose: skin
+                .joint_node_indices
+                .iter()
+                .map(|node_idx| {
+                    JointPose::from_matrix(Mat4::from_cols_array(
+                        &model.nodes[*node_idx as usize].transform,
+                    ))
+                })
+                .collect(),
+            evaluation_order,
+        })
+    }
+
+    pub fn num_joints(&self) -> usize {
+        self.joint_node_indices.len()
+    }
+
+    /// Index of the joint driven by model node `node_idx`.
+    pub fn joint_idx(&self, node_idx: u32) -> Option<u32> {
+        self.node_joint_indices.get(&node_idx).copied()
+    }
+
+    pub fn rest_pose(&self) -> &[JointPose] {
+        &self.rest_pose
+    }
+
+    /// Skinning matrices for a pose containing the local transform of every joint.
+    pub fn joint_matrices(&self, pose: &[JointPose], joint_matrices: &mut Vec<Mat4>) {
+        assert_eq!(pose.len(), self.num_joints());
+
+        joint_matrices.clear();
+        joint_matrices.resize(self.num_joints(), Mat4::IDENTITY);
+        for joint_idx in &self.evaluation_order {
+            let i = *joint_idx as usize;
+            let parent_transform = self.parent_joint_indices[i]
+                .map_or(Mat4::IDENTITY, |parent_joint_idx| {
+                    joint_matrices[parent_joint_idx as usize]
+                });
+            joint_matrices[i] = parent_transform * self.parent_offsets[i] * pose[i].matrix();
+        }
+
+        for (joint_matrix, inverse_bind_matrix) in
+            joint_matrices.iter_mut().zip(&self.inverse_bind_matrices)
+        {
+            *joint_matrix *= *inverse_bind_matrix;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationChannelProperty {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationChannelInterpolation {
+    Step,
+    Linear,
+    /// Every keyframe stores an in tangent, value and out tangent.
+    CubicSpline,
+}
+
+/// Keyframes animating a single property of a model node.
+#[derive(Debug)]
+pub struct AnimationChannel {
+    pub node_idx: u32,
+    pub property: AnimationChannelProperty,
+    pub interpolation: AnimationChannelInterpolation,
+    pub timestamps: Vec<f32>,
+    /// Xyz for translation and scale, xyzw for rotation.
+    pub values: Vec<Vec4>,
+}
+
+impl AnimationChannel {
+    fn sample(&self, time: f32) -> Vec4 {
+        let keyframe_value = |keyframe: usize| match self.interpolation {
+            AnimationChannelInterpolation::CubicSpline => self.values[keyframe * 3 + 1],
+            _ => self.values[keyframe],
+        };
+
+        let next = self
+            .timestamps
+            .partition_point(|timestamp| *timestamp <= time);
+        if next == 0 {
+            return keyframe_value(0);
+        }
+        if next == self.timestamps.len() {
+            return keyframe_value(next - 1);
+        }
+
+        let prev = next - 1;
+        let delta_time = self.timestamps[next] - self.timestamps[prev];
+        let t = if delta_time > 0.0 {
+            (time - self.timestamps[prev]) / delta_time
+        } else {
+            0.0
+        };
+
+        match self.interpolation {
+            AnimationChannelInterpolation::Step => keyframe_value(prev),
+            AnimationChannelInterpolation::Linear => {
+                let a = keyframe_value(prev);
+                let b = keyframe_value(next);
+                if self.property == AnimationChannelProperty::Rotation {
+                    Quat::from_vec4(a).slerp(Quat::from_vec4(b), t).into()
+                } else {
+                    a.lerp(b, t)
+                }
+            }
+            AnimationChannelInterpolation::CubicSpline => {
+                let t2 = t * t;
+                let t3 = t2 * t;
+                let out_tangent = self.values[prev * 3 + 2] * delta_time;
+                let in_tangent = self.values[next * 3] * delta_time;
+
+                (2.0 * t3 - 3.0 * t2 + 1.0) * keyframe_value(prev)
+                    + (t3 - 2.0 * t2 + t) * out_tangent
+                    + (-2.0 * t3 + 3.0 * t2) * keyframe_value(next)
+                    + (t3 - t2) * in_tangent
+            }
+        }
+    }
+}
+
+/// Keyframed animation of model nodes, applicable to every skeleton created from the same model.
+#[derive(Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<AnimationChannel>,
+}
+
+impl AnimationClip {
+    pub fn from_model(model: &Model, animation_idx: usize) -> Result<Self> {
+        let Some(animation) = model.animations.get(animation_idx) else {
+            bail!("Model does not contain animation {}.", animation_idx);
+        };
+
+        let mut channels = Vec::with_capacity(animation.channels.len());
+        for channel in &animation.channels {
+            let (property, num_components) = match channel.property {
+                AnimationProperty::Translation => (AnimationChannelProperty::Translation, 3),
+                AnimationProperty::Rotation => (AnimationChannelProperty::Rotation, 4),
+                AnimationProperty::Scale => (AnimationChannelProperty::Scale, 3),
+                // Morph target weights are not driven through skeletons
+                _ => continue,
+            };
+            let interpolation = match channel.interpolation {
+                AnimationInterpolation::Step => AnimationChannelInterpolation::Step,
+                AnimationInterpolation::Linear => AnimationChannelInterpolation::Linear,
+                AnimationInterpolation::CubicSpline => AnimationChannelInterpolation::CubicSpline,
+            };
+
+            let values: Vec<Vec4> = channel
+                .values
+                .chunks_exact(num_components)
+                .map(|value| {
+                    if num_components == 4 {
+                        Vec4::from_slice(value)
+                    } else {
+                        Vec3::from_slice(value).extend(0.0)
+                    }
+                })
+                .collect();
+            let values_per_keyframe = match interpolation {
+                AnimationChannelInterpolation::CubicSpline => 3,
+                _ => 1,
+            };
+            if channel.timestamps.is_empty()
+                || values.len() != channel.timestamps.len() * values_per_keyframe
+            {
+                bail!(
+                    "Animation {} has a channel with {} keyframes but {} values.",
+                    animation_idx,
+                    channel.timestamps.len(),
+                    values.len()
+                );
+            }
+
+            channels.push(AnimationChannel {
+                node_idx: channel.node_idx,
+                property,
+                interpolation,
+                timestamps: channel.timestamps.clone(),
+                values,
+            });
+        }
+
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.timestamps.last())
+            .fold(0.0f32, |duration, timestamp| duration.max(*timestamp));
+
+        Ok(Self {
+            name: animation.name.clone(),
+            duration,
+            channels,
+        })
+    }
+
+    /// Overwrite the joints of `pose` animated by this clip at `time` seconds, other joints are left untouched.
+    pub fn sample(&self, time: f32, skeleton: &Skeleton, pose: &mut [JointPose]) {
+        for channel in &self.channels {
+            let Some(joint_idx) = skeleton.joint_idx(channel.node_idx) else {
+                continue;
+            };
+            let joint_pose = &mut pose[joint_idx as usize];
+
+            let value = channel.sample(time);
+            match channel.property {
+                AnimationChannelProperty::Translation => joint_pose.translation = value.truncate(),
+                AnimationChannelProperty::Rotation => {
+                    joint_pose.rotation = Quat::from_vec4(value).normalize()
+                }
+                AnimationChannelProperty::Scale => joint_pose.scale = value.truncate(),
+            }
+        }
+    }
+}
@@ -0,0 +1,682 @@
+use glam::{Mat4, Vec3};
+use ugm::mesh::Mesh;
+
+const MAX_LEAF_TRIANGLES: usize = 4;
+const SAH_BINS: usize = 8;
+
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self {
+        min: Vec3::INFINITY,
+        max: Vec3::NEG_INFINITY,
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_triangle(triangle: &[Vec3; 3]) -> Self {
+        Self {
+            min: triangle[0].min(triangle[1]).min(triangle[2]),
+            max: triangle[0].max(triangle[1]).max(triangle[2]),
+        }
+    }
+
+    pub fn grow(&mut self, point: Vec3) {
+        self.min = self.min.min(point);
+        self.max = self.max.max(point);
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Half of the surface area, sufficient for comparing split costs.
+    fn half_area(&self) -> f32 {
+        let extent = self.extent().max(Vec3::ZERO);
+        extent.x * extent.y + extent.y * extent.z + extent.z * extent.x
+    }
+
+    pub fn expanded(&self, amount: f32) -> Self {
+        Self {
+            min: self.min - Vec3::splat(amount),
+            max: self.max + Vec3::splat(amount),
+        }
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// Bounds of this box after transforming it by `transform`.
+    pub fn transformed(&self, transform: Mat4) -> Self {
+        let center = transform.transform_point3(self.center());
+        let half_extent = self.extent() * 0.5;
+        let world_half_extent = transform.x_axis.truncate().abs() * half_extent.x
+            + transform.y_axis.truncate().abs() * half_extent.y
+            + transform.z_axis.truncate().abs() * half_extent.z;
+        Self {
+            min: center - world_half_extent,
+            max: center + world_half_extent,
+        }
+    }
+
+    /// Distance along the ray at which it enters the box, zero when it starts inside.
+    pub fn ray_intersection(
+        &self,
+        origin: Vec3,
+        inv_direction: Vec3,
+        max_distance: f32,
+    ) -> Option<f32> {
+        let t0 = (self.min - origin) * inv_direction;
+        let t1 = (self.max - origin) * inv_direction;
+        let t_min = t0.min(t1).max_element().max(0.0);
+        let t_max = t0.max(t1).min_element().min(max_distance);
+        (t_min <= t_max).then_some(t_min)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    /// Index of the left child for interior nodes, the right child directly follows it. Index of the first triangle for leaves.
+    first: u32,
+    /// Zero for interior nodes.
+    count: u32,
+}
+
+/// Closest intersection of a ray with a `MeshBvh`, in the space of the mesh.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshRayHit {
+    pub distance: f32,
+    /// Index of the triangle in the index buffer the bvh was built from.
+    pub triangle_idx: u32,
+    /// Geometric normal, not normalized and not necessarily facing the ray.
+    pub normal: Vec3,
+}
+
+/// Receives the nodes and triangles of a `MeshBvh` during `MeshBvh::traverse`.
+pub(crate) trait BvhVisitor {
+    /// Whether the children or triangles of a node with `bounds` should be visited.
+    fn visit_node(&mut self, bounds: &Aabb) -> bool;
+
+    fn visit_triangle(&mut self, triangle_idx: u32, triangle: &[Vec3; 3]);
+}
+
+/// Bounding volume hierarchy over the triangles of a single mesh, kept on the cpu for gameplay queries.
+/// Does not depend on the gpu, construct it through `from_mesh` when no `GpuResources` are available.
+#[derive(Debug, Clone)]
+pub struct MeshBvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<[Vec3; 3]>,
+    triangle_indices: Vec<u32>,
+}
+
+impl MeshBvh {
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        let positions: Vec<Vec3> = mesh
+            .packed_vertices
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position))
+            .collect();
+        Self::new(&positions, &mesh.indices)
+    }
+
+    /// Build a bvh using binned surface area heuristic splits.
+    pub fn new(positions: &[Vec3], indices: &[u32]) -> Self {
+        let triangles: Vec<[Vec3; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                [
+                    positions[triangle[0] as usize],
+                    positions[triangle[1] as usize],
+                    positions[triangle[2] as usize],
+                ]
+            })
+            .collect();
+        if triangles.is_empty() {
+            return Self {
+                nodes: Vec::new(),
+                triangles,
+                triangle_indices: Vec::new(),
+            };
+        }
+
+        let triangle_bounds: Vec<Aabb> = triangles.iter().map(Aabb::from_triangle).collect();
+        let mut triangle_indices: Vec<u32> = (0..triangles.len() as u32).collect();
+
+        let mut nodes = vec![BvhNode {
+            bounds: Aabb::EMPTY,
+            first: 0,
+            count: triangles.len() as u32,
+        }];
+        let mut stack = vec![0];
+        while let Some(node_idx) = stack.pop() {
+            let first = nodes[node_idx].first as usize;
+            let count = nodes[node_idx].count as usize;
+            let node_triangles = &mut triangle_indices[first..first + count];
+
+            let mut bounds = Aabb::EMPTY;
+            let mut centroid_bounds = Aabb::EMPTY;
+            for triangle_idx in node_triangles.iter() {
+                bounds = bounds.union(&triangle_bounds[*triangle_idx as usize]);
+                centroid_bounds.grow(triangle_bounds[*triangle_idx as usize].center());
+            }
+            nodes[node_idx].bounds = bounds;
+
+            if count <= MAX_LEAF_TRIANGLES {
+                continue;
+            }
+
+            let centroid_extent = centroid_bounds.extent();
+            let axis = if centroid_extent.x >= centroid_extent.y
+                && centroid_extent.x >= centroid_extent.z
+            {
+                0
+            } else if centroid_extent.y >= centroid_extent.z {
+                1
+            } else {
+                2
+            };
+            let axis_min = centroid_bounds.min[axis];
+            let axis_extent = centroid_extent[axis];
+
+            let bin = |triangle_idx: u32| {
+                if axis_extent <= 0.0 {
+                    return 0;
+                }
+                let centroid = triangle_bounds[triangle_idx as usize].center()[axis];
+                (((centroid - axis_min) / axis_extent * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+            };
+
+            let mut bins = [(Aabb::EMPTY, 0usize); SAH_BINS];
+            for triangle_idx in node_triangles.iter() {
+                let bin = &mut bins[bin(*triangle_idx)];
+                bin.0 = bin.0.union(&triangle_bounds[*triangle_idx as usize]);
+                bin.1 += 1;
+            }
+
+            let mut best_split = 0;
+            let mut best_cost = f32::INFINITY;
+            for split in 1..SAH_BINS {
+                let (left_bounds, left_count) = bins[..split]
+                    .iter()
+                    .fold((Aabb::EMPTY, 0), |(bounds, count), bin| {
+                        (bounds.union(&bin.0), count + bin.1)
+                    });
+                let (right_bounds, right_count) = bins[split..]
+                    .iter()
+                    .fold((Aabb::EMPTY, 0), |(bounds, count), bin| {
+                        (bounds.union(&bin.0), count + bin.1)
+                    });
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+
+                let cost = left_bounds.half_area() * left_count as f32
+                    + right_bounds.half_area() * right_count as f32;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_split = split;
+                }
+            }
+
+            // Triangles sharing a centroid cannot be binned apart, split them in half instead
+            let mid = if best_split == 0 {
+                count / 2
+            } else {
+                let mut mid = 0;
+                for i in 0..count {
+                    if bin(node_triangles[i]) < best_split {
+                        node_triangles.swap(i, mid);
+                        mid += 1;
+                    }
+                }
+                mid
+            };
+
+            let left_idx = nodes.len();
+            nodes.push(BvhNode {
+                bounds: Aabb::EMPTY,
+                first: first as u32,
+                count: mid as u32,
+            });
+            nodes.push(BvhNode {
+                bounds: Aabb::EMPTY,
+                first: (first + mid) as u32,
+                count: (count - mid) as u32,
+            });
+            nodes[node_idx].first = left_idx as u32;
+            nodes[node_idx].count = 0;
+            stack.push(left_idx);
+            stack.push(left_idx + 1);
+        }
+
+        let triangles = triangle_indices
+            .iter()
+            .map(|triangle_idx| triangles[*triangle_idx as usize])
+            .collect();
+
+        Self {
+            nodes,
+            triangles,
+            triangle_indices,
+        }
+    }
+
+    /// Bounds of all triangles, `None` for empty meshes.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub(crate) fn traverse(&self, visitor: &mut impl BvhVisitor) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_idx) = stack.pop() {
+            let node = &self.nodes[node_idx];
+            if !visitor.visit_node(&node.bounds) {
+                continue;
+            }
+
+            if node.count == 0 {
+                stack.push(node.first as usize);
+                stack.push(node.first as usize + 1);
+            } else {
+                let first = node.first as usize;
+                for i in first..first + node.count as usize {
+                    visitor.visit_triangle(self.triangle_indices[i], &self.triangles[i]);
+                }
+            }
+        }
+    }
+
+    /// Closest double sided triangle hit along a ray in the space of the mesh.
+    /// `direction` does not have to be normalized, distances are expressed in multiples of it.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<MeshRayHit> {
+        struct RaycastVisitor {
+            origin: Vec3,
+            direction: Vec3,
+            inv_direction: Vec3,
+            closest: Option<MeshRayHit>,
+            max_distance: f32,
+        }
+
+        impl BvhVisitor for RaycastVisitor {
+            fn visit_node(&mut self, bounds: &Aabb) -> bool {
+                bounds
+                    .ray_intersection(self.origin, self.inv_direction, self.max_distance)
+                    .is_some()
+            }
+
+            fn visit_triangle(&mut self, triangle_idx: u32, triangle: &[Vec3; 3]) {
+                if let Some(distance) =
+                    ray_triangle_intersection(self.origin, self.direction, triangle)
+                {
+                    if distance <= self.max_distance {
+                        self.max_distance = distance;
+                        self.closest = Some(MeshRayHit {
+                            distance,
+                            triangle_idx,
+                            normal: (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]),
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut visitor = RaycastVisitor {
+            origin,
+            direction,
+            inv_direction: direction.recip(),
+            closest: None,
+            max_distance,
+        };
+        self.traverse(&mut visitor);
+        visitor.closest
+    }
+}
+
+/// Möller-Trumbore intersection, double sided.
+pub(crate) fn ray_triangle_intersection(
+    origin: Vec3,
+    direction: Vec3,
+    triangle: &[Vec3; 3],
+) -> Option<f32> {
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inv_determinant = 1.0 / determinant;
+    let s = origin - triangle[0];
+    let u = s.dot(p) * inv_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inv_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q) * inv_determinant;
+    (t >= 0.0).then_some(t)
+}
+
+fn ray_sphere_intersection(
+    origin: Vec3,
+    direction: Vec3,
+    center: Vec3,
+    radius: f32,
+) -> Option<f32> {
+    let m = origin - center;
+    let b = m.dot(direction);
+    let c = m.dot(m) - radius * radius;
+    if c > 0.0 && b > 0.0 {
+        return None;
+    }
+
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    Some((-b - discriminant.sqrt()).max(0.0))
+}
+
+/// Intersection with the infinite cylinder around an edge, only accepted within the extent of the edge.
+fn ray_edge_cylinder_intersection(
+    origin: Vec3,
+    direction: Vec3,
+    a: Vec3,
+    b: Vec3,
+    radius: f32,
+) -> Option<f32> {
+    let ab = b - a;
+    let ao = origin - a;
+    let ab_ab = ab.dot(ab);
+    let ab_direction = ab.dot(direction);
+    let ab_ao = ab.dot(ao);
+
+    let qa = ab_ab - ab_direction * ab_direction;
+    let qb = ab_ab * ao.dot(direction) - ab_ao * ab_direction;
+    let qc = ab_ab * ao.dot(ao) - ab_ao * ab_ao - radius * radius * ab_ab;
+    // Parallel rays can only hit the edge at its end points, which are tested as spheres
+    if qa.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let discriminant = qb * qb - qa * qc;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = ((-qb - discriminant.sqrt()) / qa).max(0.0);
+    let s = (ab_ao + t * ab_direction) / ab_ab;
+    (0.0..=1.0).contains(&s).then_some(t)
+}
+
+/// Whether a point on the plane of a triangle lies within it, `normal` has to follow the winding of the triangle.
+fn point_in_triangle(point: Vec3, triangle: &[Vec3; 3], normal: Vec3) -> bool {
+    (0..3).all(|i| {
+        let a = triangle[i];
+        let b = triangle[(i + 1) % 3];
+        (b - a).cross(point - a).dot(normal) >= 0.0
+    })
+}
+
+/// Distance a sphere moves along normalized `direction` before touching a triangle, with the contact normal pointing away from the triangle.
+pub(crate) fn sphere_triangle_sweep(
+    center: Vec3,
+    direction: Vec3,
+    radius: f32,
+    triangle: &[Vec3; 3],
+) -> Option<(f32, Vec3)> {
+    let face_normal = (triangle[1] - triangle[0])
+        .cross(triangle[2] - triangle[0])
+        .normalize_or_zero();
+    if face_normal == Vec3::ZERO {
+        return None;
+    }
+
+    let mut normal = face_normal;
+    let mut plane_distance = (center - triangle[0]).dot(normal);
+    if plane_distance < 0.0 {
+        normal = -normal;
+        plane_distance = -plane_distance;
+    }
+
+    // Touching the interior of the triangle is always the earliest contact
+    if plane_distance <= radius {
+        if point_in_triangle(center - normal * plane_distance, triangle, face_normal) {
+            return Some((0.0, normal));
+        }
+    } else {
+        let approach = -direction.dot(normal);
+        if approach > 0.0 {
+            let t = (plane_distance - radius) / approach;
+            let contact = center + direction * t - normal * radius;
+            if point_in_triangle(contact, triangle, face_normal) {
+                return Some((t, normal));
+            }
+        }
+    }
+
+    let mut closest: Option<f32> = None;
+    for i in 0..3 {
+        let a = triangle[i];
+        let b = triangle[(i + 1) % 3];
+        for t in [
+            ray_sphere_intersection(center, direction, a, radius),
+            ray_edge_cylinder_intersection(center, direction, a, b, radius),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            closest = Some(closest.map_or(t, |closest| closest.min(t)));
+        }
+    }
+
+    closest.map(|t| {
+        let swept_center = center + direction * t;
+        let contact = closest_point_on_triangle(swept_center, triangle);
+        (t, (swept_center - contact).normalize_or(normal))
+    })
+}
+
+fn closest_point_on_triangle(point: Vec3, triangle: &[Vec3; 3]) -> Vec3 {
+    let [a, b, c] = *triangle;
+    let ab = b - a;
+    let ac = c - a;
+    let ap = point - a;
+
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = point - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = point - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+/// Separating axis test between a triangle and a box.
+pub(crate) fn triangle_aabb_overlap(triangle: &[Vec3; 3], aabb: &Aabb) -> bool {
+    let center = aabb.center();
+    let half_extent = aabb.extent() * 0.5;
+    let v = triangle.map(|vertex| vertex - center);
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+
+    let separated = |axis: Vec3| {
+        let p = v.map(|vertex| vertex.dot(axis));
+        let r = half_extent.dot(axis.abs());
+        p[0].min(p[1]).min(p[2]) > r || p[0].max(p[1]).max(p[2]) < -r
+    };
+
+    for edge in edges {
+        for box_axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            let axis = box_axis.cross(edge);
+            if axis != Vec3::ZERO && separated(axis) {
+                return false;
+            }
+        }
+    }
+
+    let triangle_bounds = Aabb::from_triangle(triangle);
+    if !triangle_bounds.overlaps(aabb) {
+        return false;
+    }
+
+    !separated(edges[0].cross(edges[1]))
+}
+
+/// Unit quads on the xy plane at `z`, `resolution` by `resolution` of them starting at the origin.
+#[cfg(test)]
+fn grid_mesh(resolution: u32, z: f32) -> (Vec<Vec3>, Vec<u32>) {
+    let mut positions = Vec::new();
+    for y in 0..=resolution {
+        for x in 0..=resolution {
+            positions.push(Vec3::new(x as f32, y as f32, z));
+        }
+    }
+
+    let mut indices = Vec::new();
+    for y in 0..resolution {
+        for x in 0..resolution {
+            let i = y * (resolution + 1) + x;
+            indices.extend([i, i + 1, i + resolution + 1]);
+            indices.extend([i + 1, i + resolution + 2, i + resolution + 1]);
+        }
+    }
+    (positions, indices)
+}
+
+#[test]
+fn mesh_bvh_raycast() {
+    let (positions, indices) = grid_mesh(8, 0.0);
+    let bvh = MeshBvh::new(&positions, &indices);
+    assert_eq!(bvh.num_triangles(), 128);
+    assert_eq!(
+        bvh.bounds(),
+        Some(Aabb::new(Vec3::ZERO, Vec3::new(8.0, 8.0, 0.0)))
+    );
+
+    let hit = bvh
+        .raycast(Vec3::new(2.75, 5.75, 4.0), Vec3::NEG_Z, 100.0)
+        .unwrap();
+    assert!((hit.distance - 4.0).abs() < 1e-5);
+    // Second triangle of the quad at (2, 5)
+    assert_eq!(hit.triangle_idx, (5 * 8 + 2) * 2 + 1);
+    assert!(hit.normal.normalize().abs_diff_eq(Vec3::Z, 1e-5));
+
+    // Double sided, and distances are in multiples of the direction
+    let hit = bvh
+        .raycast(Vec3::new(2.75, 5.75, -4.0), Vec3::new(0.0, 0.0, 2.0), 100.0)
+        .unwrap();
+    assert!((hit.distance - 2.0).abs() < 1e-5);
+
+    assert!(bvh
+        .raycast(Vec3::new(2.75, 5.75, 4.0), Vec3::NEG_Z, 3.0)
+        .is_none());
+    assert!(bvh
+        .raycast(Vec3::new(9.0, 5.0, 4.0), Vec3::NEG_Z, 100.0)
+        .is_none());
+    assert!(bvh
+        .raycast(Vec3::new(2.75, 5.75, 4.0), Vec3::Z, 100.0)
+        .is_none());
+    assert!(MeshBvh::new(&[], &[])
+        .raycast(Vec3::ZERO, Vec3::X, 100.0)
+        .is_none());
+}
+
+#[test]
+fn mesh_bvh_raycast_returns_closest_hit() {
+    let (mut positions, mut indices) = grid_mesh(4, 0.0);
+    let (far_positions, far_indices) = grid_mesh(4, -3.0);
+    let (near_positions, near_indices) = grid_mesh(4, 2.0);
+    for (layer_positions, layer_indices) in
+        [(far_positions, far_indices), (near_positions, near_indices)]
+    {
+        let first_vertex = positions.len() as u32;
+        positions.extend(layer_positions);
+        indices.extend(layer_indices.iter().map(|index| index + first_vertex));
+    }
+    let bvh = MeshBvh::new(&positions, &indices);
+
+    let origin = Vec3::new(1.5, 2.5, 10.0);
+    let hit = bvh.raycast(origin, Vec3::NEG_Z, 100.0).unwrap();
+    assert!((hit.distance - 8.0).abs() < 1e-5);
+    assert!(hit.triangle_idx >= 64);
+
+    let hit = bvh
+        .raycast(Vec3::new(1.5, 2.5, -10.0), Vec3::Z, 100.0)
+        .unwrap();
+    assert!((hit.distance - 7.0).abs() < 1e-5);
+    assert!((32..64).contains(&hit.triangle_idx));
+
+    // Every triangle is reachable through the hierarchy
+    for y in 0..4 {
+        for x in 0..4 {
+            let origin = Vec3::new(x as f32 + 0.75, y as f32 + 0.75, 1.0);
+            let hit = bvh.raycast(origin, Vec3::NEG_Z, 100.0).unwrap();
+            assert!((hit.distance - 1.0).abs() < 1e-5);
+            assert_eq!(hit.triangle_idx, (y * 4 + x) * 2 + 1);
+        }
+    }
+}
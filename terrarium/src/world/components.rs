@@ -1,10 +1,23 @@
-use std::sync::{Arc, Mutex};
-
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{bail, Result};
 use glam::{Mat4, Quat, Vec3};
+use specs::storage::GenericReadStorage;
 
 use crate::gpu_resources::{GpuMaterial, GpuMesh};
 
-use super::transform::Transform;
+use super::{
+    animation::{AnimationClip, Skeleton},
+    bvh::MeshBvh,
+    transform::Transform,
+};
 
 #[derive(Default)]
 pub struct DynamicComponent;
@@ -15,9 +28,10 @@ impl specs::Component for DynamicComponent {
 pub struct TransformComponent {
     global_transform: Mutex<(Mat4, bool)>,
     local_transform: Transform,
+    local_changed: AtomicBool,
     is_static: bool,
-    pub parent: Option<specs::Entity>,
-    pub children: Vec<specs::Entity>,
+    parent: Option<specs::Entity>,
+    children: Vec<specs::Entity>,
 }
 
 impl TransformComponent {
@@ -27,6 +41,7 @@ impl TransformComponent {
         Self {
             global_transform,
             local_transform,
+            local_changed: AtomicBool::new(true),
             is_static,
             parent: None,
             children: Vec::new(),
@@ -37,16 +52,28 @@ impl TransformComponent {
         self.is_static
     }
 
-    pub fn get_translation(&self, transforms: &specs::ReadStorage<'_, TransformComponent>) -> Vec3 {
-        self.resolve_global_transform(transforms);
+    pub fn parent(&self) -> Option<specs::Entity> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[specs::Entity] {
+        &self.children
+    }
 
+    pub fn get_translation<S>(&self, transforms: &S) -> Vec3
+    where
+        S: GenericReadStorage<Component = TransformComponent>,
+    {
         let (_scale, _rotation, translation) = self
             .resolve_global_transform(transforms)
             .to_scale_rotation_translation();
         translation
     }
 
-    pub fn get_rotation(&self, transforms: &specs::ReadStorage<'_, TransformComponent>) -> Quat {
+    pub fn get_rotation<S>(&self, transforms: &S) -> Quat
+    where
+        S: GenericReadStorage<Component = TransformComponent>,
+    {
         let (_scale, rotation, _translation) = self
             .resolve_global_transform(transforms)
             .to_scale_rotation_translation();
@@ -59,10 +86,12 @@ impl TransformComponent {
 
     pub fn set_local_translation(&mut self, translation: Vec3) {
         self.local_transform.set_translation(translation);
+        self.mark_local_changed();
     }
 
     pub fn translate_local(&mut self, translation: Vec3) {
         self.local_transform.translate(translation);
+        self.mark_local_changed();
     }
 
     pub fn get_local_rotation(&self) -> Quat {
@@ -71,10 +100,12 @@ impl TransformComponent {
 
     pub fn set_local_rotation(&mut self, rotation: Quat) {
         self.local_transform.set_rotation(rotation);
+        self.mark_local_changed();
     }
 
     pub fn rotate_local(&mut self, rotation: Quat) {
         self.local_transform.rotate(rotation);
+        self.mark_local_changed();
     }
 
     pub fn get_local_scale(&self) -> Vec3 {
@@ -83,12 +114,13 @@ impl TransformComponent {
 
     pub fn set_local_scale(&mut self, scale: Vec3) {
         self.local_transform.set_scale(scale);
+        self.mark_local_changed();
     }
 
-    pub fn get_local_to_world_matrix(
-        &self,
-        transforms: &specs::ReadStorage<'_, TransformComponent>,
-    ) -> Mat4 {
+    pub fn get_local_to_world_matrix<S>(&self, transforms: &S) -> Mat4
+    where
+        S: GenericReadStorage<Component = TransformComponent>,
+    {
         self.resolve_global_transform(transforms)
     }
 
@@ -96,38 +128,203 @@ impl TransformComponent {
         self.local_transform.get_matrix()
     }
 
-    fn resolve_global_transform(
-        &self,
-        transforms: &specs::ReadStorage<'_, TransformComponent>,
-    ) -> Mat4 {
-        if self.parent.is_none() {
-            return self.local_transform.get_matrix();
-        }
+    /// Set the world space translation of `entity`, converting it into its parent's space.
+    pub fn set_world_translation(
+        transforms: &mut specs::WriteStorage<'_, TransformComponent>,
+        entity: specs::Entity,
+        translation: Vec3,
+    ) -> Result<()> {
+        let parent_to_world = Self::parent_to_world_matrix(&*transforms, entity)?;
+        let transform_component = transforms.get_mut(entity).unwrap();
+
+        transform_component
+            .set_local_translation(parent_to_world.inverse().transform_point3(translation));
+        Ok(())
+    }
 
-        let mut matrix = self.global_transform.lock().unwrap();
+    /// Set the world space rotation of `entity`, converting it into its parent's space.
+    pub fn set_world_rotation(
+        transforms: &mut specs::WriteStorage<'_, TransformComponent>,
+        entity: specs::Entity,
+        rotation: Quat,
+    ) -> Result<()> {
+        let parent_to_world = Self::parent_to_world_matrix(&*transforms, entity)?;
+        let (_scale, parent_rotation, _translation) =
+            parent_to_world.to_scale_rotation_translation();
+        let transform_component = transforms.get_mut(entity).unwrap();
+
+        transform_component.set_local_rotation(parent_rotation.inverse() * rotation);
+        Ok(())
+    }
 
-        if matrix.1 {
-            let mut global_transform = self.local_transform.get_matrix();
+    /// Make `child` a child of `parent`, detaching it from its current parent first.
+    /// The local transform of `child` is kept, meaning it moves along with its new parent.
+    pub fn attach_child(
+        transforms: &mut specs::WriteStorage<'_, TransformComponent>,
+        parent: specs::Entity,
+        child: specs::Entity,
+    ) -> Result<()> {
+        Self::reparent(transforms, child, Some(parent), false)
+    }
 
-            let mut optional_parent = self.parent;
-            while let Some(parent) = optional_parent {
-                let parent_transform = transforms.get(parent).unwrap();
+    /// Turn `child` into a root, keeping its local transform.
+    pub fn detach(
+        transforms: &mut specs::WriteStorage<'_, TransformComponent>,
+        child: specs::Entity,
+    ) -> Result<()> {
+        Self::reparent(transforms, child, None, false)
+    }
 
-                let parent_local_transform = parent_transform.local_transform.get_matrix();
-                global_transform = parent_local_transform * global_transform;
+    /// Move `child` to `new_parent`, or make it a root when `None`, keeping both sides of the hierarchy consistent.
+    /// When `keep_world_transform` is set the local transform of `child` is adjusted so that it doesn't move in world space.
+    /// Fails without modifying anything when this would introduce a cycle.
+    pub fn reparent(
+        transforms: &mut specs::WriteStorage<'_, TransformComponent>,
+        child: specs::Entity,
+        new_parent: Option<specs::Entity>,
+        keep_world_transform: bool,
+    ) -> Result<()> {
+        let Some(child_component) = transforms.get(child) else {
+            bail!("Entity {:?} has no TransformComponent.", child);
+        };
+        let old_parent = child_component.parent;
+        let local_to_world = child_component.resolve_global_transform(&*transforms);
+
+        let parent_to_world = if let Some(new_parent) = new_parent {
+            if Self::is_ancestor_or_self(&*transforms, child, new_parent)? {
+                bail!(
+                    "Attaching {:?} to {:?} would create a cycle in the transform hierarchy.",
+                    child,
+                    new_parent
+                );
+            }
+            transforms
+                .get(new_parent)
+                .unwrap()
+                .resolve_global_transform(&*transforms)
+        } else {
+            Mat4::IDENTITY
+        };
+
+        if let Some(old_parent) = old_parent {
+            if let Some(old_parent_component) = transforms.get_mut(old_parent) {
+                old_parent_component
+                    .children
+                    .retain(|entity| *entity != child);
+            }
+        }
+        if let Some(new_parent) = new_parent {
+            transforms.get_mut(new_parent).unwrap().children.push(child);
+        }
+
+        let child_component = transforms.get_mut(child).unwrap();
+        child_component.parent = new_parent;
+        if keep_world_transform {
+            child_component
+                .local_transform
+                .set_matrix(parent_to_world.inverse() * local_to_world);
+        }
+        child_component.mark_local_changed();
 
-                optional_parent = parent_transform.parent;
+        transforms.get(child).unwrap().mark_dirty(&*transforms);
+        Ok(())
+    }
+
+    fn parent_to_world_matrix<S>(transforms: &S, entity: specs::Entity) -> Result<Mat4>
+    where
+        S: GenericReadStorage<Component = TransformComponent>,
+    {
+        let Some(transform_component) = transforms.get(entity) else {
+            bail!("Entity {:?} has no TransformComponent.", entity);
+        };
+
+        Ok(transform_component
+            .parent
+            .map(|parent| {
+                transforms
+                    .get(parent)
+                    .unwrap()
+                    .resolve_global_transform(transforms)
+            })
+            .unwrap_or(Mat4::IDENTITY))
+    }
+
+    /// Whether `ancestor` is `entity` or one of its parents, errors out on hierarchies that already contain a cycle.
+    fn is_ancestor_or_self<S>(
+        transforms: &S,
+        ancestor: specs::Entity,
+        entity: specs::Entity,
+    ) -> Result<bool>
+    where
+        S: GenericReadStorage<Component = TransformComponent>,
+    {
+        let mut visited = HashSet::new();
+
+        let mut optional_entity = Some(entity);
+        while let Some(current) = optional_entity {
+            if current == ancestor {
+                return Ok(true);
+            }
+            if !visited.insert(current) {
+                bail!(
+                    "Transform hierarchy of {:?} contains a cycle through {:?}.",
+                    entity,
+                    current
+                );
             }
 
-            matrix.0 = global_transform;
+            let Some(transform_component) = transforms.get(current) else {
+                bail!("Entity {:?} has no TransformComponent.", current);
+            };
+            optional_entity = transform_component.parent;
+        }
+
+        Ok(false)
+    }
+
+    fn mark_local_changed(&mut self) {
+        self.global_transform.get_mut().unwrap().1 = true;
+        *self.local_changed.get_mut() = true;
+    }
+
+    /// Consume the local change flag set by any of the local setters, used by `TransformPropagationSystem`.
+    pub(crate) fn take_local_changed(&self) -> bool {
+        self.local_changed.swap(false, Ordering::Relaxed)
+    }
+
+    pub(crate) fn mark_global_dirty(&self) {
+        self.global_transform.lock().unwrap().1 = true;
+    }
+
+    fn resolve_global_transform<S>(&self, transforms: &S) -> Mat4
+    where
+        S: GenericReadStorage<Component = TransformComponent>,
+    {
+        let Some(parent) = self.parent else {
+            return self.local_transform.get_matrix();
+        };
+
+        let mut matrix = self.global_transform.lock().unwrap();
+
+        if matrix.1 {
+            let parent_transform = transforms.get(parent).unwrap();
+
+            // Parents cache their own global transform, so only dirty links of the chain are recalculated
+            matrix.0 = parent_transform.resolve_global_transform(transforms)
+                * self.local_transform.get_matrix();
             matrix.1 = false;
         }
 
         matrix.0
     }
 
-    pub fn mark_dirty(&self, transforms: &specs::ReadStorage<'_, TransformComponent>) {
-        self.global_transform.lock().unwrap().1 = true;
+    /// Mark the global transform of this entity and all of its descendants dirty immediately,
+    /// rather than waiting for the next run of `TransformPropagationSystem`.
+    pub fn mark_dirty<S>(&self, transforms: &S)
+    where
+        S: GenericReadStorage<Component = TransformComponent>,
+    {
+        self.mark_global_dirty();
 
         for child in &self.children {
             let child_transform = transforms.get(*child).unwrap();
@@ -140,11 +337,21 @@ impl specs::Component for TransformComponent {
     type Storage = specs::VecStorage<Self>;
 }
 
+/// Model file and mesh a `MeshComponent` was created from, required to serialize it as part of a scene.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeshSource {
+    pub model_path: PathBuf,
+    pub mesh_idx: u32,
+    /// Indices into the materials of the model, one for each material used by the mesh.
+    pub material_indices: Vec<u32>,
+}
+
 #[derive(Debug)]
 pub struct MeshComponent {
     pub enabled: bool,
     pub mesh: Arc<GpuMesh>,
     pub materials: Vec<Arc<GpuMaterial>>,
+    pub source: Option<MeshSource>,
 }
 
 impl MeshComponent {
@@ -153,14 +360,269 @@ impl MeshComponent {
             enabled: true,
             mesh,
             materials,
+            source: None,
         }
     }
+
+    pub fn with_source(mut self, source: MeshSource) -> Self {
+        self.source = Some(source);
+        self
+    }
 }
 
 impl specs::Component for MeshComponent {
     type Storage = specs::VecStorage<Self>;
 }
 
+/// Triangles of a mesh kept on the cpu for `world::queries`, used instead of the `GpuMesh::cpu_bvh` of a `MeshComponent` on the same entity.
+/// Allows querying entities without a `MeshComponent` or any gpu resources. Queries skip the entity while a `MeshComponent` on it is disabled.
+#[derive(Debug, Clone)]
+pub struct MeshBvhComponent {
+    pub bvh: Arc<MeshBvh>,
+}
+
+impl MeshBvhComponent {
+    pub fn new(bvh: Arc<MeshBvh>) -> Self {
+        Self { bvh }
+    }
+}
+
+impl specs::Component for MeshBvhComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// Limits the camera distances at which the dynamic `MeshComponent` of the same entity is submitted.
+/// Distances are measured to the bounds of the mesh, overlapping ranges on multiple entities allow for simple level of detail.
+#[derive(Debug, Clone, Copy)]
+pub struct VisibilityRangeComponent {
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl VisibilityRangeComponent {
+    pub fn new(min_distance: f32, max_distance: f32) -> Self {
+        Self {
+            min_distance,
+            max_distance,
+        }
+    }
+
+    pub fn contains(&self, distance: f32) -> bool {
+        (self.min_distance..=self.max_distance).contains(&distance)
+    }
+}
+
+impl specs::Component for VisibilityRangeComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LodMetric {
+    /// Camera distance to the bounds of the finest mesh, thresholds are ascending.
+    Distance,
+    /// Projected height of the bounds of the finest mesh relative to the height of the view, thresholds are descending.
+    ScreenSize,
+}
+
+/// Level that is dithered in or out during a transition, drawn next to the mesh of the `MeshComponent`.
+#[derive(Debug, Clone)]
+pub struct LodCrossfade {
+    pub mesh: Arc<GpuMesh>,
+    /// Dither threshold of `mesh`, positive values dither it out and negative values dither it in.
+    /// The mesh of the `MeshComponent` uses the negated threshold.
+    pub fade: f32,
+}
+
+/// Swaps the mesh of the `MeshComponent` of the same entity between levels of detail, selected every frame by `LodSystem`.
+/// All levels are required to share the materials of the `MeshComponent`.
+#[derive(Debug)]
+pub struct LodMeshComponent {
+    /// Levels of detail, ordered from finest to coarsest.
+    pub meshes: Vec<Arc<GpuMesh>>,
+    /// Level `i + 1` is selected once the metric passes `thresholds[i]`.
+    pub thresholds: Vec<f32>,
+    pub metric: LodMetric,
+    /// Width of the dithered transition around every threshold, relative to that threshold. Zero switches levels instantly.
+    pub crossfade: f32,
+    lod: usize,
+    active_crossfade: Option<LodCrossfade>,
+}
+
+impl LodMeshComponent {
+    pub fn new(meshes: Vec<Arc<GpuMesh>>, thresholds: Vec<f32>, metric: LodMetric) -> Self {
+        assert_eq!(
+            meshes.len(),
+            thresholds.len() + 1,
+            "Every level of detail except the finest requires a threshold!"
+        );
+
+        Self {
+            meshes,
+            thresholds,
+            metric,
+            crossfade: 0.0,
+            lod: 0,
+            active_crossfade: None,
+        }
+    }
+
+    pub fn with_crossfade(mut self, crossfade: f32) -> Self {
+        self.crossfade = crossfade;
+        self
+    }
+
+    /// Level selected during the last update.
+    pub fn lod(&self) -> usize {
+        self.lod
+    }
+
+    pub fn active_crossfade(&self) -> Option<&LodCrossfade> {
+        self.active_crossfade.as_ref()
+    }
+
+    /// Select the level for the current value of the metric.
+    pub(crate) fn select(&mut self, value: f32) {
+        let (lod, crossfade) = select_lod(&self.thresholds, self.metric, self.crossfade, value);
+        self.lod = lod;
+        self.active_crossfade = crossfade.map(|(lod, fade)| LodCrossfade {
+            mesh: self.meshes[lod].clone(),
+            fade,
+        });
+    }
+}
+
+/// Level for `value` of the metric, together with the other level of an active transition and its dither threshold.
+fn select_lod(
+    thresholds: &[f32],
+    metric: LodMetric,
+    crossfade: f32,
+    value: f32,
+) -> (usize, Option<(usize, f32)>) {
+    let passed = |threshold: f32| match metric {
+        LodMetric::Distance => value > threshold,
+        LodMetric::ScreenSize => value < threshold,
+    };
+    let lod = thresholds
+        .iter()
+        .filter(|threshold| passed(**threshold))
+        .count();
+
+    if crossfade > 0.0 {
+        for (i, threshold) in thresholds.iter().enumerate() {
+            let width = threshold * crossfade;
+            let progress = match metric {
+                LodMetric::Distance => (value - (threshold - width * 0.5)) / width,
+                LodMetric::ScreenSize => ((threshold + width * 0.5) - value) / width,
+            };
+
+            // Level i dithers out while level i + 1 dithers in
+            if progress > 0.0 && progress < 1.0 {
+                let crossfade = if lod <= i {
+                    (i + 1, -progress)
+                } else {
+                    (i, progress)
+                };
+                return (lod, Some(crossfade));
+            }
+        }
+    }
+
+    (lod, None)
+}
+
+impl specs::Component for LodMeshComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// Deforms the `MeshComponent` of the same entity on the gpu every frame, which must be created through `GpuResources::create_deformable_gpu_mesh`.
+/// Entities containing this component are required to be dynamic, as their blas is refit every frame.
+#[derive(Debug)]
+pub struct SkinnedMeshComponent {
+    pub skeleton: Arc<Skeleton>,
+    /// Mesh in bind pose including joint weights, the `MeshComponent` receives the skinned result.
+    pub bind_pose_mesh: Arc<GpuMesh>,
+    /// Skinning matrix of every joint, written by `AnimationSystem` when an `AnimationPlayerComponent` is attached.
+    /// Can also be written directly to pose the skeleton procedurally, for example from tracked hands.
+    pub joint_matrices: Vec<Mat4>,
+}
+
+impl SkinnedMeshComponent {
+    pub fn new(skeleton: Arc<Skeleton>, bind_pose_mesh: Arc<GpuMesh>) -> Self {
+        let mut joint_matrices = Vec::new();
+        skeleton.joint_matrices(skeleton.rest_pose(), &mut joint_matrices);
+
+        Self {
+            skeleton,
+            bind_pose_mesh,
+            joint_matrices,
+        }
+    }
+}
+
+impl specs::Component for SkinnedMeshComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// Plays an `AnimationClip` on the `SkinnedMeshComponent` of the same entity.
+#[derive(Debug)]
+pub struct AnimationPlayerComponent {
+    pub clip: Option<Arc<AnimationClip>>,
+    /// Playback position in seconds.
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    pub playing: bool,
+}
+
+impl AnimationPlayerComponent {
+    pub fn new(clip: Option<Arc<AnimationClip>>) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+            playing: true,
+        }
+    }
+
+    /// Start playing `clip` from the beginning.
+    pub fn play(&mut self, clip: Arc<AnimationClip>) {
+        self.clip = Some(clip);
+        self.time = 0.0;
+        self.playing = true;
+    }
+}
+
+impl specs::Component for AnimationPlayerComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// Blends the morph targets of `bind_pose_mesh` into the `MeshComponent` of the same entity, which must be created through `GpuResources::create_deformable_gpu_mesh`.
+/// Morph targets are applied before skinning when the entity also contains a `SkinnedMeshComponent` sharing the same bind pose mesh.
+/// Entities containing this component are required to be dynamic, as their blas is refit every frame.
+#[derive(Debug)]
+pub struct MorphWeightsComponent {
+    /// Mesh including morph target deltas, the `MeshComponent` receives the morphed result.
+    pub bind_pose_mesh: Arc<GpuMesh>,
+    /// Weight of every morph target, missing weights are treated as zero.
+    pub weights: Vec<f32>,
+}
+
+impl MorphWeightsComponent {
+    pub fn new(bind_pose_mesh: Arc<GpuMesh>) -> Self {
+        let weights = vec![0.0; bind_pose_mesh.num_morph_targets as usize];
+
+        Self {
+            bind_pose_mesh,
+            weights,
+        }
+    }
+}
+
+impl specs::Component for MorphWeightsComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
 #[derive(Debug)]
 pub struct AreaLightComponent {
     pub color: Vec3,
@@ -183,3 +645,144 @@ impl AreaLightComponent {
 impl specs::Component for AreaLightComponent {
     type Storage = specs::VecStorage<Self>;
 }
+
+/// Omnidirectional light at the origin of its transform, fading out smoothly towards `range`.
+#[derive(Debug)]
+pub struct PointLightComponent {
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+}
+
+impl PointLightComponent {
+    pub fn new(color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            color,
+            intensity,
+            range,
+        }
+    }
+}
+
+impl specs::Component for PointLightComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// Cone shaped light at the origin of its transform, pointing along its `FORWARD` axis.
+/// Angles are in radians, measured from the center of the cone. Falloff happens between `inner_angle` and `outer_angle`.
+#[derive(Debug)]
+pub struct SpotLightComponent {
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl SpotLightComponent {
+    pub fn new(
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            color,
+            intensity,
+            range,
+            inner_angle,
+            outer_angle,
+        }
+    }
+}
+
+impl specs::Component for SpotLightComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+/// Infinitely distant light shining along the `FORWARD` axis of its transform, in addition to the sun.
+#[derive(Debug)]
+pub struct DirectionalLightComponent {
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl DirectionalLightComponent {
+    pub fn new(color: Vec3, intensity: f32) -> Self {
+        Self { color, intensity }
+    }
+}
+
+impl specs::Component for DirectionalLightComponent {
+    type Storage = specs::VecStorage<Self>;
+}
+
+#[test]
+fn select_lod_by_distance() {
+    let thresholds = [10.0, 20.0];
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::Distance, 0.0, 5.0),
+        (0, None)
+    );
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::Distance, 0.0, 10.0),
+        (0, None)
+    );
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::Distance, 0.0, 15.0),
+        (1, None)
+    );
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::Distance, 0.0, 25.0),
+        (2, None)
+    );
+}
+
+#[test]
+fn select_lod_by_screen_size() {
+    let thresholds = [0.5, 0.1];
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::ScreenSize, 0.0, 0.8),
+        (0, None)
+    );
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::ScreenSize, 0.0, 0.3),
+        (1, None)
+    );
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::ScreenSize, 0.0, 0.05),
+        (2, None)
+    );
+}
+
+#[test]
+fn select_lod_crossfade() {
+    // Transition between 9 and 11 around the threshold of 10
+    let thresholds = [10.0];
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::Distance, 0.2, 8.0),
+        (0, None)
+    );
+    assert_eq!(
+        select_lod(&thresholds, LodMetric::Distance, 0.2, 12.0),
+        (1, None)
+    );
+
+    // Before the threshold the coarser level dithers in, after it the finer level dithers out
+    let (lod, crossfade) = select_lod(&thresholds, LodMetric::Distance, 0.2, 9.5);
+    assert_eq!(lod, 0);
+    let (crossfade_lod, fade) = crossfade.unwrap();
+    assert_eq!(crossfade_lod, 1);
+    assert!((fade + 0.25).abs() < 1e-5);
+
+    let (lod, crossfade) = select_lod(&thresholds, LodMetric::Distance, 0.2, 10.5);
+    assert_eq!(lod, 1);
+    let (crossfade_lod, fade) = crossfade.unwrap();
+    assert_eq!(crossfade_lod, 0);
+    assert!((fade - 0.75).abs() < 1e-5);
+
+    let (lod, crossfade) = select_lod(&[0.5], LodMetric::ScreenSize, 0.2, 0.48);
+    assert_eq!(lod, 1);
+    assert_eq!(crossfade.unwrap().0, 0);
+}
@@ -1,2 +1,7 @@
+pub mod animation;
+pub mod bvh;
 pub mod components;
+pub mod queries;
+pub mod scene;
+pub mod systems;
 pub mod transform;
@@ -0,0 +1,275 @@
+use glam::{Mat4, Vec3};
+use specs::Join;
+
+use super::{
+    bvh::{self, Aabb, BvhVisitor, MeshBvh},
+    components::{MeshBvhComponent, MeshComponent, TransformComponent},
+};
+
+/// Closest hit of a ray or swept sphere against the scene, in world space.
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    pub entity: specs::Entity,
+    /// Point on the surface, for sphere casts this is the contact point rather than the center of the sphere.
+    pub position: Vec3,
+    /// Geometric normal facing against the ray.
+    pub normal: Vec3,
+    pub distance: f32,
+    /// Index of the triangle within the mesh of `entity`.
+    pub triangle_idx: u32,
+}
+
+/// Visit the local to world matrix and bvh of every entity with a `MeshBvhComponent` or a `MeshComponent` with a cpu bvh,
+/// skipping entities with a disabled `MeshComponent`.
+fn for_each_mesh_bvh(world: &specs::World, mut f: impl FnMut(specs::Entity, Mat4, &MeshBvh)) {
+    let (entities, transform_storage, mesh_bvh_storage, mesh_storage): (
+        specs::Entities<'_>,
+        specs::ReadStorage<'_, TransformComponent>,
+        specs::ReadStorage<'_, MeshBvhComponent>,
+        specs::ReadStorage<'_, MeshComponent>,
+    ) = world.system_data();
+
+    for (entity, transform_component, mesh_bvh_component, mesh_component) in (
+        &entities,
+        &transform_storage,
+        mesh_bvh_storage.maybe(),
+        mesh_storage.maybe(),
+    )
+        .join()
+    {
+        if mesh_component.is_some_and(|mesh_component| !mesh_component.enabled) {
+            continue;
+        }
+
+        let bvh = mesh_bvh_component
+            .map(|mesh_bvh_component| &mesh_bvh_component.bvh)
+            .or_else(|| {
+                mesh_component.and_then(|mesh_component| mesh_component.mesh.cpu_bvh.as_ref())
+            });
+        let Some(bvh) = bvh else {
+            continue;
+        };
+
+        let transform = transform_component.get_local_to_world_matrix(&transform_storage);
+        f(entity, transform, bvh);
+    }
+}
+
+/// Closest triangle hit along a ray through all entities with a cpu bvh, the world needs both `MeshBvhComponent` and `MeshComponent` registered.
+/// Triangles are double sided and alpha is ignored, `direction` does not have to be normalized.
+pub fn raycast(
+    world: &specs::World,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    let direction = direction.normalize();
+    let inv_direction = direction.recip();
+
+    let mut closest: Option<RaycastHit> = None;
+    for_each_mesh_bvh(world, |entity, local_to_world, bvh| {
+        let max_distance = closest.map_or(max_distance, |closest| closest.distance);
+
+        // Rejects meshes by their world space bounds before paying for the inverse
+        let Some(bounds) = bvh.bounds() else {
+            return;
+        };
+        if bounds
+            .transformed(local_to_world)
+            .ray_intersection(origin, inv_direction, max_distance)
+            .is_none()
+        {
+            return;
+        }
+
+        let world_to_local = local_to_world.inverse();
+        // The local direction is left unnormalized, keeping hit distances in world units
+        let local_origin = world_to_local.transform_point3(origin);
+        let local_direction = world_to_local.transform_vector3(direction);
+
+        if let Some(hit) = bvh.raycast(local_origin, local_direction, max_distance) {
+            let mut normal = world_to_local
+                .transpose()
+                .transform_vector3(hit.normal)
+                .normalize();
+            if normal.dot(direction) > 0.0 {
+                normal = -normal;
+            }
+
+            closest = Some(RaycastHit {
+                entity,
+                position: origin + direction * hit.distance,
+                normal,
+                distance: hit.distance,
+                triangle_idx: hit.triangle_idx,
+            });
+        }
+    });
+    closest
+}
+
+/// Closest contact of a sphere swept along a ray through all queryable meshes, see `raycast`.
+/// Spheres already overlapping geometry at `origin` report a distance of zero.
+pub fn sphere_cast(
+    world: &specs::World,
+    origin: Vec3,
+    direction: Vec3,
+    radius: f32,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    // Non-uniform scale turns the sphere into an ellipsoid in mesh space, so triangles are tested in world space
+    struct SphereCastVisitor {
+        local_to_world: Mat4,
+        origin: Vec3,
+        direction: Vec3,
+        inv_direction: Vec3,
+        radius: f32,
+        max_distance: f32,
+        closest: Option<(f32, Vec3, u32)>,
+    }
+
+    impl BvhVisitor for SphereCastVisitor {
+        fn visit_node(&mut self, bounds: &Aabb) -> bool {
+            bounds
+                .transformed(self.local_to_world)
+                .expanded(self.radius)
+                .ray_intersection(self.origin, self.inv_direction, self.max_distance)
+                .is_some()
+        }
+
+        fn visit_triangle(&mut self, triangle_idx: u32, triangle: &[Vec3; 3]) {
+            let triangle = triangle.map(|vertex| self.local_to_world.transform_point3(vertex));
+            if let Some((distance, normal)) =
+                bvh::sphere_triangle_sweep(self.origin, self.direction, self.radius, &triangle)
+            {
+                if distance <= self.max_distance {
+                    self.max_distance = distance;
+                    self.closest = Some((distance, normal, triangle_idx));
+                }
+            }
+        }
+    }
+
+    let direction = direction.normalize();
+
+    let mut closest: Option<RaycastHit> = None;
+    for_each_mesh_bvh(world, |entity, local_to_world, bvh| {
+        let mut visitor = SphereCastVisitor {
+            local_to_world,
+            origin,
+            direction,
+            inv_direction: direction.recip(),
+            radius,
+            max_distance: closest.map_or(max_distance, |closest| closest.distance),
+            closest: None,
+        };
+        bvh.traverse(&mut visitor);
+
+        if let Some((distance, normal, triangle_idx)) = visitor.closest {
+            closest = Some(RaycastHit {
+                entity,
+                position: origin + direction * distance - normal * radius,
+                normal,
+                distance,
+                triangle_idx,
+            });
+        }
+    });
+    closest
+}
+
+/// All queryable meshes with at least one triangle overlapping the world space box, see `raycast`.
+pub fn overlap_aabb(world: &specs::World, min: Vec3, max: Vec3) -> Vec<specs::Entity> {
+    struct OverlapVisitor {
+        local_to_world: Mat4,
+        aabb: Aabb,
+        overlaps: bool,
+    }
+
+    impl BvhVisitor for OverlapVisitor {
+        fn visit_node(&mut self, bounds: &Aabb) -> bool {
+            !self.overlaps && bounds.transformed(self.local_to_world).overlaps(&self.aabb)
+        }
+
+        fn visit_triangle(&mut self, _triangle_idx: u32, triangle: &[Vec3; 3]) {
+            if !self.overlaps {
+                let triangle = triangle.map(|vertex| self.local_to_world.transform_point3(vertex));
+                self.overlaps = bvh::triangle_aabb_overlap(&triangle, &self.aabb);
+            }
+        }
+    }
+
+    let aabb = Aabb::new(min, max);
+
+    let mut entities = Vec::new();
+    for_each_mesh_bvh(world, |entity, local_to_world, bvh| {
+        let mut visitor = OverlapVisitor {
+            local_to_world,
+            aabb,
+            overlaps: false,
+        };
+        bvh.traverse(&mut visitor);
+
+        if visitor.overlaps {
+            entities.push(entity);
+        }
+    });
+    entities
+}
+
+#[test]
+fn queries_without_gpu_resources() {
+    use std::sync::Arc;
+
+    use specs::{Builder, WorldExt};
+
+    use super::transform::Transform;
+
+    let mut world = specs::World::new();
+    world.register::<TransformComponent>();
+    world.register::<MeshBvhComponent>();
+    world.register::<MeshComponent>();
+
+    // A single unit quad on the xy plane
+    let bvh = Arc::new(MeshBvh::new(
+        &[Vec3::ZERO, Vec3::X, Vec3::new(1.0, 1.0, 0.0), Vec3::Y],
+        &[0, 1, 2, 0, 2, 3],
+    ));
+    let mut spawn = |translation: Vec3, scale: Vec3| {
+        world
+            .create_entity()
+            .with(TransformComponent::new(
+                Transform::new(translation, glam::Quat::IDENTITY, scale),
+                false,
+            ))
+            .with(MeshBvhComponent::new(bvh.clone()))
+            .build()
+    };
+    let far = spawn(Vec3::new(0.0, 0.0, -10.0), Vec3::splat(4.0));
+    let near = spawn(Vec3::new(0.0, 0.0, -2.0), Vec3::ONE);
+    let aside = spawn(Vec3::new(100.0, 0.0, 0.0), Vec3::ONE);
+
+    let hit = raycast(&world, Vec3::new(0.5, 0.5, 0.0), Vec3::NEG_Z, 100.0).unwrap();
+    assert_eq!(hit.entity, near);
+    assert!((hit.distance - 2.0).abs() < 1e-5);
+    assert!(hit.normal.abs_diff_eq(Vec3::Z, 1e-5));
+
+    // Scaled meshes report world space distances
+    let hit = raycast(&world, Vec3::new(3.0, 3.0, 0.0), Vec3::NEG_Z, 100.0).unwrap();
+    assert_eq!(hit.entity, far);
+    assert!((hit.distance - 10.0).abs() < 1e-5);
+    assert!(raycast(&world, Vec3::new(3.0, 3.0, 0.0), Vec3::NEG_Z, 5.0).is_none());
+
+    let hit = sphere_cast(&world, Vec3::new(0.5, 0.5, 0.0), Vec3::NEG_Z, 0.5, 100.0).unwrap();
+    assert_eq!(hit.entity, near);
+    assert!((hit.distance - 1.5).abs() < 1e-5);
+
+    assert_eq!(
+        overlap_aabb(
+            &world,
+            Vec3::new(99.0, -1.0, -1.0),
+            Vec3::new(101.0, 1.0, 1.0)
+        ),
+        vec![aside]
+    );
+}
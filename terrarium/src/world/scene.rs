@@ -0,0 +1,926 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use glam::{Quat, Vec3};
+use specs::{Builder, Join, WorldExt};
+use speedy::{Readable, Writable};
+
+use crate::{
+    gpu_resources::{GpuModel, GpuResources},
+    wgpu_util,
+};
+
+use super::{
+    components::{
+        AnimationPlayerComponent, AreaLightComponent, DirectionalLightComponent, DynamicComponent,
+        LodMeshComponent, LodMetric, MeshBvhComponent, MeshComponent, MeshSource,
+        MorphWeightsComponent, PointLightComponent, SkinnedMeshComponent, SpotLightComponent,
+        TransformComponent, VisibilityRangeComponent,
+    },
+    transform::Transform,
+};
+
+const SCENE_MAGIC: [u8; 4] = *b"TRSC";
+/// Version written by `Scene::save`, bump whenever the layout of `SceneData` changes.
+pub const SCENE_VERSION: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct SceneMesh {
+    /// Index into `Scene::model_paths`.
+    pub model_idx: u32,
+    pub mesh_idx: u32,
+    pub material_indices: Vec<u32>,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct SceneAreaLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range_bias_factor: f32,
+    pub double_sided: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct ScenePointLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct SceneSpotLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct SceneDirectionalLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Readable, Writable)]
+pub enum SceneLodMetric {
+    Distance,
+    ScreenSize,
+}
+
+/// Levels of detail of `SceneEntity::mesh`, rebuilt from the lods stored in its model.
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct SceneLodMesh {
+    pub thresholds: Vec<f32>,
+    pub metric: SceneLodMetric,
+    pub crossfade: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct SceneVisibilityRange {
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+pub struct SceneEntity {
+    /// Index into `Scene::entities`, parents are always stored before their children.
+    pub parent: Option<u32>,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+    pub is_static: bool,
+    pub dynamic: bool,
+    pub mesh: Option<SceneMesh>,
+    pub area_light: Option<SceneAreaLight>,
+    pub point_light: Option<ScenePointLight>,
+    pub spot_light: Option<SceneSpotLight>,
+    pub directional_light: Option<SceneDirectionalLight>,
+    pub visibility_range: Option<SceneVisibilityRange>,
+    pub lod_mesh: Option<SceneLodMesh>,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+struct SceneData {
+    model_paths: Vec<String>,
+    entities: Vec<SceneEntity>,
+}
+
+/// Layout of version 3 scenes, which predate levels of detail.
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+struct SceneEntityV3 {
+    parent: Option<u32>,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    is_static: bool,
+    dynamic: bool,
+    mesh: Option<SceneMesh>,
+    area_light: Option<SceneAreaLight>,
+    point_light: Option<ScenePointLight>,
+    spot_light: Option<SceneSpotLight>,
+    directional_light: Option<SceneDirectionalLight>,
+    visibility_range: Option<SceneVisibilityRange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+struct SceneDataV3 {
+    model_paths: Vec<String>,
+    entities: Vec<SceneEntityV3>,
+}
+
+impl From<SceneDataV3> for SceneData {
+    fn from(data: SceneDataV3) -> Self {
+        Self {
+            model_paths: data.model_paths,
+            entities: data
+                .entities
+                .into_iter()
+                .map(|entity| SceneEntity {
+                    parent: entity.parent,
+                    translation: entity.translation,
+                    rotation: entity.rotation,
+                    scale: entity.scale,
+                    is_static: entity.is_static,
+                    dynamic: entity.dynamic,
+                    mesh: entity.mesh,
+                    area_light: entity.area_light,
+                    point_light: entity.point_light,
+                    spot_light: entity.spot_light,
+                    directional_light: entity.directional_light,
+                    visibility_range: entity.visibility_range,
+                    lod_mesh: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Layout of version 2 scenes, which predate visibility ranges.
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+struct SceneEntityV2 {
+    parent: Option<u32>,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    is_static: bool,
+    dynamic: bool,
+    mesh: Option<SceneMesh>,
+    area_light: Option<SceneAreaLight>,
+    point_light: Option<ScenePointLight>,
+    spot_light: Option<SceneSpotLight>,
+    directional_light: Option<SceneDirectionalLight>,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+struct SceneDataV2 {
+    model_paths: Vec<String>,
+    entities: Vec<SceneEntityV2>,
+}
+
+impl From<SceneDataV2> for SceneDataV3 {
+    fn from(data: SceneDataV2) -> Self {
+        Self {
+            model_paths: data.model_paths,
+            entities: data
+                .entities
+                .into_iter()
+                .map(|entity| SceneEntityV3 {
+                    parent: entity.parent,
+                    translation: entity.translation,
+                    rotation: entity.rotation,
+                    scale: entity.scale,
+                    is_static: entity.is_static,
+                    dynamic: entity.dynamic,
+                    mesh: entity.mesh,
+                    area_light: entity.area_light,
+                    point_light: entity.point_light,
+                    spot_light: entity.spot_light,
+                    directional_light: entity.directional_light,
+                    visibility_range: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Layout of version 1 scenes, which predate punctual lights.
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+struct SceneEntityV1 {
+    parent: Option<u32>,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    is_static: bool,
+    dynamic: bool,
+    mesh: Option<SceneMesh>,
+    area_light: Option<SceneAreaLight>,
+}
+
+#[derive(Debug, Clone, PartialEq, Readable, Writable)]
+struct SceneDataV1 {
+    model_paths: Vec<String>,
+    entities: Vec<SceneEntityV1>,
+}
+
+impl From<SceneDataV1> for SceneDataV2 {
+    fn from(data: SceneDataV1) -> Self {
+        Self {
+            model_paths: data.model_paths,
+            entities: data
+                .entities
+                .into_iter()
+                .map(|entity| SceneEntityV2 {
+                    parent: entity.parent,
+                    translation: entity.translation,
+                    rotation: entity.rotation,
+                    scale: entity.scale,
+                    is_static: entity.is_static,
+                    dynamic: entity.dynamic,
+                    mesh: entity.mesh,
+                    area_light: entity.area_light,
+                    point_light: None,
+                    spot_light: None,
+                    directional_light: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How `Scene::from_world` treats a `MeshComponent` without a `MeshSource`, such as procedurally generated meshes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsourcedMeshes {
+    /// Fail instead of writing a scene that does not match the world.
+    Error,
+    /// Store the entity without its mesh, keeping its transform, hierarchy and lights.
+    Omit,
+}
+
+/// Serializable snapshot of all entities with a `TransformComponent`, including their hierarchy, mesh and light components.
+/// Meshes are stored as references to their source model, see `MeshSource`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    pub model_paths: Vec<PathBuf>,
+    pub entities: Vec<SceneEntity>,
+}
+
+impl Scene {
+    /// Fails for entities with components that can't be stored, rather than silently dropping them.
+    pub fn from_world(world: &specs::World, unsourced_meshes: UnsourcedMeshes) -> Result<Self> {
+        let (
+            entities,
+            transform_storage,
+            mesh_storage,
+            area_light_storage,
+            point_light_storage,
+            spot_light_storage,
+            directional_light_storage,
+            dynamic_storage,
+            skinned_mesh_storage,
+            animation_player_storage,
+            morph_weights_storage,
+            lod_mesh_storage,
+            visibility_range_storage,
+        ): (
+            specs::Entities<'_>,
+            specs::ReadStorage<'_, TransformComponent>,
+            specs::ReadStorage<'_, MeshComponent>,
+            specs::ReadStorage<'_, AreaLightComponent>,
+            specs::ReadStorage<'_, PointLightComponent>,
+            specs::ReadStorage<'_, SpotLightComponent>,
+            specs::ReadStorage<'_, DirectionalLightComponent>,
+            specs::ReadStorage<'_, DynamicComponent>,
+            specs::ReadStorage<'_, SkinnedMeshComponent>,
+            specs::ReadStorage<'_, AnimationPlayerComponent>,
+            specs::ReadStorage<'_, MorphWeightsComponent>,
+            specs::ReadStorage<'_, LodMeshComponent>,
+            specs::ReadStorage<'_, VisibilityRangeComponent>,
+        ) = world.system_data();
+
+        // Walk the hierarchy depth first from its roots, making sure parents are serialized before their children
+        let mut stack: Vec<specs::Entity> = (&entities, &transform_storage)
+            .join()
+            .filter(|(_, transform_component)| transform_component.parent().is_none())
+            .map(|(entity, _)| entity)
+            .collect();
+        stack.reverse();
+
+        let mut scene = Scene::default();
+        let mut entity_indices = HashMap::<specs::Entity, u32>::new();
+        let mut model_indices = HashMap::<PathBuf, u32>::new();
+
+        while let Some(entity) = stack.pop() {
+            let transform_component = transform_storage.get(entity).unwrap();
+
+            let unsupported_components = [
+                (
+                    "SkinnedMeshComponent",
+                    skinned_mesh_storage.contains(entity),
+                ),
+                (
+                    "AnimationPlayerComponent",
+                    animation_player_storage.contains(entity),
+                ),
+                (
+                    "MorphWeightsComponent",
+                    morph_weights_storage.contains(entity),
+                ),
+            ];
+            if let Some((name, _)) = unsupported_components
+                .iter()
+                .find(|(_, contained)| *contained)
+            {
+                bail!(
+                    "Entity {:?} has a {}, which cannot be serialized.",
+                    entity,
+                    name
+                );
+            }
+
+            let mesh = if let Some(mesh_component) = mesh_storage.get(entity) {
+                match (&mesh_component.source, unsourced_meshes) {
+                    (Some(source), _) => Some((source, mesh_component.enabled)),
+                    (None, UnsourcedMeshes::Omit) => None,
+                    (None, UnsourcedMeshes::Error) => bail!(
+                        "Entity {:?} has a MeshComponent without a MeshSource, it cannot be serialized.",
+                        entity
+                    ),
+                }
+            } else {
+                None
+            };
+
+            let mesh = if let Some((source, enabled)) = mesh {
+                let model_idx = *model_indices
+                    .entry(source.model_path.clone())
+                    .or_insert_with(|| {
+                        scene.model_paths.push(source.model_path.clone());
+                        scene.model_paths.len() as u32 - 1
+                    });
+
+                Some(SceneMesh {
+                    model_idx,
+                    mesh_idx: source.mesh_idx,
+                    material_indices: source.material_indices.clone(),
+                    enabled,
+                })
+            } else {
+                None
+            };
+
+            let area_light =
+                area_light_storage
+                    .get(entity)
+                    .map(|area_light_component| SceneAreaLight {
+                        color: area_light_component.color.to_array(),
+                        intensity: area_light_component.intensity,
+                        range_bias_factor: area_light_component.range_bias_factor,
+                        double_sided: area_light_component.double_sided,
+                    });
+            let point_light = point_light_storage
+                .get(entity)
+                .map(|point_light_component| ScenePointLight {
+                    color: point_light_component.color.to_array(),
+                    intensity: point_light_component.intensity,
+                    range: point_light_component.range,
+                });
+            let spot_light =
+                spot_light_storage
+                    .get(entity)
+                    .map(|spot_light_component| SceneSpotLight {
+                        color: spot_light_component.color.to_array(),
+                        intensity: spot_light_component.intensity,
+                        range: spot_light_component.range,
+                        inner_angle: spot_light_component.inner_angle,
+                        outer_angle: spot_light_component.outer_angle,
+                    });
+            let directional_light =
+                directional_light_storage
+                    .get(entity)
+                    .map(|directional_light_component| SceneDirectionalLight {
+                        color: directional_light_component.color.to_array(),
+                        intensity: directional_light_component.intensity,
+                    });
+            let lod_mesh = if let Some(lod_mesh_component) = lod_mesh_storage.get(entity) {
+                if mesh.is_none() {
+                    bail!(
+                        "Entity {:?} has a LodMeshComponent without a serializable mesh.",
+                        entity
+                    );
+                }
+
+                Some(SceneLodMesh {
+                    thresholds: lod_mesh_component.thresholds.clone(),
+                    metric: match lod_mesh_component.metric {
+                        LodMetric::Distance => SceneLodMetric::Distance,
+                        LodMetric::ScreenSize => SceneLodMetric::ScreenSize,
+                    },
+                    crossfade: lod_mesh_component.crossfade,
+                })
+            } else {
+                None
+            };
+            let visibility_range =
+                visibility_range_storage
+                    .get(entity)
+                    .map(|visibility_range_component| SceneVisibilityRange {
+                        min_distance: visibility_range_component.min_distance,
+                        max_distance: visibility_range_component.max_distance,
+                    });
+
+            entity_indices.insert(entity, scene.entities.len() as u32);
+            scene.entities.push(SceneEntity {
+                parent: transform_component
+                    .parent()
+                    .map(|parent| *entity_indices.get(&parent).unwrap()),
+                translation: transform_component.get_local_translation().to_array(),
+                rotation: transform_component.get_local_rotation().to_array(),
+                scale: transform_component.get_local_scale().to_array(),
+                is_static: transform_component.is_static(),
+                dynamic: dynamic_storage.contains(entity),
+                mesh,
+                area_light,
+                point_light,
+                spot_light,
+                directional_light,
+                visibility_range,
+                lod_mesh,
+            });
+
+            stack.extend(transform_component.children().iter().rev());
+        }
+
+        Ok(scene)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()?)
+            .with_context(|| format!("Failed to write scene {}", path.display()))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let buffer = std::fs::read(path)
+            .with_context(|| format!("Failed to read scene {}", path.display()))?;
+
+        Self::from_bytes(&buffer)
+            .with_context(|| format!("Failed to load scene {}", path.display()))
+    }
+
+    /// Encode the scene the way `save` stores it, including its header.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let data = SceneData {
+            model_paths: self
+                .model_paths
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect(),
+            entities: self.entities.clone(),
+        };
+
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&SCENE_MAGIC);
+        buffer.extend_from_slice(&SCENE_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&data.write_to_vec()?);
+
+        Ok(buffer)
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self> {
+        if buffer.len() < 8 || buffer[0..4] != SCENE_MAGIC {
+            bail!("Not a terrarium scene.");
+        }
+        let version = u32::from_le_bytes(buffer[4..8].try_into().unwrap());
+
+        let data = match version {
+            1 => SceneDataV3::from(SceneDataV2::from(
+                SceneDataV1::read_from_buffer(&buffer[8..]).context("Failed to parse scene")?,
+            ))
+            .into(),
+            2 => SceneDataV3::from(
+                SceneDataV2::read_from_buffer(&buffer[8..]).context("Failed to parse scene")?,
+            )
+            .into(),
+            3 => SceneDataV3::read_from_buffer(&buffer[8..])
+                .context("Failed to parse scene")?
+                .into(),
+            4 => SceneData::read_from_buffer(&buffer[8..]).context("Failed to parse scene")?,
+            _ => bail!(
+                "Scene has version {}, only versions up to {} are supported.",
+                version,
+                SCENE_VERSION
+            ),
+        };
+
+        Ok(Self {
+            model_paths: data.model_paths.into_iter().map(PathBuf::from).collect(),
+            entities: data.entities,
+        })
+    }
+
+    /// Spawn all entities into `world`, uploading every referenced model through `GpuModel::from_file`.
+    /// Returns the created entities in the same order as `entities`.
+    pub fn spawn(
+        &self,
+        world: &mut specs::World,
+        gpu_resources: &mut GpuResources,
+        command_encoder: &mut wgpu::CommandEncoder,
+        ctx: &wgpu_util::Context,
+    ) -> Result<Vec<specs::Entity>> {
+        world.register::<TransformComponent>();
+        world.register::<MeshComponent>();
+        world.register::<AreaLightComponent>();
+        world.register::<PointLightComponent>();
+        world.register::<SpotLightComponent>();
+        world.register::<DirectionalLightComponent>();
+        world.register::<DynamicComponent>();
+        world.register::<SkinnedMeshComponent>();
+        world.register::<AnimationPlayerComponent>();
+        world.register::<MorphWeightsComponent>();
+        world.register::<VisibilityRangeComponent>();
+        world.register::<LodMeshComponent>();
+        world.register::<MeshBvhComponent>();
+
+        let gpu_models = self
+            .model_paths
+            .iter()
+            .map(|path| GpuModel::from_file(path, gpu_resources, command_encoder, ctx))
+            .collect::<Result<Vec<GpuModel>>>()?;
+
+        let mut spawned_entities: Vec<specs::Entity> = Vec::with_capacity(self.entities.len());
+        for (i, scene_entity) in self.entities.iter().enumerate() {
+            let transform = Transform::new(
+                Vec3::from_array(scene_entity.translation),
+                Quat::from_array(scene_entity.rotation),
+                Vec3::from_array(scene_entity.scale),
+            );
+            let transform_component = TransformComponent::new(transform, scene_entity.is_static);
+
+            if let Some(parent) = scene_entity.parent {
+                if parent as usize >= i {
+                    bail!(
+                        "Scene entity {} references parent {} that is not stored before it.",
+                        i,
+                        parent
+                    );
+                }
+            }
+
+            let mut builder = world.create_entity().with(transform_component);
+
+            if scene_entity.dynamic {
+                builder = builder.with(DynamicComponent);
+            }
+
+            if let Some(scene_mesh) = &scene_entity.mesh {
+                let gpu_model = gpu_models
+                    .get(scene_mesh.model_idx as usize)
+                    .with_context(|| format!("Scene entity {} references a missing model.", i))?;
+                let mesh = gpu_model
+                    .gpu_meshes
+                    .get(scene_mesh.mesh_idx as usize)
+                    .cloned()
+                    .flatten()
+                    .with_context(|| format!("Scene entity {} references a missing mesh.", i))?;
+                let materials = scene_mesh
+                    .material_indices
+                    .iter()
+                    .map(|material_idx| {
+                        gpu_model
+                            .gpu_materials
+                            .get(*material_idx as usize)
+                            .cloned()
+                            .with_context(|| {
+                                format!("Scene entity {} references a missing material.", i)
+                            })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                let lod_mesh_component = if let Some(scene_lod_mesh) = &scene_entity.lod_mesh {
+                    let metric = match scene_lod_mesh.metric {
+                        SceneLodMetric::Distance => LodMetric::Distance,
+                        SceneLodMetric::ScreenSize => LodMetric::ScreenSize,
+                    };
+                    let (_, lod_mesh_component) = gpu_model
+                        .lod_mesh_components(
+                            scene_mesh.mesh_idx as usize,
+                            scene_lod_mesh.thresholds.clone(),
+                            metric,
+                        )
+                        .with_context(|| {
+                            format!("Scene entity {} references a missing lod mesh.", i)
+                        })?;
+                    Some(lod_mesh_component.with_crossfade(scene_lod_mesh.crossfade))
+                } else {
+                    None
+                };
+
+                let mut mesh_component =
+                    MeshComponent::new(mesh, materials).with_source(MeshSource {
+                        model_path: self.model_paths[scene_mesh.model_idx as usize].clone(),
+                        mesh_idx: scene_mesh.mesh_idx,
+                        material_indices: scene_mesh.material_indices.clone(),
+                    });
+                mesh_component.enabled = scene_mesh.enabled;
+
+                builder = builder.with(mesh_component);
+                if let Some(lod_mesh_component) = lod_mesh_component {
+                    builder = builder.with(lod_mesh_component);
+                }
+            }
+
+            if let Some(scene_area_light) = &scene_entity.area_light {
+                builder = builder.with(AreaLightComponent::new(
+                    Vec3::from_array(scene_area_light.color),
+                    scene_area_light.intensity,
+                    scene_area_light.range_bias_factor,
+                    scene_area_light.double_sided,
+                ));
+            }
+
+            if let Some(scene_point_light) = &scene_entity.point_light {
+                builder = builder.with(PointLightComponent::new(
+                    Vec3::from_array(scene_point_light.color),
+                    scene_point_light.intensity,
+                    scene_point_light.range,
+                ));
+            }
+
+            if let Some(scene_spot_light) = &scene_entity.spot_light {
+                builder = builder.with(SpotLightComponent::new(
+                    Vec3::from_array(scene_spot_light.color),
+                    scene_spot_light.intensity,
+                    scene_spot_light.range,
+                    scene_spot_light.inner_angle,
+                    scene_spot_light.outer_angle,
+                ));
+            }
+
+            if let Some(scene_directional_light) = &scene_entity.directional_light {
+                builder = builder.with(DirectionalLightComponent::new(
+                    Vec3::from_array(scene_directional_light.color),
+                    scene_directional_light.intensity,
+                ));
+            }
+
+            if let Some(scene_visibility_range) = &scene_entity.visibility_range {
+                builder = builder.with(VisibilityRangeComponent::new(
+                    scene_visibility_range.min_distance,
+                    scene_visibility_range.max_distance,
+                ));
+            }
+
+            spawned_entities.push(builder.build());
+        }
+
+        {
+            let mut transform_storage = world.write_storage::<TransformComponent>();
+            for (scene_entity, entity) in self.entities.iter().zip(spawned_entities.iter()) {
+                if let Some(parent) = scene_entity.parent {
+                    TransformComponent::attach_child(
+                        &mut transform_storage,
+                        spawned_entities[parent as usize],
+                        *entity,
+                    )?;
+                }
+            }
+        }
+
+        for entity in &spawned_entities {
+            gpu_resources.mark_static_entity_dirty(*entity);
+        }
+
+        Ok(spawned_entities)
+    }
+}
+
+#[test]
+fn scene_bytes_round_trip() {
+    let scene = Scene {
+        model_paths: vec![PathBuf::from("assets/sponza.ugm")],
+        entities: vec![
+            SceneEntity {
+                parent: None,
+                translation: [1.0, 2.0, 3.0],
+                rotation: Quat::from_rotation_y(0.5).to_array(),
+                scale: [1.0, 1.0, 1.0],
+                is_static: true,
+                dynamic: false,
+                mesh: Some(SceneMesh {
+                    model_idx: 0,
+                    mesh_idx: 3,
+                    material_indices: vec![1, 2],
+                    enabled: true,
+                }),
+                area_light: None,
+                point_light: None,
+                spot_light: None,
+                directional_light: None,
+                visibility_range: None,
+                lod_mesh: Some(SceneLodMesh {
+                    thresholds: vec![10.0, 20.0],
+                    metric: SceneLodMetric::Distance,
+                    crossfade: 0.1,
+                }),
+            },
+            SceneEntity {
+                parent: Some(0),
+                translation: [0.0, 1.0, 0.0],
+                rotation: Quat::IDENTITY.to_array(),
+                scale: [2.0, 2.0, 2.0],
+                is_static: false,
+                dynamic: true,
+                mesh: None,
+                area_light: Some(SceneAreaLight {
+                    color: [1.0, 0.5, 0.25],
+                    intensity: 10.0,
+                    range_bias_factor: 0.5,
+                    double_sided: true,
+                }),
+                point_light: Some(ScenePointLight {
+                    color: [1.0, 1.0, 1.0],
+                    intensity: 5.0,
+                    range: 20.0,
+                }),
+                spot_light: None,
+                directional_light: Some(SceneDirectionalLight {
+                    color: [1.0, 0.9, 0.8],
+                    intensity: 3.0,
+                }),
+                visibility_range: Some(SceneVisibilityRange {
+                    min_distance: 5.0,
+                    max_distance: 50.0,
+                }),
+                lod_mesh: None,
+            },
+        ],
+    };
+
+    assert_eq!(
+        Scene::from_bytes(&scene.to_bytes().unwrap()).unwrap(),
+        scene
+    );
+    assert!(Scene::from_bytes(b"TRSC").is_err());
+}
+
+#[test]
+fn scene_from_world() {
+    let mut world = specs::World::new();
+    world.register::<TransformComponent>();
+    world.register::<MeshComponent>();
+    world.register::<AreaLightComponent>();
+    world.register::<PointLightComponent>();
+    world.register::<SpotLightComponent>();
+    world.register::<DirectionalLightComponent>();
+    world.register::<DynamicComponent>();
+    world.register::<SkinnedMeshComponent>();
+    world.register::<AnimationPlayerComponent>();
+    world.register::<MorphWeightsComponent>();
+    world.register::<VisibilityRangeComponent>();
+    world.register::<LodMeshComponent>();
+
+    let parent = world
+        .create_entity()
+        .with(TransformComponent::new(
+            Transform::new(Vec3::new(1.0, 0.0, 0.0), Quat::IDENTITY, Vec3::ONE),
+            false,
+        ))
+        .with(DynamicComponent)
+        .build();
+    let child = world
+        .create_entity()
+        .with(TransformComponent::new(
+            Transform::new(Vec3::new(0.0, 2.0, 0.0), Quat::IDENTITY, Vec3::ONE),
+            false,
+        ))
+        .with(PointLightComponent::new(Vec3::ONE, 5.0, 20.0))
+        .build();
+    TransformComponent::attach_child(&mut world.write_storage(), parent, child).unwrap();
+
+    let scene = Scene::from_world(&world, UnsourcedMeshes::Error).unwrap();
+    assert_eq!(scene.entities.len(), 2);
+    assert_eq!(scene.entities[0].parent, None);
+    assert!(scene.entities[0].dynamic);
+    assert_eq!(scene.entities[1].parent, Some(0));
+    assert_eq!(scene.entities[1].translation, [0.0, 2.0, 0.0]);
+    assert_eq!(
+        scene.entities[1].point_light,
+        Some(ScenePointLight {
+            color: [1.0, 1.0, 1.0],
+            intensity: 5.0,
+            range: 20.0,
+        })
+    );
+    assert_eq!(
+        Scene::from_bytes(&scene.to_bytes().unwrap()).unwrap(),
+        scene
+    );
+
+    world
+        .write_storage()
+        .insert(child, VisibilityRangeComponent::new(0.0, 10.0))
+        .unwrap();
+    let scene = Scene::from_world(&world, UnsourcedMeshes::Error).unwrap();
+    assert_eq!(
+        scene.entities[1].visibility_range,
+        Some(SceneVisibilityRange {
+            min_distance: 0.0,
+            max_distance: 10.0,
+        })
+    );
+
+    world
+        .write_storage()
+        .insert(child, AnimationPlayerComponent::new(None))
+        .unwrap();
+    assert!(Scene::from_world(&world, UnsourcedMeshes::Error).is_err());
+}
+
+#[test]
+fn scene_v2_upgrade() {
+    let data = SceneDataV2 {
+        model_paths: Vec::new(),
+        entities: vec![SceneEntityV2 {
+            parent: None,
+            translation: [1.0, 2.0, 3.0],
+            rotation: Quat::IDENTITY.to_array(),
+            scale: [1.0, 1.0, 1.0],
+            is_static: false,
+            dynamic: true,
+            mesh: None,
+            area_light: None,
+            point_light: Some(ScenePointLight {
+                color: [1.0, 1.0, 1.0],
+                intensity: 5.0,
+                range: 20.0,
+            }),
+            spot_light: None,
+            directional_light: None,
+        }],
+    };
+
+    let mut buffer = Vec::new();
+    buffer.extend_from_slice(&SCENE_MAGIC);
+    buffer.extend_from_slice(&2u32.to_le_bytes());
+    buffer.extend_from_slice(&data.write_to_vec().unwrap());
+
+    let scene = Scene::from_bytes(&buffer).unwrap();
+    assert_eq!(scene.entities.len(), 1);
+    assert_eq!(scene.entities[0].translation, [1.0, 2.0, 3.0]);
+    assert!(scene.entities[0].point_light.is_some());
+    assert_eq!(scene.entities[0].visibility_range, None);
+    assert_eq!(scene.entities[0].lod_mesh, None);
+}
+
+#[test]
+fn scene_v3_upgrade() {
+    let data = SceneDataV3 {
+        model_paths: vec![String::from("assets/sponza.ugm")],
+        entities: vec![SceneEntityV3 {
+            parent: None,
+            translation: [1.0, 2.0, 3.0],
+            rotation: Quat::IDENTITY.to_array(),
+            scale: [1.0, 1.0, 1.0],
+            is_static: true,
+            dynamic: false,
+            mesh: Some(SceneMesh {
+                model_idx: 0,
+                mesh_idx: 3,
+                material_indices: vec![1],
+                enabled: true,
+            }),
+            area_light: None,
+            point_light: None,
+            spot_light: None,
+            directional_light: None,
+            visibility_range: Some(SceneVisibilityRange {
+                min_distance: 5.0,
+                max_distance: 50.0,
+            }),
+        }],
+    };
+
+    let mut buffer = Vec::new();
+    buffer.extend_from_slice(&SCENE_MAGIC);
+    buffer.extend_from_slice(&3u32.to_le_bytes());
+    buffer.extend_from_slice(&data.write_to_vec().unwrap());
+
+    let scene = Scene::from_bytes(&buffer).unwrap();
+    assert_eq!(scene.model_paths, vec![PathBuf::from("assets/sponza.ugm")]);
+    assert_eq!(scene.entities.len(), 1);
+    assert_eq!(scene.entities[0].mesh.as_ref().unwrap().mesh_idx, 3);
+    assert_eq!(
+        scene.entities[0].visibility_range,
+        Some(SceneVisibilityRange {
+            min_distance: 5.0,
+            max_distance: 50.0,
+        })
+    );
+    assert_eq!(scene.entities[0].lod_mesh, None);
+}
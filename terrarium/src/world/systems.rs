@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use glam::Vec3;
+use specs::Join;
+
+use crate::gpu_resources::culling::bounding_sphere;
+
+use super::components::{
+    AnimationPlayerComponent, LodMeshComponent, LodMetric, MeshComponent, SkinnedMeshComponent,
+    TransformComponent,
+};
+
+/// Propagates local transform changes down the hierarchy, marking the cached global transform of every descendant dirty.
+/// Walks the hierarchy top-down from its roots, so each entity is visited once regardless of how many ancestors changed.
+/// `GpuResources::update` runs this every frame, run it earlier when up to date world transforms are needed before rendering.
+/// Static entities that moved are collected so their instance can be patched, pass them to `GpuResources::mark_static_entity_dirty` when running it yourself.
+#[derive(Default)]
+pub struct TransformPropagationSystem {
+    pub changed_static_entities: Vec<specs::Entity>,
+}
+
+impl<'a> specs::System<'a> for TransformPropagationSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, TransformComponent>,
+    );
+
+    fn run(&mut self, (entities, transforms): Self::SystemData) {
+        let mut stack: Vec<(specs::Entity, bool)> = (&entities, &transforms)
+            .join()
+            .filter(|(_, transform_component)| transform_component.parent().is_none())
+            .map(|(entity, _)| (entity, false))
+            .collect();
+
+        while let Some((entity, parent_dirty)) = stack.pop() {
+            let Some(transform_component) = transforms.get(entity) else {
+                continue;
+            };
+
+            let dirty = transform_component.take_local_changed() || parent_dirty;
+            if dirty && transform_component.parent().is_some() {
+                transform_component.mark_global_dirty();
+            }
+            if dirty && transform_component.is_static() {
+                self.changed_static_entities.push(entity);
+            }
+
+            stack.extend(
+                transform_component
+                    .children()
+                    .iter()
+                    .map(|child| (*child, dirty)),
+            );
+        }
+    }
+}
+
+/// Advances every `AnimationPlayerComponent` by `delta_time` seconds and writes the sampled pose into the `SkinnedMeshComponent` of the same entity.
+/// `GpuResources::update` runs this every frame.
+pub struct AnimationSystem {
+    pub delta_time: f32,
+}
+
+impl<'a> specs::System<'a> for AnimationSystem {
+    type SystemData = (
+        specs::WriteStorage<'a, AnimationPlayerComponent>,
+        specs::WriteStorage<'a, SkinnedMeshComponent>,
+    );
+
+    fn run(&mut self, (mut animation_players, mut skinned_meshes): Self::SystemData) {
+        let mut pose = Vec::new();
+        for (animation_player, skinned_mesh) in (&mut animation_players, &mut skinned_meshes).join()
+        {
+            let Some(clip) = animation_player.clip.clone() else {
+                continue;
+            };
+
+            if animation_player.playing {
+                animation_player.time += self.delta_time * animation_player.speed;
+                if animation_player.looping && clip.duration > 0.0 {
+                    animation_player.time = animation_player.time.rem_euclid(clip.duration);
+                } else {
+                    let time = animation_player.time.clamp(0.0, clip.duration);
+                    animation_player.playing = time == animation_player.time;
+                    animation_player.time = time;
+                }
+            }
+
+            let skeleton = &skinned_mesh.skeleton;
+            pose.clear();
+            pose.extend_from_slice(skeleton.rest_pose());
+            clip.sample(animation_player.time, skeleton, &mut pose);
+            skeleton.joint_matrices(&pose, &mut skinned_mesh.joint_matrices);
+        }
+    }
+}
+
+/// Selects the level of every `LodMeshComponent` and assigns its mesh to the `MeshComponent` of the same entity.
+/// `GpuResources::update` runs this every frame, static entities that switched level or are transitioning are collected so their instance can be patched.
+pub struct LodSystem {
+    pub camera_position: Vec3,
+    /// Vertical focal length of the projection, converts the size of bounds over their distance into a screen size.
+    pub projection_scale: f32,
+    pub changed_static_entities: Vec<specs::Entity>,
+}
+
+impl<'a> specs::System<'a> for LodSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, TransformComponent>,
+        specs::WriteStorage<'a, LodMeshComponent>,
+        specs::WriteStorage<'a, MeshComponent>,
+    );
+
+    fn run(&mut self, (entities, transforms, mut lod_meshes, mut meshes): Self::SystemData) {
+        for (entity, transform_component, lod_mesh, mesh) in
+            (&entities, &transforms, &mut lod_meshes, &mut meshes).join()
+        {
+            let transform = transform_component.get_local_to_world_matrix(&transforms);
+            let finest_mesh = &lod_mesh.meshes[0];
+            let (center, radius) =
+                bounding_sphere(transform, finest_mesh.bounds_min, finest_mesh.bounds_max);
+            let center_distance = center.distance(self.camera_position);
+
+            let value = match lod_mesh.metric {
+                LodMetric::Distance => (center_distance - radius).max(0.0),
+                LodMetric::ScreenSize => {
+                    radius * self.projection_scale / center_distance.max(f32::EPSILON)
+                }
+            };
+
+            let was_transitioning = lod_mesh.active_crossfade().is_some();
+            lod_mesh.select(value);
+            let lod_gpu_mesh = &lod_mesh.meshes[lod_mesh.lod()];
+            let switched = !Arc::ptr_eq(&mesh.mesh, lod_gpu_mesh);
+            if switched {
+                mesh.mesh = lod_gpu_mesh.clone();
+            }
+
+            // The dither threshold of a static instance changes every frame of a transition
+            if transform_component.is_static()
+                && (switched || was_transitioning || lod_mesh.active_crossfade().is_some())
+            {
+                self.changed_static_entities.push(entity);
+            }
+        }
+    }
+}